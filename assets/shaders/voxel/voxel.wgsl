@@ -25,6 +25,8 @@ const LIGHT_COLOR: vec3f = vec3f(
     1.0,
 );
 const DIFFUSE_MIN: f32 = 0.2;
+// The brightness of a fully occluded corner.
+const AO_MIN: f32 = 0.4;
 const DIFFUSE_MAX: f32 = 1.0;
 
 fn calc_diffuse(diff: f32) -> f32 {
@@ -36,12 +38,37 @@ fn lightmap_index(x: u32, y: u32, z: u32) -> u32 {
     return x | (z << 4) | (y << 8);
 }
 
+fn pad_index(a: u32, b: u32) -> u32 {
+    return a | (b << 4);
+}
+
+// Samples the light of the block at a local coordinate in the range -1 to 16.
+// Coordinates outside of 0 to 15 are read from the padding of the neighboring sections.
+fn sample_light(coord: vec3<i32>) -> f32 {
+    let c = vec3<u32>(clamp(coord, vec3<i32>(0), vec3<i32>(15)));
+    if coord.x < 0 {
+        return lightmap_pad_neg_x[pad_index(c.y, c.z)];
+    } else if coord.x > 15 {
+        return lightmap_pad_pos_x[pad_index(c.y, c.z)];
+    } else if coord.y < 0 {
+        return lightmap_pad_neg_y[pad_index(c.x, c.z)];
+    } else if coord.y > 15 {
+        return lightmap_pad_pos_y[pad_index(c.x, c.z)];
+    } else if coord.z < 0 {
+        return lightmap_pad_neg_z[pad_index(c.x, c.y)];
+    } else if coord.z > 15 {
+        return lightmap_pad_pos_z[pad_index(c.x, c.y)];
+    }
+    return lightmap[lightmap_index(c.x, c.y, c.z)];
+}
+
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
     @location(0) position: vec3<f32>,
     @location(1) uv: vec2<f32>,
     @location(2) normal: vec3<f32>,
     @location(3) texindex: u32,
+    @location(4) ao: f32,
 };
 
 struct Fragment {
@@ -50,6 +77,7 @@ struct Fragment {
     @location(1) normal: vec3<f32>,
     @location(2) texindex: u32,
     @location(3) localpos: vec4<f32>,
+    @location(4) ao: f32,
 };
 
 @vertex
@@ -64,6 +92,7 @@ fn vertex(input: Vertex) -> Fragment {
     output.uv = input.uv;
     output.normal = input.normal;
     output.texindex = input.texindex;
+    output.ao = input.ao;
     return output;
 }
 
@@ -83,18 +112,14 @@ fn fragment(input: Fragment) -> @location(0) vec4<f32> {
     let diff = calc_diffuse(max(dot(norm, -LIGHT_DIR), 0.0));
     let diffuse = diff * LIGHT_COLOR;
     // color.rgb = color.rgb * diffuse;
-    let rgb = color.rgb * diffuse;
-    let output = vec4f(rgb, color.a);
-    // Light level adjustment. I have to rewrite this for 3D.
-    // let x = u32(rem_euclid(input.localpos.x, 16.0));
-    // let y = u32(rem_euclid(input.localpos.z, 16.0));
-    // var index = lightmap_index(x, y);
-    // let light = lightmap[index];
-    // let min_light = (1.0 - MIN_LIGHT) * light + MIN_LIGHT;
-    // let adj_light_level = (1.0 - light_level) * min_light + light_level;
-    // let adj_rgb = color.rgb * adj_light_level;
-    // let output = vec4f(adj_rgb, color.a);
-    // let output = vec4f(color.rgb, color.a);
+    let ao = mix(AO_MIN, 1.0, input.ao);
+    let rgb = color.rgb * diffuse * ao;
+    // The light of a face comes from the block in front of it.
+    let sample_pos = floor(input.localpos.xyz + norm * 0.5);
+    let light = sample_light(vec3<i32>(sample_pos));
+    let min_light = (1.0 - MIN_LIGHT) * light + MIN_LIGHT;
+    let adj_light_level = (1.0 - light_level) * min_light + light_level;
+    let output = vec4f(rgb * adj_light_level, color.a);
     return output;
 }
 
@@ -354,6 +354,7 @@ fn on_enter_main_menu(
         lightmap_pad_neg_y: vec![],
         lightmap_pad_pos_z: vec![],
         lightmap_pad_neg_z: vec![],
+        alpha_mode: AlphaMode::Opaque,
     });
     // vox_data.as_mut().vox_mat = Some(vox_mat.clone());
     let mesh_holder = MeshHolder {
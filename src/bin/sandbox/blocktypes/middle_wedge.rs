@@ -1,6 +1,8 @@
 use std::sync::LazyLock;
 
-use unvoga::{blockstate, core::{util::modelimporter::{read_model_data, ModelData}, voxel::{level_of_detail::{self, LOD}, rendering::meshbuilder::MeshBuilder, world::VoxelWorld}}, prelude::{Block, Coord, Direction, Occluder, OcclusionRect, OcclusionShape, OcclusionShape2x2, OcclusionShape4x4, Orientation, StateValue}};
+use bevy::math::{vec3, Vec3};
+
+use unvoga::{blockstate, core::{math::aabb::AABB, util::modelimporter::{read_model_data, ModelData}, voxel::{level_of_detail::{self, LOD}, rendering::{meshbuilder::MeshBuilder, sectionmesh::SectionSnapshot}, world::VoxelWorld}}, prelude::{Block, Coord, Direction, Occluder, OcclusionRect, OcclusionShape, OcclusionShape2x2, OcclusionShape4x4, Orientation, StateValue}};
 
 pub struct MiddleWedge {
     mesh_data: ModelData,
@@ -83,6 +85,18 @@ impl Block for MiddleWedge {
         &OCCLUDEE
     }
 
+    fn selection_shape(&self, world: &VoxelWorld, coord: Coord, state: unvoga::prelude::Id) -> Vec<AABB> {
+        vec![AABB::new(Vec3::splat(-0.5), vec3(0.5, 0.25, 0.5))]
+    }
+
+    fn collision_shape(&self, world: &VoxelWorld, coord: Coord, state: unvoga::prelude::Id) -> Vec<AABB> {
+        // The slope is approximated with two steps.
+        vec![
+            AABB::new(Vec3::splat(-0.5), vec3(0.5, 0.25, 0.0)),
+            AABB::new(vec3(-0.5, -0.5, 0.0), vec3(0.5, 0.0, 0.5)),
+        ]
+    }
+
     fn orientation(&self, world: &VoxelWorld, coord: Coord, state: unvoga::prelude::Id) -> unvoga::prelude::Orientation {
         if let StateValue::Orientation(orientation) = state["orientation"] {
             orientation
@@ -95,7 +109,7 @@ impl Block for MiddleWedge {
         blockstate!(middle_wedge, orientation=orientation).register()
     }
 
-    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, world: &VoxelWorld, coord: unvoga::prelude::Coord, state: unvoga::prelude::Id, occlusion: unvoga::prelude::Occlusion, orientation: Orientation) {
+    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: unvoga::prelude::Coord, state: unvoga::prelude::Id, occlusion: unvoga::prelude::Occlusion, orientation: Orientation) {
         // static MODEL: LazyLock<ModelData> = LazyLock::new(|| {
         //     read_model_data("./assets/debug/models/middle_wedge.json", None).expect("Failed to read model for middle_wedge.")
         // });
@@ -7,11 +7,11 @@ use blocktypes::middle_wedge::MiddleWedge;
 use bevy_egui::egui::epaint::Shadow;
 use unvoga::core::voxel::level_of_detail::{self, LOD};
 use unvoga::core::voxel::rendering::meshbuilder::MeshBuilder;
+use unvoga::core::voxel::rendering::sectionmesh::SectionSnapshot;
 use unvoga::game::cameras::{CameraContoller, CameraType};
 
 // mod textureregistry;
 use std::cell::LazyCell;
-use std::ops::Range;
 use std::path::PathBuf;
 use std::sync::LazyLock;
 use std::time::Instant;
@@ -27,9 +27,10 @@ use hashbrown::HashMap;
 use rollgrid::rollgrid3d::Bounds3D;
 use unvoga::core::voxel::blocklayer::BlockLayer;
 use unvoga::core::voxel::blockstate::BlockState;
+use unvoga::core::voxel::entity::{EntityId, VoxelEntity};
 use unvoga::core::voxel::rendering::voxelmaterial::VoxelMaterial;
 use unvoga::core::voxel::rendering::voxelmesh::MeshData;
-use unvoga::core::voxel::world::{RaycastResult, RenderChunkMarker};
+use unvoga::core::voxel::world::{externevent::{forward_extern_events, EventFilter, ExternEvent}, history::HistoryLimits, RaycastResult, RenderChunkMarker};
 use unvoga::prelude::*;
 use unvoga::core::voxel::region::regionfile::RegionFile;
 use unvoga::prelude::*;
@@ -72,7 +73,10 @@ pub fn main() {
         .add_plugins(MaterialPlugin::<VoxelMaterial>::default())
         .add_systems(Startup, setup)
         .add_systems(Update, update_input)
+        .add_systems(Update, sync_entities.after(update_input))
         .add_systems(PostUpdate, update_bevy)
+        .add_systems(PostUpdate, forward_extern_events.after(update_bevy))
+        .add_event::<ExternEvent>()
         .insert_resource(Assets::<VoxelMaterial>::default())
         .insert_resource(Assets::<Mesh>::default())
         // .insert_resource(Assets::<Image>::default())
@@ -265,6 +269,7 @@ fn setup(
     reg_block_tex!(fancy_wood_green);
     reg_block_tex!(fancy_wood_blue);
     reg_block_tex!(fancy_wood_yellow);
+    reg_block_tex!(missing);
     // let side_texture_paths = vec![
     //     // "./assets/debug/textures/cube_sides/pos_y.png",     // 0
     //     // "./assets/debug/textures/cube_sides/pos_x.png",     // 1
@@ -293,6 +298,7 @@ fn setup(
             0, 0,
             0, 0,
         ],
+        ao: Vec::new(),
         indices: vec![
             0, 2, 1,
             1, 2, 3,
@@ -314,6 +320,7 @@ fn setup(
     blocks::register_block(SolidBlock::single("fancy_wood_blue", blockstate!(fancy_wood_blue), texreg::get_texture_index("fancy_wood_blue")));
     blocks::register_block(SolidBlock::single("fancy_wood_yellow", blockstate!(fancy_wood_yellow), texreg::get_texture_index("fancy_wood_yellow")));
     blocks::register_block(MiddleWedge::new());
+    blocks::set_unknown_texture(texreg::get_texture_index("missing"));
     let texture_array = images.add(texreg::build_texture_array(256, 256).expect("Failed to build texture array"));
     // blocks::register_block(RotatedBlock);
     // std::fs::remove_dir_all("ignore/worldgen");
@@ -326,7 +333,9 @@ fn setup(
         &mut meshes,
         &mut materials,
         None,
-    );
+    ).expect("Failed to open the world");
+    world.enable_history(HistoryLimits::default());
+    world.event_filter = EventFilter::ALL - EventFilter::LIGHT;
     // let dirt = blockstate!(dirt).register();
     // let bricks = blockstate!(stone_bricks).register();
     // world.set_block((1, 1, 1), dirt);
@@ -509,27 +518,27 @@ fn update_input(
         println!("{dynsize}");
     }
     const BOUND_SIZE: i32 = 32;
-    const X_BOUND: Range<i32> = -BOUND_SIZE..BOUND_SIZE;
-    const Y_BOUND: Range<i32> = -BOUND_SIZE..BOUND_SIZE;
-    const Z_BOUND: Range<i32> = -BOUND_SIZE..BOUND_SIZE;
+    let edit_area = Selection::cuboid(Coord::splat(-BOUND_SIZE), Coord::splat(BOUND_SIZE - 1));
     if keys.just_pressed(KeyCode::KeyI) {
-        for y in Y_BOUND {
-            for z in Z_BOUND {
-                for x in X_BOUND {
-                    let coord_dirt = blockstate!(stone_bricks, coord=IVec3::new(x, y, z)).register();
-                    world.set_block((x, y, z), coord_dirt);
-                }
-            }
-        }
-        println!("Set Blocks");
+        let count = world.transaction("Fill", |world| world.edit_blocks(edit_area, |coord, _| {
+            Some(blockstate!(stone_bricks, coord=IVec3::new(coord.x, coord.y, coord.z)).register())
+        }));
+        println!("Set {count} Blocks");
     }
     // U for Ungage
     if keys.just_pressed(KeyCode::KeyU) {
-        for y in Y_BOUND {
-            for z in Z_BOUND {
-                for x in X_BOUND {
-                    world.set_block((x, y, z), Id::AIR);
-                }
+        world.transaction("Clear", |world| world.fill(edit_area, Id::AIR));
+    }
+    // Ctrl+Z to undo, Ctrl+Y to redo.
+    if keys.pressed(KeyCode::ControlLeft) {
+        if keys.just_pressed(KeyCode::KeyZ) {
+            if let Some(name) = world.undo() {
+                println!("Undo {name}");
+            }
+        }
+        if keys.just_pressed(KeyCode::KeyY) {
+            if let Some(name) = world.redo() {
+                println!("Redo {name}");
             }
         }
     }
@@ -548,6 +557,13 @@ fn update_input(
         //     }
         // }
     }
+    // F to throw a box.
+    if keys.just_pressed(KeyCode::KeyF) {
+        let forward: Vec3 = transform.forward().into();
+        let entity = VoxelEntity::new("box", transform.translation + forward * 2.0, Vec3::splat(0.5))
+            .with_velocity(forward * 10.0);
+        world.spawn_entity(entity);
+    }
     // if keys.just_pressed(KeyCode::KeyR) {
     //     let ray = Ray3d::new(Vec3::ZERO, Vec3::NEG_Z);
     //     if let Some((coord, id)) = world.world.raycast(ray, 100.0) {
@@ -555,19 +571,22 @@ fn update_input(
     //     }
     // }
     if let Some(RaycastResult { hit_point, coord, direction, id }) = world.raycast(Ray3d::new(transform.translation, transform.forward().into()), 500.0) {
+        id.block().selection_boxes(&world, coord, id).into_iter().for_each(|aabb| {
+            gizmos.cuboid(Transform::from_translation(aabb.center()).with_scale(aabb.size()), Color::WHITE);
+        });
         gizmos.arrow(hit_point, hit_point + Vec3::X * 0.25, Color::RED);
         gizmos.arrow(hit_point, hit_point + Vec3::Y * 0.25, Color::GREEN);
         gizmos.arrow(hit_point, hit_point + Vec3::Z * 0.25, Color::BLUE);
         if mouse_buttons.just_pressed(MouseButton::Left) {
             if let Some(direction) = direction {
                 let next = coord + direction;
-                world.set_block(next, selection.0);
+                world.transaction("Place", |world| world.set_block(next, selection.0));
             } else {
                 println!("No direction");
             }
         }
         if mouse_buttons.just_pressed(MouseButton::Right) {
-            world.set_block(coord, Id::AIR);
+            world.transaction("Break", |world| world.set_block(coord, Id::AIR));
         }
         if keys.just_pressed(KeyCode::Backspace) {
             let occlusion = world.get_occlusion(coord);
@@ -587,7 +606,7 @@ fn update_input(
             let state = world.get_block(coord);
             let orientation = state.block().orientation(&world, coord, state);
             let new_state = state.block().reorient(&world, coord, state, orientation.rotate_y(1));
-            world.set_block(coord, new_state);
+            world.transaction("Rotate", |world| world.set_block(coord, new_state));
         }
     }
     campos.position = transform.translation;
@@ -614,17 +633,67 @@ fn update_bevy(
     mut render_chunks: Query<&mut Transform, With<RenderChunkMarker>>,
     mut world: ResMut<VoxelWorld>,
     keys: Res<ButtonInput<KeyCode>>,
+    campos: Res<CameraLocation>,
 ) {
     // I for Ingage (lol, yes I know it's spelled wrong)
     
     // let now = Instant::now();
     // let state = world.world.get_block((0,0,0));
     // world.world.set_block((0, 0, 0), if state.is_air() { blockstate!(dirt).register() } else { Id::AIR });
+    world.sort_transparent_faces(campos.position, &mut meshes);
     world.talk_to_bevy(commands, meshes, materials, render_chunks);
     // let elapsed = now.elapsed();
     // println!("Frame time: {}", elapsed.as_secs_f64());
 }
 
+/// Shows a [VoxelEntity] as a cube.
+#[derive(Component)]
+struct EntityVisual(EntityId);
+
+/// Moves the world's entities and keeps a cube in sync with each of them.
+fn sync_entities(
+    mut commands: Commands,
+    mut world: ResMut<VoxelWorld>,
+    mut visuals: Query<(Entity, &EntityVisual, &mut Transform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut handles: Local<Option<(Handle<Mesh>, Handle<StandardMaterial>)>>,
+    time: Res<Time>,
+) {
+    world.update_entities(time.delta_seconds());
+    let mut shown = hashbrown::HashSet::new();
+    visuals.iter_mut().for_each(|(visual_entity, visual, mut transform)| {
+        let Some(entity) = world.get_entity(visual.0) else {
+            commands.entity(visual_entity).despawn();
+            return;
+        };
+        transform.translation = entity.position + Vec3::Y * entity.size.y * 0.5;
+        transform.scale = entity.size;
+        shown.insert(visual.0);
+    });
+    let (mesh, material) = handles.get_or_insert_with(|| (
+        meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
+        // The sandbox doesn't have any lights.
+        materials.add(StandardMaterial {
+            base_color: Color::ORANGE,
+            unlit: true,
+            ..default()
+        }),
+    )).clone();
+    world.entities.iter().filter(|(id, _)| !shown.contains(id)).for_each(|(id, entity)| {
+        commands.spawn((
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(entity.position + Vec3::Y * entity.size.y * 0.5)
+                    .with_scale(entity.size),
+                ..default()
+            },
+            EntityVisual(id),
+        ));
+    });
+}
+
 #[derive(Default, Resource)]
 struct CameraRotation {
     x: f32,
@@ -691,6 +760,7 @@ fn write_read_test() -> Result<()> {
 
 struct SolidBlock {
     mesh_data: Faces<MeshData>,
+    texindices: Faces<u32>,
     name: String,
     default_state: BlockState,
 }
@@ -728,6 +798,7 @@ impl SolidBlock {
                 0, 0,
                 0, 0,
             ],
+            ao: Vec::new(),
             indices: vec![
                 0, 2, 1,
                 1, 2, 3,
@@ -752,6 +823,7 @@ impl SolidBlock {
                 neg_y: neg_y_mesh,
                 neg_z: neg_z_mesh
             },
+            texindices,
             name: name.as_ref().to_owned(),
             default_state,
         }
@@ -767,7 +839,7 @@ impl Block for SolidBlock {
         self.default_state.clone()
     }
 
-    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, world: &VoxelWorld, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
+    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
         Direction::iter().for_each(|dir| {
             if occlusion.visible(dir) {
                 // get the source face because the mesh_builder will orient that face
@@ -777,6 +849,17 @@ impl Block for SolidBlock {
             }
         });
     }
+
+    fn greedy_faces(&self, state: Id) -> Option<Faces<u32>> {
+        Some(Faces::new(
+            self.texindices.neg_x,
+            self.texindices.neg_y,
+            self.texindices.neg_z,
+            self.texindices.pos_x,
+            self.texindices.pos_y,
+            self.texindices.pos_z,
+        ))
+    }
 }
 
 struct StoneBricksBlock;
@@ -790,7 +873,7 @@ impl Block for StoneBricksBlock {
         blockstate!(stone_bricks)
     }
 
-    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, world: &VoxelWorld, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
+    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
         static MESH_DATA: LazyLock<Faces<MeshData>> = LazyLock::new(|| {
             let sides_index = texreg::get_texture_index("stone_bricks");
             let y_index = texreg::get_texture_index("cement");
@@ -811,6 +894,7 @@ impl Block for StoneBricksBlock {
                     y_index, y_index,
                     y_index, y_index,
                 ],
+                ao: Vec::new(),
                 indices: vec![
                     0, 2, 1,
                     1, 2, 3,
@@ -879,7 +963,7 @@ impl Block for DirtBlock {
         &Occluder::FULL_FACES
     }
 
-    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, world: &VoxelWorld, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
+    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
         static MESH_DATA: LazyLock<Faces<MeshData>> = LazyLock::new(|| {
             let texindex = texreg::get_texture_index("dirt");
             let pos_y_mesh = MeshData {
@@ -899,6 +983,7 @@ impl Block for DirtBlock {
                     texindex, texindex,
                     texindex, texindex,
                 ],
+                ao: Vec::new(),
                 indices: vec![
                     0, 2, 1,
                     1, 2, 3,
@@ -1013,7 +1098,7 @@ impl Block for DebugBlock {
         BlockLayer::Other(0)
     }
 
-    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, world: &VoxelWorld, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
+    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
         static MESH_DATA: LazyLock<Faces<MeshData>> = LazyLock::new(|| {
             let pos_x_index = texreg::get_texture_index("pos_x");
             let pos_y_index = texreg::get_texture_index("pos_y");
@@ -1038,6 +1123,7 @@ impl Block for DebugBlock {
                     pos_y_index, pos_y_index,
                     pos_y_index, pos_y_index,
                 ],
+                ao: Vec::new(),
                 indices: vec![
                     0, 2, 1,
                     1, 2, 3,
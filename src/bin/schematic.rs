@@ -0,0 +1,202 @@
+// Command line tool for exporting and importing schematics.
+use std::process::ExitCode;
+
+use unvoga::core::voxel::{
+    blockstate::BlockState,
+    world::{clipboard::{PasteMask, PasteOptions}, schematic::Schematic, VoxelWorld, PADDED_WORLD_SIZE_MAX, WORLD_SIZE_PAD},
+};
+use unvoga::prelude::*;
+
+const USAGE: &str = "\
+Usage:
+    schematic info <file>
+    schematic export <world> <x1> <y1> <z1> <x2> <y2> <z2> <file> [--author <name>] [--origin <x> <y> <z>] [--block <name>]...
+    schematic import <world> <file> <x> <y> <z> [--rotate <quarter turns>] [--flip <x|y|z|xy|...>] [--skip-air] [--only-air] [--block <name>]...
+
+Blocks that are in the world must be registered with --block <name> so that the world can be loaded.";
+
+/// Stands in for blocks that are only known by name.
+struct NamedBlock(String);
+
+impl Block for NamedBlock {
+    fn name(&self) -> &str {
+        &self.0
+    }
+
+    fn default_state(&self) -> BlockState {
+        BlockState::new(self.0.as_str(), [])
+    }
+}
+
+fn register_named<S: AsRef<str>>(name: S) {
+    let name = name.as_ref();
+    if blocks::find_block(name).is_none() {
+        blocks::register_block(NamedBlock(name.to_owned()));
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("info") => info(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("import") => import(&args[1..]),
+        _ => Err(String::from("Expected a command.")),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Splits `--flag` options from positional arguments.
+struct Args<'a> {
+    positional: Vec<&'a str>,
+    flags: Vec<(&'a str, Vec<&'a str>)>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(args: &'a [String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut iter = args.iter().map(String::as_str);
+        while let Some(arg) = iter.next() {
+            let value_count = match arg {
+                "--author" | "--block" | "--rotate" | "--flip" => 1,
+                "--origin" => 3,
+                "--skip-air" | "--only-air" => 0,
+                flag if flag.starts_with("--") => return Err(format!("Unknown option: {flag}")),
+                _ => {
+                    positional.push(arg);
+                    continue;
+                }
+            };
+            let values: Vec<&str> = iter.by_ref().take(value_count).collect();
+            if values.len() != value_count {
+                return Err(format!("Expected {value_count} value(s) for {arg}"));
+            }
+            flags.push((arg, values));
+        }
+        Ok(Self { positional, flags })
+    }
+
+    fn flag(&self, name: &str) -> Option<&[&'a str]> {
+        self.flags.iter().rev().find(|(flag, _)| *flag == name).map(|(_, values)| values.as_slice())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.flag(name).is_some()
+    }
+
+    fn all(&self, name: &'a str) -> impl Iterator<Item = &'a str> + '_ {
+        self.flags.iter().filter(move |(flag, _)| *flag == name).flat_map(|(_, values)| values.iter().copied())
+    }
+
+    fn expect_positional(&self, count: usize) -> Result<(), String> {
+        if self.positional.len() != count {
+            return Err(format!("Expected {count} argument(s), but got {}.", self.positional.len()));
+        }
+        Ok(())
+    }
+}
+
+fn parse_i32(value: &str) -> Result<i32, String> {
+    value.parse().map_err(|_| format!("Not a number: {value}"))
+}
+
+fn parse_coord(values: &[&str]) -> Result<Coord, String> {
+    Ok(Coord::new(parse_i32(values[0])?, parse_i32(values[1])?, parse_i32(values[2])?))
+}
+
+fn parse_flip(value: &str) -> Result<Flip, String> {
+    value.chars().try_fold(Flip::NONE, |flip, axis| match axis {
+        'x' | 'X' => Ok(flip | Flip::X),
+        'y' | 'Y' => Ok(flip | Flip::Y),
+        'z' | 'Z' => Ok(flip | Flip::Z),
+        _ => Err(format!("Invalid flip: {value}")),
+    })
+}
+
+/// Opens a world with every chunk between `min` and `max` loaded.
+fn open_world(directory: &str, min: Coord, max: Coord) -> Result<VoxelWorld, String> {
+    let center = Coord::new((min.x + max.x) / 2, (min.y + max.y) / 2, (min.z + max.z) / 2);
+    let extent = (max.x - min.x).max(max.z - min.z);
+    let render_distance = (extent / 32 + 2) as usize;
+    if render_distance + WORLD_SIZE_PAD > PADDED_WORLD_SIZE_MAX {
+        return Err(format!("Area is too large ({extent} blocks across)."));
+    }
+    let mut world = VoxelWorld::open_headless(directory, render_distance as u8, center, None).map_err(|err| err.to_string())?;
+    world.flush_load_queue();
+    Ok(world)
+}
+
+fn info(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    args.expect_positional(1)?;
+    let schematic = Schematic::load(args.positional[0]).map_err(|err| err.to_string())?;
+    let size = schematic.size();
+    let origin = schematic.origin;
+    println!("   Size: {} {} {}", size.x, size.y, size.z);
+    println!(" Author: {}", schematic.author);
+    println!(" Origin: {} {} {}", origin.x, origin.y, origin.z);
+    println!("Created: {}", schematic.created.time());
+    println!("Palette:");
+    schematic.palette().iter().for_each(|state| println!("    {state}"));
+    Ok(())
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    args.expect_positional(8)?;
+    args.all("--block").for_each(register_named);
+    let world_dir = args.positional[0];
+    let a = parse_coord(&args.positional[1..4])?;
+    let b = parse_coord(&args.positional[4..7])?;
+    let path = args.positional[7];
+    let min = Coord::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Coord::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+    let world = open_world(world_dir, min, max)?;
+    let mut schematic = Schematic::from_clipboard(&world.copy(min, max));
+    if let Some(author) = args.flag("--author") {
+        schematic = schematic.with_author(author[0]);
+    }
+    if let Some(origin) = args.flag("--origin") {
+        schematic = schematic.with_origin(parse_coord(origin)?);
+    }
+    schematic.save(path).map_err(|err| err.to_string())?;
+    let size = schematic.size();
+    println!("Exported {}x{}x{} blocks to {path}", size.x, size.y, size.z);
+    Ok(())
+}
+
+fn import(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    args.expect_positional(5)?;
+    args.all("--block").for_each(register_named);
+    let world_dir = args.positional[0];
+    let path = args.positional[1];
+    let position = parse_coord(&args.positional[2..5])?;
+    let schematic = Schematic::load(path).map_err(|err| err.to_string())?;
+    schematic.palette().iter().for_each(|state| register_named(state.name()));
+    let rotation = args.flag("--rotate").map(|turns| parse_i32(turns[0])).transpose()?.unwrap_or(0);
+    let flip = args.flag("--flip").map(|axes| parse_flip(axes[0])).transpose()?.unwrap_or(Flip::NONE);
+    let mask = match (args.has("--skip-air"), args.has("--only-air")) {
+        (false, false) => PasteMask::All,
+        (true, false) => PasteMask::SkipAir,
+        (false, true) => PasteMask::OnlyReplaceAir,
+        (true, true) => return Err(String::from("--skip-air and --only-air can't be used together.")),
+    };
+    let orientation = Orientation::new(Rotation::new(Direction::PosY, rotation), flip);
+    let options = PasteOptions::new().with_orientation(orientation).with_mask(mask);
+    // The pasted area is somewhere within the schematic's largest extent around the paste position.
+    let size = schematic.size();
+    let reach = size.x.max(size.y).max(size.z);
+    let mut world = open_world(world_dir, position - Coord::splat(reach), position + Coord::splat(reach))?;
+    let count = world.paste_schematic(&schematic, position, options);
+    world.save_world().map_err(|err| err.to_string())?;
+    println!("Imported {count} blocks from {path}");
+    Ok(())
+}
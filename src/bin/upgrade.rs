@@ -0,0 +1,133 @@
+// Command line tool for upgrading the chunks of a world to the current data version.
+use std::process::ExitCode;
+
+use serde::Deserialize;
+use unvoga::core::voxel::statevalue::StateValue;
+use unvoga::core::voxel::world::migration::{self, MapProperty, MigrationRegistry, RenameBlock, RenameProperty};
+
+const USAGE: &str = "\
+Usage:
+    upgrade <world> [--dry-run] [--migrations <file>]
+
+The migrations file is a JSON array of migrations, in order of their versions:
+    [
+        { \"version\": 1, \"type\": \"rename_block\", \"from\": \"old_name\", \"to\": \"new_name\" },
+        { \"version\": 2, \"type\": \"rename_property\", \"block\": \"new_name\", \"from\": \"axis\", \"to\": \"facing\" },
+        { \"version\": 3, \"type\": \"map_property\", \"block\": \"new_name\", \"property\": \"facing\", \"values\": [[0, \"x\"], [1, \"y\"]] }
+    ]
+
+A map_property migration changes each value of a property that is listed in its values to the value that follows it.
+Values can be null, integers, booleans, or strings. Values that aren't listed are kept.
+
+The world must not be open while it's upgraded. With --dry-run, the report is printed but nothing is written.";
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MigrationSpec {
+    RenameBlock {
+        version: u16,
+        from: String,
+        to: String,
+    },
+    RenameProperty {
+        version: u16,
+        block: String,
+        from: String,
+        to: String,
+    },
+    MapProperty {
+        version: u16,
+        block: String,
+        property: String,
+        values: Vec<(serde_json::Value, serde_json::Value)>,
+    },
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match upgrade(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn upgrade(args: &[String]) -> Result<(), String> {
+    let mut world = None;
+    let mut dry_run = false;
+    let mut migrations = None;
+    let mut iter = args.iter().map(String::as_str);
+    while let Some(arg) = iter.next() {
+        match arg {
+            "--dry-run" => dry_run = true,
+            "--migrations" => {
+                migrations = Some(iter.next().ok_or_else(|| String::from("Expected a file for --migrations"))?);
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {flag}")),
+            _ if world.is_some() => return Err(format!("Unexpected argument: {arg}")),
+            _ => world = Some(arg),
+        }
+    }
+    let world = world.ok_or_else(|| String::from("Expected a world."))?;
+    let migrations = match migrations {
+        Some(path) => read_migrations(path)?,
+        None => migration::registered_migrations(),
+    };
+    let report = migration::upgrade_world_with(world, &migrations, dry_run).map_err(|err| err.to_string())?;
+    println!("{report}");
+    Ok(())
+}
+
+fn read_migrations(path: &str) -> Result<MigrationRegistry, String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("Failed to open {path}: {err}"))?;
+    let specs: Vec<MigrationSpec> = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|err| format!("Invalid migrations file: {err}"))?;
+    let mut migrations = MigrationRegistry::new();
+    let mut latest = 0;
+    for spec in specs {
+        let version = match &spec {
+            MigrationSpec::RenameBlock { version, .. }
+            | MigrationSpec::RenameProperty { version, .. }
+            | MigrationSpec::MapProperty { version, .. } => *version,
+        };
+        // MigrationRegistry::register panics on out of order versions, so they're checked here first.
+        if version <= latest {
+            return Err(format!("Migration version {version} must be greater than {latest}."));
+        }
+        latest = version;
+        match spec {
+            MigrationSpec::RenameBlock { version, from, to } => {
+                migrations.register(version, RenameBlock::new(from, to));
+            }
+            MigrationSpec::RenameProperty { version, block, from, to } => {
+                migrations.register(version, RenameProperty::new(block, from, to));
+            }
+            MigrationSpec::MapProperty { version, block, property, values } => {
+                let values = values.iter().map(|(from, to)| {
+                    Ok((state_value(from)?, state_value(to)?))
+                }).collect::<Result<Vec<_>, String>>()?;
+                migrations.register(version, MapProperty::new(block, property, move |value: &StateValue| {
+                    values.iter()
+                        .find(|(from, _)| from == value)
+                        .map(|(_, to)| to.clone())
+                        .unwrap_or_else(|| value.clone())
+                }));
+            }
+        }
+    }
+    Ok(migrations)
+}
+
+fn state_value(value: &serde_json::Value) -> Result<StateValue, String> {
+    match value {
+        serde_json::Value::Null => Ok(StateValue::Null),
+        serde_json::Value::Bool(value) => Ok(StateValue::Bool(*value)),
+        serde_json::Value::String(value) => Ok(StateValue::String(value.clone())),
+        serde_json::Value::Number(number) => number.as_i64()
+            .map(StateValue::Int)
+            .ok_or_else(|| format!("Property values must be integers: {number}")),
+        value => Err(format!("Unsupported property value: {value}")),
+    }
+}
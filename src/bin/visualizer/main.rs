@@ -110,6 +110,7 @@ fn setup(
         lightmap_pad_neg_y: vec![],
         lightmap_pad_pos_z: vec![],
         lightmap_pad_neg_z: vec![],
+        alpha_mode: AlphaMode::Opaque,
     });
     // Now to build the mesh. I'm going to assume the orientation code works because why not?
     let pos_y_mesh = MeshData {
@@ -129,6 +130,7 @@ fn setup(
             0, 0,
             0, 0,
         ],
+        ao: Vec::new(),
         indices: vec![
             0, 2, 1,
             1, 2, 3,
@@ -776,6 +778,7 @@ fn on_enter_main_menu(
         lightmap_pad_neg_y: vec![],
         lightmap_pad_pos_z: vec![],
         lightmap_pad_neg_z: vec![],
+        alpha_mode: AlphaMode::Opaque,
     });
     // vox_data.as_mut().vox_mat = Some(vox_mat.clone());
     let mesh_holder = MeshHolder {
@@ -0,0 +1,161 @@
+#![allow(unused)]
+use std::{marker::PhantomData, mem::MaybeUninit, ptr, sync::{atomic::{AtomicPtr, AtomicUsize, Ordering}, Mutex}};
+
+/// The capacity of the first segment. Each segment after it is twice as large as the one before.
+const FIRST_SEGMENT_SIZE: usize = 64;
+const SEGMENT_COUNT: usize = usize::BITS as usize - FIRST_SEGMENT_SIZE.trailing_zeros() as usize;
+
+/// A list that can only be appended to. Elements are stored in segments that are never moved or freed,
+/// so references to elements stay valid for as long as the list does, even while other threads push.
+///
+/// Reading is lock-free. Pushing takes a lock, so pushes from different threads are serialized.
+pub struct AppendList<T> {
+    segments: [AtomicPtr<MaybeUninit<T>>; SEGMENT_COUNT],
+    len: AtomicUsize,
+    push_lock: Mutex<()>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for AppendList<T> {}
+unsafe impl<T: Send + Sync> Sync for AppendList<T> {}
+
+impl<T> AppendList<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            segments: std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            len: AtomicUsize::new(0),
+            push_lock: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the segment and the offset into that segment of an index.
+    #[inline]
+    fn locate(index: usize) -> (usize, usize) {
+        let scaled = index / FIRST_SEGMENT_SIZE + 1;
+        let segment = (usize::BITS - 1 - scaled.leading_zeros()) as usize;
+        let segment_start = FIRST_SEGMENT_SIZE * ((1 << segment) - 1);
+        (segment, index - segment_start)
+    }
+
+    #[inline]
+    fn segment_size(segment: usize) -> usize {
+        FIRST_SEGMENT_SIZE << segment
+    }
+
+    /// Appends an element and returns its index.
+    pub fn push(&self, value: T) -> usize {
+        let _guard = self.push_lock.lock().expect("Failed to lock AppendList");
+        let index = self.len.load(Ordering::Acquire);
+        let (segment, offset) = Self::locate(index);
+        let mut segment_ptr = self.segments[segment].load(Ordering::Acquire);
+        if segment_ptr.is_null() {
+            let storage: Box<[MaybeUninit<T>]> = (0..Self::segment_size(segment)).map(|_| MaybeUninit::uninit()).collect();
+            segment_ptr = Box::into_raw(storage) as *mut MaybeUninit<T>;
+            self.segments[segment].store(segment_ptr, Ordering::Release);
+        }
+        unsafe {
+            (*segment_ptr.add(offset)).write(value);
+        }
+        // The element is written before the length is published, so readers never see an uninitialized element.
+        self.len.store(index + 1, Ordering::Release);
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let (segment, offset) = Self::locate(index);
+        unsafe {
+            let segment_ptr = self.segments[segment].load(Ordering::Acquire);
+            Some((*segment_ptr.add(offset)).assume_init_ref())
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let len = self.len();
+        (0..len).map(move |index| self.get(index).expect("Index was out of bounds"))
+    }
+}
+
+impl<T> std::ops::Index<usize> for AppendList<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        let Some(value) = self.get(index) else {
+            panic!("Index out of bounds: {index} (length is {})", self.len());
+        };
+        value
+    }
+}
+
+impl<T> Drop for AppendList<T> {
+    fn drop(&mut self) {
+        let len = *self.len.get_mut();
+        let mut remaining = len;
+        for segment in 0..SEGMENT_COUNT {
+            let segment_ptr = *self.segments[segment].get_mut();
+            if segment_ptr.is_null() {
+                break;
+            }
+            let size = Self::segment_size(segment);
+            let initialized = remaining.min(size);
+            remaining -= initialized;
+            unsafe {
+                let mut storage = Box::from_raw(ptr::slice_from_raw_parts_mut(segment_ptr, size));
+                storage[..initialized].iter_mut().for_each(|value| value.assume_init_drop());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_list_test() {
+        let list = AppendList::new();
+        let first: &String = {
+            list.push(String::from("first"));
+            &list[0]
+        };
+        for i in 1..10000 {
+            assert_eq!(list.push(i.to_string()), i);
+        }
+        // References from before the list grew are still valid.
+        assert_eq!(first, "first");
+        assert_eq!(list.len(), 10000);
+        assert_eq!(list.get(9999).map(String::as_str), Some("9999"));
+        assert_eq!(list.get(10000), None);
+        assert!(list.iter().skip(1).enumerate().all(|(i, value)| *value == (i + 1).to_string()));
+    }
+
+    #[test]
+    fn append_list_threads_test() {
+        let list = std::sync::Arc::new(AppendList::new());
+        let threads = (0..4).map(|thread| {
+            let list = list.clone();
+            std::thread::spawn(move || {
+                (0..1000).for_each(|i| {
+                    let index = list.push(thread * 1000 + i);
+                    assert_eq!(list[index], thread * 1000 + i);
+                });
+            })
+        }).collect::<Vec<_>>();
+        threads.into_iter().for_each(|thread| thread.join().expect("Thread panicked"));
+        let mut values = list.iter().copied().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, (0..4000).collect::<Vec<_>>());
+    }
+}
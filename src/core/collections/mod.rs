@@ -1,3 +1,4 @@
 #![allow(unused)]
+pub mod appendlist;
 pub mod grid;
 pub mod objectpool;
\ No newline at end of file
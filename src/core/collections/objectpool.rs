@@ -78,6 +78,19 @@ impl<T,M: Copy> ObjectPool<T,M> {
         self.unused.push(id);
     }
 
+    /// Removes a value and returns it. Unlike [ObjectPool::remove], this returns `None` for a stale id instead of panicking.
+    pub fn take(&mut self, id: PoolId<M>) -> Option<T> {
+        self.get(id)?;
+        let pool_index = self.indices[id.index()];
+        let (_, value) = self.pool.swap_remove(pool_index);
+        if pool_index < self.pool.len() {
+            let moved = self.pool[pool_index].0;
+            self.indices[moved.index()] = pool_index;
+        }
+        self.unused.push(id);
+        Some(value)
+    }
+
     /// Removes the old id and then inserts the new value and replaces the id with the new id.
     pub fn swap_insert(&mut self, id: &mut PoolId<M>, insert: T) {
         let old = id.swap_null();
@@ -115,11 +128,12 @@ impl<T,M: Copy> ObjectPool<T,M> {
 
     #[must_use]
     pub fn get(&self, id: PoolId<M>) -> Option<&T> {
-        if id.null() || id.pool_id() != self.id {
+        if id.null() || id.pool_id() != self.id || id.index() >= self.indices.len() {
             return None;
         }
         let pool_index = self.indices[id.index()];
-        if self.pool[pool_index].0.0 != id.0 {
+        // The id is stale if its element was removed from the end of the pool.
+        if pool_index >= self.pool.len() || self.pool[pool_index].0.0 != id.0 {
             return None;
         }
         Some(&self.pool[pool_index].1)
@@ -127,11 +141,12 @@ impl<T,M: Copy> ObjectPool<T,M> {
 
     #[must_use]
     pub fn get_mut(&mut self, id: PoolId<M>) -> Option<&mut T> {
-        if id.null() || id.pool_id() != self.id {
+        if id.null() || id.pool_id() != self.id || id.index() >= self.indices.len() {
             return None;
         }
         let pool_index = self.indices[id.index()];
-        if self.pool[pool_index].0.0 != id.0 {
+        // The id is stale if its element was removed from the end of the pool.
+        if pool_index >= self.pool.len() || self.pool[pool_index].0.0 != id.0 {
             return None;
         }
         Some(&mut self.pool[pool_index].1)
@@ -35,6 +35,18 @@ pub enum Error {
     GltfError(#[from] gltf::Error),
     #[error("Mesh Extraction Error")]
     MeshExtractionError,
+    #[error("File was not a schematic")]
+    NotASchematic,
+    #[error("Unsupported schematic version ({0})")]
+    UnsupportedSchematicVersion(u16),
+    #[error("Schematic is too large ({0}x{1}x{2})")]
+    SchematicTooLarge(i32, i32, i32),
+    #[error("Unsupported world version ({0})")]
+    UnsupportedWorldVersion(u16),
+    #[error("Invalid subworld name: {0:?}")]
+    InvalidSubworldName(String),
+    #[error("Subworld is not open: {0}")]
+    SubworldNotOpen(String),
     #[error("Custom Error: {0}")]
     Custom(&'static str),
 }
@@ -71,6 +71,16 @@ pub trait Writeable {
     fn write_to<W: Write>(&self, writer: &mut W) -> Result<u64>;
 }
 
+/// Reads a value that was appended to a format after data had already been written without it.
+/// Reaching the end of the data before the value is the same as the value not being there.
+pub fn read_optional<T: Readable, R: Read>(reader: &mut R) -> Result<Option<T>> {
+    match T::read_from(reader) {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 // pub trait ReaderExt: Read + Sized {
 //     fn read_value<T: Readable>(&mut self) -> Result<T>;
 // }
@@ -11,6 +11,10 @@ pub struct AABB {
 }
 
 impl AABB {
+    /// A full block centered on the origin. Block shapes are made of boxes in this space so that
+    /// they can be oriented with [AABB::orient_voxel].
+    pub const FULL_BLOCK: AABB = AABB::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+
     pub const fn new(min: Vec3, max: Vec3) -> Self {
         Self {
             min,
@@ -25,6 +29,74 @@ impl AABB {
         }
     }
 
+    #[inline]
+    pub const fn min(self) -> Vec3 {
+        self.min
+    }
+
+    #[inline]
+    pub const fn max(self) -> Vec3 {
+        self.max
+    }
+
+    pub fn size(self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn center(self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn translate(self, offset: Vec3) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+
+    /// Stretches the box in the direction of `delta`. This is the area that a box covers while it moves by `delta`.
+    pub fn expand_towards(self, delta: Vec3) -> Self {
+        Self::new(self.min + delta.min(Vec3::ZERO), self.max + delta.max(Vec3::ZERO))
+    }
+
+    /// Clips movement of `other` along the x axis so that it stops at the face of this box.
+    /// Returns `delta` unchanged if `other` can't hit this box by moving along the x axis.
+    pub fn clip_x(self, other: AABB, delta: f32) -> f32 {
+        if other.max.y <= self.min.y || other.min.y >= self.max.y
+        || other.max.z <= self.min.z || other.min.z >= self.max.z {
+            return delta;
+        }
+        Self::clip_axis(self.min.x, self.max.x, other.min.x, other.max.x, delta)
+    }
+
+    /// Clips movement of `other` along the y axis so that it stops at the face of this box.
+    /// Returns `delta` unchanged if `other` can't hit this box by moving along the y axis.
+    pub fn clip_y(self, other: AABB, delta: f32) -> f32 {
+        if other.max.x <= self.min.x || other.min.x >= self.max.x
+        || other.max.z <= self.min.z || other.min.z >= self.max.z {
+            return delta;
+        }
+        Self::clip_axis(self.min.y, self.max.y, other.min.y, other.max.y, delta)
+    }
+
+    /// Clips movement of `other` along the z axis so that it stops at the face of this box.
+    /// Returns `delta` unchanged if `other` can't hit this box by moving along the z axis.
+    pub fn clip_z(self, other: AABB, delta: f32) -> f32 {
+        if other.max.x <= self.min.x || other.min.x >= self.max.x
+        || other.max.y <= self.min.y || other.min.y >= self.max.y {
+            return delta;
+        }
+        Self::clip_axis(self.min.z, self.max.z, other.min.z, other.max.z, delta)
+    }
+
+    #[inline]
+    fn clip_axis(min: f32, max: f32, other_min: f32, other_max: f32, delta: f32) -> f32 {
+        if delta > 0.0 && other_max <= min {
+            delta.min(min - other_max)
+        } else if delta < 0.0 && other_min >= max {
+            delta.max(max - other_min)
+        } else {
+            delta
+        }
+    }
+
     #[inline]
     pub fn intersects(self, ray: Ray3d) -> Option<f32> {
         self.intersects_frac(ray, Self::calc_dirfrac(ray))
@@ -49,6 +121,25 @@ impl AABB {
         }
     }
 
+    /// Like [AABB::intersects], but also returns the face of the box that the ray enters through.
+    pub fn intersects_face(self, ray: Ray3d) -> Option<(f32, Direction)> {
+        let dirfrac = Self::calc_dirfrac(ray);
+        let dist = self.intersects_frac(ray, dirfrac)?;
+        let near_x = ((self.min.x - ray.origin.x) * dirfrac.x).min((self.max.x - ray.origin.x) * dirfrac.x);
+        let near_y = ((self.min.y - ray.origin.y) * dirfrac.y).min((self.max.y - ray.origin.y) * dirfrac.y);
+        // The entry distance is the distance to the farthest of the near planes, so the face is on that plane's axis.
+        let face = if dist == near_x {
+            if ray.direction.x > 0.0 { Direction::NegX } else { Direction::PosX }
+        } else if dist == near_y {
+            if ray.direction.y > 0.0 { Direction::NegY } else { Direction::PosY }
+        } else if ray.direction.z > 0.0 {
+            Direction::NegZ
+        } else {
+            Direction::PosZ
+        };
+        Some((dist, face))
+    }
+
     pub fn intersection_point(self, ray: Ray3d) -> Option<Vec3> {
         let dist = self.intersects(ray)?;
         Some(ray.origin + (ray.direction * dist))
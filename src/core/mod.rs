@@ -12,7 +12,6 @@ TODO:
 Mesh builder
 Light engine
 Block updates
-Maybe a water engine
 
 */
 
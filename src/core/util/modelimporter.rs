@@ -107,6 +107,7 @@ impl ModelMesh {
             uvs: self.uvs,
             indices: self.indices,
             texindices,
+            ao: Vec::new(),
         }
     }
 }
@@ -4,7 +4,7 @@ use bevy::math::Ray3d;
 
 use crate::{core::math::aabb::AABB, prelude::*};
 
-use super::{blocklayer::BlockLayer, blocks::Id, blockstate::BlockState, coord::Coord, direction::Direction, engine::VoxelEngine, faces::Faces, level_of_detail::LOD, lighting::lightargs::LightArgs, occluder::Occluder, occlusionshape::OcclusionShape, rendering::meshbuilder::MeshBuilder, tag::Tag, world::{occlusion::Occlusion, PlaceContext, VoxelWorld}};
+use super::{blocklayer::BlockLayer, blocks::Id, blockstate::BlockState, coord::Coord, direction::Direction, engine::VoxelEngine, entity::EntityId, faces::Faces, level_of_detail::LOD, lighting::lightargs::LightArgs, occluder::Occluder, occlusionshape::OcclusionShape, rendering::{meshbuilder::MeshBuilder, sectionmesh::SectionSnapshot}, tag::Tag, world::{occlusion::Occlusion, PlaceContext, VoxelWorld}};
 
 use crate::prelude::Rgb;
 
@@ -16,7 +16,8 @@ mod sealed {
     impl<B: Block> Sealed for B {}
 }
 
-pub trait Block: Any {
+/// Blocks must be [Send] and [Sync] because sections are meshed on worker threads.
+pub trait Block: Any + Send + Sync {
     fn name(&self) -> &str;
     fn on_register(&mut self) {}
     /// The occluder that occludes
@@ -46,26 +47,75 @@ pub trait Block: Any {
         state
     }
     fn enable_on_place(&self, world: &VoxelWorld, coord: Coord, state: Id) -> bool { false }
+    /// Blocks that override this should also override [Block::state_light_args].
     fn light_args(&self, world: &VoxelWorld, coord: Coord, state: Id) -> LightArgs {
-        LightArgs::new(15, 0)
+        self.state_light_args(state).unwrap_or(LightArgs::new(15, 0))
+    }
+    /// The [LightArgs] of a state that don't depend on the world, which lets chunks be lit off of the main thread.
+    /// Return `None` if [Block::light_args] depends on the world, and chunks with this block will be lit on the main thread.
+    fn state_light_args(&self, state: Id) -> Option<LightArgs> {
+        Some(LightArgs::new(15, 0))
     }
     fn neighbor_updated(&self, world: &mut VoxelWorld, direction: Direction, coord: Coord, neighbor_coord: Coord, state: Id, neighbor_state: Id) {}
     fn light_updated(&self, world: &mut VoxelWorld, coord: Coord, old_level: u8, new_level: u8) {}
     fn call(&self, world: &mut VoxelWorld, coord: Coord, state: Id, function: &str, arg: Tag) -> Tag { Tag::Null }
-    fn on_entity_collide(&self, world: &mut VoxelWorld, coord: Coord, state: Id, face: Direction, entity: ()) {}
+    /// Called after an entity moves into this block. `face` is the face of this block that the entity hit.
+    fn on_entity_collide(&self, world: &mut VoxelWorld, coord: Coord, state: Id, face: Direction, entity: EntityId) {}
     fn on_interact(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {}
     fn on_update(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {}
+    /// Called when an update that was scheduled with [VoxelWorld::schedule_update] is due.
+    fn on_scheduled_update(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {}
+    /// Return true to receive [Block::on_random_tick].
+    fn ticks_randomly(&self) -> bool { false }
+    /// Return true if fluids can flow into this block, replacing it. Air is always replaceable.
+    /// By default, blocks without a [Block::collision_shape] are replaceable.
+    fn fluid_replaceable(&self, world: &VoxelWorld, coord: Coord, state: Id) -> bool {
+        self.collision_shape(world, coord, state).is_empty()
+    }
+    /// Called when this block is picked for a random tick. Only called if [Block::ticks_randomly] returns true.
+    fn on_random_tick(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {}
     fn on_place(&self, world: &mut VoxelWorld, context: &mut PlaceContext) { }
     fn on_remove(&self, world: &mut VoxelWorld, coord: Coord, old: Id, new: Id) {}
     fn on_data_set(&self, world: &mut VoxelWorld, coord: Coord, state: Id, data: &mut Tag) {}
     fn on_data_delete(&self, world: &mut VoxelWorld, coord: Coord, state: Id, data: Tag) {}
     fn on_enabled_changed(&self, world: &mut VoxelWorld, coord: Coord, state: Id, enabled: bool) {}
-    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, world: &VoxelWorld, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {}
+    fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {}
+    /// Return the texture index of each face to opt into greedy meshing.
+    /// Greedy meshing is only used when the block's occluder is [Occluder::FULL_FACES] and the block
+    /// has the default [Orientation]. Otherwise, [Block::push_mesh] is used.
+    fn greedy_faces(&self, state: Id) -> Option<Faces<u32>> { None }
     // fn rotate(&self, coord: Coord, state: Id, rotation: Rotation) -> Id { state }
     fn default_state(&self) -> BlockState;
-    fn raycast(&self, ray: Ray3d, world: &VoxelWorld, coord: Coord, state: Id, orientation: Orientation) -> Option<f32> {
-        let aabb = AABB::voxel(coord);
-        aabb.intersects(ray)
+    /// The boxes that rays hit and that are outlined when the block is selected.
+    /// Shapes are made of boxes in the space of [AABB::FULL_BLOCK] and are oriented with the block's [Orientation].
+    fn selection_shape(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+        vec![AABB::FULL_BLOCK]
+    }
+    /// The boxes that entities collide with. This is the same as the selection shape unless it's overridden.
+    /// Return an empty list for blocks that entities can move through.
+    fn collision_shape(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+        self.selection_shape(world, coord, state)
+    }
+    /// The [Block::selection_shape] in world space.
+    fn selection_boxes(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+        let orientation = self.orientation(world, coord, state);
+        self.selection_shape(world, coord, state).into_iter()
+            .map(|aabb| aabb.orient_voxel(coord, orientation))
+            .collect()
+    }
+    /// The [Block::collision_shape] in world space.
+    fn collision_boxes(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+        let orientation = self.orientation(world, coord, state);
+        self.collision_shape(world, coord, state).into_iter()
+            .map(|aabb| aabb.orient_voxel(coord, orientation))
+            .collect()
+    }
+    /// Returns the distance to the nearest box of the [Block::selection_shape] that the ray hits,
+    /// and the face of that box that the ray enters through.
+    fn raycast(&self, ray: Ray3d, world: &VoxelWorld, coord: Coord, state: Id, orientation: Orientation) -> Option<(f32, Direction)> {
+        self.selection_shape(world, coord, state).into_iter()
+            .filter_map(|aabb| aabb.orient_voxel(coord, orientation).intersects_face(ray))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
     }
 }
 
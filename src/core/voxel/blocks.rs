@@ -1,158 +1,211 @@
 #![allow(unused)]
-use std::{borrow::Borrow, ops::{Deref, Index}, sync::{atomic::{AtomicBool, Ordering}, OnceLock}};
+use std::{borrow::Borrow, ops::{Deref, Index}, sync::{atomic::{AtomicBool, AtomicU32, Ordering}, LazyLock, RwLock, RwLockReadGuard}};
 
 use bevy::{math::Ray3d, utils::hashbrown::HashMap};
 
-use crate::{blockstate, core::voxel::blockstate};
+use crate::{blockstate, core::{collections::appendlist::AppendList, math::aabb::AABB, voxel::blockstate}};
 
-use super::{block::Block, statevalue::StateValue, blockstate::BlockState, coord::Coord, lighting::lightargs::LightArgs, occluder::Occluder, occlusionshape::OcclusionShape, world::VoxelWorld};
+use super::{block::Block, faces::Faces, statevalue::StateValue, blockstate::BlockState, coord::Coord, lighting::lightargs::LightArgs, occluder::Occluder, occlusionshape::OcclusionShape, world::VoxelWorld};
 
 struct RegistryEntry {
     state: BlockState,
     block_ref: BlockId,
 }
 
-static mut STATES: OnceLock<Vec<RegistryEntry>> = OnceLock::new();
-static mut BLOCKS: OnceLock<Vec<Box<dyn Block>>> = OnceLock::new();
-static mut ID_LOOKUP: OnceLock<HashMap<BlockState, Id>> = OnceLock::new();
-static mut BLOCK_LOOKUP: OnceLock<HashMap<String, BlockId>> = OnceLock::new();
-static mut INITIALIZED: AtomicBool = AtomicBool::new(false);
-
-/// Returns true if initialization occurred.
-/// You don't really need to call this function since every other function calls it.
-fn init() -> bool {
-    unsafe {
-        if INITIALIZED.swap(true, Ordering::SeqCst) {
-            return false;
+#[derive(Default)]
+struct Lookup {
+    ids: HashMap<BlockState, Id>,
+    blocks: HashMap<String, BlockId>,
+}
+
+/// The block registry is shared by the main thread and the chunk workers, which register
+/// states while generating chunks and read blocks while meshing.
+/// States and blocks are stored in [AppendList]s so that the `&'static` references handed
+/// out by the registry stay valid while other threads register. The lookup tables are
+/// behind a lock, which is also held while registering so that a state is never registered twice.
+struct Registry {
+    states: AppendList<RegistryEntry>,
+    blocks: AppendList<Box<dyn Block>>,
+    lookup: RwLock<Lookup>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        let registry = Self {
+            states: AppendList::new(),
+            blocks: AppendList::new(),
+            lookup: RwLock::new(Lookup::default()),
+        };
+        {
+            let mut lookup = registry.lookup.write().expect("Failed to lock block registry");
+            registry.insert_block(&mut lookup, AirBlock);
+            let air = registry.insert_state(&mut lookup, blockstate!(air), BlockId(0));
+            debug_assert_eq!(air, Id::AIR);
+            let unknown = registry.insert_block(&mut lookup, UnknownBlock);
+            debug_assert_eq!(unknown, UNKNOWN_BLOCK);
         }
-        STATES.set(Vec::with_capacity(4096));
-        BLOCKS.set(Vec::with_capacity(512));
-        ID_LOOKUP.set(HashMap::new());
-        BLOCK_LOOKUP.set(HashMap::new());
-        register_block(AirBlock);
-        register_state(blockstate!(air));
-        true
+        registry
+    }
+
+    fn lookup(&self) -> RwLockReadGuard<'_, Lookup> {
+        self.lookup.read().expect("Failed to lock block registry")
+    }
+
+    fn insert_block<B: Block>(&self, lookup: &mut Lookup, block: B) -> BlockId {
+        let name = block.name().to_owned();
+        let id = BlockId(self.blocks.push(Box::new(block)) as u32);
+        lookup.blocks.insert(name, id);
+        id
+    }
+
+    fn insert_state(&self, lookup: &mut Lookup, state: BlockState, block_ref: BlockId) -> Id {
+        let id = Id(self.states.push(RegistryEntry { state: state.clone(), block_ref }) as u32);
+        lookup.ids.insert(state, id);
+        id
+    }
+
+    fn entry(&self, id: Id) -> &RegistryEntry {
+        &self.states[id.0 as usize]
+    }
+
+    fn block(&self, id: BlockId) -> &dyn Block {
+        self.blocks[id.0 as usize].as_ref()
     }
 }
 
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+/// Set once the first unknown state is registered so that chunk loading can skip counting unknown blocks.
+static HAS_UNKNOWN_STATES: AtomicBool = AtomicBool::new(false);
+static UNKNOWN_TEXTURE_INDEX: AtomicU32 = AtomicU32::new(0);
+/// [UnknownBlock] is always the second block that is registered (after air).
+const UNKNOWN_BLOCK: BlockId = BlockId(1);
+
 #[must_use]
 pub fn register_state<B: Borrow<BlockState>>(state: B) -> Id {
-    init();
-    unsafe {
-        let id_lookup = ID_LOOKUP.get_mut().expect("Failed to get");
-        if let Some(&id) = id_lookup.get(state.borrow()) {
-            return id;
-        }
-        let state: BlockState = state.borrow().clone();
-        let block_lookup = BLOCK_LOOKUP.get().expect("Failed to get");
-        let block_id = if let Some(&block_id) = block_lookup.get(state.name()) {
-            block_id
-        } else {
-            panic!("Block not found: {}", state.name());
-        };
-        let states = STATES.get_mut().expect("Failed to get");
-        let id = states.len() as u32;
-        id_lookup.insert(state.clone(), Id(id));
-        states.push(RegistryEntry { block_ref: block_id, state });
-        Id(id)
+    let state: &BlockState = state.borrow();
+    if let Some(&id) = REGISTRY.lookup().ids.get(state) {
+        return id;
     }
+    let mut lookup = REGISTRY.lookup.write().expect("Failed to lock block registry");
+    // Another thread may have registered the state while the lock was released.
+    if let Some(&id) = lookup.ids.get(state) {
+        return id;
+    }
+    let Some(block_id) = lookup.blocks.get(state.name()).copied() else {
+        // Release the lock before panicking so that the registry isn't poisoned.
+        drop(lookup);
+        panic!("Block not found: {}", state.name());
+    };
+    REGISTRY.insert_state(&mut lookup, state.clone(), block_id)
 }
 
 #[must_use]
 pub fn register_block<B: Block>(mut block: B) -> BlockId {
-    init();
-    unsafe {
-        let block_lookup = BLOCK_LOOKUP.get_mut().expect("Failed to get");
-        if block_lookup.contains_key(block.name()) {
-            panic!("Block already registered: {}", block.name());
-        }
-        let blocks = BLOCKS.get_mut().expect("Failed to get");
-        let id = blocks.len() as u32;
-        block_lookup.insert(block.name().to_owned(), BlockId(id));
-        block.on_register();
-        blocks.push(Box::new(block));
-        BlockId(id)
+    if find_block(block.name()).is_some() {
+        panic!("Block already registered: {}", block.name());
+    }
+    // The lock isn't held here so that blocks can register states when they're registered.
+    block.on_register();
+    let mut lookup = REGISTRY.lookup.write().expect("Failed to lock block registry");
+    if lookup.blocks.contains_key(block.name()) {
+        drop(lookup);
+        panic!("Block already registered: {}", block.name());
+    }
+    REGISTRY.insert_block(&mut lookup, block)
+}
+
+/// Registers a state whose block isn't registered (for example, a block that was removed or renamed) as an
+/// [UnknownBlock]. The state keeps its name and properties, so it is saved unchanged. If the state's block is
+/// registered, this is the same as [register_state].
+/// 
+/// A state that was registered as unknown stays unknown, even if its block is registered afterward.
+#[must_use]
+pub fn register_state_or_unknown<B: Borrow<BlockState>>(state: B) -> Id {
+    let state: &BlockState = state.borrow();
+    if let Some(&id) = REGISTRY.lookup().ids.get(state) {
+        return id;
+    }
+    let mut lookup = REGISTRY.lookup.write().expect("Failed to lock block registry");
+    if let Some(&id) = lookup.ids.get(state) {
+        return id;
+    }
+    if let Some(&block_id) = lookup.blocks.get(state.name()) {
+        return REGISTRY.insert_state(&mut lookup, state.clone(), block_id);
+    }
+    bevy::log::warn!("Block not found: {state}. It will be loaded as an unknown block.");
+    let id = REGISTRY.insert_state(&mut lookup, state.clone(), UNKNOWN_BLOCK);
+    HAS_UNKNOWN_STATES.store(true, Ordering::Relaxed);
+    id
+}
+
+/// Every state that has been registered as an [UnknownBlock].
+#[must_use]
+pub fn unknown_states() -> Vec<Id> {
+    if !has_unknown_states() {
+        return Vec::new();
     }
+    REGISTRY.states.iter().enumerate()
+        .filter(|(_, entry)| entry.block_ref == UNKNOWN_BLOCK)
+        .map(|(index, _)| Id(index as u32))
+        .collect()
+}
+
+/// Determines if any state has been registered as an [UnknownBlock].
+pub fn has_unknown_states() -> bool {
+    HAS_UNKNOWN_STATES.load(Ordering::Relaxed)
+}
+
+/// Sets the texture (an index into the block texture array) that [UnknownBlock] is rendered with.
+pub fn set_unknown_texture(texture_index: u32) {
+    UNKNOWN_TEXTURE_INDEX.store(texture_index, Ordering::Relaxed);
 }
 
 /// If the [BlockState] has already been registered, find the associated [Id].
 
 #[must_use]
 pub fn find_state<B: Borrow<BlockState>>(state: B) -> Option<Id> {
-    init();
-    unsafe {
-        let id_lookup = ID_LOOKUP.get().expect("Failed to get");
-        id_lookup.get(state.borrow()).map(|&id| id)
-    }
+    REGISTRY.lookup().ids.get(state.borrow()).copied()
 }
 
 
 #[must_use]
 pub fn find_block<S: AsRef<str>>(name: S) -> Option<BlockId> {
-    init();
-    unsafe {
-        let block_lookup = BLOCK_LOOKUP.get().expect("Failed to get block lookup");
-        block_lookup.get(name.as_ref()).map(|&id| id)
-    }
+    REGISTRY.lookup().blocks.get(name.as_ref()).copied()
 }
 
 
 #[must_use]
 pub fn get_block_ref(id: Id) -> BlockId {
-    unsafe {
-        let states = STATES.get().expect("Failed to get states");
-        states[id.0 as usize].block_ref
-    }
+    REGISTRY.entry(id).block_ref
 }
 
 
 #[must_use]
 pub fn get_state(id: Id) -> &'static BlockState {
-    // Id is only issued by the registry, so this doesn't need
-    // to call init because it can be assumed that init has already
-    // been called.
-    // It can also be assumed that Id is associated with a BlockState
-    unsafe {
-        let states = STATES.get().expect("Failed to get states");
-        &states[id.0 as usize].state
-    }
+    // Id is only issued by the registry, so it can be assumed that
+    // Id is associated with a BlockState.
+    &REGISTRY.entry(id).state
 }
 
 
 #[must_use]
 pub fn get_block(id: BlockId) -> &'static dyn Block {
-    // BlockRef is only issued by the registry, so this doesn't need
-    // to call init because it can be assumed that init has already
-    // been called.
-    // It can also be assumed that BlockRef is associated with a Block.
-    unsafe {
-        let blocks = BLOCKS.get().expect("Failed to get blocks");
-        blocks[id.0 as usize].as_ref()
-    }
+    // BlockRef is only issued by the registry, so it can be assumed
+    // that BlockRef is associated with a Block.
+    REGISTRY.block(id)
 }
 
 
 #[must_use]
 pub fn get_block_for(id: Id) -> &'static dyn Block {
-    unsafe {
-        let states = STATES.get().expect("Failed to get states");
-        let block_id = states[id.0 as usize].block_ref;
-        let blocks = BLOCKS.get().expect("Failed to get blocks");
-        blocks[block_id.0 as usize].as_ref()
-    }
+    REGISTRY.block(REGISTRY.entry(id).block_ref)
 }
 
 
 #[must_use]
 pub fn get_state_and_block(id: Id) -> (&'static BlockState, &'static dyn Block) {
-    unsafe {
-        let states = STATES.get().expect("Failed to get states");
-        let block_id = states[id.0 as usize].block_ref;
-        let state = &states[id.0 as usize].state;
-        let blocks = BLOCKS.get().expect("Failed to get blocks");
-        let block = blocks[block_id.0 as usize].as_ref();
-        (state, block)
-    }
+    let entry = REGISTRY.entry(id);
+    (&entry.state, REGISTRY.block(entry.block_ref))
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -163,14 +216,14 @@ pub struct BlockId(u32);
 
 impl Id {
     pub const AIR: Self = Id(0);
-    /// Make sure you don't register any states while this reference is held.
+    /// Registry references are never invalidated, so this is safe to call. It's kept for compatibility.
     
     #[must_use]
     pub unsafe fn unsafe_state(self) -> &'static BlockState {
         get_state(self)
     }
 
-    /// Make sure you don't register any blocks while this reference is held.
+    /// Registry references are never invalidated, so this is safe to call. It's kept for compatibility.
     
     #[must_use]
     pub unsafe fn unsafe_block(self) -> &'static dyn Block {
@@ -189,7 +242,7 @@ impl Id {
         self.0 != 0
     }
 
-    /// Don't register anything while these references are held.
+    /// Registry references are never invalidated, so this is safe to call. It's kept for compatibility.
     
     #[must_use]
     pub unsafe fn unsafe_state_and_block(self) -> (&'static BlockState, &'static dyn Block) {
@@ -211,6 +264,11 @@ impl Id {
         self.0 == 0
     }
 
+    /// Returns true if this state was registered with [register_state_or_unknown] because its block isn't registered.
+    pub fn is_unknown(self) -> bool {
+        has_unknown_states() && get_block_ref(self) == UNKNOWN_BLOCK
+    }
+
     
     #[must_use]
     pub fn clone_state(self) -> BlockState {
@@ -256,10 +314,7 @@ impl Deref for Id {
 
     
     fn deref(&self) -> &Self::Target {
-        unsafe {
-            let states = STATES.get().expect("Failed to get");
-            &states[self.0 as usize].state
-        }
+        get_state(*self)
     }
 }
 
@@ -268,10 +323,7 @@ impl Deref for BlockId {
 
     
     fn deref(&self) -> &Self::Target {
-        unsafe {
-            let blocks = BLOCKS.get().expect("Failed to get");
-            blocks[self.0 as usize].as_ref()
-        }
+        get_block(*self)
     }
 }
 
@@ -286,16 +338,20 @@ pub struct AirBlock;
 
 impl Block for AirBlock {
 
-    fn raycast(&self, ray: Ray3d, world: &VoxelWorld, coord: Coord, state: Id, orientation: crate::prelude::Orientation) -> Option<f32> {
+    fn raycast(&self, ray: Ray3d, world: &VoxelWorld, coord: Coord, state: Id, orientation: crate::prelude::Orientation) -> Option<(f32, crate::prelude::Direction)> {
         None
     }
+
+    fn selection_shape(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+        Vec::new()
+    }
     
     fn name(&self) -> &str {
         "air"
     }
     
-    fn light_args(&self, world: &VoxelWorld, coord: Coord, state: Id) -> LightArgs {
-        LightArgs::new(1, 0)
+    fn state_light_args(&self, state: Id) -> Option<LightArgs> {
+        Some(LightArgs::new(1, 0))
     }
 
     
@@ -306,4 +362,47 @@ impl Block for AirBlock {
     fn default_state(&self) -> BlockState {
         blockstate!(air)
     }
-}
\ No newline at end of file
+}
+
+/// Stands in for blocks that aren't registered so that worlds that contain them can still be loaded.
+/// Unknown blocks are solid cubes that are rendered with the texture set by [set_unknown_texture].
+pub struct UnknownBlock;
+
+impl Block for UnknownBlock {
+    fn name(&self) -> &str {
+        "unknown"
+    }
+
+    fn default_state(&self) -> BlockState {
+        blockstate!(unknown)
+    }
+
+    fn greedy_faces(&self, state: Id) -> Option<Faces<u32>> {
+        let texture_index = UNKNOWN_TEXTURE_INDEX.load(Ordering::Relaxed);
+        Some(Faces::new(texture_index, texture_index, texture_index, texture_index, texture_index, texture_index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_threads_test() {
+        // Every thread registers the same states, so they should all get the same ids.
+        let threads = (0..4).map(|_| {
+            std::thread::spawn(|| {
+                (0..500i64).map(|value| {
+                    let state = blockstate!(registry_threads_test, value = value);
+                    let id = register_state_or_unknown(&state);
+                    assert_eq!(&*id, &state);
+                    assert!(id.is_unknown());
+                    id
+                }).collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>();
+        let ids = threads.into_iter().map(|thread| thread.join().expect("Thread panicked")).collect::<Vec<_>>();
+        assert!(ids.iter().all(|thread_ids| thread_ids == &ids[0]));
+        assert_eq!(find_state(blockstate!(registry_threads_test, value = 499i64)), Some(ids[0][499]));
+    }
+}
@@ -40,6 +40,10 @@ impl BlockState {
         &self.name
     }
 
+    pub fn set_name<S: StrToOwned>(&mut self, name: S) {
+        self.name = name.str_to_owned();
+    }
+
     pub fn properties(&self) -> &[BlockProperty] {
         &self.sorted_properties
     }
@@ -74,11 +78,25 @@ impl BlockState {
         }
     }
 
+    pub fn remove_property<S: AsRef<str>>(&mut self, name: S) -> Option<StateValue> {
+        let name = name.as_ref();
+        let index = self.sorted_properties.binary_search_by(|prop| {
+            let prop_name: &str = &prop.name;
+            prop_name.cmp(name)
+        }).ok()?;
+        Some(self.sorted_properties.remove(index).value)
+    }
+
     /// Registers this [BlockState] with this block registry.
     pub fn register(&self) -> Id {
         blocks::register_state(self)
     }
 
+    /// Registers this [BlockState], or registers it as an [UnknownBlock](super::blocks::UnknownBlock) if its block isn't registered.
+    pub fn register_or_unknown(&self) -> Id {
+        blocks::register_state_or_unknown(self)
+    }
+
     /// Finds the [BlockState] in the block registry.
     pub fn find(&self) -> Option<Id> {
         blocks::find_state(self)
@@ -150,6 +168,14 @@ impl BlockProperty {
             value: value.into(),
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &StateValue {
+        &self.value
+    }
 }
 
 #[macro_export]
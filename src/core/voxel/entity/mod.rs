@@ -0,0 +1,154 @@
+#![allow(unused)]
+pub mod physics;
+
+use bevy::math::{vec3, Vec3};
+use hashbrown::HashMap;
+
+use crate::core::collections::objectpool::PoolId;
+use crate::core::math::aabb::AABB;
+
+use super::{coord::Coord, direction::Direction, tag::Tag};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityMarker;
+/// A handle to a [VoxelEntity] in a [VoxelWorld](crate::core::voxel::world::VoxelWorld).
+/// Handles go stale when the entity is removed or its chunk is unloaded.
+pub type EntityId = PoolId<EntityMarker>;
+
+/// The height of the ledges that entities can walk up without jumping. This is tall enough for slabs, but not full blocks.
+pub const DEFAULT_STEP_HEIGHT: f32 = 0.6;
+
+/// Something that moves around the world and collides with blocks.
+///
+/// Entities belong to the chunk that their position is in and are saved with that chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxelEntity {
+    /// What sort of entity this is (such as `"item"` or `"zombie"`). The engine doesn't use this,
+    /// but it's saved so that games can tell their entities apart.
+    pub kind: String,
+    /// The center of the bottom of the entity's bounding box.
+    pub position: Vec3,
+    /// Blocks per second.
+    pub velocity: Vec3,
+    /// The width, height, and depth of the bounding box.
+    pub size: Vec3,
+    pub gravity: bool,
+    pub step_height: f32,
+    /// True if the entity landed on something the last time that it moved.
+    pub on_ground: bool,
+    /// Extra data that is saved with the entity.
+    pub data: Tag,
+    /// The chunk that the entity is stored in.
+    pub(crate) chunk: (i32, i32),
+    /// True if the entity moved since its chunk was last saved.
+    pub(crate) moved: bool,
+    /// The blocks (and the faces of them) that the entity was touching after it last moved.
+    pub(crate) contacts: Vec<(Coord, Direction)>,
+}
+
+impl VoxelEntity {
+    pub fn new<S: Into<String>>(kind: S, position: Vec3, size: Vec3) -> Self {
+        Self {
+            kind: kind.into(),
+            position,
+            velocity: Vec3::ZERO,
+            size,
+            gravity: true,
+            step_height: DEFAULT_STEP_HEIGHT,
+            on_ground: false,
+            data: Tag::Null,
+            chunk: Self::chunk_at(position),
+            moved: false,
+            contacts: Vec::new(),
+        }
+    }
+
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: bool) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_step_height(mut self, step_height: f32) -> Self {
+        self.step_height = step_height;
+        self
+    }
+
+    pub fn with_data<T: Into<Tag>>(mut self, data: T) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    /// The bounding box of the entity in world space.
+    pub fn aabb(&self) -> AABB {
+        let half = vec3(self.size.x * 0.5, 0.0, self.size.z * 0.5);
+        AABB::new(self.position - half, self.position + half + vec3(0.0, self.size.y, 0.0))
+    }
+
+    /// The chunk that the entity is stored in.
+    pub fn chunk(&self) -> (i32, i32) {
+        self.chunk
+    }
+
+    /// The chunk that contains a position.
+    pub fn chunk_at(position: Vec3) -> (i32, i32) {
+        ((position.x.floor() as i32) >> 4, (position.z.floor() as i32) >> 4)
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        Tag::from(HashMap::from([
+            ("kind".to_owned(), Tag::from(self.kind.as_str())),
+            ("position".to_owned(), Tag::from(self.position)),
+            ("velocity".to_owned(), Tag::from(self.velocity)),
+            ("size".to_owned(), Tag::from(self.size)),
+            ("gravity".to_owned(), Tag::from(self.gravity)),
+            ("step_height".to_owned(), Tag::from(self.step_height)),
+            ("on_ground".to_owned(), Tag::from(self.on_ground)),
+            ("data".to_owned(), self.data.clone()),
+        ]))
+    }
+
+    /// Reads an entity that was written with [VoxelEntity::to_tag].
+    /// Returns `None` if the tag is missing the kind, position, or size.
+    pub fn from_tag(tag: &Tag) -> Option<Self> {
+        let (Tag::String(kind), Tag::Vec3(position), Tag::Vec3(size)) = (&tag["kind"], &tag["position"], &tag["size"]) else {
+            return None;
+        };
+        let mut entity = Self::new(kind.as_str(), *position, *size);
+        if let Tag::Vec3(velocity) = &tag["velocity"] {
+            entity.velocity = *velocity;
+        }
+        if let Tag::Bool(gravity) = &tag["gravity"] {
+            entity.gravity = *gravity;
+        }
+        if let Tag::F32(step_height) = &tag["step_height"] {
+            entity.step_height = *step_height;
+        }
+        if let Tag::Bool(on_ground) = &tag["on_ground"] {
+            entity.on_ground = *on_ground;
+        }
+        entity.data = tag["data"].clone();
+        Some(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_test() {
+        let entity = VoxelEntity::new("test", vec3(-3.5, 12.0, 40.25), vec3(0.6, 1.8, 0.6))
+            .with_velocity(vec3(1.0, 2.0, 3.0))
+            .with_step_height(1.0)
+            .with_data(HashMap::from([("health".to_owned(), Tag::from(20u8))]));
+        assert_eq!(entity.chunk(), (-1, 2));
+        let tag = entity.to_tag();
+        assert_eq!(VoxelEntity::from_tag(&tag), Some(entity));
+        assert_eq!(VoxelEntity::from_tag(&Tag::Null), None);
+    }
+}
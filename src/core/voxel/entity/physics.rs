@@ -0,0 +1,163 @@
+#![allow(unused)]
+use bevy::math::{vec3, Vec3};
+
+use crate::core::math::aabb::AABB;
+use crate::core::voxel::{coord::Coord, direction::Direction, world::{VoxelWorld, WORLD_BOTTOM, WORLD_TOP}};
+
+/// Blocks per second per second.
+pub const GRAVITY: f32 = 32.0;
+/// The fastest that gravity can make an entity fall, in blocks per second.
+pub const TERMINAL_VELOCITY: f32 = 78.0;
+
+/// The result of moving a box through the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    /// Where the box ended up.
+    pub aabb: AABB,
+    /// How far the box moved. An axis that is different from the requested movement hit something.
+    pub motion: Vec3,
+    /// The blocks that were hit and the face of each block that was hit.
+    pub hits: Vec<(Coord, Direction)>,
+}
+
+impl Sweep {
+    /// Determines if the box landed on top of something.
+    pub fn landed(&self) -> bool {
+        self.hits.iter().any(|&(_, face)| face == Direction::PosY)
+    }
+}
+
+/// Gathers the collision boxes of every block that overlaps `area`.
+/// Blocks outside of the loaded area are solid so that nothing can move out of the world.
+pub fn collision_boxes(world: &VoxelWorld, area: AABB) -> Vec<(Coord, AABB)> {
+    let min = area.min().floor();
+    let max = area.max().ceil();
+    let bounds = world.bounds();
+    let mut boxes = Vec::new();
+    for y in (min.y as i32).max(WORLD_BOTTOM)..(max.y as i32).min(WORLD_TOP) {
+        for z in min.z as i32..max.z as i32 {
+            for x in min.x as i32..max.x as i32 {
+                let coord = Coord::new(x, y, z);
+                if !bounds.contains(coord) {
+                    boxes.push((coord, AABB::voxel(coord)));
+                    continue;
+                }
+                let state = world.get_block(coord);
+                if state.is_air() {
+                    continue;
+                }
+                boxes.extend(state.block().collision_boxes(world, coord, state).into_iter().map(|aabb| (coord, aabb)));
+            }
+        }
+    }
+    boxes
+}
+
+/// Moves `aabb` by `motion`, stopping at the collision boxes of blocks.
+/// The box moves along the y axis first, then the x axis, then the z axis, so it slides along whatever it hits.
+pub fn sweep(world: &VoxelWorld, aabb: AABB, motion: Vec3) -> Sweep {
+    let boxes = collision_boxes(world, aabb.expand_towards(motion));
+    sweep_boxes(&boxes, aabb, motion)
+}
+
+/// Moves `aabb` like [sweep], but if the box is stopped horizontally while it's standing on something
+/// (`grounded`, or landing during this move), it tries to climb up to `step_height` onto whatever stopped it.
+/// The climb is only used if it gets the box further than it would have gone without it.
+pub fn move_aabb(world: &VoxelWorld, aabb: AABB, motion: Vec3, step_height: f32, grounded: bool) -> Sweep {
+    let area = aabb.expand_towards(motion).expand_towards(vec3(0.0, step_height.max(0.0), 0.0));
+    let boxes = collision_boxes(world, area);
+    let moved = sweep_boxes(&boxes, aabb, motion);
+    let blocked = moved.motion.x != motion.x || moved.motion.z != motion.z;
+    if step_height <= 0.0 || !blocked || !(grounded || moved.landed()) {
+        return moved;
+    }
+    // Climb, move horizontally, then settle back down onto whatever was climbed.
+    let climb = sweep_boxes(&boxes, aabb, vec3(motion.x, step_height, motion.z));
+    let settle = sweep_boxes(&boxes, climb.aabb, vec3(0.0, motion.y.min(0.0) - climb.motion.y, 0.0));
+    let horizontal = |motion: Vec3| motion.x * motion.x + motion.z * motion.z;
+    if horizontal(climb.motion) <= horizontal(moved.motion) {
+        return moved;
+    }
+    let mut hits = climb.hits;
+    settle.hits.into_iter().for_each(|hit| {
+        if !hits.contains(&hit) {
+            hits.push(hit);
+        }
+    });
+    Sweep {
+        aabb: settle.aabb,
+        motion: climb.motion + settle.motion,
+        hits,
+    }
+}
+
+fn sweep_boxes(boxes: &[(Coord, AABB)], aabb: AABB, motion: Vec3) -> Sweep {
+    let mut hits = Vec::new();
+    let y = clip_axis(boxes, aabb, motion.y, AABB::clip_y, Direction::NegY, Direction::PosY, &mut hits);
+    let aabb = aabb.translate(vec3(0.0, y, 0.0));
+    let x = clip_axis(boxes, aabb, motion.x, AABB::clip_x, Direction::NegX, Direction::PosX, &mut hits);
+    let aabb = aabb.translate(vec3(x, 0.0, 0.0));
+    let z = clip_axis(boxes, aabb, motion.z, AABB::clip_z, Direction::NegZ, Direction::PosZ, &mut hits);
+    let aabb = aabb.translate(vec3(0.0, 0.0, z));
+    Sweep {
+        aabb,
+        motion: vec3(x, y, z),
+        hits,
+    }
+}
+
+/// Clips movement along one axis. Moving in the positive direction hits the negative face of a block.
+fn clip_axis(
+    boxes: &[(Coord, AABB)],
+    aabb: AABB,
+    delta: f32,
+    clip: fn(AABB, AABB, f32) -> f32,
+    neg_face: Direction,
+    pos_face: Direction,
+    hits: &mut Vec<(Coord, Direction)>,
+) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
+    let clipped = boxes.iter().fold(delta, |delta, &(_, block)| clip(block, aabb, delta));
+    if clipped == delta {
+        return delta;
+    }
+    let face = if delta > 0.0 { neg_face } else { pos_face };
+    // Every box that stops the movement at the same distance was hit.
+    boxes.iter()
+        .filter(|&&(_, block)| clip(block, aabb, delta) == clipped)
+        .for_each(|&(coord, _)| {
+            if !hits.contains(&(coord, face)) {
+                hits.push((coord, face));
+            }
+        });
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_boxes_test() {
+        let floor = [
+            (Coord::new(0, 0, 0), AABB::voxel((0, 0, 0))),
+            (Coord::new(1, 0, 0), AABB::voxel((1, 0, 0))),
+            (Coord::new(2, 1, 0), AABB::voxel((2, 1, 0))),
+        ];
+        // Falls onto the seam between two blocks and lands on both.
+        let aabb = AABB::new(vec3(0.75, 2.0, 0.25), vec3(1.25, 3.0, 0.75));
+        let sweep = sweep_boxes(&floor, aabb, vec3(0.0, -5.0, 0.0));
+        assert_eq!(sweep.motion, vec3(0.0, -1.0, 0.0));
+        assert_eq!(sweep.hits, vec![
+            (Coord::new(0, 0, 0), Direction::PosY),
+            (Coord::new(1, 0, 0), Direction::PosY),
+        ]);
+        assert!(sweep.landed());
+        // Slides into the wall and stops at its face.
+        let sweep = sweep_boxes(&floor, sweep.aabb, vec3(2.0, 0.0, 0.5));
+        assert_eq!(sweep.motion, vec3(0.75, 0.0, 0.5));
+        assert_eq!(sweep.hits, vec![(Coord::new(2, 1, 0), Direction::NegX)]);
+    }
+}
@@ -0,0 +1,447 @@
+#![allow(unused)]
+use std::sync::OnceLock;
+
+use bevy::math::Ray3d;
+
+use crate::{core::math::aabb::AABB, prelude::Orientation};
+
+use super::{block::Block, blocks::Id, blockstate::{BlockProperty, BlockState}, coord::Coord, direction::Direction, lighting::lightargs::LightArgs, occluder::Occluder, statevalue::StateValue, world::{PlaceContext, VoxelWorld}};
+
+/// The name of the [StateValue::Int] property that holds the level of a fluid.
+pub const LEVEL_PROPERTY: &str = "level";
+/// The name of the [StateValue::Bool] property that is true for source blocks.
+pub const SOURCE_PROPERTY: &str = "source";
+/// The level of sources and falling fluid. Fluid that flows sideways loses
+/// [FluidSettings::decay] levels per block and disappears at `0`.
+pub const MAX_FLUID_LEVEL: u8 = 8;
+
+/// The directions that fluid spreads in after it can't flow down any further.
+const SIDEWAYS: [Direction; 4] = [Direction::PosX, Direction::NegX, Direction::PosZ, Direction::NegZ];
+
+/// Called when a fluid is updated next to a different fluid that it has an interaction with.
+/// Returns the state that replaces the fluid at `coord`, or `None` to let it update normally.
+pub type FluidInteraction = fn(world: &VoxelWorld, coord: Coord, fluid: Id, other: Id) -> Option<Id>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FluidSettings {
+    /// The number of ticks between a fluid changing and it spreading.
+    pub tick_rate: u32,
+    /// The number of levels that are lost for each block that the fluid flows sideways.
+    pub decay: u8,
+    /// When true, flowing fluid between two sources becomes a source.
+    pub infinite: bool,
+    pub light: LightArgs,
+}
+
+impl FluidSettings {
+    pub const WATER: Self = Self {
+        tick_rate: 5,
+        decay: 1,
+        infinite: true,
+        light: LightArgs::new(2, 0),
+    };
+
+    pub const LAVA: Self = Self {
+        tick_rate: 30,
+        decay: 2,
+        infinite: false,
+        light: LightArgs::new(15, 15),
+    };
+}
+
+/// A block that flows using scheduled updates.
+///
+/// Fluid has a source state and flowing states. Each time a fluid updates, it flows down if it can.
+/// Otherwise, it spreads sideways with a lower level. Flowing fluid that no longer has anything
+/// feeding it dries up over time. Fluid can flow into air and into blocks that return true from
+/// [Block::fluid_replaceable].
+pub struct FluidBlock {
+    name: String,
+    settings: FluidSettings,
+    interactions: Vec<(String, FluidInteraction)>,
+    /// The source state followed by the flowing state of each level. The states can only be registered
+    /// after the block is, so they're registered the first time that they're needed.
+    states: OnceLock<[Id; MAX_FLUID_LEVEL as usize + 1]>,
+}
+
+impl FluidBlock {
+    pub fn new<S: Into<String>>(name: S, settings: FluidSettings) -> Self {
+        Self {
+            name: name.into(),
+            settings,
+            interactions: Vec::new(),
+            states: OnceLock::new(),
+        }
+    }
+
+    /// Adds an interaction with the fluid named `other`. The interaction is checked every time that this fluid
+    /// updates next to the other fluid.
+    pub fn with_interaction<S: Into<String>>(mut self, other: S, interaction: FluidInteraction) -> Self {
+        self.interactions.push((other.into(), interaction));
+        self
+    }
+
+    pub fn settings(&self) -> &FluidSettings {
+        &self.settings
+    }
+
+    /// The state of a source block of this fluid.
+    pub fn source(&self) -> Id {
+        self.states()[0]
+    }
+
+    /// The state of flowing fluid with the given level.
+    pub fn flowing(&self, level: u8) -> Id {
+        self.states()[level.clamp(1, MAX_FLUID_LEVEL) as usize]
+    }
+
+    fn states(&self) -> &[Id; MAX_FLUID_LEVEL as usize + 1] {
+        self.states.get_or_init(|| std::array::from_fn(|index| {
+            let (level, source) = if index == 0 {
+                (MAX_FLUID_LEVEL, true)
+            } else {
+                (index as u8, false)
+            };
+            BlockState::new(&self.name, [
+                BlockProperty::new(LEVEL_PROPERTY, level as i64),
+                BlockProperty::new(SOURCE_PROPERTY, source),
+            ]).register()
+        }))
+    }
+
+    fn same_fluid(&self, state: Id) -> bool {
+        !state.is_air() && state.block().name() == self.name
+    }
+
+    /// The level that this fluid spreads from, or `0` if `state` isn't this fluid.
+    fn level_of(&self, state: Id) -> u8 {
+        if !self.same_fluid(state) {
+            return 0;
+        }
+        if is_source(state) {
+            MAX_FLUID_LEVEL
+        } else {
+            fluid_level(state).unwrap_or(0)
+        }
+    }
+
+    /// Determines if this fluid can flow into the block at `coord`.
+    fn can_replace(&self, world: &VoxelWorld, coord: Coord, state: Id) -> bool {
+        world.bounds().contains(coord)
+        && (state.is_air() || (!self.same_fluid(state) && state.block().fluid_replaceable(world, coord, state)))
+    }
+
+    /// Finds the first interaction with a neighboring fluid that replaces this fluid.
+    fn interact(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Option<Id> {
+        if self.interactions.is_empty() {
+            return None;
+        }
+        let neighbors = world.neighbors(coord);
+        Direction::iter().find_map(|dir| {
+            let other = neighbors[dir];
+            if other.is_air() {
+                return None;
+            }
+            let other_name = other.block().name();
+            self.interactions.iter()
+                .filter(|(name, _)| name == other_name)
+                .find_map(|(_, interaction)| interaction(world, coord, state, other))
+        })
+    }
+
+    /// The level that flowing fluid at `coord` should have based on its neighbors.
+    /// Flowing fluid below the same fluid is falling, so it has the maximum level.
+    fn fed_level(&self, world: &VoxelWorld, coord: Coord) -> u8 {
+        if self.same_fluid(world.get_block(coord + Direction::PosY)) {
+            return MAX_FLUID_LEVEL;
+        }
+        SIDEWAYS.iter()
+            .map(|&dir| self.level_of(world.get_block(coord + dir)))
+            .max()
+            .unwrap_or(0)
+            .saturating_sub(self.settings.decay)
+    }
+
+    /// Determines if flowing fluid at `coord` is between two sources and resting on something
+    /// that it can't flow into, so that it becomes a source.
+    fn forms_source(&self, world: &VoxelWorld, coord: Coord) -> bool {
+        if !self.settings.infinite {
+            return false;
+        }
+        let sources = SIDEWAYS.iter()
+            .filter(|&&dir| {
+                let state = world.get_block(coord + dir);
+                self.same_fluid(state) && is_source(state)
+            })
+            .count();
+        if sources < 2 {
+            return false;
+        }
+        let below_coord = coord + Direction::NegY;
+        let below = world.get_block(below_coord);
+        if self.same_fluid(below) {
+            is_source(below)
+        } else {
+            !self.can_replace(world, below_coord, below)
+        }
+    }
+
+    /// Recalculates the fluid at `coord` and spreads it to its neighbors.
+    fn flow(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {
+        if let Some(replacement) = self.interact(world, coord, state) {
+            world.set_block(coord, replacement);
+            return;
+        }
+        let level = if is_source(state) {
+            MAX_FLUID_LEVEL
+        } else if self.forms_source(world, coord) {
+            world.set_block(coord, self.source());
+            return;
+        } else {
+            let level = self.fed_level(world, coord);
+            if level == 0 {
+                world.set_block(coord, Id::AIR);
+                return;
+            }
+            if Some(level) != fluid_level(state) {
+                // Changing the state schedules another update, which does the spreading.
+                world.set_block(coord, self.flowing(level));
+                return;
+            }
+            level
+        };
+        let below_coord = coord + Direction::NegY;
+        let below = world.get_block(below_coord);
+        if self.can_replace(world, below_coord, below) {
+            world.set_block(below_coord, self.flowing(MAX_FLUID_LEVEL));
+            return;
+        }
+        // Fluid that is already falling into the same fluid doesn't spread sideways.
+        if self.same_fluid(below) && !is_source(below) {
+            return;
+        }
+        let spread = level.saturating_sub(self.settings.decay);
+        if spread == 0 {
+            return;
+        }
+        SIDEWAYS.iter().for_each(|&dir| {
+            let side_coord = coord + dir;
+            let side = world.get_block(side_coord);
+            let flows = if self.same_fluid(side) {
+                !is_source(side) && fluid_level(side).unwrap_or(0) < spread
+            } else {
+                self.can_replace(world, side_coord, side)
+            };
+            if flows {
+                world.set_block(side_coord, self.flowing(spread));
+            }
+        });
+    }
+
+    fn schedule(&self, world: &mut VoxelWorld, coord: Coord) {
+        world.schedule_update(coord, self.settings.tick_rate, 0);
+    }
+}
+
+impl Block for FluidBlock {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn default_state(&self) -> BlockState {
+        BlockState::new(&self.name, [
+            BlockProperty::new(LEVEL_PROPERTY, MAX_FLUID_LEVEL as i64),
+            BlockProperty::new(SOURCE_PROPERTY, true),
+        ])
+    }
+
+    /// Fluids don't hide the faces of the blocks around them.
+    fn occluder(&self, world: &VoxelWorld, state: Id) -> &Occluder {
+        &Occluder::EMPTY_FACES
+    }
+
+    fn state_light_args(&self, state: Id) -> Option<LightArgs> {
+        Some(self.settings.light)
+    }
+
+    fn raycast(&self, ray: Ray3d, world: &VoxelWorld, coord: Coord, state: Id, orientation: Orientation) -> Option<(f32, Direction)> {
+        None
+    }
+
+    fn selection_shape(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+        Vec::new()
+    }
+
+    /// Fluids don't have a collision shape, but other fluids interact with them instead of replacing them.
+    fn fluid_replaceable(&self, world: &VoxelWorld, coord: Coord, state: Id) -> bool {
+        false
+    }
+
+    fn on_place(&self, world: &mut VoxelWorld, context: &mut PlaceContext) {
+        self.schedule(world, context.coord());
+    }
+
+    fn neighbor_updated(&self, world: &mut VoxelWorld, direction: Direction, coord: Coord, neighbor_coord: Coord, state: Id, neighbor_state: Id) {
+        self.schedule(world, coord);
+    }
+
+    fn on_scheduled_update(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {
+        self.flow(world, coord, state);
+    }
+}
+
+/// Gets the level of a fluid state, or `None` if the state doesn't have a level.
+pub fn fluid_level(state: Id) -> Option<u8> {
+    match state[LEVEL_PROPERTY] {
+        StateValue::Int(level) => Some(level.clamp(0, MAX_FLUID_LEVEL as i64) as u8),
+        _ => None,
+    }
+}
+
+/// Determines if a state is a fluid source.
+pub fn is_source(state: Id) -> bool {
+    matches!(state[SOURCE_PROPERTY], StateValue::Bool(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{blocks, testing::{self, open_world, TestDirectory}};
+
+    use super::*;
+
+    /// Entities can move through it, so fluids wash it away.
+    struct FlowerTestBlock;
+    impl Block for FlowerTestBlock {
+        fn name(&self) -> &str {
+            "test_flower"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_flower)
+        }
+
+        fn collision_shape(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+            Vec::new()
+        }
+    }
+
+    /// Lava that touches water turns into `test_stone`.
+    fn lava_meets_water(world: &VoxelWorld, coord: Coord, fluid: Id, other: Id) -> Option<Id> {
+        Some(blockstate!(test_stone).register())
+    }
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(FlowerTestBlock);
+            let instant = FluidSettings { tick_rate: 1, ..FluidSettings::WATER };
+            blocks::register_block(FluidBlock::new("test_water", instant));
+            let instant = FluidSettings { tick_rate: 1, ..FluidSettings::LAVA };
+            blocks::register_block(FluidBlock::new("test_lava", instant).with_interaction("test_water", lava_meets_water));
+        });
+    }
+
+    fn water(level: u8, source: bool) -> Id {
+        BlockState::new("test_water", [
+            BlockProperty::new(LEVEL_PROPERTY, level as i64),
+            BlockProperty::new(SOURCE_PROPERTY, source),
+        ]).register()
+    }
+
+    fn settle(world: &mut VoxelWorld) {
+        for _ in 0..256 {
+            if world.scheduled_updates.is_empty() {
+                return;
+            }
+            world.update();
+        }
+        panic!("Fluid did not settle.");
+    }
+
+    fn fluid_world(directory: &TestDirectory) -> VoxelWorld {
+        register_test_blocks();
+        let mut world = open_world(directory, 2, (0, 0, 0));
+        let floor = BlockState::new("test_stone", []).register();
+        for x in -16..40 {
+            for z in -16..16 {
+                world.set_block((x, 0, z), floor);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn states_test() {
+        register_test_blocks();
+        let fluid = FluidBlock::new("test_water", FluidSettings::WATER);
+        assert_eq!(fluid.source(), water(MAX_FLUID_LEVEL, true));
+        (1..=MAX_FLUID_LEVEL).for_each(|level| {
+            assert_eq!(fluid.flowing(level), water(level, false));
+        });
+        assert_eq!(fluid.flowing(0), water(1, false));
+        assert_eq!(fluid.flowing(MAX_FLUID_LEVEL + 1), water(MAX_FLUID_LEVEL, false));
+    }
+
+    #[test]
+    fn spread_test() {
+        let directory = TestDirectory::new("fluid_spread_test");
+        let mut world = fluid_world(&directory);
+        let flower = BlockState::new("test_flower", []).register();
+        world.set_block((18, 1, 0), flower);
+        // The source is beside a chunk boundary so that the fluid has to cross it.
+        world.set_block((15, 4, 0), water(MAX_FLUID_LEVEL, true));
+        settle(&mut world);
+        // Falling fluid goes straight down first.
+        assert_eq!(world.get_block((15, 3, 0)), water(MAX_FLUID_LEVEL, false));
+        assert_eq!(world.get_block((15, 1, 0)), water(MAX_FLUID_LEVEL, false));
+        assert_eq!(world.get_block((16, 3, 0)), Id::AIR);
+        // Then it spreads sideways with decay, replacing the flower.
+        for distance in 1..MAX_FLUID_LEVEL as i32 {
+            let level = MAX_FLUID_LEVEL - distance as u8;
+            assert_eq!(world.get_block((15 + distance, 1, 0)), water(level, false));
+            assert_eq!(world.get_block((15 - distance, 1, 0)), water(level, false));
+            assert_eq!(world.get_block((15, 1, distance)), water(level, false));
+        }
+        assert_eq!(world.get_block((15 + MAX_FLUID_LEVEL as i32, 1, 0)), Id::AIR);
+        assert_eq!(world.get_block((16, 1, 1)), water(MAX_FLUID_LEVEL - 2, false));
+        // Removing the source dries up all of the flowing fluid.
+        world.set_block((15, 4, 0), Id::AIR);
+        settle(&mut world);
+        for x in 0..32 {
+            assert_eq!(world.get_block((x, 1, 0)), Id::AIR);
+        }
+    }
+
+    #[test]
+    fn infinite_source_test() {
+        let directory = TestDirectory::new("fluid_infinite_source_test");
+        let mut world = fluid_world(&directory);
+        world.set_block((0, 1, 0), water(MAX_FLUID_LEVEL, true));
+        world.set_block((2, 1, 0), water(MAX_FLUID_LEVEL, true));
+        settle(&mut world);
+        assert_eq!(world.get_block((1, 1, 0)), water(MAX_FLUID_LEVEL, true));
+    }
+
+    #[test]
+    fn interaction_test() {
+        let directory = TestDirectory::new("fluid_interaction_test");
+        let mut world = fluid_world(&directory);
+        let floor = BlockState::new("test_stone", []).register();
+        let lava = BlockState::new("test_lava", [
+            BlockProperty::new(LEVEL_PROPERTY, MAX_FLUID_LEVEL as i64),
+            BlockProperty::new(SOURCE_PROPERTY, true),
+        ]).register();
+        world.set_block((0, 1, 0), lava);
+        settle(&mut world);
+        // Lava decays by 2, so it only spreads 3 blocks.
+        assert_eq!(fluid_level(world.get_block((3, 1, 0))), Some(2));
+        assert_eq!(world.get_block((4, 1, 0)), Id::AIR);
+        world.set_block((0, 1, 5), water(MAX_FLUID_LEVEL, true));
+        settle(&mut world);
+        // The water flowed next to the lava, which turned into the floor block where they met.
+        assert_eq!(world.get_block((0, 1, 3)), floor);
+    }
+}
@@ -1,5 +1,9 @@
 // level_of_detail.rs
 
+/// The default distances (in sections) from the center of the world at which
+/// [LOD::Level1], [LOD::Level2], and [LOD::Level3] begin.
+pub const DEFAULT_LOD_DISTANCES: [i32; 3] = [8, 16, 32];
+
 #[repr(u8)]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum LOD {
@@ -8,4 +12,42 @@ pub enum LOD {
     Level1 = 1,
     Level2 = 2,
     Level3 = 3,
-}
\ No newline at end of file
+}
+
+impl LOD {
+    /// The width of the cube of blocks that is merged into a single cell at this level.
+    /// Level0 is `1`, Level1 is `2`, Level2 is `4`, and Level3 is `8`.
+    pub const fn scale(self) -> i32 {
+        1 << self as i32
+    }
+
+    /// Picks the level for a section that is `distance` sections away from the center of the world.
+    /// `distances` are the distances at which Level1, Level2, and Level3 begin.
+    pub fn from_distance(distance: i32, distances: &[i32; 3]) -> Self {
+        if distance < distances[0] {
+            LOD::Level0
+        } else if distance < distances[1] {
+            LOD::Level1
+        } else if distance < distances[2] {
+            LOD::Level2
+        } else {
+            LOD::Level3
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_distance_test() {
+        let distances = [2, 4, 8];
+        assert_eq!(LOD::from_distance(0, &distances), LOD::Level0);
+        assert_eq!(LOD::from_distance(1, &distances), LOD::Level0);
+        assert_eq!(LOD::from_distance(2, &distances), LOD::Level1);
+        assert_eq!(LOD::from_distance(7, &distances), LOD::Level2);
+        assert_eq!(LOD::from_distance(100, &distances), LOD::Level3);
+        assert_eq!(LOD::Level3.scale(), 8);
+    }
+}
@@ -0,0 +1,614 @@
+#![allow(unused)]
+use std::collections::VecDeque;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::core::voxel::{blocks::Id, coord::Coord, direction::Direction, world::{chunk::{Chunk, SkyLightState}, section::{LightChange, Section}, VoxelWorld, WORLD_BOTTOM, WORLD_HEIGHT, WORLD_TOP}};
+
+/// Keeps track of the original light level of every block touched while propagating light
+/// so that [Block::light_updated](crate::core::voxel::block::Block::light_updated) can be called
+/// once per block after propagation has finished.
+///
+/// Calling `light_updated` in the middle of propagation would allow blocks to modify
+/// the world while the light engine is still working on it.
+///
+/// It also collects the neighboring sections whose lightmap padding changed, so that each
+/// of them is marked light dirty once instead of once per changed block.
+#[derive(Debug, Default)]
+pub struct LightTracker {
+    old_levels: HashMap<Coord, u8>,
+    light_dirty: HashSet<Coord>,
+}
+
+impl LightTracker {
+    pub fn record(&mut self, coord: Coord, change: LightChange) {
+        if change.changed() {
+            self.light_dirty.extend(VoxelWorld::light_neighbor_sections(coord));
+        }
+        if change.max_changed() {
+            self.old_levels.entry(coord).or_insert(change.old_max);
+        }
+    }
+
+    /// Marks the collected sections light dirty, then calls `light_updated` on every block whose light level has changed.
+    pub fn notify(self, world: &mut VoxelWorld) {
+        world.mark_light_dirty_sections(self.light_dirty);
+        self.old_levels.into_iter().for_each(|(coord, old_level)| {
+            let new_level = world.get_light_level(coord);
+            if new_level == old_level {
+                return;
+            }
+            let state = world.get_block(coord);
+            if state != Id::AIR {
+                state.block().light_updated(world, coord, old_level, new_level);
+            }
+        });
+    }
+}
+
+/// The amount of light that is lost when light enters the block at `coord`.
+/// This is never less than 1 so that light always falls off with distance.
+pub fn light_filter(world: &VoxelWorld, coord: Coord) -> u8 {
+    let state = world.get_block(coord);
+    state.block().light_args(world, coord, state).filter().max(1)
+}
+
+/// The amount of light emitted by the block at `coord`.
+pub fn light_emit(world: &VoxelWorld, coord: Coord) -> u8 {
+    let state = world.get_block(coord);
+    state.block().light_args(world, coord, state).emit()
+}
+
+/// Block light and sky light are propagated with the same rules.
+/// The only difference is where the light comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LightChannel {
+    /// Light emitted by blocks.
+    Block,
+    /// Light from the sky. Every block at or above the top of its column has full sky light.
+    Sky,
+}
+
+impl LightChannel {
+    pub fn get(self, world: &VoxelWorld, coord: Coord) -> u8 {
+        match self {
+            LightChannel::Block => world.get_block_light(coord),
+            LightChannel::Sky => world.get_sky_light(coord),
+        }
+    }
+
+    fn set(self, world: &mut VoxelWorld, coord: Coord, level: u8, tracker: &mut LightTracker) {
+        let change = match self {
+            LightChannel::Block => world.set_block_light_silent(coord, level),
+            LightChannel::Sky => world.set_sky_light_silent(coord, level),
+        };
+        tracker.record(coord, change);
+    }
+
+    /// The level of light that originates at `coord`.
+    pub fn source(self, world: &VoxelWorld, coord: Coord) -> u8 {
+        match self {
+            LightChannel::Block => light_emit(world, coord),
+            LightChannel::Sky => if coord.y >= world.height(coord.x, coord.z) {
+                15
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Updates the block light around `coord` after the block at `coord` has changed.
+/// Light is only propagated within the bounds of the world (which includes the padded area).
+pub fn update_block_light(world: &mut VoxelWorld, coord: Coord) {
+    update_light(world, LightChannel::Block, [coord]);
+}
+
+/// Updates the light of a channel after the blocks (or sky exposure) at `coords` have changed.
+/// The light at each coordinate is removed and then recalculated from its source and its neighbors.
+pub fn update_light<It: IntoIterator<Item = Coord>>(world: &mut VoxelWorld, channel: LightChannel, coords: It) {
+    let coords: Vec<Coord> = coords.into_iter().filter(|&coord| world.bounds().contains(coord)).collect();
+    let mut tracker = LightTracker::default();
+    let mut remove_queue = VecDeque::new();
+    let mut add_queue = VecDeque::new();
+    coords.iter().cloned().for_each(|coord| {
+        let old_level = channel.get(world, coord);
+        if old_level != 0 {
+            channel.set(world, coord, 0, &mut tracker);
+            remove_queue.push_back((coord, old_level));
+        }
+    });
+    remove_light(world, channel, &mut remove_queue, &mut add_queue, &mut tracker);
+    coords.iter().cloned().for_each(|coord| {
+        let source = channel.source(world, coord);
+        if source != 0 {
+            channel.set(world, coord, source, &mut tracker);
+            add_queue.push_back(coord);
+        }
+        // Neighboring light needs to flow back in through the new block.
+        Direction::iter().for_each(|dir| {
+            let Some(adj) = coord.checked_neighbor(dir) else {
+                return;
+            };
+            if channel.get(world, adj) != 0 {
+                add_queue.push_back(adj);
+            }
+        });
+    });
+    spread_light(world, channel, &mut add_queue, &mut tracker);
+    tracker.notify(world);
+}
+
+/// Darkness propagation. Every coordinate in the `remove_queue` has already been set to 0.
+/// Neighbors that were lit by the removed light are set to 0, and neighbors that are
+/// lit by some other source are added to the `add_queue` so that they can relight the area.
+fn remove_light(
+    world: &mut VoxelWorld,
+    channel: LightChannel,
+    remove_queue: &mut VecDeque<(Coord, u8)>,
+    add_queue: &mut VecDeque<Coord>,
+    tracker: &mut LightTracker,
+) {
+    while let Some((coord, level)) = remove_queue.pop_front() {
+        for dir in Direction::iter() {
+            let Some(adj) = coord.checked_neighbor(dir) else {
+                continue;
+            };
+            if !world.bounds().contains(adj) {
+                continue;
+            }
+            let adj_level = channel.get(world, adj);
+            if adj_level == 0 {
+                continue;
+            }
+            if adj_level < level {
+                channel.set(world, adj, 0, tracker);
+                remove_queue.push_back((adj, adj_level));
+                // Sources keep their own light.
+                let source = channel.source(world, adj);
+                if source != 0 {
+                    channel.set(world, adj, source, tracker);
+                    add_queue.push_back(adj);
+                }
+            } else {
+                add_queue.push_back(adj);
+            }
+        }
+    }
+}
+
+/// Light propagation. Spreads the light from each coordinate in the `add_queue`
+/// into its neighbors, filtered by the neighbor's [LightArgs](crate::core::voxel::lighting::lightargs::LightArgs).
+fn spread_light(
+    world: &mut VoxelWorld,
+    channel: LightChannel,
+    add_queue: &mut VecDeque<Coord>,
+    tracker: &mut LightTracker,
+) {
+    while let Some(coord) = add_queue.pop_front() {
+        let level = channel.get(world, coord);
+        if level <= 1 {
+            continue;
+        }
+        for dir in Direction::iter() {
+            let Some(adj) = coord.checked_neighbor(dir) else {
+                continue;
+            };
+            if !world.bounds().contains(adj) {
+                continue;
+            }
+            let next_level = level.saturating_sub(light_filter(world, adj));
+            if next_level > channel.get(world, adj) {
+                channel.set(world, adj, next_level, tracker);
+                add_queue.push_back(adj);
+            }
+        }
+    }
+}
+
+/// Updates the sky light after the block at `coord` has changed.
+/// `old_height` is the height of the column before the change.
+/// Every block in the column between the old height and the new height has
+/// changed exposure to the sky, so they all need to be updated.
+pub fn update_sky_light(world: &mut VoxelWorld, coord: Coord, old_height: i32) {
+    let new_height = world.height(coord.x, coord.z);
+    let (low, high) = (old_height.min(new_height), old_height.max(new_height));
+    let column = (low..high).map(|y| Coord::new(coord.x, y, coord.z));
+    if (low..high).contains(&coord.y) {
+        update_light(world, LightChannel::Sky, column);
+    } else {
+        update_light(world, LightChannel::Sky, column.chain([coord]));
+    }
+}
+
+/// Recalculates the sky light of an entire chunk from its heightmap.
+/// Everything below the top of each column is darkened, then relit from the sky above and from the sides.
+///
+/// Only the blocks that can light a dark block are used as seeds: the block on top of each column, the
+/// blocks beside a column that are open to the sky, and the lit blocks of the neighboring chunks that face
+/// a dark column. Darkness is only spread from the edges of the chunk, since that's the only place the old
+/// light could have reached the neighboring chunks from.
+pub fn compute_chunk_sky_light(world: &mut VoxelWorld, chunk_x: i32, chunk_z: i32) {
+    const SIDES: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    let channel = LightChannel::Sky;
+    let block_x = chunk_x * 16;
+    let block_z = chunk_z * 16;
+    let in_chunk = |x: i32, z: i32| (block_x..block_x + 16).contains(&x) && (block_z..block_z + 16).contains(&z);
+    let mut tracker = LightTracker::default();
+    let mut remove_queue = VecDeque::new();
+    let mut add_queue = VecDeque::new();
+    for z in block_z..block_z + 16 {
+        for x in block_x..block_x + 16 {
+            let edge = x == block_x || x == block_x + 15 || z == block_z || z == block_z + 15;
+            for y in WORLD_BOTTOM..world.height(x, z) {
+                let coord = Coord::new(x, y, z);
+                let old_level = channel.get(world, coord);
+                if old_level != 0 {
+                    channel.set(world, coord, 0, &mut tracker);
+                    if edge {
+                        remove_queue.push_back((coord, old_level));
+                    }
+                }
+            }
+        }
+    }
+    remove_light(world, channel, &mut remove_queue, &mut add_queue, &mut tracker);
+    for z in block_z..block_z + 16 {
+        for x in block_x..block_x + 16 {
+            let height = world.height(x, z);
+            if height == WORLD_BOTTOM {
+                continue;
+            }
+            if height < WORLD_TOP {
+                add_queue.push_back(Coord::new(x, height, z));
+            }
+            for (offset_x, offset_z) in SIDES {
+                let (side_x, side_z) = (x + offset_x, z + offset_z);
+                if !world.bounds().contains(Coord::new(side_x, WORLD_BOTTOM, side_z)) {
+                    continue;
+                }
+                // The columns in this chunk are dark below their top, so only the part above it can spread light.
+                let bottom = if in_chunk(side_x, side_z) {
+                    world.height(side_x, side_z)
+                } else {
+                    WORLD_BOTTOM
+                };
+                for y in bottom..height {
+                    let side = Coord::new(side_x, y, side_z);
+                    if channel.get(world, side) > 1 {
+                        add_queue.push_back(side);
+                    }
+                }
+            }
+        }
+    }
+    spread_light(world, channel, &mut add_queue, &mut tracker);
+    tracker.notify(world);
+    if let Some(chunk) = world.get_chunk_mut((chunk_x, chunk_z)) {
+        chunk.sky_light_state = SkyLightState::Valid;
+    }
+}
+
+/// Calculates the sky light of a chunk that isn't in a world from its heightmap, so that it can be done on the
+/// [ChunkWorkers](crate::core::voxel::world::worker::ChunkWorkers). The chunk is lit as if the chunks around it
+/// were dark, and [merge_chunk_sky_light] adds the light that crosses its edges once it's in the world.
+///
+/// Returns false and leaves the chunk unchanged if any of its blocks don't have [Block::state_light_args](crate::core::voxel::block::Block::state_light_args).
+pub fn compute_detached_sky_light(chunk: &mut Chunk) -> bool {
+    let index = |x: i32, y: i32, z: i32| (x | z << 4) as usize | (y as usize) << 8;
+    let heights: Vec<i32> = (0..256).map(|i| chunk.heightmap.height(i & 0xF, i >> 4)).collect();
+    let mut light = vec![0u8; 256 * WORLD_HEIGHT];
+    let mut filters = HashMap::<Id, u8>::new();
+    let mut queue = VecDeque::new();
+    for z in 0..16 {
+        for x in 0..16 {
+            let height = heights[index(x, 0, z)];
+            (height..WORLD_HEIGHT as i32).for_each(|y| light[index(x, y, z)] = 15);
+            if height == 0 {
+                continue;
+            }
+            if height < WORLD_HEIGHT as i32 {
+                queue.push_back((x, height, z));
+            }
+            // The open part of a column beside this one can light this column from the side.
+            for (side_x, side_z) in [(x - 1, z), (x + 1, z), (x, z - 1), (x, z + 1)] {
+                if (0..16).contains(&side_x) && (0..16).contains(&side_z) {
+                    (heights[index(side_x, 0, side_z)]..height).for_each(|y| queue.push_back((side_x, y, side_z)));
+                }
+            }
+        }
+    }
+    while let Some((x, y, z)) = queue.pop_front() {
+        let level = light[index(x, y, z)];
+        if level <= 1 {
+            continue;
+        }
+        for (adj_x, adj_y, adj_z) in [(x - 1, y, z), (x + 1, y, z), (x, y - 1, z), (x, y + 1, z), (x, y, z - 1), (x, y, z + 1)] {
+            if !(0..16).contains(&adj_x) || !(0..16).contains(&adj_z) || !(0..WORLD_HEIGHT as i32).contains(&adj_y) {
+                continue;
+            }
+            let state = chunk.sections[adj_y as usize >> 4].get_block(Coord::new(adj_x, adj_y, adj_z));
+            let filter = match filters.get(&state) {
+                Some(&filter) => filter,
+                None => {
+                    let Some(args) = state.block().state_light_args(state) else {
+                        return false;
+                    };
+                    *filters.entry(state).or_insert(args.filter().max(1))
+                }
+            };
+            let next_level = level.saturating_sub(filter);
+            let adj_index = index(adj_x, adj_y, adj_z);
+            if next_level > light[adj_index] {
+                light[adj_index] = next_level;
+                queue.push_back((adj_x, adj_y, adj_z));
+            }
+        }
+    }
+    chunk.sections.iter_mut().enumerate().for_each(|(i, section)| {
+        (0..4096u16).for_each(|section_index| {
+            let coord = Section::coord(section_index);
+            section.set_sky_light(coord, light[section_index as usize | i << 12]);
+        });
+    });
+    chunk.sky_light_state = SkyLightState::Detached;
+    true
+}
+
+/// Adds the light that crosses the edges of a chunk whose sky light was calculated by [compute_detached_sky_light].
+/// The neighboring chunks were lit while this chunk was unloaded, which is as if it was open to the sky, so the
+/// light that they got from it is removed first. Only the blocks along the edges of the chunk are visited.
+pub fn merge_chunk_sky_light(world: &mut VoxelWorld, chunk_x: i32, chunk_z: i32) {
+    let channel = LightChannel::Sky;
+    let block_x = chunk_x * 16;
+    let block_z = chunk_z * 16;
+    // Each column on the edge of the chunk and the column that it faces in the neighboring chunk.
+    let edges = (0..16).flat_map(|i| [
+        ((block_x, block_z + i), (block_x - 1, block_z + i)),
+        ((block_x + 15, block_z + i), (block_x + 16, block_z + i)),
+        ((block_x + i, block_z), (block_x + i, block_z - 1)),
+        ((block_x + i, block_z + 15), (block_x + i, block_z + 16)),
+    ]).filter(|&(_, (x, z))| world.bounds().contains(Coord::new(x, WORLD_BOTTOM, z)))
+    .map(|((x, z), (side_x, side_z))| ((x, z, world.height(x, z)), (side_x, side_z, world.height(side_x, side_z))))
+    .collect::<Vec<_>>();
+    let mut tracker = LightTracker::default();
+    let mut remove_queue = VecDeque::new();
+    let mut add_queue = VecDeque::new();
+    // The light below the top of both columns is the light that came in through this chunk.
+    edges.iter().for_each(|&((_, _, height), (side_x, side_z, side_height))| {
+        (WORLD_BOTTOM..height.min(side_height)).for_each(|y| {
+            let side = Coord::new(side_x, y, side_z);
+            let old_level = channel.get(world, side);
+            if old_level != 0 {
+                channel.set(world, side, 0, &mut tracker);
+                remove_queue.push_back((side, old_level));
+            }
+        });
+    });
+    remove_light(world, channel, &mut remove_queue, &mut add_queue, &mut tracker);
+    // Light only needs to cross an edge where the column on the other side is dark.
+    edges.iter().for_each(|&((x, z, height), (side_x, side_z, side_height))| {
+        (WORLD_BOTTOM..height.max(side_height)).for_each(|y| {
+            let (coord, side) = (Coord::new(x, y, z), Coord::new(side_x, y, side_z));
+            if y < side_height && channel.get(world, coord) > 1 {
+                add_queue.push_back(coord);
+            }
+            if y < height && channel.get(world, side) > 1 {
+                add_queue.push_back(side);
+            }
+        });
+    });
+    spread_light(world, channel, &mut add_queue, &mut tracker);
+    tracker.notify(world);
+    if let Some(chunk) = world.get_chunk_mut((chunk_x, chunk_z)) {
+        chunk.sky_light_state = SkyLightState::Valid;
+    }
+}
+
+/// Recalculates the block light of an entire chunk from the blocks in it that emit light.
+pub fn compute_chunk_block_light(world: &mut VoxelWorld, chunk_x: i32, chunk_z: i32) {
+    let Some(chunk) = world.get_chunk((chunk_x, chunk_z)) else {
+        return;
+    };
+    let block_offset = chunk.block_offset;
+    let occupied: Vec<i32> = chunk.sections.iter().enumerate()
+        .filter(|(_, section)| section.blocks.is_some())
+        .map(|(i, _)| block_offset.y + i as i32 * 16)
+        .collect();
+    let mut coords = Vec::new();
+    for section_y in occupied {
+        for y in section_y..section_y + 16 {
+            for z in block_offset.z..block_offset.z + 16 {
+                for x in block_offset.x..block_offset.x + 16 {
+                    let coord = Coord::new(x, y, z);
+                    if light_emit(world, coord) != 0 {
+                        coords.push(coord);
+                    }
+                }
+            }
+        }
+    }
+    update_light(world, LightChannel::Block, coords);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{block::Block, blocks, blockstate::BlockState, lighting::lightargs::LightArgs, testing::{self, open_world, TestDirectory}};
+
+    use super::*;
+
+    /// Emits full block light.
+    struct LampTestBlock;
+    impl Block for LampTestBlock {
+        fn name(&self) -> &str {
+            "test_lamp"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_lamp)
+        }
+
+        fn state_light_args(&self, state: Id) -> Option<LightArgs> {
+            Some(LightArgs::new(15, 15))
+        }
+    }
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(LampTestBlock);
+        });
+    }
+
+    #[test]
+    fn block_light_test() {
+        register_test_blocks();
+        let lamp = blockstate!(test_lamp).register();
+        let wall = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("block_light_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // Crosses the chunk boundary at x = 16.
+        world.set_block((14, 0, 0), lamp);
+        assert_eq!(world.get_block_light((14, 0, 0)), 15);
+        assert_eq!(world.get_block_light((15, 0, 0)), 14);
+        assert_eq!(world.get_block_light((16, 0, 0)), 13);
+        assert_eq!(world.get_block_light((14, 3, 2)), 10);
+        assert_eq!(world.get_block_light((0, 0, 0)), 1);
+        // Walls block light, so the light has to go around.
+        world.set_block((16, 0, 0), wall);
+        assert_eq!(world.get_block_light((16, 0, 0)), 0);
+        assert_eq!(world.get_block_light((17, 0, 0)), 10);
+        world.set_block((16, 0, 0), Id::AIR);
+        assert_eq!(world.get_block_light((17, 0, 0)), 12);
+        // Removing the lamp removes all of its light.
+        world.set_block((14, 0, 0), Id::AIR);
+        assert_eq!(world.get_block_light((14, 0, 0)), 0);
+        assert_eq!(world.get_block_light((16, 0, 0)), 0);
+        assert_eq!(world.get_block_light((14, 3, 2)), 0);
+    }
+
+    #[test]
+    fn compute_sky_light_test() {
+        register_test_blocks();
+        let wall = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("compute_sky_light_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        world.defer_lighting = true;
+        for z in 1..=3 {
+            for x in 1..=3 {
+                world.set_block((x, 10, z), wall);
+            }
+        }
+        world.defer_lighting = false;
+        assert_eq!(world.get_chunk((0, 0)).unwrap().sky_light_state, SkyLightState::Stale);
+        compute_chunk_sky_light(&mut world, 0, 0);
+        assert_eq!(world.get_chunk((0, 0)).unwrap().sky_light_state, SkyLightState::Valid);
+        assert_eq!(world.get_sky_light((2, 10, 2)), 0);
+        assert_eq!(world.get_sky_light((2, 9, 2)), 13);
+        assert_eq!(world.get_sky_light((3, 9, 2)), 14);
+        assert_eq!(world.get_sky_light((2, 0, 2)), 13);
+        assert_eq!(world.get_sky_light((2, 11, 2)), 15);
+        assert_eq!(world.get_sky_light((4, 0, 2)), 15);
+    }
+
+    #[test]
+    fn detached_sky_light_test() {
+        register_test_blocks();
+        let wall = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("detached_sky_light_test");
+        // A roof over all of chunk (1, 0) and half of chunk (0, 0), so most of the light under it comes from the neighboring chunks.
+        let roof = (8..32).flat_map(|x| (0..16).map(move |z| Coord::new(x, 10, z))).collect::<Vec<_>>();
+        let area = (-2..32).flat_map(|x| (-2..18).flat_map(move |z| (-2..12).map(move |y| Coord::new(x, y, z)))).collect::<Vec<_>>();
+        let expected = {
+            let mut world = open_world(&directory, 2, (0, 0, 0));
+            roof.iter().for_each(|&coord| {
+                world.set_block(coord, wall);
+            });
+            let expected = area.iter().map(|&coord| world.get_sky_light(coord)).collect::<Vec<_>>();
+            // Changing a block while lighting is deferred makes the chunks stale, so their light is calculated again when they're loaded.
+            world.defer_lighting = true;
+            [(8, 0, 0), (16, 0, 0)].into_iter().for_each(|coord| {
+                world.set_block(coord, wall);
+                world.set_block(coord, Id::AIR);
+            });
+            assert_eq!(world.get_chunk((1, 0)).unwrap().sky_light_state, SkyLightState::Stale);
+            world.save_world().expect("Failed to save the world");
+            expected
+        };
+        assert_eq!(expected[area.iter().position(|&coord| coord == Coord::new(16, 9, 8)).unwrap()], 14);
+        for workers in [false, true] {
+            let mut world = VoxelWorld::open_headless(&directory, 2, (0, 0, 0), None).expect("Failed to open the world");
+            if workers {
+                world.enable_workers(1);
+            }
+            world.flush_load_queue();
+            assert_eq!(world.get_chunk((0, 0)).unwrap().sky_light_state, SkyLightState::Valid);
+            assert_eq!(world.get_chunk((1, 0)).unwrap().sky_light_state, SkyLightState::Valid);
+            area.iter().zip(expected.iter()).for_each(|(&coord, &level)| {
+                assert_eq!(world.get_sky_light(coord), level, "Wrong sky light at {coord}");
+            });
+            world.disable_workers();
+        }
+        // A chunk that is lit without its neighbors is dark wherever the sky doesn't reach it directly.
+        let mut chunk = Chunk::new(Coord::new(16, WORLD_BOTTOM, 0));
+        roof.iter().filter(|coord| coord.x >= 16).for_each(|&coord| {
+            chunk.set_block(coord, wall);
+        });
+        assert!(compute_detached_sky_light(&mut chunk));
+        assert_eq!(chunk.sky_light_state, SkyLightState::Detached);
+        let section = &chunk.sections[(10 - WORLD_BOTTOM) as usize >> 4];
+        assert_eq!(section.get_sky_light(Coord::new(0, 11, 0)), 15);
+        assert_eq!(section.get_sky_light(Coord::new(0, 9, 0)), 0);
+    }
+
+    #[test]
+    fn light_dirty_test() {
+        register_test_blocks();
+        let lamp = blockstate!(test_lamp).register();
+        let directory = TestDirectory::new("light_dirty_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // Only the sections that have the block in their lightmap padding are neighbors.
+        let neighbors: Vec<Coord> = VoxelWorld::light_neighbor_sections(Coord::new(15, 0, 7)).collect();
+        assert_eq!(neighbors.len(), 2);
+        assert!(neighbors.contains(&Coord::new(1, 0, 0)) && neighbors.contains(&Coord::new(0, -1, 0)));
+        assert_eq!(VoxelWorld::light_neighbor_sections(Coord::new(7, 7, 7)).count(), 0);
+        world.get_section_mut(Coord::new(2, 0, 0)).unwrap().light_dirty.mark_clean();
+        // The light doesn't reach the section or the edge of the section next to it.
+        world.set_block((14, 0, 0), lamp);
+        assert!(!world.get_section(Coord::new(2, 0, 0)).unwrap().light_dirty.dirty());
+        world.set_block((30, 0, 0), lamp);
+        assert!(world.get_section(Coord::new(2, 0, 0)).unwrap().light_dirty.dirty());
+    }
+
+    #[test]
+    fn sky_light_test() {
+        register_test_blocks();
+        let wall = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("sky_light_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        assert_eq!(world.get_sky_light((0, 0, 0)), 15);
+        // A single block only casts a shadow on itself since light comes in from the sides.
+        world.set_block((0, 10, 0), wall);
+        assert_eq!(world.get_sky_light((0, 10, 0)), 0);
+        assert_eq!(world.get_sky_light((0, 9, 0)), 14);
+        assert_eq!(world.get_sky_light((0, 0, 0)), 14);
+        assert_eq!(world.get_sky_light((0, 11, 0)), 15);
+        // A roof casts a deeper shadow.
+        for z in -1..=1 {
+            for x in -1..=1 {
+                world.set_block((x, 10, z), wall);
+            }
+        }
+        assert_eq!(world.get_sky_light((0, 9, 0)), 13);
+        assert_eq!(world.get_sky_light((1, 9, 0)), 14);
+        assert_eq!(world.get_chunk((0, 0)).unwrap().sky_light_state, SkyLightState::Valid);
+        // Opening the roof lets the sky back in.
+        world.set_block((0, 10, 0), Id::AIR);
+        assert_eq!(world.get_sky_light((0, 9, 0)), 15);
+        assert_eq!(world.get_sky_light((0, 0, 0)), 15);
+        assert_eq!(world.get_sky_light((1, 9, 0)), 14);
+    }
+}
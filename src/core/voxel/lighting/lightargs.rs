@@ -6,9 +6,9 @@ pub struct LightArgs(u8);
 
 impl LightArgs {
     
-    pub fn new(filter: u8, emit: u8) -> LightArgs {
-        let filter = filter.min(15);
-        let emit = emit.min(15);
+    pub const fn new(filter: u8, emit: u8) -> LightArgs {
+        let filter = if filter > 15 { 15 } else { filter };
+        let emit = if emit > 15 { 15 } else { emit };
         LightArgs(filter | emit << 4)
     }
 
@@ -1,2 +1,3 @@
 #![allow(unused)]
-pub mod lightargs;
\ No newline at end of file
+pub mod lightargs;
+pub mod engine;
\ No newline at end of file
@@ -21,6 +21,10 @@ pub mod statevalue;
 pub mod blocklayer;
 pub mod procgen;
 pub mod level_of_detail;
+pub mod fluid;
+pub mod entity;
+#[cfg(test)]
+pub(crate) mod testing;
 
 #[cfg(test)]
 mod tests {
@@ -42,6 +42,11 @@ impl Occluder {
     }
     
     
+    /// Determines if every face is [OcclusionShape::Full].
+    pub fn is_full(&self) -> bool {
+        Direction::iter().all(|dir| self.face(dir).is_full())
+    }
+
     pub fn face(&self, face: Direction) -> &OcclusionShape {
         match face {
             Direction::NegX => &self.neg_x,
@@ -1,13 +1,37 @@
 use rollgrid::rollgrid2d::Bounds2D;
 
-use crate::{core::voxel::world::{VoxelWorld, WORLD_BOTTOM, WORLD_TOP}, prelude::Id};
+use crate::core::error::*;
+use crate::core::io::{Readable, Writeable};
+use crate::core::voxel::{blockstate::BlockState, tag::{Array, Tag}};
+use crate::{core::voxel::{coord::Coord, world::{chunk::Chunk, VoxelWorld, WORLD_BOTTOM, WORLD_TOP}}, prelude::Id};
 
+/// A generator that writes directly into the [VoxelWorld].
+///
+/// Because it needs the world, a `WorldGenerator` always runs on the main thread, even when the world has
+/// [ChunkWorkers](crate::core::voxel::world::worker::ChunkWorkers). Only a [ChunkGenerator] is run off of the main thread.
 pub trait WorldGenerator: Send + Sync {
     fn generate_chunk(&mut self, world: &mut VoxelWorld, area: Bounds2D) {}
 }
 
 impl WorldGenerator for () {}
 
+/// A generator that writes into a detached [Chunk] so that it can run on a worker thread.
+/// The blocks of the chunk are moved into the world, then occlusion and light are calculated on the main thread.
+pub trait ChunkGenerator: Send + Sync {
+    fn generate_chunk(&self, chunk: &mut Chunk, area: Bounds2D) {}
+}
+
+impl ChunkGenerator for () {}
+
+/// A generator that can be stored in the [WorldMetadata](crate::core::voxel::world::metadata::WorldMetadata)
+/// and recreated later. A generator that is recreated from the same seed and config must generate the same terrain.
+pub trait GeneratorConfig: Sized {
+    /// The name that the generator is stored under.
+    const NAME: &'static str;
+    fn to_config(&self) -> Tag;
+    fn from_config(seed: u64, config: &Tag) -> Result<Self>;
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Hash)]
 pub struct FlatLayer {
     height: u16,
@@ -49,6 +73,46 @@ impl<L: Into<FlatLayer>> FromIterator<L> for FlatWorldGenerator {
     }
 }
 
+/// The config is an array of maps with the `height` of each layer and the `block` (a [BlockState] written as bytes).
+impl GeneratorConfig for FlatWorldGenerator {
+    const NAME: &'static str = "flat";
+
+    fn to_config(&self) -> Tag {
+        let layers = self.layers.iter().map(|layer| {
+            let mut block = Vec::new();
+            let blockstate: &BlockState = &*layer.block;
+            blockstate.write_to(&mut block).expect("Failed to write to Vec.");
+            let mut map = hashbrown::HashMap::new();
+            map.insert(String::from("height"), Tag::from(layer.height));
+            map.insert(String::from("block"), Tag::from(block));
+            Tag::from(map)
+        }).collect::<Vec<Tag>>();
+        Tag::from(layers)
+    }
+
+    /// Panics if a layer's block isn't registered.
+    fn from_config(_seed: u64, config: &Tag) -> Result<Self> {
+        let Tag::Array(array) = config else {
+            return Err(Error::InvalidBinaryFormat);
+        };
+        let layers = match array.as_ref() {
+            Array::Empty => return Ok(Self::new(Vec::new())),
+            Array::Tag(layers) => layers,
+            _ => return Err(Error::InvalidBinaryFormat),
+        };
+        layers.iter().map(|layer| {
+            let (&Tag::U16(height), Tag::Array(block)) = (&layer["height"], &layer["block"]) else {
+                return Err(Error::InvalidBinaryFormat);
+            };
+            let Array::U8(block) = block.as_ref() else {
+                return Err(Error::InvalidBinaryFormat);
+            };
+            let state = BlockState::read_from(&mut block.as_slice())?;
+            Ok(FlatLayer::new(height, state.register()))
+        }).collect()
+    }
+}
+
 impl WorldGenerator for FlatWorldGenerator {
     fn generate_chunk(&mut self, world: &mut VoxelWorld, area: Bounds2D) {
         for (x, z) in area.iter() {
@@ -62,4 +126,19 @@ impl WorldGenerator for FlatWorldGenerator {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl ChunkGenerator for FlatWorldGenerator {
+    fn generate_chunk(&self, chunk: &mut Chunk, area: Bounds2D) {
+        for (x, z) in area.iter() {
+            let mut y_bottom = WORLD_BOTTOM;
+            for layer in self.layers.iter() {
+                let y_top = (y_bottom + layer.height as i32).min(WORLD_TOP);
+                for y in y_bottom..y_top {
+                    chunk.set_block(Coord::new(x, y, z), layer.block);
+                }
+                y_bottom = y_top;
+            }
+        }
+    }
+}
@@ -6,6 +6,10 @@ use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use crate::{core::{error::*, voxel::region::sectoroffset::SectorOffset}, prelude::{write_zeros, Readable, WriteExt, Writeable}};
 use super::{header::RegionHeader, regioncoord::RegionCoord, sectormanager::SectorManager, sectoroffset::BlockSize, timestamp::Timestamp};
 
+/// Set on the length of a chunk's data when the length is followed by the chunk's data version.
+/// Chunks that were written before data versions existed don't have this flag, and have a data version of `0`.
+const VERSIONED_FLAG: u32 = 0x8000_0000;
+
 pub struct RegionFile {
     sector_manager: SectorManager,
     /// Used for both reading and writing. The file is kept locked while the region is open.
@@ -99,6 +103,11 @@ impl RegionFile {
     }
 
     pub fn read<'a, C: Into<RegionCoord>, R, F: FnMut(&mut GzDecoder<Take<BufReader<&'a mut File>>>) -> Result<R>>(&'a mut self, coord: C, mut read: F) -> Result<R> {
+        self.read_versioned(coord, |reader, _| read(reader))
+    }
+
+    /// Reads a chunk along with the data version that it was written with.
+    pub fn read_versioned<'a, C: Into<RegionCoord>, R, F: FnMut(&mut GzDecoder<Take<BufReader<&'a mut File>>>, u16) -> Result<R>>(&'a mut self, coord: C, mut read: F) -> Result<R> {
         let coord: RegionCoord = coord.into();
         let sector = self.header.offsets[coord];
         if sector.is_empty() {
@@ -106,9 +115,21 @@ impl RegionFile {
         }
         let mut reader = BufReader::new(&mut self.io);
         reader.seek(SeekFrom::Start(sector.file_offset()))?;
-        let length = u32::read_from(&mut reader)?;
+        let (length, version) = read_length_and_version(&mut reader)?;
         let mut decoder = GzDecoder::new(reader.take(length as u64));
-        read(&mut decoder)
+        read(&mut decoder, version)
+    }
+
+    /// Gets the data version that a chunk was written with without reading the chunk.
+    pub fn get_version<C: Into<RegionCoord>>(&mut self, coord: C) -> Result<u16> {
+        let coord: RegionCoord = coord.into();
+        let sector = self.header.offsets[coord];
+        if sector.is_empty() {
+            return Err(Error::ChunkNotFound);
+        }
+        self.io.seek(SeekFrom::Start(sector.file_offset()))?;
+        let (_, version) = read_length_and_version(&mut self.io)?;
+        Ok(version)
     }
 
     pub fn read_value<C: Into<RegionCoord>, T: Readable>(&mut self, coord: C) -> Result<T> {
@@ -118,7 +139,16 @@ impl RegionFile {
         self.read(coord, read_inner)
     }
 
-    pub fn write<C: Into<RegionCoord>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, mut write: F) -> Result<()> {
+    pub fn write<C: Into<RegionCoord>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, write: F) -> Result<()> {
+        self.write_inner(coord, None, write)
+    }
+
+    /// Writes a chunk along with the data version that it was written with (see [RegionFile::read_versioned]).
+    pub fn write_versioned<C: Into<RegionCoord>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, version: u16, write: F) -> Result<()> {
+        self.write_inner(coord, Some(version), write)
+    }
+
+    fn write_inner<C: Into<RegionCoord>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, version: Option<u16>, mut write: F) -> Result<()> {
         let coord: RegionCoord = coord.into();
         self.write_buffer.get_mut().clear();
         self.write_buffer.seek(SeekFrom::Start(0))?;
@@ -126,7 +156,9 @@ impl RegionFile {
         write(&mut encoder)?;
         encoder.finish()?;
         let length = self.write_buffer.get_ref().len() as u64;
-        let padded_size = padded_size(length + 4);
+        // The length is followed by the version if there is one.
+        let head_size = if version.is_some() { 6 } else { 4 };
+        let padded_size = padded_size(length + head_size);
         if padded_size > BlockSize::MAX_BLOCK_COUNT as u64 * 4096 {
             return Err(Error::ChunkTooLarge);
         }
@@ -138,9 +170,14 @@ impl RegionFile {
         let mut writer = BufWriter::new(&mut self.io);
         writer.seek(SeekFrom::Start(new_sector.file_offset()))?;
         let len = length as u32;
-        len.write_to(&mut writer)?;
+        if let Some(version) = version {
+            (len | VERSIONED_FLAG).write_to(&mut writer)?;
+            version.write_to(&mut writer)?;
+        } else {
+            len.write_to(&mut writer)?;
+        }
         writer.write_all(self.write_buffer.get_ref().as_slice())?;
-        write_zeros(&mut writer, pad_size(length as u64 + 4))?;
+        write_zeros(&mut writer, pad_size(length as u64 + head_size))?;
         writer.seek(SeekFrom::Start(coord.sector_offset()))?;
         new_sector.write_to(&mut writer)?;
         writer.flush()?;
@@ -154,9 +191,18 @@ impl RegionFile {
         })
     }
 
-    pub fn write_timestamped<C: Into<RegionCoord>, Ts: Into<Timestamp>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, timestamp: Ts, mut write: F) -> Result<()> {
+    pub fn write_timestamped<C: Into<RegionCoord>, Ts: Into<Timestamp>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, timestamp: Ts, write: F) -> Result<()> {
+        self.write_timestamped_inner(coord, timestamp, None, write)
+    }
+
+    /// Writes a chunk with a timestamp and the data version that it was written with.
+    pub fn write_timestamped_versioned<C: Into<RegionCoord>, Ts: Into<Timestamp>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, timestamp: Ts, version: u16, write: F) -> Result<()> {
+        self.write_timestamped_inner(coord, timestamp, Some(version), write)
+    }
+
+    fn write_timestamped_inner<C: Into<RegionCoord>, Ts: Into<Timestamp>, F: FnMut(&mut GzEncoder<&mut Cursor<Vec<u8>>>) -> Result<()>>(&mut self, coord: C, timestamp: Ts, version: Option<u16>, write: F) -> Result<()> {
         let coord: RegionCoord = coord.into();
-        let allocation = self.write(coord, write)?;
+        let allocation = self.write_inner(coord, version, write)?;
         let timestamp: Timestamp = timestamp.into();
         self.header.timestamps[coord] = timestamp;
         let mut writer = BufWriter::new(&mut self.io);
@@ -186,6 +232,15 @@ impl RegionFile {
 } 
 
 
+/// Reads the length of a chunk's data and the data version that follows it (or `0` if it was written without one).
+fn read_length_and_version<R: Read>(reader: &mut R) -> Result<(u32, u16)> {
+    let length = u32::read_from(reader)?;
+    if length & VERSIONED_FLAG == 0 {
+        return Ok((length, 0));
+    }
+    Ok((length & !VERSIONED_FLAG, u16::read_from(reader)?))
+}
+
 fn pad_size(length: u64) -> u64 {
     4096 - (length & 4095) & 4095
 }
@@ -203,6 +258,7 @@ mod tests {
     use hashbrown::HashMap;
     use rand::rngs::OsRng;
 
+    use crate::core::voxel::testing::TestDirectory;
     use crate::prelude::{Array, Tag};
 
     use super::*;
@@ -251,4 +307,34 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn versioned_test() -> Result<()> {
+        let directory = TestDirectory::new("versioned_test");
+        let path = directory.path().join("versioned_test.rg");
+        {
+            let mut region = RegionFile::create(&path)?;
+            region.write((0, 0), |writer| {
+                Tag::from("legacy").write_to(writer)?;
+                Ok(())
+            })?;
+            region.write_timestamped_versioned((1, 0), Timestamp::new(1234), 7, |writer| {
+                Tag::from("versioned").write_to(writer)?;
+                Ok(())
+            })?;
+        }
+        let mut region = RegionFile::open(&path)?;
+        assert_eq!(region.get_version((0, 0))?, 0);
+        assert_eq!(region.get_version((1, 0))?, 7);
+        assert!(matches!(region.get_version((2, 0)), Err(Error::ChunkNotFound)));
+        assert_eq!(region.get_timestamp((1, 0)), Timestamp::new(1234));
+        let (tag, version) = region.read_versioned((1, 0), |reader, version| {
+            Ok((Tag::read_from(reader)?, version))
+        })?;
+        assert_eq!(tag, Tag::from("versioned"));
+        assert_eq!(version, 7);
+        let tag: Tag = region.read_value((0, 0))?;
+        assert_eq!(tag, Tag::from("legacy"));
+        Ok(())
+    }
+}
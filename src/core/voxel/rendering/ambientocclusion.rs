@@ -0,0 +1,164 @@
+#![allow(unused)]
+use bevy::math::Vec3;
+
+use crate::core::voxel::{axis::Axis, coord::Coord, direction::Direction};
+
+/// The solidity of a block and the 26 blocks around it, indexed `[x][y][z]` where `[1][1][1]` is the block itself.
+pub type Neighborhood = [[[bool; 3]; 3]; 3];
+
+/// The ambient occlusion level of a corner that has nothing around it.
+pub const AO_UNOCCLUDED: u8 = 3;
+
+/// Calculates the ambient occlusion level of a corner of a face of the block in the center of the neighborhood.
+/// Levels range from `0` (fully occluded) to [AO_UNOCCLUDED].
+///
+/// `corner` points from the center of the block toward the corner. Each component is `-1`, `0`, or `1`,
+/// and the component along the axis of the face is ignored. A component of `0` means that the corner is
+/// on the middle of an edge, so there is no neighbor on that side.
+///
+/// The level comes from the two blocks that are beside the corner and the block that is diagonal to it,
+/// all in the layer in front of the face. When both sides are solid, the corner is fully occluded no matter
+/// what the diagonal block is.
+pub fn vertex_ao(neighborhood: &Neighborhood, face: Direction, corner: Coord) -> u8 {
+    let front = Coord::from(face);
+    let (side_a, side_b) = match face.axis() {
+        Axis::X => (Coord::new(0, corner.y.signum(), 0), Coord::new(0, 0, corner.z.signum())),
+        Axis::Y => (Coord::new(corner.x.signum(), 0, 0), Coord::new(0, 0, corner.z.signum())),
+        Axis::Z => (Coord::new(corner.x.signum(), 0, 0), Coord::new(0, corner.y.signum(), 0)),
+    };
+    let zero = Coord::new(0, 0, 0);
+    let solid = |offset: Coord| {
+        let coord = front + offset;
+        neighborhood[(coord.x + 1) as usize][(coord.y + 1) as usize][(coord.z + 1) as usize]
+    };
+    let side1 = side_a != zero && solid(side_a);
+    let side2 = side_b != zero && solid(side_b);
+    if side1 && side2 {
+        return 0;
+    }
+    let diagonal = side_a != zero && side_b != zero && solid(side_a + side_b);
+    AO_UNOCCLUDED - side1 as u8 - side2 as u8 - diagonal as u8
+}
+
+/// Converts an ambient occlusion level to the value that is stored in the
+/// [AO_ATTRIB](super::voxelmesh::AO_ATTRIB).
+pub fn ao_value(level: u8) -> f32 {
+    level.min(AO_UNOCCLUDED) as f32 / AO_UNOCCLUDED as f32
+}
+
+/// Converts a value from the [AO_ATTRIB](super::voxelmesh::AO_ATTRIB) back to a level.
+pub fn ao_level(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * AO_UNOCCLUDED as f32).round() as u8
+}
+
+/// Determines if a quad should be split along its other diagonal.
+/// The corners are in the order `[(-u, -v), (+u, -v), (-u, +v), (+u, +v)]` and the quad is
+/// normally split between `(+u, -v)` and `(-u, +v)`.
+///
+/// Interpolating across the wrong diagonal makes a single dark corner bleed across the whole quad,
+/// so the quad is split along the diagonal whose corners are brighter.
+pub fn flip_quad(levels: [u8; 4]) -> bool {
+    levels[0] + levels[3] > levels[1] + levels[2]
+}
+
+/// Finds the face that a normal is most aligned with, or `None` if the normal is zero.
+pub fn face_direction(normal: Vec3) -> Option<Direction> {
+    let abs = normal.abs();
+    if abs.max_element() < 0.5 {
+        return None;
+    }
+    Some(if abs.x >= abs.y && abs.x >= abs.z {
+        if normal.x > 0.0 { Direction::PosX } else { Direction::NegX }
+    } else if abs.y >= abs.z {
+        if normal.y > 0.0 { Direction::PosY } else { Direction::NegY }
+    } else {
+        if normal.z > 0.0 { Direction::PosZ } else { Direction::NegZ }
+    })
+}
+
+/// Looks for pairs of triangles that make up a quad in groups of 6 indices and splits each quad along
+/// the other diagonal when that diagonal is brighter (see [flip_quad]). The winding of the triangles is kept.
+/// Groups that aren't two triangles sharing an edge are left alone.
+pub fn fix_quad_diagonals(indices: &mut [u32], ao: &[f32]) {
+    let level = |index: u32| ao_level(ao[index as usize]);
+    indices.chunks_exact_mut(6).for_each(|quad| {
+        let first = [quad[0], quad[1], quad[2]];
+        let second = [quad[3], quad[4], quad[5]];
+        if first.iter().filter(|index| second.contains(index)).count() != 2 {
+            return;
+        }
+        let (Some(a), Some(b)) = (
+            (0..3).find(|&i| !second.contains(&first[i])),
+            (0..3).find(|&i| !first.contains(&second[i])),
+        ) else {
+            return;
+        };
+        let (opposite_a, x, y) = (first[a], first[(a + 1) % 3], first[(a + 2) % 3]);
+        let opposite_b = second[b];
+        // The shared edge must be walked in the opposite direction by the second triangle.
+        if second[(b + 1) % 3] != y || second[(b + 2) % 3] != x {
+            return;
+        }
+        if flip_quad([level(opposite_a), level(x), level(y), level(opposite_b)]) {
+            quad.copy_from_slice(&[opposite_a, x, opposite_b, opposite_b, y, opposite_a]);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighborhood(solid: &[(i32, i32, i32)]) -> Neighborhood {
+        let mut neighborhood = Neighborhood::default();
+        solid.iter().for_each(|&(x, y, z)| {
+            neighborhood[(x + 1) as usize][(y + 1) as usize][(z + 1) as usize] = true;
+        });
+        neighborhood
+    }
+
+    #[test]
+    fn vertex_ao_test() {
+        let corner = Coord::new(1, 1, 1);
+        assert_eq!(vertex_ao(&neighborhood(&[]), Direction::PosY, corner), 3);
+        // Blocks below the top face don't matter.
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 0, 0), (1, -1, 1)]), Direction::PosY, corner), 3);
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 0)]), Direction::PosY, corner), 2);
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 1)]), Direction::PosY, corner), 2);
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 0), (1, 1, 1)]), Direction::PosY, corner), 1);
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 0), (0, 1, 1)]), Direction::PosY, corner), 0);
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 0), (0, 1, 1), (1, 1, 1)]), Direction::PosY, corner), 0);
+        // The other corners of the face aren't affected by the side blocks.
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 0), (0, 1, 1)]), Direction::PosY, Coord::new(-1, 1, -1)), 3);
+        assert_eq!(vertex_ao(&neighborhood(&[(1, 1, 0), (0, 1, 1)]), Direction::PosY, Coord::new(-1, 1, 1)), 2);
+        // The component along the face is ignored.
+        assert_eq!(vertex_ao(&neighborhood(&[(-1, 0, 1)]), Direction::NegX, Coord::new(1, 0, 1)), 2);
+        // Corners on the middle of an edge only look at one side.
+        assert_eq!(vertex_ao(&neighborhood(&[(0, 0, -1), (1, 0, -1)]), Direction::NegZ, Coord::new(1, 0, -1)), 2);
+    }
+
+    #[test]
+    fn flip_quad_test() {
+        assert!(!flip_quad([3, 3, 3, 3]));
+        assert!(!flip_quad([0, 3, 3, 3]));
+        assert!(flip_quad([3, 0, 3, 3]));
+        assert!(flip_quad([3, 3, 2, 3]));
+    }
+
+    #[test]
+    fn fix_quad_diagonals_test() {
+        let ao = [1.0, 0.0, 1.0, 1.0];
+        let mut indices = [0, 2, 1, 1, 2, 3];
+        fix_quad_diagonals(&mut indices, &ao);
+        assert_eq!(indices, [0, 2, 3, 3, 1, 0]);
+        // A dark corner that is off of the shared diagonal is left alone.
+        let ao = [0.0, 1.0, 1.0, 1.0];
+        let mut indices = [0, 2, 1, 1, 2, 3];
+        fix_quad_diagonals(&mut indices, &ao);
+        assert_eq!(indices, [0, 2, 1, 1, 2, 3]);
+        // Triangles that don't share an edge in opposite directions are left alone.
+        let mut indices = [0, 1, 2, 1, 2, 3];
+        fix_quad_diagonals(&mut indices, &[0.0, 1.0, 1.0, 1.0]);
+        assert_eq!(indices, [0, 1, 2, 1, 2, 3]);
+    }
+}
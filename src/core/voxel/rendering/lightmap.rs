@@ -0,0 +1,152 @@
+#![allow(unused)]
+use crate::core::voxel::{direction::Direction, faces::Faces};
+
+use super::voxelmaterial::VoxelMaterial;
+
+/// The number of light values in a section.
+pub const LIGHTMAP_SIZE: usize = 4096;
+/// The number of light values in one face of padding around a section.
+pub const LIGHTMAP_PAD_SIZE: usize = 256;
+
+/// The lightmap for a single section, along with the light of the 6 faces of blocks that border it.
+/// The padding is needed so that faces on the edge of the section can be lit by the blocks in front of them.
+#[derive(Debug, Clone)]
+pub struct SectionLightmap {
+    pub lightmap: Vec<f32>,
+    pub pads: Faces<Vec<f32>>,
+}
+
+/// The index into the 4096 entry lightmap (matches [Section::index](crate::core::voxel::world::section::Section::index)).
+pub const fn lightmap_index(x: usize, y: usize, z: usize) -> usize {
+    x | z << 4 | y << 8
+}
+
+/// The index into a 256 entry padding face.
+/// The two coordinates are the local coordinates on the axes that the face does not point along,
+/// in `x, y, z` order with the first coordinate in the low bits:
+/// * X faces: `y | z << 4`
+/// * Y faces: `x | z << 4`
+/// * Z faces: `x | y << 4`
+pub const fn pad_index(a: usize, b: usize) -> usize {
+    a | b << 4
+}
+
+/// Converts a light level in the range `0..=15` to a light value in the range `0.0..=1.0`.
+pub fn light_value(level: u8) -> f32 {
+    level.min(15) as f32 / 15.0
+}
+
+/// Assembles the lightmap buffers for a section.
+/// `levels` is the 4096 light levels of the section in lightmap order (see [lightmap_index]).
+/// `pads` are the 256 light levels of the blocks that border each face of the section in pad order (see [pad_index]).
+pub fn assemble_lightmap(levels: &[u8], pads: &Faces<[u8; LIGHTMAP_PAD_SIZE]>) -> SectionLightmap {
+    assert_eq!(levels.len(), LIGHTMAP_SIZE, "Lightmap must have {LIGHTMAP_SIZE} levels.");
+    let convert = |pad: &[u8; LIGHTMAP_PAD_SIZE]| -> Vec<f32> {
+        pad.iter().cloned().map(light_value).collect()
+    };
+    SectionLightmap {
+        lightmap: levels.iter().cloned().map(light_value).collect(),
+        pads: Faces::new(
+            convert(&pads.neg_x),
+            convert(&pads.neg_y),
+            convert(&pads.neg_z),
+            convert(&pads.pos_x),
+            convert(&pads.pos_y),
+            convert(&pads.pos_z),
+        ),
+    }
+}
+
+/// Gathers the padding levels for a section using a function that returns the light level
+/// at a local coordinate. Local coordinates range from -1 to 16 on each axis, where -1 and 16
+/// are in the neighboring sections.
+pub fn gather_pads<F: FnMut(i32, i32, i32) -> u8>(mut light: F) -> Faces<[u8; LIGHTMAP_PAD_SIZE]> {
+    let mut pads = Faces::new(
+        [0u8; LIGHTMAP_PAD_SIZE],
+        [0u8; LIGHTMAP_PAD_SIZE],
+        [0u8; LIGHTMAP_PAD_SIZE],
+        [0u8; LIGHTMAP_PAD_SIZE],
+        [0u8; LIGHTMAP_PAD_SIZE],
+        [0u8; LIGHTMAP_PAD_SIZE],
+    );
+    for b in 0..16 {
+        for a in 0..16 {
+            let i = pad_index(a, b);
+            let (a, b) = (a as i32, b as i32);
+            pads.neg_x[i] = light(-1, a, b);
+            pads.pos_x[i] = light(16, a, b);
+            pads.neg_y[i] = light(a, -1, b);
+            pads.pos_y[i] = light(a, 16, b);
+            pads.neg_z[i] = light(a, b, -1);
+            pads.pos_z[i] = light(a, b, 16);
+        }
+    }
+    pads
+}
+
+impl VoxelMaterial {
+    /// Replace the lightmap buffers of this material.
+    pub fn apply_lightmap(&mut self, lightmap: SectionLightmap) {
+        let SectionLightmap { lightmap, pads } = lightmap;
+        self.lightmap = lightmap;
+        self.lightmap_pad_neg_x = pads.neg_x;
+        self.lightmap_pad_neg_y = pads.neg_y;
+        self.lightmap_pad_neg_z = pads.neg_z;
+        self.lightmap_pad_pos_x = pads.pos_x;
+        self.lightmap_pad_pos_y = pads.pos_y;
+        self.lightmap_pad_pos_z = pads.pos_z;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_lightmap_test() {
+        let levels: Vec<u8> = (0..LIGHTMAP_SIZE).map(|i| (i % 16) as u8).collect();
+        // Each face gets a unique level so that we can tell them apart.
+        let pads = gather_pads(|x, y, z| {
+            match (x, y, z) {
+                (-1, _, _) => 1,
+                (16, _, _) => 2,
+                (_, -1, _) => 3,
+                (_, 16, _) => 4,
+                (_, _, -1) => 5,
+                (_, _, 16) => 6,
+                _ => panic!("Interior coordinate requested for padding: {x} {y} {z}"),
+            }
+        });
+        let lightmap = assemble_lightmap(&levels, &pads);
+        assert_eq!(lightmap.lightmap.len(), LIGHTMAP_SIZE);
+        assert_eq!(lightmap.lightmap[lightmap_index(0, 0, 0)], 0.0);
+        assert_eq!(lightmap.lightmap[lightmap_index(15, 3, 7)], 1.0);
+        assert_eq!(lightmap.lightmap[lightmap_index(5, 0, 0)], light_value(5));
+        Direction::iter().for_each(|dir| {
+            let expect = light_value(match dir {
+                Direction::NegX => 1,
+                Direction::PosX => 2,
+                Direction::NegY => 3,
+                Direction::PosY => 4,
+                Direction::NegZ => 5,
+                Direction::PosZ => 6,
+            });
+            assert_eq!(lightmap.pads[dir].len(), LIGHTMAP_PAD_SIZE);
+            assert!(lightmap.pads[dir].iter().all(|&value| value == expect));
+        });
+    }
+
+    #[test]
+    fn pad_layout_test() {
+        let pads = gather_pads(|x, y, z| {
+            if x == 16 {
+                (y + z) as u8 % 16
+            } else {
+                0
+            }
+        });
+        assert_eq!(pads.pos_x[pad_index(3, 0)], 3);
+        assert_eq!(pads.pos_x[pad_index(3, 4)], 7);
+        assert_eq!(pads.neg_x[pad_index(3, 4)], 0);
+    }
+}
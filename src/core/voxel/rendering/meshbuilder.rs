@@ -11,6 +11,7 @@ pub struct MeshBuilder {
     pub normals: Vec<Vec3>,
     pub uvs: Vec<Vec2>,
     pub texindices: Vec<u32>,
+    pub ao: Vec<f32>,
     pub indices: Vec<u32>,
     pub offset: Option<Vec3>,
     pub orientation: Option<Orientation>,
@@ -62,6 +63,7 @@ impl MeshBuilder {
         self.normals.extend(mesh_data.normals.iter().cloned());
         self.uvs.extend(mesh_data.uvs.iter().cloned());
         self.texindices.extend(mesh_data.texindices.iter().cloned());
+        self.extend_ao(&mesh_data.ao, mesh_data.vertices.len());
         self.indices.extend(mesh_data.indices.iter().map(|&i| start_index + i));
     }
 
@@ -91,6 +93,7 @@ impl MeshBuilder {
         }
         self.uvs.extend(mesh_data.uvs.iter().cloned());
         self.texindices.extend(mesh_data.texindices.iter().cloned());
+        self.extend_ao(&mesh_data.ao, mesh_data.vertices.len());
         if invert_indices {
             self.indices.extend(mesh_data.indices.iter().rev().map(|&i| start_index + i));
         } else {
@@ -139,6 +142,7 @@ impl MeshBuilder {
             }
             self.uvs.extend(uvs.into_iter().map(U::into));
             self.texindices.extend(texture_indices.into_iter().map(T::into));
+            self.ao.resize(self.vertices.len(), 1.0);
             if invert_indices {
                 self.indices.extend(indices.iter().cloned().rev().map(|i| start_index + i));
             } else {
@@ -168,15 +172,27 @@ impl MeshBuilder {
             self.normals.extend(normals.into_iter().map(N::into));
             self.uvs.extend(uvs.into_iter().map(U::into));
             self.texindices.extend(texture_indices.into_iter().map(T::into));
+            self.ao.resize(self.vertices.len(), 1.0);
             self.indices.extend(indices.iter().cloned().map(|i| start_index + i));
         }
 
+    /// Pushes the ambient occlusion of pushed mesh data, or unoccluded values if the mesh data has none.
+    fn extend_ao(&mut self, ao: &[f32], vertex_count: usize) {
+        if ao.len() == vertex_count {
+            self.ao.extend(ao.iter().cloned());
+        } else {
+            self.ao.extend((0..vertex_count).map(|_| 1.0));
+        }
+    }
+
     pub fn push_to_mesh(self, mesh: &mut Mesh) {
         use super::voxelmesh::*;
+        let ao = vertex_ao_values(self.ao, self.vertices.len());
         mesh.insert_attribute(POSITION_ATTRIB.clone(), self.vertices);
         mesh.insert_attribute(NORMAL_ATTRIB.clone(), self.normals);
         mesh.insert_attribute(UV_ATTRIB.clone(), self.uvs);
         mesh.insert_attribute(TEXINDEX_ATTRIB.clone(), self.texindices);
+        mesh.insert_attribute(AO_ATTRIB.clone(), ao);
         mesh.insert_indices(Indices::U32(self.indices));
     }
 
@@ -186,6 +202,7 @@ impl MeshBuilder {
             normals: self.normals,
             uvs: self.uvs,
             texindices: self.texindices,
+            ao: self.ao,
             indices: self.indices,
         }
     }
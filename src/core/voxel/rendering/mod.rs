@@ -3,6 +3,9 @@ pub mod voxelmaterial;
 pub mod voxelmesh;
 pub mod meshbuilder;
 pub mod color;
+pub mod lightmap;
+pub mod sectionmesh;
+pub mod ambientocclusion;
 
 use bevy::{asset::Handle, render::mesh::Mesh};
 
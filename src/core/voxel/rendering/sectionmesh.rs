@@ -0,0 +1,815 @@
+#![allow(unused)]
+use bevy::math::{vec2, vec3, Vec3};
+
+use crate::core::voxel::{axis::Axis, blocklayer::BlockLayer, blocks::Id, coord::Coord, direction::Direction, level_of_detail::LOD, world::occlusion::Occlusion};
+use crate::prelude::{Orientation, Rotation};
+
+use super::ambientocclusion::{ao_value, face_direction, fix_quad_diagonals, flip_quad, vertex_ao, Neighborhood};
+use super::{meshbuilder::MeshBuilder, voxelmesh::MeshData};
+
+/// The width of a section snapshot, including the one block border on each side.
+pub const SNAPSHOT_WIDTH: usize = 18;
+/// The number of blocks in a section snapshot.
+pub const SNAPSHOT_SIZE: usize = SNAPSHOT_WIDTH * SNAPSHOT_WIDTH * SNAPSHOT_WIDTH;
+
+/// A read-only copy of everything that is needed to mesh a section.
+/// This includes the blocks in a one block border around the section so that blocks on the edge
+/// of the section can look at their neighbors.
+///
+/// Snapshots don't reference the [VoxelWorld](crate::core::voxel::world::VoxelWorld), so they can be meshed on any thread.
+#[derive(Debug, Clone)]
+pub struct SectionSnapshot {
+    /// The section coordinate of the section.
+    pub section_coord: Coord,
+    states: Box<[Id]>,
+    occlusion: Box<[Occlusion]>,
+    orientations: Box<[Orientation]>,
+    /// Blocks that are meshed by the greedy mesher instead of [Block::push_mesh](crate::core::voxel::block::Block::push_mesh).
+    greedy: Box<[bool]>,
+    /// Blocks that cast ambient occlusion onto the faces around them.
+    solid: Box<[bool]>,
+    /// The [BlockLayer] that each block is meshed on.
+    layers: Box<[BlockLayer]>,
+    /// The faces of the section that border a section meshed at a different [LOD], indexed by [Direction].
+    seams: [bool; 6],
+}
+
+impl SectionSnapshot {
+    /// Creates an empty (all air) snapshot.
+    pub fn new(section_coord: Coord) -> Self {
+        Self {
+            section_coord,
+            states: (0..SNAPSHOT_SIZE).map(|_| Id::AIR).collect(),
+            occlusion: (0..SNAPSHOT_SIZE).map(|_| Occlusion::UNOCCLUDED).collect(),
+            orientations: (0..SNAPSHOT_SIZE).map(|_| Orientation::UNORIENTED).collect(),
+            greedy: (0..SNAPSHOT_SIZE).map(|_| false).collect(),
+            solid: (0..SNAPSHOT_SIZE).map(|_| false).collect(),
+            layers: (0..SNAPSHOT_SIZE).map(|_| BlockLayer::Base).collect(),
+            seams: [false; 6],
+        }
+    }
+
+    /// The block coordinate of the minimum corner of the section (not including the border).
+    pub fn block_offset(&self) -> Coord {
+        self.section_coord * 16
+    }
+
+    /// Returns the index of a block coordinate, or `None` if it's outside of the snapshot.
+    fn index(&self, coord: Coord) -> Option<usize> {
+        let local = coord - self.block_offset();
+        if local.x < -1 || local.y < -1 || local.z < -1
+        || local.x > 16 || local.y > 16 || local.z > 16 {
+            return None;
+        }
+        let (x, y, z) = ((local.x + 1) as usize, (local.y + 1) as usize, (local.z + 1) as usize);
+        Some(x + z * SNAPSHOT_WIDTH + y * SNAPSHOT_WIDTH * SNAPSHOT_WIDTH)
+    }
+
+    /// Determines if the block coordinate is in the section or its border.
+    pub fn contains(&self, coord: Coord) -> bool {
+        self.index(coord).is_some()
+    }
+
+    /// Sets the state, occlusion, and orientation of the block at a block coordinate.
+    /// Coordinates outside of the snapshot are ignored.
+    pub fn set(&mut self, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
+        let Some(index) = self.index(coord) else {
+            return;
+        };
+        self.states[index] = state;
+        self.occlusion[index] = occlusion;
+        self.orientations[index] = orientation;
+        self.greedy[index] = false;
+        self.solid[index] = false;
+        self.layers[index] = BlockLayer::Base;
+    }
+
+    /// Marks a block to be greedy meshed. The block must return the texture indices of its faces from
+    /// [Block::greedy_faces](crate::core::voxel::block::Block::greedy_faces).
+    pub fn set_greedy(&mut self, coord: Coord, greedy: bool) {
+        if let Some(index) = self.index(coord) {
+            self.greedy[index] = greedy;
+        }
+    }
+
+    /// Marks a block as solid for ambient occlusion.
+    pub fn set_solid(&mut self, coord: Coord, solid: bool) {
+        if let Some(index) = self.index(coord) {
+            self.solid[index] = solid;
+        }
+    }
+
+    /// Sets the [BlockLayer] that a block is meshed on.
+    pub fn set_layer(&mut self, coord: Coord, layer: BlockLayer) {
+        if let Some(index) = self.index(coord) {
+            self.layers[index] = layer;
+        }
+    }
+
+    /// Marks a face of the section as bordering a section that is meshed at a different [LOD].
+    /// Faces on that side of the section are never culled against the border, so the meshes of the two
+    /// sections overlap instead of leaving cracks between them.
+    pub fn set_seam(&mut self, face: Direction, seam: bool) {
+        self.seams[face as usize] = seam;
+    }
+
+    pub fn seam(&self, face: Direction) -> bool {
+        self.seams[face as usize]
+    }
+
+    /// Returns the block at a block coordinate. Coordinates outside of the snapshot are air.
+    pub fn get_block(&self, coord: Coord) -> Id {
+        self.index(coord).map(|index| self.states[index]).unwrap_or(Id::AIR)
+    }
+
+    pub fn occlusion(&self, coord: Coord) -> Occlusion {
+        self.index(coord).map(|index| self.occlusion[index]).unwrap_or(Occlusion::UNOCCLUDED)
+    }
+
+    pub fn orientation(&self, coord: Coord) -> Orientation {
+        self.index(coord).map(|index| self.orientations[index]).unwrap_or(Orientation::UNORIENTED)
+    }
+
+    pub fn greedy(&self, coord: Coord) -> bool {
+        self.index(coord).map(|index| self.greedy[index]).unwrap_or(false)
+    }
+
+    pub fn solid(&self, coord: Coord) -> bool {
+        self.index(coord).map(|index| self.solid[index]).unwrap_or(false)
+    }
+
+    pub fn layer(&self, coord: Coord) -> BlockLayer {
+        self.index(coord).map(|index| self.layers[index]).unwrap_or(BlockLayer::Base)
+    }
+
+    /// Returns the solidity of the 3x3x3 blocks centered on a block coordinate.
+    pub fn neighborhood(&self, coord: Coord) -> Neighborhood {
+        self.scaled_neighborhood(coord, 1)
+    }
+
+    /// Returns the solidity of the 3x3x3 cells of width `scale` around the cell that starts at a block coordinate.
+    /// Each cell is sampled at its minimum corner.
+    pub fn scaled_neighborhood(&self, coord: Coord, scale: i32) -> Neighborhood {
+        let mut neighborhood = Neighborhood::default();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    let offset = Coord::new(x as i32 - 1, y as i32 - 1, z as i32 - 1) * scale;
+                    neighborhood[x][y][z] = self.solid(coord + offset);
+                }
+            }
+        }
+        neighborhood
+    }
+
+    /// Finds the dominant block in a cube of `scale` blocks starting at `min`.
+    /// The dominant block is the first block of the most common non-air state, as long as at least half of the
+    /// cube isn't air or one of its horizontal layers is completely filled. The filled layer keeps thin surfaces
+    /// like the ground from disappearing at coarse levels, where they only fill a small part of the cube.
+    fn dominant_block(&self, min: Coord, scale: i32) -> Option<Coord> {
+        let mut counts: Vec<(Id, u32, Coord)> = Vec::new();
+        let mut filled = 0;
+        let mut layer_filled = false;
+        for y in 0..scale {
+            let mut layer = 0;
+            for z in 0..scale {
+                for x in 0..scale {
+                    let coord = min + Coord::new(x, y, z);
+                    let state = self.get_block(coord);
+                    if state == Id::AIR {
+                        continue;
+                    }
+                    filled += 1;
+                    layer += 1;
+                    if let Some(count) = counts.iter_mut().find(|(id, _, _)| *id == state) {
+                        count.1 += 1;
+                    } else {
+                        counts.push((state, 1, coord));
+                    }
+                }
+            }
+            layer_filled |= layer == scale * scale;
+        }
+        if filled * 2 < scale * scale * scale && !layer_filled {
+            return None;
+        }
+        // max_by_key returns the last maximum, so reverse to keep the first.
+        counts.into_iter().rev().max_by_key(|(_, count, _)| *count).map(|(_, _, coord)| coord)
+    }
+
+    /// Determines if every border block in front of a face of a cell is solid.
+    fn border_solid(&self, min: Coord, scale: i32, face: Direction) -> bool {
+        let layer = if face.to_vec3().max_element() > 0.0 {
+            scale
+        } else {
+            -1
+        };
+        (0..scale).all(|a| (0..scale).all(|b| {
+            let offset = match face.axis() {
+                Axis::X => Coord::new(layer, a, b),
+                Axis::Y => Coord::new(a, layer, b),
+                Axis::Z => Coord::new(a, b, layer),
+            };
+            self.solid(min + offset)
+        }))
+    }
+
+    /// Creates a copy of the snapshot for meshing at a coarser [LOD].
+    /// 
+    /// Every cube of [LOD::scale] blocks is filled with its dominant block (see [SectionSnapshot::dominant_block]), then
+    /// occlusion is recalculated so that faces are hidden by solid blocks. Faces on the edge of the section
+    /// are only hidden if every border block in front of their cell is solid, and never on seams,
+    /// since the neighboring section may be meshed at a different level.
+    pub fn downsample(&self, level_of_detail: LOD) -> SectionSnapshot {
+        let scale = level_of_detail.scale();
+        let block_offset = self.block_offset();
+        let mut result = self.clone();
+        for cell_y in (0..16).step_by(scale as usize) {
+            for cell_z in (0..16).step_by(scale as usize) {
+                for cell_x in (0..16).step_by(scale as usize) {
+                    let min = block_offset + Coord::new(cell_x, cell_y, cell_z);
+                    let source = self.dominant_block(min, scale);
+                    for y in 0..scale {
+                        for z in 0..scale {
+                            for x in 0..scale {
+                                let coord = min + Coord::new(x, y, z);
+                                let Some(source) = source else {
+                                    result.set(coord, Id::AIR, Occlusion::UNOCCLUDED, Orientation::UNORIENTED);
+                                    continue;
+                                };
+                                result.set(coord, self.get_block(source), Occlusion::UNOCCLUDED, self.orientation(source));
+                                result.set_greedy(coord, self.greedy(source));
+                                result.set_solid(coord, self.solid(source));
+                                result.set_layer(coord, self.layer(source));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for cell_y in (0..16).step_by(scale as usize) {
+            for cell_z in (0..16).step_by(scale as usize) {
+                for cell_x in (0..16).step_by(scale as usize) {
+                    let min = block_offset + Coord::new(cell_x, cell_y, cell_z);
+                    let mut edge_hidden = [false; 6];
+                    Direction::iter().for_each(|dir| {
+                        edge_hidden[dir as usize] = !self.seam(dir) && self.border_solid(min, scale, dir);
+                    });
+                    for y in 0..scale {
+                        for z in 0..scale {
+                            for x in 0..scale {
+                                let coord = min + Coord::new(x, y, z);
+                                let Some(index) = result.index(coord) else {
+                                    continue;
+                                };
+                                let state = result.states[index];
+                                if state == Id::AIR {
+                                    continue;
+                                }
+                                let transparent = result.layers[index].transparent();
+                                let mut occlusion = Occlusion::UNOCCLUDED;
+                                Direction::iter().for_each(|dir| {
+                                    let neighbor = coord + dir;
+                                    let local = neighbor - block_offset;
+                                    let inside = local.x >= 0 && local.y >= 0 && local.z >= 0
+                                        && local.x < 16 && local.y < 16 && local.z < 16;
+                                    let hidden = if inside {
+                                        // Transparent blocks are also hidden by the same block.
+                                        result.solid(neighbor)
+                                        || (transparent && result.get_block(neighbor).block_id() == state.block_id())
+                                    } else {
+                                        edge_hidden[dir as usize]
+                                    };
+                                    if hidden {
+                                        occlusion.hide(dir);
+                                    }
+                                });
+                                result.occlusion[index] = occlusion;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Shows the faces of the blocks on the edge of the section that face a seam.
+    fn show_seam_faces(&mut self) {
+        let block_offset = self.block_offset();
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let coord = block_offset + Coord::new(x, y, z);
+                    let Some(index) = self.index(coord) else {
+                        continue;
+                    };
+                    Direction::iter().for_each(|dir| {
+                        let on_edge = match dir {
+                            Direction::NegX => x == 0,
+                            Direction::NegY => y == 0,
+                            Direction::NegZ => z == 0,
+                            Direction::PosX => x == 15,
+                            Direction::PosY => y == 15,
+                            Direction::PosZ => z == 15,
+                        };
+                        if on_edge && self.seams[dir as usize] {
+                            self.occlusion[index].show(dir);
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The meshes of a section, one for each [BlockLayer] that has any blocks in the section.
+#[derive(Debug, Default, Clone)]
+pub struct SectionMesh {
+    /// The mesh of each layer, sorted by layer.
+    pub layers: Vec<(BlockLayer, MeshData)>,
+}
+
+impl SectionMesh {
+    pub fn layer(&self, layer: BlockLayer) -> Option<&MeshData> {
+        self.layers.iter().find(|(mesh_layer, _)| *mesh_layer == layer).map(|(_, mesh)| mesh)
+    }
+
+    /// Removes the mesh of a layer, returning an empty mesh if the layer has no blocks.
+    pub fn take_layer(&mut self, layer: BlockLayer) -> MeshData {
+        self.layers.iter()
+            .position(|(mesh_layer, _)| *mesh_layer == layer)
+            .map(|index| self.layers.remove(index).1)
+            .unwrap_or_default()
+    }
+}
+
+/// Builds the meshes of a section from a snapshot, one for each [BlockLayer].
+/// The vertices are relative to the section's block offset.
+/// 
+/// At coarser levels of detail, the snapshot is downsampled (see [SectionSnapshot::downsample]) and each cell
+/// is meshed by its dominant block as if it were a single block, then scaled up to the size of the cell.
+pub fn mesh_section(snapshot: &SectionSnapshot, level_of_detail: LOD) -> SectionMesh {
+    let prepared;
+    let snapshot = if level_of_detail != LOD::Level0 {
+        prepared = snapshot.downsample(level_of_detail);
+        &prepared
+    } else if snapshot.seams.contains(&true) {
+        let mut seamed = snapshot.clone();
+        seamed.show_seam_faces();
+        prepared = seamed;
+        &prepared
+    } else {
+        snapshot
+    };
+    let block_offset = snapshot.block_offset();
+    let mut layers = Vec::<BlockLayer>::new();
+    for y in 0..16 {
+        for z in 0..16 {
+            for x in 0..16 {
+                let block_coord = block_offset + Coord::new(x, y, z);
+                let layer = snapshot.layer(block_coord);
+                if snapshot.get_block(block_coord) != Id::AIR && !layers.contains(&layer) {
+                    layers.push(layer);
+                }
+            }
+        }
+    }
+    layers.sort();
+    SectionMesh {
+        layers: layers.into_iter()
+            .map(|layer| (layer, mesh_layer(snapshot, level_of_detail, layer)))
+            .filter(|(_, mesh)| !mesh.vertices.is_empty())
+            .collect(),
+    }
+}
+
+/// Meshes the blocks of a single [BlockLayer] in a prepared snapshot.
+fn mesh_layer(snapshot: &SectionSnapshot, level_of_detail: LOD, layer: BlockLayer) -> MeshData {
+    let scale = level_of_detail.scale();
+    let block_offset = snapshot.block_offset();
+    MeshBuilder::build(|build| {
+        for y in (0..16).step_by(scale as usize) {
+            for z in (0..16).step_by(scale as usize) {
+                for x in (0..16).step_by(scale as usize) {
+                    let block_coord = block_offset + Coord::new(x, y, z);
+                    let state = snapshot.get_block(block_coord);
+                    if state == Id::AIR || snapshot.greedy(block_coord) || snapshot.layer(block_coord) != layer {
+                        continue;
+                    }
+                    let orientation = snapshot.orientation(block_coord);
+                    let occlusion = cell_occlusion(snapshot, block_coord, scale);
+                    let center = vec3(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5);
+                    build.set_offset(center);
+                    build.set_orientation(orientation);
+                    let (vertex_start, index_start) = (build.vertices.len(), build.indices.len());
+                    state.block().push_mesh(build, level_of_detail, snapshot, block_coord, state, occlusion, orientation);
+                    if build.vertices.len() > vertex_start {
+                        apply_block_ao(build, &snapshot.scaled_neighborhood(block_coord, scale), center, vertex_start, index_start);
+                        if scale > 1 {
+                            scale_cell(build, vec3(x as f32, y as f32, z as f32), scale as f32, vertex_start);
+                        }
+                    }
+                }
+            }
+        }
+        push_greedy_faces(build, snapshot, layer);
+    }).to_mesh_data()
+}
+
+/// The occlusion of a cell of width `scale` that starts at `min`. Each face is taken from the block
+/// on that side of the cell.
+fn cell_occlusion(snapshot: &SectionSnapshot, min: Coord, scale: i32) -> Occlusion {
+    if scale == 1 {
+        return snapshot.occlusion(min);
+    }
+    let max = min + Coord::new(scale - 1, scale - 1, scale - 1);
+    let mut occlusion = Occlusion::UNOCCLUDED;
+    Direction::iter().for_each(|dir| {
+        let side = if dir.to_vec3().max_element() > 0.0 {
+            max
+        } else {
+            min
+        };
+        if snapshot.occlusion(side).hidden(dir) {
+            occlusion.hide(dir);
+        }
+    });
+    occlusion
+}
+
+/// Scales the vertices that a block pushed at the minimum corner of a cell up to the size of the cell.
+/// The UVs are scaled as well so that the texture still tiles once per block.
+fn scale_cell(build: &mut MeshBuilder, min: Vec3, scale: f32, vertex_start: usize) {
+    for i in vertex_start..build.vertices.len() {
+        build.vertices[i] = min + (build.vertices[i] - min) * scale;
+        build.uvs[i] *= scale;
+    }
+}
+
+/// Calculates the ambient occlusion of the vertices that a block pushed, then fixes the diagonals of its quads.
+/// Only vertices that lie on the surface of the block's cube are occluded, since the neighborhood says nothing
+/// about the space inside of the block.
+fn apply_block_ao(build: &mut MeshBuilder, neighborhood: &Neighborhood, center: Vec3, vertex_start: usize, index_start: usize) {
+    let sign = |c: f32| if c > 0.01 {
+        1
+    } else if c < -0.01 {
+        -1
+    } else {
+        0
+    };
+    for i in vertex_start..build.vertices.len() {
+        let Some(face) = face_direction(build.normals[i]) else {
+            continue;
+        };
+        let offset = build.vertices[i] - center;
+        if (offset.dot(face.to_vec3()) - 0.5).abs() > 0.01 {
+            continue;
+        }
+        let corner = Coord::new(sign(offset.x), sign(offset.y), sign(offset.z));
+        build.ao[i] = ao_value(vertex_ao(neighborhood, face, corner));
+    }
+    fix_quad_diagonals(&mut build.indices[index_start..], &build.ao);
+}
+
+/// Maps an index along an axis of the section to a local coordinate.
+/// Negative axes count down from 15 so that every face can be walked in the direction of its UVs.
+fn grid_coord(axis: Vec3, index: i32) -> Coord {
+    let component = |c: f32| if c > 0.5 {
+        index
+    } else if c < -0.5 {
+        15 - index
+    } else {
+        0
+    };
+    Coord::new(component(axis.x), component(axis.y), component(axis.z))
+}
+
+/// Meshes the faces of the blocks that are marked as greedy, merging coplanar visible faces
+/// with the same texture and ambient occlusion into larger quads.
+/// 
+/// The quads are laid out the same way as a unit quad facing [Direction::PosY] that is rotated to face each direction,
+/// so the UVs of a merged quad are the same as the UVs of the individual faces placed side by side.
+/// The UVs of a merged quad go past 1.0, and the voxel shader wraps them so that the texture tiles once per block.
+fn push_greedy_faces(build: &mut MeshBuilder, snapshot: &SectionSnapshot, layer: BlockLayer) {
+    let block_offset = snapshot.block_offset();
+    Direction::iter().for_each(|dir| {
+        let orientation: Orientation = Rotation::new(dir, 0).into();
+        let u_axis = orientation.transform(Vec3::X).round();
+        let v_axis = orientation.transform(Vec3::Z).round();
+        let normal = dir.to_vec3();
+        let depth_axis = normal.abs();
+        for depth in 0..16 {
+            // The texture index and corner AO levels of each visible greedy face at this depth.
+            let mut mask = [None::<(u32, [u8; 4])>; 256];
+            for t in 0..16 {
+                for s in 0..16 {
+                    let coord = block_offset + grid_coord(u_axis, s) + grid_coord(v_axis, t) + grid_coord(depth_axis, depth);
+                    if !snapshot.greedy(coord) || snapshot.layer(coord) != layer || snapshot.occlusion(coord).hidden(dir) {
+                        continue;
+                    }
+                    let state = snapshot.get_block(coord);
+                    let Some(faces) = state.block().greedy_faces(state) else {
+                        continue;
+                    };
+                    let neighborhood = snapshot.neighborhood(coord);
+                    let levels = [-u_axis - v_axis, u_axis - v_axis, v_axis - u_axis, u_axis + v_axis].map(|corner| {
+                        vertex_ao(&neighborhood, dir, Coord::new(corner.x as i32, corner.y as i32, corner.z as i32))
+                    });
+                    mask[(s | t << 4) as usize] = Some((faces[dir], levels));
+                }
+            }
+            for t in 0..16usize {
+                let mut s = 0usize;
+                while s < 16 {
+                    let Some(face) = mask[s | t << 4] else {
+                        s += 1;
+                        continue;
+                    };
+                    let mut width = 1;
+                    while s + width < 16 && mask[(s + width) | t << 4] == Some(face) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    'grow: while t + height < 16 {
+                        for i in s..s + width {
+                            if mask[i | (t + height) << 4] != Some(face) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+                    for j in t..t + height {
+                        for i in s..s + width {
+                            mask[i | j << 4] = None;
+                        }
+                    }
+                    let local = grid_coord(u_axis, s as i32) + grid_coord(v_axis, t as i32) + grid_coord(depth_axis, depth);
+                    let center = vec3(local.x as f32 + 0.5, local.y as f32 + 0.5, local.z as f32 + 0.5);
+                    let corner = center + normal * 0.5 - u_axis * 0.5 - v_axis * 0.5;
+                    let (w, h) = (width as f32, height as f32);
+                    let (texindex, levels) = face;
+                    let indices: &[u32] = if flip_quad(levels) {
+                        &[0, 2, 3, 3, 1, 0]
+                    } else {
+                        &[0, 2, 1, 1, 2, 3]
+                    };
+                    build.push_exact_iter(
+                        [corner, corner + u_axis * w, corner + v_axis * h, corner + u_axis * w + v_axis * h],
+                        [normal; 4],
+                        [vec2(0.0, 0.0), vec2(w, 0.0), vec2(0.0, h), vec2(w, h)],
+                        [texindex; 4],
+                        indices,
+                    );
+                    let ao_start = build.ao.len() - 4;
+                    build.ao[ao_start..].iter_mut().zip(levels).for_each(|(ao, level)| *ao = ao_value(level));
+                    s += width;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{Vec2, Vec3};
+
+    use crate::blockstate;
+    use crate::core::voxel::{block::Block, blocks, blockstate::BlockState, faces::Faces, testing};
+
+    use super::*;
+
+    /// Pushes a single vertex at the center of every visible face.
+    struct FaceMarkerTestBlock;
+    impl Block for FaceMarkerTestBlock {
+        fn name(&self) -> &str {
+            "test_face_marker"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_face_marker)
+        }
+
+        fn push_mesh(&self, mesh_builder: &mut MeshBuilder, level_of_detail: LOD, snapshot: &SectionSnapshot, coord: Coord, state: Id, occlusion: Occlusion, orientation: Orientation) {
+            Direction::iter().for_each(|dir| {
+                if occlusion.visible(dir) {
+                    let normal = dir.to_vec3();
+                    let center = mesh_builder.offset.unwrap_or_default() + normal * 0.5;
+                    mesh_builder.push_exact_iter(
+                        [center],
+                        [normal],
+                        [Vec2::ZERO],
+                        [0u32],
+                        &[0],
+                    );
+                }
+            });
+        }
+    }
+
+    /// Greedy meshed with a different texture on each face.
+    struct GreedyTestBlock;
+    impl Block for GreedyTestBlock {
+        fn name(&self) -> &str {
+            "test_greedy"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_greedy)
+        }
+
+        fn greedy_faces(&self, state: Id) -> Option<Faces<u32>> {
+            Some(Faces::new(1, 2, 3, 4, 5, 6))
+        }
+    }
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(FaceMarkerTestBlock);
+            blocks::register_block(GreedyTestBlock);
+        });
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let mut snapshot = SectionSnapshot::new(Coord::new(1, -2, 0));
+        assert_eq!(snapshot.block_offset(), Coord::new(16, -32, 0));
+        assert!(snapshot.contains(Coord::new(15, -33, -1)));
+        assert!(snapshot.contains(Coord::new(32, -16, 16)));
+        assert!(!snapshot.contains(Coord::new(14, -32, 0)));
+        assert!(!snapshot.contains(Coord::new(16, -32, 17)));
+        assert_eq!(snapshot.get_block(Coord::new(100, 0, 0)), Id::AIR);
+        assert_eq!(snapshot.occlusion(Coord::new(15, -32, 0)), Occlusion::UNOCCLUDED);
+    }
+
+    #[test]
+    fn mesh_section_test() {
+        register_test_blocks();
+        let state = blockstate!(test_face_marker).register();
+        let mut snapshot = SectionSnapshot::new(Coord::new(0, 0, 0));
+        // Two blocks next to each other share a hidden face.
+        let mut left = Occlusion::UNOCCLUDED;
+        left.hide(Direction::PosX);
+        let mut right = Occlusion::UNOCCLUDED;
+        right.hide(Direction::NegX);
+        snapshot.set(Coord::new(3, 4, 5), state, left, Orientation::UNORIENTED);
+        snapshot.set(Coord::new(4, 4, 5), state, right, Orientation::UNORIENTED);
+        // Border blocks are not meshed.
+        snapshot.set(Coord::new(-1, 0, 0), state, Occlusion::UNOCCLUDED, Orientation::UNORIENTED);
+        let mesh = mesh_section(&snapshot, LOD::Level0).take_layer(BlockLayer::Base);
+        assert_eq!(mesh.vertices.len(), 10);
+        assert_eq!(mesh.indices.len(), 10);
+        assert!(mesh.vertices.contains(&vec3(3.0, 4.5, 5.5)));
+        assert!(mesh.vertices.contains(&vec3(5.0, 4.5, 5.5)));
+        assert!(!mesh.vertices.contains(&vec3(4.0, 4.5, 5.5)));
+        assert!(mesh.normals.iter().all(|normal| normal.length() == 1.0));
+    }
+
+    #[test]
+    fn greedy_mesh_test() {
+        register_test_blocks();
+        let state = blockstate!(test_greedy).register();
+        let mut snapshot = SectionSnapshot::new(Coord::new(0, 0, 0));
+        // A 3x2 slab of blocks, one block thick.
+        for z in 0..2 {
+            for x in 0..3 {
+                let mut occlusion = Occlusion::UNOCCLUDED;
+                if x > 0 {
+                    occlusion.hide(Direction::NegX);
+                }
+                if x < 2 {
+                    occlusion.hide(Direction::PosX);
+                }
+                if z > 0 {
+                    occlusion.hide(Direction::NegZ);
+                }
+                if z < 1 {
+                    occlusion.hide(Direction::PosZ);
+                }
+                let coord = Coord::new(x, 7, z);
+                snapshot.set(coord, state, occlusion, Orientation::UNORIENTED);
+                snapshot.set_greedy(coord, true);
+            }
+        }
+        let mesh = mesh_section(&snapshot, LOD::Level0).take_layer(BlockLayer::Base);
+        // Each of the 6 sides of the slab is a single quad.
+        assert_eq!(mesh.vertices.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        // The top of the slab covers 3x2 blocks, so the texture repeats 3 times along one axis and 2 times along the other.
+        let top = (0..mesh.vertices.len()).filter(|&i| mesh.normals[i] == Vec3::Y).collect::<Vec<_>>();
+        assert_eq!(top.len(), 4);
+        top.iter().for_each(|&i| {
+            assert_eq!(mesh.vertices[i].y, 8.0);
+            assert_eq!(mesh.texindices[i], 5);
+        });
+        let max_uv = top.iter().fold(vec2(0.0, 0.0), |max, &i| max.max(mesh.uvs[i]));
+        assert_eq!(max_uv.x * max_uv.y, 6.0);
+        // Hidden faces are respected, which splits the top into three quads.
+        let mut occlusion = snapshot.occlusion(Coord::new(1, 7, 0));
+        occlusion.hide(Direction::PosY);
+        snapshot.set(Coord::new(1, 7, 0), state, occlusion, Orientation::UNORIENTED);
+        snapshot.set_greedy(Coord::new(1, 7, 0), true);
+        let mesh = mesh_section(&snapshot, LOD::Level0).take_layer(BlockLayer::Base);
+        let top_count = mesh.normals.iter().filter(|&&normal| normal == Vec3::Y).count();
+        assert_eq!(top_count, 12);
+    }
+
+    #[test]
+    fn greedy_ao_test() {
+        register_test_blocks();
+        let state = blockstate!(test_greedy).register();
+        let mut snapshot = SectionSnapshot::new(Coord::new(0, 0, 0));
+        snapshot.set(Coord::new(5, 5, 5), state, Occlusion::UNOCCLUDED, Orientation::UNORIENTED);
+        snapshot.set_greedy(Coord::new(5, 5, 5), true);
+        // A solid block diagonally above the +X edge of the top face darkens the two corners on that edge.
+        snapshot.set_solid(Coord::new(6, 6, 5), true);
+        let mesh = mesh_section(&snapshot, LOD::Level0).take_layer(BlockLayer::Base);
+        assert_eq!(mesh.ao.len(), mesh.vertices.len());
+        let top = (0..mesh.vertices.len()).filter(|&i| mesh.normals[i] == Vec3::Y).collect::<Vec<_>>();
+        assert_eq!(top.len(), 4);
+        top.iter().for_each(|&i| {
+            if mesh.vertices[i].x == 6.0 {
+                assert_eq!(mesh.ao[i], ao_value(2));
+            } else {
+                assert_eq!(mesh.ao[i], 1.0);
+            }
+        });
+        // The bottom face can't see the block.
+        assert!((0..mesh.vertices.len()).filter(|&i| mesh.normals[i] == Vec3::NEG_Y).all(|i| mesh.ao[i] == 1.0));
+    }
+
+    #[test]
+    fn downsample_test() {
+        register_test_blocks();
+        let state = blockstate!(test_greedy).register();
+        let mut snapshot = SectionSnapshot::new(Coord::new(0, 0, 0));
+        let place = |snapshot: &mut SectionSnapshot, coord: Coord| {
+            snapshot.set(coord, state, Occlusion::UNOCCLUDED, Orientation::UNORIENTED);
+            snapshot.set_greedy(coord, true);
+            snapshot.set_solid(coord, true);
+        };
+        // A 2x2x2 cell that is missing one block is filled in.
+        for y in 0..2 {
+            for z in 0..2 {
+                for x in 0..2 {
+                    if (x, y, z) != (1, 1, 1) {
+                        place(&mut snapshot, Coord::new(x, y, z));
+                    }
+                }
+            }
+        }
+        // A cell that is mostly air is removed.
+        place(&mut snapshot, Coord::new(4, 0, 0));
+        place(&mut snapshot, Coord::new(5, 0, 0));
+        place(&mut snapshot, Coord::new(4, 1, 0));
+        let downsampled = snapshot.downsample(LOD::Level1);
+        assert_eq!(downsampled.get_block(Coord::new(1, 1, 1)), state);
+        assert_eq!(downsampled.get_block(Coord::new(4, 0, 0)), Id::AIR);
+        assert!(downsampled.occlusion(Coord::new(0, 0, 0)).hidden(Direction::PosX));
+        assert!(downsampled.occlusion(Coord::new(0, 0, 0)).visible(Direction::NegX));
+        let mesh = mesh_section(&snapshot, LOD::Level1).take_layer(BlockLayer::Base);
+        assert_eq!(mesh.vertices.len(), 24);
+        assert!(mesh.vertices.iter().all(|vert| vert.max_element() <= 2.0));
+    }
+
+    #[test]
+    fn downsample_ground_test() {
+        register_test_blocks();
+        let state = blockstate!(test_greedy).register();
+        let mut snapshot = SectionSnapshot::new(Coord::new(0, 0, 0));
+        // Ground that is a single block thick only fills 1/8 of a cell at Level3, but it's kept.
+        for z in 0..16 {
+            for x in 0..16 {
+                let coord = Coord::new(x, 3, z);
+                snapshot.set(coord, state, Occlusion::UNOCCLUDED, Orientation::UNORIENTED);
+                snapshot.set_greedy(coord, true);
+                snapshot.set_solid(coord, true);
+            }
+        }
+        // A single block with air around it is still removed.
+        snapshot.set(Coord::new(4, 12, 4), state, Occlusion::UNOCCLUDED, Orientation::UNORIENTED);
+        [LOD::Level2, LOD::Level3].into_iter().for_each(|level_of_detail| {
+            let downsampled = snapshot.downsample(level_of_detail);
+            assert_eq!(downsampled.get_block(Coord::new(15, 0, 15)), state);
+            assert_eq!(downsampled.get_block(Coord::new(4, 12, 4)), Id::AIR);
+        });
+    }
+
+    #[test]
+    fn seam_test() {
+        register_test_blocks();
+        let state = blockstate!(test_greedy).register();
+        let mut snapshot = SectionSnapshot::new(Coord::new(0, 0, 0));
+        let mut occlusion = Occlusion::UNOCCLUDED;
+        occlusion.hide(Direction::NegX);
+        snapshot.set(Coord::new(0, 5, 5), state, occlusion, Orientation::UNORIENTED);
+        snapshot.set_greedy(Coord::new(0, 5, 5), true);
+        assert_eq!(mesh_section(&snapshot, LOD::Level0).take_layer(BlockLayer::Base).vertices.len(), 20);
+        // The face that is hidden by the border is shown when the neighbor has a different LOD.
+        snapshot.set_seam(Direction::NegX, true);
+        assert_eq!(mesh_section(&snapshot, LOD::Level0).take_layer(BlockLayer::Base).vertices.len(), 24);
+    }
+}
@@ -1,6 +1,11 @@
 #![allow(unused)]
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{RenderPipelineDescriptor, SpecializedMeshPipelineError};
 use bevy::{prelude::*, render::render_resource::AsBindGroup};
 
+use super::voxelmesh::{AO_ATTRIB, NORMAL_ATTRIB, POSITION_ATTRIB, TEXINDEX_ATTRIB, UV_ATTRIB};
+
 #[derive(AsBindGroup, Debug, Clone, Asset, TypePath)]
 pub struct VoxelMaterial {
     #[texture(0, dimension = "2d_array")]
@@ -22,6 +27,8 @@ pub struct VoxelMaterial {
     pub lightmap_pad_pos_z: Vec<f32>,
     #[storage(9, read_only)]
     pub lightmap_pad_neg_z: Vec<f32>,
+    /// [AlphaMode::Blend] for the meshes of [BlockLayer::Transparent](crate::core::voxel::blocklayer::BlockLayer::Transparent).
+    pub alpha_mode: AlphaMode,
 }
 
 pub const MIN_LIGHT_LEVEL: f32 = 0.025;
@@ -46,6 +53,7 @@ impl VoxelMaterial {
             lightmap_pad_neg_y: (0..256).map(|_| 1.0).collect(),
             lightmap_pad_pos_z: (0..256).map(|_| 1.0).collect(),
             lightmap_pad_neg_z: (0..256).map(|_| 1.0).collect(),
+            alpha_mode: AlphaMode::Opaque,
         }
     }
 }
@@ -59,7 +67,25 @@ impl Material for VoxelMaterial {
         "shaders/voxel/voxel.wgsl".into()
     }
 
-    // fn alpha_mode(&self) -> AlphaMode {
-    //     AlphaMode::
-    // }
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    /// Binds the voxel attributes to the locations in the shader's `Vertex`. Bevy only knows about its
+    /// own attributes, so the [AO_ATTRIB] wouldn't be bound otherwise.
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.vertex.buffers = vec![layout.get_layout(&[
+            POSITION_ATTRIB.at_shader_location(0),
+            UV_ATTRIB.at_shader_location(1),
+            NORMAL_ATTRIB.at_shader_location(2),
+            TEXINDEX_ATTRIB.at_shader_location(3),
+            AO_ATTRIB.at_shader_location(4),
+        ])?];
+        Ok(())
+    }
 }
\ No newline at end of file
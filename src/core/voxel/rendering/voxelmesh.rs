@@ -9,6 +9,10 @@ pub const POSITION_ATTRIB: MeshVertexAttribute = MeshVertexAttribute::new("posit
 pub const NORMAL_ATTRIB: MeshVertexAttribute = MeshVertexAttribute::new("normal", 2, VertexFormat::Float32x3);
 pub const UV_ATTRIB: MeshVertexAttribute = MeshVertexAttribute::new("uv", 1, VertexFormat::Float32x2);
 pub const TEXINDEX_ATTRIB: MeshVertexAttribute = MeshVertexAttribute::new("texindex", 3, VertexFormat::Uint32);
+/// The ambient occlusion of a vertex in the range `0.0..=1.0`, where `1.0` is unoccluded.
+/// The id is large so that it doesn't collide with Bevy's built-in attributes (4 is `Mesh::ATTRIBUTE_TANGENT`).
+/// [VoxelMaterial](super::voxelmaterial::VoxelMaterial) binds it to shader location 4.
+pub const AO_ATTRIB: MeshVertexAttribute = MeshVertexAttribute::new("ao", 1_947_204_713, VertexFormat::Float32);
 
 #[derive(Debug, Default, Clone)]
 pub struct MeshData {
@@ -16,6 +20,8 @@ pub struct MeshData {
     pub normals: Vec<Vec3>,
     pub uvs: Vec<Vec2>,
     pub texindices: Vec<u32>,
+    /// Per-vertex ambient occlusion. If this doesn't have one value per vertex, every vertex is unoccluded.
+    pub ao: Vec<f32>,
     pub indices: Vec<u32>,
 }
 
@@ -32,6 +38,7 @@ impl MeshData {
             normals,
             uvs,
             texindices,
+            ao: Vec::new(),
             indices
         }
     }
@@ -66,16 +73,42 @@ impl MeshData {
         self
     }
 
+    /// Replace the attributes and indices of an existing mesh with this mesh data.
+    pub fn push_to_mesh(self, mesh: &mut Mesh) {
+        let ao = vertex_ao_values(self.ao, self.vertices.len());
+        mesh.insert_attribute(POSITION_ATTRIB.clone(), self.vertices);
+        mesh.insert_attribute(NORMAL_ATTRIB.clone(), self.normals);
+        mesh.insert_attribute(UV_ATTRIB.clone(), self.uvs);
+        mesh.insert_attribute(TEXINDEX_ATTRIB.clone(), self.texindices);
+        mesh.insert_attribute(AO_ATTRIB.clone(), ao);
+        mesh.insert_indices(bevy::render::mesh::Indices::U32(self.indices));
+    }
+
     pub fn to_mesh(self, render_asset_usages: RenderAssetUsages) -> Mesh {
         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, render_asset_usages);
+        let ao = vertex_ao_values(self.ao, self.vertices.len());
         mesh.insert_attribute(POSITION_ATTRIB.clone(), self.vertices);
         mesh.insert_attribute(UV_ATTRIB.clone(), self.uvs);
         mesh.insert_attribute(NORMAL_ATTRIB.clone(), self.normals);
         mesh.insert_attribute(TEXINDEX_ATTRIB.clone(), self.texindices);
+        mesh.insert_attribute(AO_ATTRIB.clone(), ao);
         mesh.insert_indices(bevy::render::mesh::Indices::U32(self.indices));
         mesh
     }
 
+    /// Returns the indices with the triangles sorted from farthest to nearest to a viewpoint.
+    /// Translucent meshes must be drawn back-to-front to blend correctly.
+    pub fn sorted_indices(&self, viewpoint: Vec3) -> Vec<u32> {
+        let mut triangles = self.indices.chunks_exact(3)
+            .map(|tri| {
+                let center = (self.vertices[tri[0] as usize] + self.vertices[tri[1] as usize] + self.vertices[tri[2] as usize]) / 3.0;
+                (center.distance_squared(viewpoint), [tri[0], tri[1], tri[2]])
+            })
+            .collect::<Vec<_>>();
+        triangles.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        triangles.into_iter().flat_map(|(_, tri)| tri).collect()
+    }
+
     pub fn recalculate_normals(mut self) -> Self {
         (0..self.indices.len()).step_by(3)
             .map(|i| [self.indices[i] as usize, self.indices[i + 1] as usize, self.indices[i + 2] as usize])
@@ -94,6 +127,16 @@ impl MeshData {
     }
 }
 
+/// The voxel shader requires the [AO_ATTRIB] on every mesh, so meshes without
+/// ambient occlusion are given unoccluded vertices.
+pub(crate) fn vertex_ao_values(ao: Vec<f32>, vertex_count: usize) -> Vec<f32> {
+    if ao.len() == vertex_count {
+        ao
+    } else {
+        vec![1.0; vertex_count]
+    }
+}
+
 pub struct VoxelMesh {
     pub faces: Faces<MeshData>,
     pub unoriented: MeshData,
@@ -126,4 +169,26 @@ impl Into<Mesh> for MeshData {
 //             pos_y_normal, pos_y_normal, 
 //         ]
 //     };
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_indices_test() {
+        // Two triangles, one at z = 0 and one at z = 10.
+        let mesh = MeshData::new(
+            vec![
+                vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 0.0, 10.0), vec3(1.0, 0.0, 10.0), vec3(0.0, 1.0, 10.0),
+            ],
+            vec![Vec3::Z; 6],
+            vec![Vec2::ZERO; 6],
+            vec![0; 6],
+            vec![0, 1, 2, 3, 4, 5],
+        );
+        assert_eq!(mesh.sorted_indices(vec3(0.0, 0.0, -5.0)), vec![3, 4, 5, 0, 1, 2]);
+        assert_eq!(mesh.sorted_indices(vec3(0.0, 0.0, 15.0)), vec![0, 1, 2, 3, 4, 5]);
+    }
+}
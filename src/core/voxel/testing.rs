@@ -0,0 +1,104 @@
+//! Fixtures that are shared by the tests of the voxel modules.
+//!
+//! Every test world lives in its own [TestDirectory] so that tests that run at the same time never share files.
+//! The blocks that the tests of more than one module use are registered once by [register_test_blocks].
+//! Blocks that only one module's tests use are defined next to those tests.
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::{AtomicUsize, Ordering}, Once};
+
+use crate::blockstate;
+
+use super::{block::Block, blocks::{self, Id}, blockstate::BlockState, coord::Coord, procgen::worldgenerator::WorldGenerator, world::VoxelWorld};
+
+/// A directory for the files of a single test. It's removed when it's dropped.
+pub(crate) struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    /// Creates an empty directory in the temp directory with a name that no other test uses.
+    pub fn new(name: &str) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir()
+            .join("unvoga_tests")
+            .join(format!("{name}_{}_{id}", std::process::id()));
+        // Left over from a process with the same id that didn't clean up.
+        if path.exists() {
+            std::fs::remove_dir_all(&path).expect("Failed to remove the old test directory");
+        }
+        std::fs::create_dir_all(&path).expect("Failed to create the test directory");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TestDirectory {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+/// Opens a headless world in `directory` and loads all of its chunks.
+pub(crate) fn open_world<C: Into<(i32, i32, i32)>>(directory: &TestDirectory, render_distance: u8, center: C) -> VoxelWorld {
+    open_world_with_generator(directory, render_distance, center, None)
+}
+
+/// Opens a headless world in `directory` with a generator and loads all of its chunks.
+/// The chunks that weren't saved are left in the worldgen queue.
+pub(crate) fn open_world_with_generator<C: Into<(i32, i32, i32)>>(
+    directory: &TestDirectory,
+    render_distance: u8,
+    center: C,
+    generator: Option<Box<dyn WorldGenerator>>,
+) -> VoxelWorld {
+    let mut world = VoxelWorld::open_headless(directory, render_distance, center, generator).expect("Failed to open the world");
+    world.flush_load_queue();
+    world
+}
+
+/// Registers the shared test blocks. This can be called any number of times from any number of tests.
+pub(crate) fn register_test_blocks() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        blocks::register_block(PlainTestBlock("test_stone"));
+        blocks::register_block(ScheduledTestBlock);
+    });
+}
+
+/// A full, opaque block with no behavior.
+pub(crate) struct PlainTestBlock(pub &'static str);
+impl Block for PlainTestBlock {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn default_state(&self) -> BlockState {
+        BlockState::new(self.0, [])
+    }
+}
+
+/// Disappears when its scheduled update happens, like a block that falls.
+struct ScheduledTestBlock;
+impl Block for ScheduledTestBlock {
+    fn name(&self) -> &str {
+        "test_scheduled"
+    }
+
+    fn default_state(&self) -> BlockState {
+        blockstate!(test_scheduled)
+    }
+
+    fn on_scheduled_update(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {
+        world.set_block(coord, Id::AIR);
+    }
+}
@@ -1,12 +1,14 @@
 #![allow(unused)]
 use std::io::{Read, Write};
+use std::sync::Arc;
 
 use bevy::{asset::Assets, prelude::{state_changed, ResMut}, render::mesh::Mesh, utils::tracing::Instrument};
 
-use crate::{core::{collections::objectpool::PoolId, voxel::{blocks::Id, blockstate::BlockState, coord::Coord, direction::Direction, region::timestamp::Timestamp, rendering::voxelmaterial::VoxelMaterial, tag::Tag}}, prelude::SwapVal};
+use crate::{core::{collections::objectpool::{ObjectPool, PoolId}, io::{read_optional, Writeable}, voxel::{blocks::Id, blockstate::BlockState, coord::Coord, direction::Direction, entity::{EntityId, EntityMarker, VoxelEntity}, region::timestamp::Timestamp, rendering::voxelmaterial::VoxelMaterial, tag::{Array, Tag}}}, prelude::SwapVal};
 
-use super::{dirty::Dirty, heightmap::Heightmap, occlusion::Occlusion, query::VoxelQuery, section::{LightChange, Section, SectionUpdate, StateChange}, update::UpdateRef, LoadChunkMarker, MemoryUsage, SaveIdMarker, VoxelWorld, WorldGenMarker, WORLD_BOTTOM, WORLD_HEIGHT};
+use super::{dirty::Dirty, worker::ChunkJobMarker, heightmap::Heightmap, migration::{self, Migration}, occlusion::Occlusion, query::VoxelQuery, section::{LightChange, Section, SectionUpdate, StateChange}, tick::TickScheduler, update::UpdateRef, LoadChunkMarker, MemoryUsage, SaveIdMarker, VoxelWorld, WorldGenMarker, WORLD_BOTTOM, WORLD_HEIGHT};
 use crate::core::error::*;
+use crate::core::voxel::lighting::engine::{compute_detached_sky_light, merge_chunk_sky_light};
 
 pub struct Chunk {
     pub sections: Box<[Section]>,
@@ -18,6 +20,42 @@ pub struct Chunk {
     pub save_id: PoolId<SaveIdMarker>,
     pub world_gen_id: PoolId<WorldGenMarker>,
     pub load_id: PoolId<LoadChunkMarker>,
+    /// The id of the background job that this chunk is waiting on.
+    pub job_id: PoolId<ChunkJobMarker>,
+    /// The entities that are stored in this chunk.
+    pub entities: Vec<EntityId>,
+    pub sky_light_state: SkyLightState,
+    /// True if the chunk is on disk but couldn't be read. The chunk is left empty and is never saved,
+    /// so that the data on disk isn't overwritten.
+    pub load_failed: bool,
+}
+
+/// How much of the sky light of a [Chunk] has been calculated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SkyLightState {
+    /// The sky light doesn't match the blocks, such as after blocks were changed while lighting was deferred,
+    /// or for chunks that were saved before sky light existed.
+    #[default]
+    Stale,
+    /// The sky light was calculated without the neighboring chunks by [compute_detached_sky_light]. The light
+    /// that crosses the edges of the chunk is added by [merge_chunk_sky_light] once the chunk is in the world.
+    Detached,
+    /// The sky light matches the blocks. This is saved with the chunk so that it doesn't need to be calculated again.
+    Valid,
+}
+
+/// A chunk that was read off the main thread with [Chunk::decode]. The parts of it that belong to the world
+/// are moved into the world by [Chunk::take_decoded].
+pub struct DecodedChunk {
+    /// The heightmap and sections.
+    pub chunk: Chunk,
+    /// The indices of the blocks in each section that have updates enabled.
+    pub enabled: Vec<Vec<u16>>,
+    /// The indices of the sections whose blocks were changed by migrations.
+    pub migrated: Vec<usize>,
+    /// The `(coord, delay, priority)` of the chunk's pending updates.
+    pub ticks: Vec<(Coord, u32, i32)>,
+    pub entities: Vec<VoxelEntity>,
 }
 
 impl Chunk {
@@ -33,6 +71,10 @@ impl Chunk {
             save_id: PoolId::NULL,
             world_gen_id: PoolId::NULL,
             load_id: PoolId::NULL,
+            job_id: PoolId::NULL,
+            entities: Vec::new(),
+            sky_light_state: SkyLightState::Stale,
+            load_failed: false,
         }
     }
 
@@ -42,6 +84,18 @@ impl Chunk {
         })
     }
 
+    /// Moves the blocks, heightmap, occlusion, and light of a chunk that was generated outside of the world
+    /// (by a [ChunkGenerator](crate::core::voxel::procgen::worldgenerator::ChunkGenerator)) into this chunk.
+    pub fn take_generated(&mut self, generated: Chunk) {
+        self.heightmap = generated.heightmap;
+        self.sections.iter_mut().zip(generated.sections.into_vec()).for_each(|(section, generated)| {
+            section.take_generated(generated);
+        });
+        self.used_count = self.sections.iter().filter(|section| !section.is_empty()).count() as u16;
+        self.sky_light_state = generated.sky_light_state;
+        self.mark_modified();
+    }
+
     pub fn y(&self) -> i32 {
         self.block_offset.y
     }
@@ -50,6 +104,11 @@ impl Chunk {
         self.block_offset.y >> 4
     }
 
+    /// The (x, z) coordinate of the chunk.
+    pub fn chunk_coord(&self) -> (i32, i32) {
+        (self.block_offset.x >> 4, self.block_offset.z >> 4)
+    }
+
     pub fn mark_modified(&mut self) {
         self.edit_time = Timestamp::utc_now();
     }
@@ -274,37 +333,120 @@ impl Chunk {
     }
 
     
-    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<u64> {
+    /// Writes the chunk along with its pending updates from the [TickScheduler], its entities, and whether its sky light is valid.
+    pub fn write_to<W: Write>(&self, writer: &mut W, ticks: &TickScheduler, entities: &ObjectPool<VoxelEntity, EntityMarker>) -> Result<u64> {
         let mut length = self.heightmap.write_to(writer)?;
         for i in 0..self.sections.len() {
             // the y offset of the bottom-most block
             length += self.sections[i].write_to(writer)?;
         }
+        let (chunk_x, chunk_z) = self.chunk_coord();
+        length += ticks.write_chunk(writer, chunk_x, chunk_z)?;
+        let entities = self.entities.iter()
+            .filter_map(|&id| entities.get(id))
+            .map(VoxelEntity::to_tag)
+            .collect::<Vec<_>>();
+        length += Tag::from(entities).write_to(writer)?;
+        length += (self.sky_light_state == SkyLightState::Valid).write_to(writer)?;
         Ok(length)
     }
 
     
-    pub fn read_from<R: Read>(&mut self, reader: &mut R, world: &mut VoxelWorld) -> Result<()> {
+    /// Reads a chunk that was saved with the data version `version`, applying the migrations that were registered after that version.
+    /// Returns the indices of the sections whose blocks were changed by the migrations. Their heightmap, occlusion, and light are stale.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R, world: &mut VoxelWorld, version: u16) -> Result<Vec<usize>> {
         self.unload(world);
-        self.heightmap.read_from(reader)?;
-        for i in 0..self.sections.len() {
-            let y = i as i32 * 16 + self.block_offset.y;
-            // wtf is this?
-            let offset = Coord::new(self.block_offset.x, y, self.block_offset.z);
-            // and this?
-            /* let marked =  */self.sections[i].read_from(reader, world, offset)?;
-            // if offset is not in the render bounds, we don't want to add it
-            // to the dirty_queue
-            if !self.sections[i].is_empty() {
-                self.used_count += 1;
+        let (chunk_x, chunk_z) = self.chunk_coord();
+        let decoded = Chunk::decode(reader, chunk_x, chunk_z, &world.migrations.migrations_since(version))?;
+        Ok(self.take_decoded(decoded, world))
+    }
+
+    /// Reads a chunk like [Chunk::read_from] without touching the world, so that chunks can be read on the IO worker.
+    /// `migrations` are the migrations that were registered after the version that the chunk was saved with.
+    pub fn decode<R: Read>(reader: &mut R, chunk_x: i32, chunk_z: i32, migrations: &[Arc<dyn Migration>]) -> Result<DecodedChunk> {
+        let mut chunk = Chunk::new(Coord::new(chunk_x * 16, WORLD_BOTTOM, chunk_z * 16));
+        chunk.heightmap.read_from(reader)?;
+        let mut enabled = Vec::with_capacity(chunk.sections.len());
+        let mut migrated = Vec::new();
+        for i in 0..chunk.sections.len() {
+            let mut section_enabled = Vec::new();
+            if chunk.sections[i].decode(reader, migrations, |index| section_enabled.push(index))? {
+                migrated.push(i);
             }
+            enabled.push(section_enabled);
+            if !chunk.sections[i].is_empty() {
+                chunk.used_count += 1;
+            }
+        }
+        // upgrade_chunk_data can't calculate occlusion or light, so it clears the heightmap of the chunks that it changed.
+        if chunk.heightmap.is_empty() && chunk.used_count > 0 {
+            migrated = (0..chunk.sections.len()).filter(|&i| chunk.sections[i].blocks.is_some()).collect();
         }
-        Ok(())
+        let ticks = TickScheduler::decode_chunk(reader, chunk_x, chunk_z)?;
+        // Chunks that were saved before entities existed don't have an entity list.
+        let entities = match read_optional::<Tag, _>(reader)? {
+            Some(Tag::Array(entities)) => match *entities {
+                Array::Tag(entities) => entities.iter().filter_map(VoxelEntity::from_tag).collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        // Chunks that were saved before the flag existed are lit again. So are chunks whose blocks were changed by migrations,
+        // which is done here so that the main thread only needs to merge the light at the edges of the chunk.
+        let sky_light_valid = read_optional::<bool, _>(reader)?.unwrap_or(false);
+        migrated.iter().for_each(|&index| chunk.rebuild_heightmap(index));
+        if sky_light_valid && migrated.is_empty() {
+            chunk.sky_light_state = SkyLightState::Valid;
+        } else {
+            compute_detached_sky_light(&mut chunk);
+        }
+        Ok(DecodedChunk {
+            chunk,
+            enabled,
+            migrated,
+            ticks,
+            entities,
+        })
+    }
+
+    /// Moves a chunk that was read with [Chunk::decode] into this chunk, which should be unloaded. Its pending updates
+    /// and entities are added to the world. Returns the indices of the sections whose blocks were changed by migrations.
+    pub fn take_decoded(&mut self, decoded: DecodedChunk, world: &mut VoxelWorld) -> Vec<usize> {
+        let DecodedChunk { chunk, enabled, migrated, ticks, entities } = decoded;
+        self.heightmap = chunk.heightmap;
+        self.used_count = chunk.used_count;
+        self.sky_light_state = chunk.sky_light_state;
+        self.sections.iter_mut().zip(chunk.sections.into_vec()).zip(enabled).enumerate().for_each(|(i, ((section, decoded), enabled))| {
+            let offset = self.block_offset + Coord::new(0, i as i32 * 16, 0);
+            section.take_decoded(decoded, world, offset, &enabled);
+        });
+        ticks.into_iter().for_each(|(coord, delay, priority)| {
+            world.scheduled_updates.schedule(coord, delay, priority);
+        });
+        let chunk_coord = self.chunk_coord();
+        entities.into_iter().for_each(|mut entity| {
+            entity.chunk = chunk_coord;
+            self.entities.push(world.entities.insert(entity));
+        });
+        migrated
+    }
+
+    /// Calculates the heightmap of the blocks in a section again.
+    pub fn rebuild_heightmap(&mut self, section_index: usize) {
+        let y = section_index as i32 * 16;
+        let blocks = self.sections[section_index].blocks.as_ref();
+        (0..4096u16).for_each(|index| {
+            let nonair = blocks.is_some_and(|blocks| blocks[index as usize].is_non_air());
+            self.heightmap.set(Section::coord(index) + Coord::new(0, y, 0), nonair);
+        });
     }
 
     
     pub fn unload(&mut self, world: &mut VoxelWorld) {
         self.used_count = 0;
+        self.sky_light_state = SkyLightState::Stale;
+        self.load_failed = false;
+        self.heightmap = Heightmap::new();
         for i in 0..self.sections.len() {
             let y = i as i32 * 16 + self.block_offset.y;
             let offset = Coord::new(self.block_offset.x, y, self.block_offset.z);
@@ -314,6 +456,14 @@ impl Chunk {
         world.save_queue.remove(save_id);
         let gen_id = self.world_gen_id.swap_null();
         world.worldgen_queue.remove(gen_id);
+        // Any job that is still in flight for this chunk is now stale.
+        let job_id = self.job_id.swap_null();
+        world.pending_jobs.remove(job_id);
+        let (chunk_x, chunk_z) = self.chunk_coord();
+        world.scheduled_updates.unschedule_chunk(chunk_x, chunk_z);
+        self.entities.drain(..).for_each(|id| {
+            world.entities.take(id);
+        });
     }
 
 }
@@ -0,0 +1,342 @@
+#![allow(unused)]
+use hashbrown::{HashMap, HashSet};
+
+use crate::core::voxel::{blocks::Id, coord::Coord, tag::Tag};
+use crate::prelude::Orientation;
+
+use super::VoxelWorld;
+
+/// Determines which blocks are pasted from a [Clipboard].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PasteMask {
+    /// Every block in the clipboard is pasted.
+    #[default]
+    All,
+    /// Air in the clipboard isn't pasted, so it doesn't clear the blocks that are already in the world.
+    SkipAir,
+    /// Blocks are only pasted where there is air in the world.
+    OnlyReplaceAir,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PasteOptions {
+    /// The orientation that is applied to the clipboard. Blocks are oriented around the paste position.
+    pub orientation: Orientation,
+    pub mask: PasteMask,
+}
+
+impl PasteOptions {
+    pub const fn new() -> Self {
+        Self {
+            orientation: Orientation::UNORIENTED,
+            mask: PasteMask::All,
+        }
+    }
+
+    pub const fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub const fn with_mask(mut self, mask: PasteMask) -> Self {
+        self.mask = mask;
+        self
+    }
+}
+
+impl Default for PasteOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A box of blocks that has been copied out of a [VoxelWorld]. The clipboard is detached from
+/// the world, so it can be pasted after the area that it was copied from has unloaded.
+///
+/// Coordinates in the clipboard are relative to its min corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clipboard {
+    size: Coord,
+    /// The blocks in yzx order.
+    blocks: Box<[Id]>,
+    data: HashMap<Coord, Tag>,
+    enabled: HashSet<Coord>,
+}
+
+impl Clipboard {
+    /// Creates a clipboard that is filled with air.
+    pub fn new<C: Into<(i32, i32, i32)>>(size: C) -> Self {
+        let size: (i32, i32, i32) = size.into();
+        let size = Coord::new(size.0.max(0), size.1.max(0), size.2.max(0));
+        Self {
+            size,
+            blocks: (0..size.x as usize * size.y as usize * size.z as usize).map(|_| Id::AIR).collect(),
+            data: HashMap::new(),
+            enabled: HashSet::new(),
+        }
+    }
+
+    pub fn size(&self) -> Coord {
+        self.size
+    }
+
+    /// The number of blocks in the clipboard (including air).
+    pub fn volume(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn contains<C: Into<(i32, i32, i32)>>(&self, coord: C) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.index(coord).is_some()
+    }
+
+    fn index(&self, coord: Coord) -> Option<usize> {
+        if coord.x < 0 || coord.y < 0 || coord.z < 0
+        || coord.x >= self.size.x || coord.y >= self.size.y || coord.z >= self.size.z {
+            return None;
+        }
+        let (x, y, z) = (coord.x as usize, coord.y as usize, coord.z as usize);
+        let (size_x, size_z) = (self.size.x as usize, self.size.z as usize);
+        Some(x + z * size_x + y * size_x * size_z)
+    }
+
+    /// Gets the block at `coord`. This is air if `coord` is outside of the clipboard.
+    pub fn get<C: Into<(i32, i32, i32)>>(&self, coord: C) -> Id {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.index(coord).map(|index| self.blocks[index]).unwrap_or(Id::AIR)
+    }
+
+    /// Sets the block at `coord` and returns the old block.
+    /// Nothing happens if `coord` is outside of the clipboard.
+    pub fn set<C: Into<(i32, i32, i32)>, S: Into<Id>>(&mut self, coord: C, state: S) -> Id {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        let Some(index) = self.index(coord) else {
+            return Id::AIR;
+        };
+        std::mem::replace(&mut self.blocks[index], state.into())
+    }
+
+    pub fn get_data<C: Into<(i32, i32, i32)>>(&self, coord: C) -> Option<&Tag> {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.data.get(&coord)
+    }
+
+    pub fn set_data<C: Into<(i32, i32, i32)>, T: Into<Tag>>(&mut self, coord: C, tag: T) {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        if self.index(coord).is_some() {
+            self.data.insert(coord, tag.into());
+        }
+    }
+
+    pub fn delete_data<C: Into<(i32, i32, i32)>>(&mut self, coord: C) -> Option<Tag> {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.data.remove(&coord)
+    }
+
+    /// Determines if the block at `coord` is enabled for updates when it's pasted.
+    pub fn enabled<C: Into<(i32, i32, i32)>>(&self, coord: C) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.enabled.contains(&coord)
+    }
+
+    pub fn set_enabled<C: Into<(i32, i32, i32)>>(&mut self, coord: C, enabled: bool) {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        if !enabled {
+            self.enabled.remove(&coord);
+        } else if self.index(coord).is_some() {
+            self.enabled.insert(coord);
+        }
+    }
+
+    /// Iterates over every block in the clipboard in yzx order.
+    pub fn iter(&self) -> impl Iterator<Item = (Coord, Id)> + '_ {
+        let size = self.size;
+        (0..size.y).flat_map(move |y| {
+            (0..size.z).flat_map(move |z| {
+                (0..size.x).map(move |x| Coord::new(x, y, z))
+            })
+        }).zip(self.blocks.iter().cloned())
+    }
+}
+
+impl VoxelWorld {
+    /// Copies the blocks between two corners (inclusive) into a [Clipboard], along with their data and
+    /// whether they are enabled. Blocks outside of the world bounds are copied as air.
+    pub fn copy<A: Into<(i32, i32, i32)>, B: Into<(i32, i32, i32)>>(&self, a: A, b: B) -> Clipboard {
+        let a: (i32, i32, i32) = a.into();
+        let a: Coord = a.into();
+        let b: (i32, i32, i32) = b.into();
+        let b: Coord = b.into();
+        let min = Coord::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Coord::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let mut clipboard = Clipboard::new(max - min + Coord::splat(1));
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    let coord = Coord::new(x, y, z);
+                    let local = coord - min;
+                    let state = self.get_block(coord);
+                    if state.is_air() {
+                        continue;
+                    }
+                    clipboard.set(local, state);
+                    if let Some(data) = self.get_data(coord) {
+                        clipboard.set_data(local, data.clone());
+                    }
+                    if self.enabled(coord) {
+                        clipboard.set_enabled(local, true);
+                    }
+                }
+            }
+        }
+        clipboard
+    }
+
+    /// Pastes a [Clipboard] so that its min corner is at `position`.
+    ///
+    /// The orientation in the `options` is applied around `position` with [Orientation::transform],
+    /// and every block is reoriented with [Block::reorient](crate::core::voxel::block::Block::reorient)
+    /// so that directional blocks face the right way. The blocks are set with [VoxelWorld::set_blocks],
+    /// then the data and enabled flags of the pasted blocks are copied from the clipboard.
+    ///
+    /// Returns the number of blocks that were changed.
+    pub fn paste<C: Into<(i32, i32, i32)>>(&mut self, clipboard: &Clipboard, position: C, options: PasteOptions) -> usize {
+        let position: (i32, i32, i32) = position.into();
+        let position: Coord = position.into();
+        let bounds = self.bounds();
+        let mut edits = Vec::new();
+        // (destination, local)
+        let mut pasted = Vec::new();
+        for (local, state) in clipboard.iter() {
+            if options.mask == PasteMask::SkipAir && state.is_air() {
+                continue;
+            }
+            let dest = position + options.orientation.transform(local);
+            if !bounds.contains(dest) {
+                continue;
+            }
+            if options.mask == PasteMask::OnlyReplaceAir && !self.get_block(dest).is_air() {
+                continue;
+            }
+            let state = if state.is_air() || options.orientation == Orientation::UNORIENTED {
+                state
+            } else {
+                let block = state.block();
+                let orientation = block.orientation(self, dest, state).reorient(options.orientation);
+                block.reorient(self, dest, state, orientation)
+            };
+            edits.push((dest, state));
+            pasted.push((dest, local));
+        }
+        let changed = self.set_blocks(edits);
+        pasted.into_iter().for_each(|(dest, local)| {
+            if let Some(data) = clipboard.get_data(local) {
+                self.set_data(dest, data.clone());
+            } else {
+                self.take_data(dest);
+            }
+            if self.enabled(dest) != clipboard.enabled(local) {
+                self.set_enabled(dest, clipboard.enabled(local));
+            }
+        });
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{block::Block, blocks, blockstate::BlockState, testing::{self, open_world, TestDirectory}};
+    use crate::prelude::{Direction, Flip, Rotation, StateValue};
+
+    use super::*;
+
+    /// Stores its orientation in its state.
+    struct OrientedTestBlock;
+    impl Block for OrientedTestBlock {
+        fn name(&self) -> &str {
+            "test_oriented"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_oriented, orientation = Orientation::UNORIENTED)
+        }
+
+        fn orientation(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Orientation {
+            if let StateValue::Orientation(orientation) = state["orientation"] {
+                orientation
+            } else {
+                Orientation::UNORIENTED
+            }
+        }
+
+        fn reorient(&self, world: &VoxelWorld, coord: Coord, state: Id, orientation: Orientation) -> Id {
+            blockstate!(test_oriented, orientation = orientation).register()
+        }
+    }
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(OrientedTestBlock);
+        });
+    }
+
+    #[test]
+    fn clipboard_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let oriented = blockstate!(test_oriented, orientation = Orientation::UNORIENTED).register();
+        let directory = TestDirectory::new("clipboard_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        world.set_block((0, 0, 0), stone);
+        world.set_block((1, 0, 0), stone);
+        world.set_block((2, 0, 0), oriented);
+        world.set_data((0, 0, 0), "hello");
+        world.set_enabled((1, 0, 0), true);
+        let clipboard = world.copy((2, 1, 0), (0, 0, 0));
+        assert_eq!(clipboard.size(), Coord::new(3, 2, 1));
+        assert_eq!(clipboard.get((2, 0, 0)), oriented);
+        assert_eq!(clipboard.get((0, 1, 0)), Id::AIR);
+        assert_eq!(clipboard.get_data((0, 0, 0)), Some(&Tag::from("hello")));
+        assert!(clipboard.enabled((1, 0, 0)));
+        assert!(!clipboard.enabled((0, 0, 0)));
+        // Flipping on the x axis mirrors the blocks around the paste position.
+        let flip = Orientation::new(Rotation::new(Direction::PosY, 0), Flip::X);
+        assert_eq!(world.paste(&clipboard, (10, 0, 0), PasteOptions::new().with_orientation(flip)), 3);
+        assert_eq!(world.get_block((10, 0, 0)), stone);
+        assert_eq!(world.get_block((9, 0, 0)), stone);
+        assert_eq!(world.get_data((10, 0, 0)), Some(&Tag::from("hello")));
+        assert!(world.enabled((9, 0, 0)));
+        let flipped = world.get_block((8, 0, 0));
+        assert_eq!(flipped["orientation"], StateValue::Orientation(Orientation::UNORIENTED.reorient(flip)));
+        // Rotated blocks end up where the orientation puts them.
+        let rotation = Orientation::UNORIENTED.rotate_y(1);
+        let position = Coord::new(0, 0, 8);
+        world.paste(&clipboard, position, PasteOptions::new().with_orientation(rotation));
+        assert_eq!(world.get_block(position + rotation.transform(Coord::new(1, 0, 0))), stone);
+        let rotated = world.get_block(position + rotation.transform(Coord::new(2, 0, 0)));
+        assert_eq!(rotated["orientation"], StateValue::Orientation(rotation));
+        // The air in the clipboard clears blocks unless it's skipped.
+        world.set_block((20, 1, 0), stone);
+        world.paste(&clipboard, (20, 0, 0), PasteOptions::new().with_mask(PasteMask::SkipAir));
+        assert_eq!(world.get_block((20, 1, 0)), stone);
+        world.paste(&clipboard, (20, 0, 0), PasteOptions::new());
+        assert_eq!(world.get_block((20, 1, 0)), Id::AIR);
+        // Only air is replaced.
+        world.set_block((30, 0, 0), oriented);
+        assert_eq!(world.paste(&clipboard, (30, 0, 0), PasteOptions::new().with_mask(PasteMask::OnlyReplaceAir)), 2);
+        assert_eq!(world.get_block((30, 0, 0)), oriented);
+        assert_eq!(world.get_block((31, 0, 0)), stone);
+    }
+}
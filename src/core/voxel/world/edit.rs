@@ -0,0 +1,396 @@
+#![allow(unused)]
+use hashbrown::{HashMap, HashSet};
+use rollgrid::rollgrid3d::Bounds3D;
+
+use crate::core::voxel::axis::Axis;
+use crate::core::voxel::lighting::engine::{update_light, LightChannel};
+use crate::core::voxel::lighting::lightargs::LightArgs;
+use crate::core::voxel::{blocks::Id, coord::Coord, direction::Direction};
+
+use super::chunkcoord::ChunkCoord;
+use super::externevent::ExternEvent;
+use super::update::UpdateRef;
+use super::VoxelWorld;
+
+/// An area of the world for world-edit operations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    /// Every block from `min` to `max` (inclusive).
+    Cuboid {
+        min: Coord,
+        max: Coord,
+    },
+    /// Every block whose center is within `radius` of the center of the `center` block.
+    Sphere {
+        center: Coord,
+        radius: f32,
+    },
+}
+
+impl Selection {
+    /// Selects every block between two corners (inclusive). The corners can be given in any order.
+    pub fn cuboid<A: Into<(i32, i32, i32)>, B: Into<(i32, i32, i32)>>(a: A, b: B) -> Self {
+        let a: (i32, i32, i32) = a.into();
+        let a: Coord = a.into();
+        let b: (i32, i32, i32) = b.into();
+        let b: Coord = b.into();
+        Selection::Cuboid {
+            min: Coord::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Coord::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn sphere<C: Into<(i32, i32, i32)>>(center: C, radius: f32) -> Self {
+        let center: (i32, i32, i32) = center.into();
+        let center: Coord = center.into();
+        Selection::Sphere {
+            center,
+            radius: radius.max(0.0),
+        }
+    }
+
+    /// The smallest box that contains the selection. The max is exclusive.
+    pub fn bounds(self) -> Bounds3D {
+        match self {
+            Selection::Cuboid { min, max } => Bounds3D::new(
+                min.xyz(),
+                (max.x + 1, max.y + 1, max.z + 1),
+            ),
+            Selection::Sphere { center, radius } => {
+                let r = radius.floor() as i32;
+                Bounds3D::new(
+                    (center.x - r, center.y - r, center.z - r),
+                    (center.x + r + 1, center.y + r + 1, center.z + r + 1),
+                )
+            }
+        }
+    }
+
+    pub fn contains<C: Into<(i32, i32, i32)>>(self, coord: C) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        match self {
+            Selection::Cuboid { min, max } => {
+                coord.x >= min.x && coord.y >= min.y && coord.z >= min.z
+                && coord.x <= max.x && coord.y <= max.y && coord.z <= max.z
+            }
+            Selection::Sphere { center, radius } => {
+                let diff = coord - center;
+                let dist_sq = (diff.x as i64 * diff.x as i64 + diff.y as i64 * diff.y as i64 + diff.z as i64 * diff.z as i64) as f32;
+                dist_sq <= radius * radius
+            }
+        }
+    }
+
+    /// Determines if `coord` is in the selection and has a neighbor that isn't.
+    pub fn on_surface<C: Into<(i32, i32, i32)>>(self, coord: C) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.contains(coord)
+        && Direction::iter().any(|dir| !self.contains(coord + dir))
+    }
+
+    /// Determines if `coord` is in the selection and has a neighbor on the x or z axis that isn't.
+    /// The top and bottom of the selection are not part of the walls.
+    pub fn on_wall<C: Into<(i32, i32, i32)>>(self, coord: C) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.contains(coord)
+        && Direction::iter()
+            .filter(|dir| dir.axis() != Axis::Y)
+            .any(|dir| !self.contains(coord + dir))
+    }
+
+    /// Iterates over every coordinate in the selection in yzx order.
+    pub fn iter(self) -> impl Iterator<Item = Coord> {
+        let Bounds3D { min, max } = self.bounds();
+        (min.1..max.1).flat_map(move |y| {
+            (min.2..max.2).flat_map(move |z| {
+                (min.0..max.0).map(move |x| Coord::new(x, y, z))
+            })
+        }).filter(move |&coord| self.contains(coord))
+    }
+}
+
+impl VoxelWorld {
+    /// Sets many blocks at once. This is the fast path that every world-edit operation uses.
+    ///
+    /// Blocks are written straight into their sections. None of the block callbacks are called
+    /// (`on_place`, `on_remove`, `neighbor_updated`, `on_data_delete`), the data of replaced blocks is
+    /// deleted, and replaced blocks are removed from the update queue. Occlusion and light are
+    /// recalculated for the whole edit at once, and each affected section is marked dirty once.
+    ///
+    /// Blocks outside of the world bounds are ignored. Returns the number of blocks that were changed.
+    pub fn set_blocks<C: Into<(i32, i32, i32)>, S: Into<Id>, It: IntoIterator<Item = (C, S)>>(&mut self, blocks: It) -> usize {
+        let bounds = self.bounds();
+        // The state and light of each block before the edit.
+        let mut original: HashMap<Coord, (Id, LightArgs)> = HashMap::new();
+        // The height of each column before the edit.
+        let mut heights: HashMap<(i32, i32), i32> = HashMap::new();
+        let mut touched = Vec::new();
+        for (coord, state) in blocks {
+            let coord: (i32, i32, i32) = coord.into();
+            let coord: Coord = coord.into();
+            let state: Id = state.into();
+            if !bounds.contains(coord) {
+                continue;
+            }
+            let old = self.get_block(coord);
+            if old == state {
+                continue;
+            }
+            if !original.contains_key(&coord) {
+                self.record_change(coord);
+                let old_light = old.block().light_args(self, coord, old);
+                original.insert(coord, (old, old_light));
+                touched.push(coord);
+            }
+            if !heights.contains_key(&coord.xz()) {
+                heights.insert(coord.xz(), self.height(coord.x, coord.z));
+            }
+            let chunk = self.chunks.get_mut((coord.x >> 4, coord.z >> 4)).expect("Chunk was None");
+            let deleted = chunk.delete_data(coord).is_some();
+            chunk.set_block(coord, state);
+            let update_ref = chunk.set_update_ref(coord, UpdateRef::NULL);
+            if deleted {
+                self.emit(ExternEvent::DataDeleted(coord));
+            }
+            if !update_ref.null() {
+                self.update_queue.remove(update_ref);
+                self.emit(ExternEvent::EnabledChanged { coord, enabled: false });
+            }
+        }
+        // A block can be set more than once, so it may have ended up where it started.
+        let changed: Vec<Coord> = touched.into_iter()
+            .filter(|coord| self.get_block(*coord) != original[coord].0)
+            .collect();
+        if changed.is_empty() {
+            return 0;
+        }
+        changed.iter().for_each(|&coord| {
+            let (old, _) = original[&coord];
+            let new = self.get_block(coord);
+            self.emit(ExternEvent::BlockChanged { coord, old, new });
+        });
+        let changed_set: HashSet<Coord> = changed.iter().cloned().collect();
+        let mut sections = HashSet::new();
+        for &coord in changed.iter() {
+            sections.insert(coord.section_coord());
+            for dir in Direction::iter() {
+                let Some(adj_coord) = coord.checked_neighbor(dir) else {
+                    continue;
+                };
+                // Faces between two changed blocks only need to be calculated once.
+                let positive = matches!(dir, Direction::PosX | Direction::PosY | Direction::PosZ);
+                if !positive && changed_set.contains(&adj_coord) {
+                    continue;
+                }
+                self.occlude_pair(coord, dir);
+                if bounds.contains(adj_coord) {
+                    sections.insert(adj_coord.section_coord());
+                }
+            }
+        }
+        if !self.defer_lighting {
+            let light_changed: Vec<Coord> = changed.iter().cloned().filter(|&coord| {
+                let state = self.get_block(coord);
+                state.block().light_args(self, coord, state) != original[&coord].1
+            }).collect();
+            let mut sky_coords = light_changed.clone();
+            heights.iter().for_each(|(&(x, z), &old_height)| {
+                let new_height = self.height(x, z);
+                let (low, high) = (old_height.min(new_height), old_height.max(new_height));
+                sky_coords.extend((low..high).map(|y| Coord::new(x, y, z)));
+            });
+            update_light(self, LightChannel::Block, light_changed);
+            update_light(self, LightChannel::Sky, sky_coords);
+        } else {
+            changed.iter().for_each(|&coord| self.mark_sky_light_stale(coord));
+        }
+        let chunks: HashSet<(i32, i32)> = sections.iter().map(|section_coord| section_coord.xz()).collect();
+        chunks.into_iter().for_each(|(chunk_x, chunk_z)| {
+            self.mark_modified(ChunkCoord::new(chunk_x, chunk_z));
+        });
+        sections.into_iter().for_each(|section_coord| {
+            self.mark_section_dirty(section_coord);
+        });
+        changed.len()
+    }
+
+    /// Calculates the occlusion of the faces between the block at `coord` and its neighbor in `dir`.
+    /// This writes to the chunks directly, so the sections still need to be marked dirty in the world.
+    pub(crate) fn occlude_pair(&mut self, coord: Coord, dir: Direction) {
+        let adj_coord = coord + dir;
+        let adj_dir = dir.invert();
+        let state = self.get_block(coord);
+        let adj_state = self.get_block(adj_coord);
+        let block = state.block();
+        let adj_block = adj_state.block();
+        let my_layer = block.layer(self, coord, state);
+        let adj_layer = adj_block.layer(self, adj_coord, adj_state);
+        // No occlusion happens if they are on different layers.
+        // Transparent blocks only occlude the same block so that they can be seen through each other.
+        if adj_layer != my_layer
+        || (my_layer.transparent() && adj_state.block_id() != state.block_id()) {
+            self.set_face_hidden(coord, dir, false);
+            self.set_face_hidden(adj_coord, adj_dir, false);
+            return;
+        }
+        let my_orient = block.orientation(self, coord, state);
+        let my_occl = block.occluder(self, state);
+        let my_occlee = block.occludee(self, state);
+        let adj_orient = adj_block.orientation(self, adj_coord, adj_state);
+        let adj_occl = adj_block.occluder(self, adj_state);
+        let adj_occlee = adj_block.occludee(self, adj_state);
+        let hidden = my_occlee.occluded_by(my_orient, dir, adj_occl, adj_orient);
+        self.set_face_hidden(coord, dir, hidden);
+        let adj_hidden = adj_occlee.occluded_by(adj_orient, adj_dir, my_occl, my_orient);
+        self.set_face_hidden(adj_coord, adj_dir, adj_hidden);
+    }
+
+    fn set_face_hidden(&mut self, coord: Coord, face: Direction, hidden: bool) {
+        if !self.bounds().contains(coord) {
+            return;
+        }
+        let chunk = self.chunks.get_mut((coord.x >> 4, coord.z >> 4)).expect("Chunk was None");
+        if hidden {
+            chunk.hide_face(coord, face);
+        } else {
+            chunk.show_face(coord, face);
+        }
+    }
+
+    /// Calls `f` with the coordinate and state of every block in the selection.
+    /// Every block that `f` returns a state for is set to that state with [VoxelWorld::set_blocks].
+    /// Returns the number of blocks that were changed.
+    pub fn edit_blocks<F: FnMut(Coord, Id) -> Option<Id>>(&mut self, selection: Selection, mut f: F) -> usize {
+        let edits: Vec<(Coord, Id)> = selection.iter()
+            .filter_map(|coord| f(coord, self.get_block(coord)).map(|state| (coord, state)))
+            .collect();
+        self.set_blocks(edits)
+    }
+
+    /// Sets every block in the selection to `state`. Returns the number of blocks that were changed.
+    pub fn fill<S: Into<Id>>(&mut self, selection: Selection, state: S) -> usize {
+        let state: Id = state.into();
+        self.edit_blocks(selection, |_, _| Some(state))
+    }
+
+    /// Replaces every `from` state in the selection with `to`. Returns the number of blocks that were changed.
+    pub fn replace<A: Into<Id>, B: Into<Id>>(&mut self, selection: Selection, from: A, to: B) -> usize {
+        let (from, to): (Id, Id) = (from.into(), to.into());
+        self.edit_blocks(selection, |_, old| (old == from).then_some(to))
+    }
+
+    /// Replaces every block in the selection that matches the `predicate` with `state`.
+    /// Returns the number of blocks that were changed.
+    pub fn replace_where<S: Into<Id>, F: FnMut(Id) -> bool>(&mut self, selection: Selection, mut predicate: F, state: S) -> usize {
+        let state: Id = state.into();
+        self.edit_blocks(selection, |_, old| predicate(old).then_some(state))
+    }
+
+    /// Sets the sides of the selection to `state`. The top and bottom are left alone.
+    /// Returns the number of blocks that were changed.
+    pub fn walls<S: Into<Id>>(&mut self, selection: Selection, state: S) -> usize {
+        let state: Id = state.into();
+        self.edit_blocks(selection, |coord, _| selection.on_wall(coord).then_some(state))
+    }
+
+    /// Sets the surface of the selection to `state`. The inside is left alone.
+    /// Returns the number of blocks that were changed.
+    pub fn outline<S: Into<Id>>(&mut self, selection: Selection, state: S) -> usize {
+        let state: Id = state.into();
+        self.edit_blocks(selection, |coord, _| selection.on_surface(coord).then_some(state))
+    }
+
+    /// Sets the surface of the selection to `state` and clears the inside to air.
+    /// Returns the number of blocks that were changed.
+    pub fn hollow<S: Into<Id>>(&mut self, selection: Selection, state: S) -> usize {
+        let state: Id = state.into();
+        self.edit_blocks(selection, |coord, _| {
+            Some(if selection.on_surface(coord) { state } else { Id::AIR })
+        })
+    }
+
+    /// Places `state` on top of the highest block in each column of the selection if there's air above it.
+    /// Returns the number of blocks that were changed.
+    pub fn overlay<S: Into<Id>>(&mut self, selection: Selection, state: S) -> usize {
+        let state: Id = state.into();
+        let Bounds3D { min, max } = selection.bounds();
+        let mut edits = Vec::new();
+        for z in min.2..max.2 {
+            for x in min.0..max.0 {
+                let top = (min.1..max.1).rev()
+                    .map(|y| Coord::new(x, y, z))
+                    .filter(|&coord| selection.contains(coord))
+                    .find(|&coord| !self.get_block(coord).is_air());
+                let Some(top) = top else {
+                    continue;
+                };
+                let above = top + Direction::PosY;
+                if self.get_block(above).is_air() {
+                    edits.push((above, state));
+                }
+            }
+        }
+        self.set_blocks(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::testing::{open_world, register_test_blocks, TestDirectory};
+
+    use super::*;
+
+    #[test]
+    fn selection_test() {
+        let cuboid = Selection::cuboid((2, 3, 4), (0, 0, 0));
+        assert_eq!(cuboid.iter().count(), 3 * 4 * 5);
+        assert!(cuboid.on_surface((0, 1, 1)));
+        assert!(!cuboid.on_surface((1, 1, 1)));
+        assert!(cuboid.on_wall((0, 1, 1)));
+        assert!(!cuboid.on_wall((1, 0, 1)));
+        let sphere = Selection::sphere((0, 0, 0), 1.0);
+        assert_eq!(sphere.iter().count(), 7);
+        assert!(!sphere.contains((1, 1, 0)));
+    }
+
+    #[test]
+    fn world_edit_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("world_edit_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // The box crosses chunk and section borders.
+        let area = Selection::cuboid((-2, -2, -2), (2, 2, 2));
+        assert_eq!(world.fill(area, stone), 125);
+        assert_eq!(world.fill(area, stone), 0);
+        assert_eq!(world.get_block((-2, 2, 2)), stone);
+        // Faces between filled blocks are hidden and faces on the outside are visible.
+        assert!(world.get_occlusion((0, 0, 0)).hidden(Direction::NegX));
+        assert!(world.get_occlusion((-1, 0, 0)).hidden(Direction::PosX));
+        assert!(world.get_occlusion((2, 0, 0)).visible(Direction::PosX));
+        assert_eq!(world.height(0, 0), 3);
+        assert_eq!(world.get_sky_light((0, 3, 0)), 15);
+        assert_eq!(world.get_sky_light((0, 2, 0)), 0);
+        // Hollowing out the box clears the 3x3x3 inside.
+        assert_eq!(world.hollow(area, stone), 27);
+        assert_eq!(world.get_block((0, 0, 0)), Id::AIR);
+        assert!(world.get_occlusion((-2, 0, 0)).visible(Direction::PosX));
+        assert_eq!(world.replace(area, stone, Id::AIR), 98);
+        assert_eq!(world.walls(area, stone), 80);
+        assert_eq!(world.get_block((0, 0, 0)), Id::AIR);
+        assert_eq!(world.replace_where(area, |state| state == stone, Id::AIR), 80);
+        // A floor with a single pillar gets covered once per column.
+        world.fill(Selection::cuboid((0, 0, 0), (3, 0, 3)), stone);
+        world.set_block((1, 1, 1), stone);
+        assert_eq!(world.overlay(Selection::cuboid((0, 0, 0), (3, 8, 3)), stone), 16);
+        assert_eq!(world.get_block((1, 2, 1)), stone);
+        assert_eq!(world.get_block((1, 1, 0)), stone);
+        assert_eq!(world.fill(Selection::sphere((0, 20, 0), 1.0), stone), 7);
+        // Blocks outside of the world are ignored.
+        assert_eq!(world.set_blocks([((0, 10000, 0), stone)]), 0);
+    }
+}
@@ -1,7 +1,211 @@
-use crate::prelude::Coord;
+use bevy::ecs::{event::{Event, EventWriter}, system::ResMut};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use crate::core::voxel::{blocks::Id, lighting::engine::LightChannel};
+use crate::prelude::{ChunkCoord, Coord};
+
+use super::VoxelWorld;
+
+/// Changes to the world that game systems outside of the [Block](crate::core::voxel::block::Block) trait
+/// (audio, particles, networking, etc.) might be interested in.
+///
+/// Events are only recorded if they pass the world's [EventFilter]. Recorded events are drained with
+/// [VoxelWorld::drain_events], or forwarded to Bevy by the [forward_extern_events] system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Event)]
 pub enum ExternEvent {
+    BlockChanged {
+        coord: Coord,
+        old: Id,
+        new: Id,
+    },
+    DataSet(Coord),
+    DataDeleted(Coord),
+    EnabledChanged {
+        coord: Coord,
+        enabled: bool,
+    },
+    LightChanged {
+        coord: Coord,
+        channel: LightChannel,
+        old: u8,
+        new: u8,
+    },
+    ChunkLoaded(ChunkCoord),
+    ChunkUnloaded(ChunkCoord),
+    ChunkGenerated(ChunkCoord),
+    /// (section coord)
+    SectionMeshed(Coord),
     /// (section coord)
     RenderChunkMoved(Coord),
-}
\ No newline at end of file
+}
+
+impl ExternEvent {
+    /// The [EventFilter] flag that allows this event.
+    pub const fn filter(self) -> EventFilter {
+        match self {
+            ExternEvent::BlockChanged { .. } => EventFilter::BLOCK_CHANGED,
+            ExternEvent::DataSet(_) | ExternEvent::DataDeleted(_) => EventFilter::DATA,
+            ExternEvent::EnabledChanged { .. } => EventFilter::ENABLED,
+            ExternEvent::LightChanged { .. } => EventFilter::LIGHT,
+            ExternEvent::ChunkLoaded(_) | ExternEvent::ChunkUnloaded(_) | ExternEvent::ChunkGenerated(_) => EventFilter::CHUNKS,
+            ExternEvent::SectionMeshed(_) => EventFilter::SECTION_MESHED,
+            ExternEvent::RenderChunkMoved(_) => EventFilter::RENDER_CHUNK_MOVED,
+        }
+    }
+}
+
+/// Determines which [ExternEvent]s are recorded by a [VoxelWorld].
+/// Nothing is recorded by default so that the event queue doesn't grow when nothing drains it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventFilter(pub u16);
+
+impl EventFilter {
+    pub const NONE: EventFilter = EventFilter(0);
+    pub const BLOCK_CHANGED: EventFilter = EventFilter(0b0000001);
+    /// [ExternEvent::DataSet] and [ExternEvent::DataDeleted].
+    pub const DATA: EventFilter = EventFilter(0b0000010);
+    pub const ENABLED: EventFilter = EventFilter(0b0000100);
+    /// This is the highest volume event, since a single block change can change the light of thousands of blocks.
+    pub const LIGHT: EventFilter = EventFilter(0b0001000);
+    /// [ExternEvent::ChunkLoaded], [ExternEvent::ChunkUnloaded], and [ExternEvent::ChunkGenerated].
+    pub const CHUNKS: EventFilter = EventFilter(0b0010000);
+    pub const SECTION_MESHED: EventFilter = EventFilter(0b0100000);
+    pub const RENDER_CHUNK_MOVED: EventFilter = EventFilter(0b1000000);
+    /// The events that happen to individual blocks.
+    pub const BLOCK_EVENTS: EventFilter = EventFilter(0b0001111);
+    pub const ALL: EventFilter = EventFilter(0b1111111);
+
+    pub const fn contains(self, filter: EventFilter) -> bool {
+        self.0 & filter.0 == filter.0
+    }
+
+    pub const fn allows(self, event: ExternEvent) -> bool {
+        self.contains(event.filter())
+    }
+}
+
+impl std::ops::BitOr<EventFilter> for EventFilter {
+    type Output = Self;
+
+    fn bitor(self, rhs: EventFilter) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign<EventFilter> for EventFilter {
+    fn bitor_assign(&mut self, rhs: EventFilter) {
+        *self = *self | rhs;
+    }
+}
+
+impl std::ops::BitAnd<EventFilter> for EventFilter {
+    type Output = Self;
+
+    fn bitand(self, rhs: EventFilter) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::Sub<EventFilter> for EventFilter {
+    type Output = EventFilter;
+
+    fn sub(self, rhs: EventFilter) -> Self::Output {
+        self & !rhs
+    }
+}
+
+impl std::ops::Not for EventFilter {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(!self.0 & EventFilter::ALL.0)
+    }
+}
+
+impl VoxelWorld {
+    /// Records an event if the [EventFilter] allows it.
+    #[inline]
+    pub(crate) fn emit(&mut self, event: ExternEvent) {
+        if self.event_filter.allows(event) {
+            self.events.push(event);
+        }
+    }
+
+    /// Removes every recorded event from the queue (in the order that they happened).
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, ExternEvent> {
+        self.events.drain(..)
+    }
+
+    /// Calls `f` with [EventFilter::BLOCK_EVENTS] filtered out. Chunk loading and generation use this so
+    /// that they emit a single chunk event rather than an event for every block.
+    pub(crate) fn without_block_events<R, F: FnOnce(&mut Self) -> R>(&mut self, f: F) -> R {
+        let event_filter = self.event_filter;
+        self.event_filter = event_filter - EventFilter::BLOCK_EVENTS;
+        let result = f(self);
+        self.event_filter = event_filter;
+        result
+    }
+}
+
+/// Forwards the events recorded by the [VoxelWorld] resource as Bevy [Event]s.
+/// Register [ExternEvent] with `App::add_event` to use this system.
+pub fn forward_extern_events(mut world: ResMut<VoxelWorld>, mut events: EventWriter<ExternEvent>) {
+    if world.events.is_empty() {
+        return;
+    }
+    events.send_batch(world.drain_events());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::testing::{open_world, register_test_blocks, TestDirectory};
+
+    use super::*;
+
+    #[test]
+    fn filter_test() {
+        let filter = EventFilter::ALL - EventFilter::LIGHT;
+        assert!(filter.contains(EventFilter::BLOCK_CHANGED | EventFilter::CHUNKS));
+        assert!(!filter.allows(ExternEvent::LightChanged { coord: Coord::splat(0), channel: LightChannel::Sky, old: 0, new: 15 }));
+        assert!(filter.allows(ExternEvent::DataDeleted(Coord::splat(0))));
+        assert_eq!(!EventFilter::NONE, EventFilter::ALL);
+    }
+
+    #[test]
+    fn extern_event_test() {
+        register_test_blocks();
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("extern_event_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // Nothing is recorded by default.
+        world.set_block((0, 0, 0), state);
+        assert_eq!(world.drain_events().count(), 0);
+        world.event_filter = EventFilter::ALL - EventFilter::LIGHT;
+        world.set_block((1, 0, 0), state);
+        world.set_data((1, 0, 0), 5i32);
+        world.enable((1, 0, 0));
+        world.set_block((1, 0, 0), Id::AIR);
+        let events: Vec<ExternEvent> = world.drain_events().collect();
+        assert_eq!(events, vec![
+            ExternEvent::BlockChanged { coord: Coord::new(1, 0, 0), old: Id::AIR, new: state },
+            ExternEvent::DataSet(Coord::new(1, 0, 0)),
+            ExternEvent::EnabledChanged { coord: Coord::new(1, 0, 0), enabled: true },
+            ExternEvent::DataDeleted(Coord::new(1, 0, 0)),
+            ExternEvent::BlockChanged { coord: Coord::new(1, 0, 0), old: state, new: Id::AIR },
+            ExternEvent::EnabledChanged { coord: Coord::new(1, 0, 0), enabled: false },
+        ]);
+        assert_eq!(world.drain_events().count(), 0);
+        world.event_filter = EventFilter::LIGHT;
+        world.set_block((0, 0, 0), Id::AIR);
+        let events: Vec<ExternEvent> = world.drain_events().collect();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| matches!(event, ExternEvent::LightChanged { .. })));
+        // Moving the world unloads chunks and loads the chunks that replace them.
+        world.event_filter = EventFilter::CHUNKS;
+        world.move_center((16 * 8, 0, 0));
+        world.flush_load_queue();
+        let events: Vec<ExternEvent> = world.drain_events().collect();
+        assert!(events.iter().any(|event| matches!(event, ExternEvent::ChunkUnloaded(_))));
+        assert!(events.iter().all(|event| event.filter() == EventFilter::CHUNKS));
+    }
+}
@@ -141,6 +141,11 @@ impl Heightmap {
         old
     }
 
+    /// Returns true if every column of the heightmap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.heightmap.iter().all(|&height| height == 0)
+    }
+
     pub fn height(&self, x: i32, z: i32) -> i32 {
         let x = x & 0xF;
         let z = z & 0xF;
@@ -0,0 +1,469 @@
+#![allow(unused)]
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+
+use crate::core::io::Writeable;
+use crate::core::voxel::{blocks::Id, coord::Coord, tag::Tag};
+
+use super::chunkcoord::ChunkCoord;
+use super::externevent::ExternEvent;
+use super::update::UpdateRef;
+use super::VoxelWorld;
+
+/// The state of a single block at some point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSnapshot {
+    pub state: Id,
+    pub data: Option<Tag>,
+    pub enabled: bool,
+}
+
+/// A change to a single block that can be undone and redone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockChange {
+    pub coord: Coord,
+    pub old: BlockSnapshot,
+    pub new: BlockSnapshot,
+}
+
+impl BlockChange {
+    /// An estimate of the number of bytes that this change keeps in memory.
+    fn memory_usage(&self) -> usize {
+        fn tag_size(tag: &Option<Tag>) -> usize {
+            tag.as_ref().map_or(0, |tag| tag.write_to(&mut std::io::sink()).unwrap_or(0) as usize)
+        }
+        std::mem::size_of::<BlockChange>() + tag_size(&self.old.data) + tag_size(&self.new.data)
+    }
+}
+
+/// A named group of changes that are undone and redone together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub name: String,
+    pub changes: Vec<BlockChange>,
+    memory_usage: usize,
+}
+
+impl Transaction {
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+}
+
+/// The amount of history that an [EditHistory] keeps before it starts forgetting the oldest transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryLimits {
+    /// The maximum number of transactions that can be undone.
+    pub max_transactions: usize,
+    /// The (estimated) maximum number of bytes used by the transactions that can be undone.
+    pub max_memory: usize,
+}
+
+impl HistoryLimits {
+    pub const fn new(max_transactions: usize, max_memory: usize) -> Self {
+        Self {
+            max_transactions,
+            max_memory,
+        }
+    }
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self::new(100, 64 * 1024 * 1024)
+    }
+}
+
+/// The transaction that is being recorded. Only the state from before the transaction is
+/// recorded for each block, the new state is read from the world when the transaction ends.
+#[derive(Debug)]
+struct OpenTransaction {
+    name: String,
+    depth: u32,
+    coords: Vec<Coord>,
+    before: HashMap<Coord, BlockSnapshot>,
+}
+
+/// Records changes made with [VoxelWorld::set_block], [VoxelWorld::set_blocks], [VoxelWorld::set_data],
+/// and [VoxelWorld::set_enabled] (along with the rest of the data API) so that they can be undone.
+///
+/// Changes are only recorded while a transaction is open (see [VoxelWorld::begin_transaction]).
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    pub limits: HistoryLimits,
+    undo_stack: VecDeque<Transaction>,
+    redo_stack: Vec<Transaction>,
+    open: Option<OpenTransaction>,
+    memory_usage: usize,
+}
+
+impl EditHistory {
+    pub fn new(limits: HistoryLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// The name of the transaction that will be undone next.
+    pub fn undo_name(&self) -> Option<&str> {
+        self.undo_stack.back().map(|transaction| transaction.name.as_str())
+    }
+
+    /// The name of the transaction that will be redone next.
+    pub fn redo_name(&self) -> Option<&str> {
+        self.redo_stack.last().map(|transaction| transaction.name.as_str())
+    }
+
+    pub fn undo_count(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn redo_count(&self) -> usize {
+        self.redo_stack.len()
+    }
+
+    /// The (estimated) number of bytes used by the transactions that can be undone or redone.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn recording(&self) -> bool {
+        self.open.is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.memory_usage = 0;
+    }
+
+    fn needs_snapshot(&self, coord: Coord) -> bool {
+        self.open.as_ref().is_some_and(|open| !open.before.contains_key(&coord))
+    }
+
+    fn push_undo(&mut self, transaction: Transaction) {
+        self.memory_usage += transaction.memory_usage;
+        self.undo_stack.push_back(transaction);
+        self.enforce_limits();
+    }
+
+    /// Forgets the oldest transactions until the history is within its limits.
+    fn enforce_limits(&mut self) {
+        while self.undo_stack.len() > self.limits.max_transactions
+        || (self.memory_usage > self.limits.max_memory && !self.undo_stack.is_empty()) {
+            let Some(oldest) = self.undo_stack.pop_front() else {
+                unreachable!()
+            };
+            self.memory_usage -= oldest.memory_usage;
+        }
+        // Redo transactions are only kept within the memory limit if there's room for them.
+        while self.memory_usage > self.limits.max_memory {
+            let Some(oldest) = self.redo_stack.first() else {
+                break;
+            };
+            self.memory_usage -= oldest.memory_usage;
+            self.redo_stack.remove(0);
+        }
+    }
+}
+
+impl VoxelWorld {
+    /// Starts recording changes so that they can be undone. Does nothing if the history is already enabled.
+    pub fn enable_history(&mut self, limits: HistoryLimits) {
+        if self.history.is_none() {
+            self.history = Some(EditHistory::new(limits));
+        }
+    }
+
+    /// Stops recording changes and forgets the recorded history.
+    pub fn disable_history(&mut self) -> Option<EditHistory> {
+        self.history.take()
+    }
+
+    /// Opens a transaction with the given name. Every change made until [VoxelWorld::end_transaction]
+    /// is undone and redone as one. Transactions can be nested, in which case the inner transactions
+    /// become part of the outermost transaction.
+    ///
+    /// Does nothing if the history is not enabled.
+    pub fn begin_transaction<S: Into<String>>(&mut self, name: S) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+        if let Some(open) = history.open.as_mut() {
+            open.depth += 1;
+            return;
+        }
+        history.open = Some(OpenTransaction {
+            name: name.into(),
+            depth: 1,
+            coords: Vec::new(),
+            before: HashMap::new(),
+        });
+    }
+
+    /// Closes the transaction opened by [VoxelWorld::begin_transaction]. When the outermost transaction
+    /// is closed, it is added to the history (unless nothing changed) and the redo history is cleared.
+    /// Returns `true` if a transaction was added to the history.
+    pub fn end_transaction(&mut self) -> bool {
+        let Some(history) = self.history.as_mut() else {
+            return false;
+        };
+        let Some(open) = history.open.as_mut() else {
+            return false;
+        };
+        open.depth -= 1;
+        if open.depth > 0 {
+            return false;
+        }
+        let Some(OpenTransaction { name, coords, mut before, .. }) = history.open.take() else {
+            unreachable!()
+        };
+        let mut memory_usage = 0;
+        let changes: Vec<BlockChange> = coords.into_iter().filter_map(|coord| {
+            let old = before.remove(&coord)?;
+            let new = self.snapshot_block(coord);
+            if old == new {
+                return None;
+            }
+            let change = BlockChange { coord, old, new };
+            memory_usage += change.memory_usage();
+            Some(change)
+        }).collect();
+        if changes.is_empty() {
+            return false;
+        }
+        let Some(history) = self.history.as_mut() else {
+            unreachable!()
+        };
+        history.redo_stack.clear();
+        history.memory_usage = history.undo_stack.iter().map(Transaction::memory_usage).sum();
+        history.push_undo(Transaction { name, changes, memory_usage });
+        true
+    }
+
+    /// Calls `f` within a transaction with the given name.
+    pub fn transaction<S: Into<String>, R, F: FnOnce(&mut Self) -> R>(&mut self, name: S, f: F) -> R {
+        self.begin_transaction(name);
+        let result = f(self);
+        self.end_transaction();
+        result
+    }
+
+    /// Reverts the last transaction. Blocks are restored without calling any of their callbacks
+    /// (such as `on_place`), but occlusion, light, and dirty flags are updated.
+    ///
+    /// Returns the name of the transaction that was undone. Nothing is undone while a transaction is open.
+    pub fn undo(&mut self) -> Option<String> {
+        let history = self.history.as_mut()?;
+        if history.open.is_some() {
+            return None;
+        }
+        let transaction = history.undo_stack.pop_back()?;
+        self.restore_blocks(transaction.changes.iter().map(|change| (change.coord, &change.old)));
+        let name = transaction.name.clone();
+        let Some(history) = self.history.as_mut() else {
+            unreachable!()
+        };
+        history.redo_stack.push(transaction);
+        Some(name)
+    }
+
+    /// Reapplies the last transaction that was undone. Like [VoxelWorld::undo], this does not call block callbacks.
+    ///
+    /// Returns the name of the transaction that was redone. Nothing is redone while a transaction is open.
+    pub fn redo(&mut self) -> Option<String> {
+        let history = self.history.as_mut()?;
+        if history.open.is_some() {
+            return None;
+        }
+        let transaction = history.redo_stack.pop()?;
+        history.memory_usage -= transaction.memory_usage;
+        self.restore_blocks(transaction.changes.iter().map(|change| (change.coord, &change.new)));
+        let name = transaction.name.clone();
+        let Some(history) = self.history.as_mut() else {
+            unreachable!()
+        };
+        history.push_undo(transaction);
+        Some(name)
+    }
+
+    /// Records the state of the block at `coord` if a transaction is open and it hasn't been recorded yet.
+    /// This must be called before the block is changed.
+    pub(crate) fn record_change(&mut self, coord: Coord) {
+        if !self.history.as_ref().is_some_and(|history| history.needs_snapshot(coord)) {
+            return;
+        }
+        let snapshot = self.snapshot_block(coord);
+        let Some(open) = self.history.as_mut().and_then(|history| history.open.as_mut()) else {
+            unreachable!()
+        };
+        open.coords.push(coord);
+        open.before.insert(coord, snapshot);
+    }
+
+    fn snapshot_block(&self, coord: Coord) -> BlockSnapshot {
+        BlockSnapshot {
+            state: self.get_block(coord),
+            data: self.get_data(coord).cloned(),
+            enabled: self.enabled(coord),
+        }
+    }
+
+    /// Sets blocks back to the given snapshots. The history is taken out of the world while this
+    /// happens so that restoring isn't recorded.
+    fn restore_blocks<'a, It: Iterator<Item = (Coord, &'a BlockSnapshot)>>(&mut self, snapshots: It) {
+        let history = self.history.take();
+        let snapshots: Vec<(Coord, &BlockSnapshot)> = snapshots.collect();
+        self.set_blocks(snapshots.iter().map(|&(coord, snapshot)| (coord, snapshot.state)));
+        let bounds = self.bounds();
+        for &(coord, snapshot) in snapshots.iter() {
+            if !bounds.contains(coord) {
+                continue;
+            }
+            let chunk = self.chunks.get_mut((coord.x >> 4, coord.z >> 4)).expect("Chunk was None");
+            let data_event = if chunk.get_data(coord) != snapshot.data.as_ref() {
+                match &snapshot.data {
+                    Some(data) => {
+                        chunk.set_data(coord, data.clone());
+                        Some(ExternEvent::DataSet(coord))
+                    },
+                    None => chunk.delete_data(coord).map(|_| ExternEvent::DataDeleted(coord)),
+                }
+            } else {
+                None
+            };
+            let cur_ref = chunk.get_update_ref(coord);
+            let enabled_event = if cur_ref.null() && snapshot.enabled && !snapshot.state.is_air() {
+                let new_ref = self.update_queue.push(coord);
+                chunk.set_update_ref(coord, new_ref);
+                Some(ExternEvent::EnabledChanged { coord, enabled: true })
+            } else if !cur_ref.null() && !snapshot.enabled {
+                chunk.set_update_ref(coord, UpdateRef::NULL);
+                self.update_queue.remove(cur_ref);
+                Some(ExternEvent::EnabledChanged { coord, enabled: false })
+            } else {
+                None
+            };
+            data_event.into_iter().chain(enabled_event).for_each(|event| self.emit(event));
+            self.mark_modified(coord.chunk_coord());
+        }
+        self.history = history;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{block::Block, blocks, blockstate::BlockState, direction::Direction, testing::{self, open_world, TestDirectory}, world::{edit::Selection, PlaceContext}};
+
+    use super::*;
+
+    /// Places `test_stone` above itself when it is placed.
+    struct StackTestBlock;
+    impl Block for StackTestBlock {
+        fn name(&self) -> &str {
+            "test_stack"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_stack)
+        }
+
+        fn on_place(&self, world: &mut VoxelWorld, context: &mut PlaceContext) {
+            world.set_block(context.coord() + Coord::new(0, 1, 0), blockstate!(test_stone));
+        }
+    }
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(StackTestBlock);
+        });
+    }
+
+    #[test]
+    fn history_test() {
+        register_test_blocks();
+        let test_block = blockstate!(test_stone).register();
+        let stack_block = blockstate!(test_stack).register();
+        let directory = TestDirectory::new("history_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // Nothing is recorded until the history is enabled.
+        world.set_block((0, 0, 0), test_block);
+        assert_eq!(world.undo(), None);
+        world.enable_history(HistoryLimits::default());
+        // Changes outside of a transaction aren't recorded.
+        world.set_block((1, 0, 0), test_block);
+        assert_eq!(world.undo(), None);
+        world.transaction("place", |world| {
+            world.set_block((2, 0, 0), stack_block);
+            world.set_data((2, 0, 0), 3i32);
+            world.enable((2, 0, 0));
+        });
+        assert_eq!(world.get_block((2, 1, 0)), test_block);
+        world.begin_transaction("fill");
+        world.fill(Selection::cuboid((4, 0, 0), (5, 1, 1)), test_block);
+        world.begin_transaction("nested");
+        world.set_block((0, 0, 0), Id::AIR);
+        world.end_transaction();
+        // Nothing can be undone while a transaction is open.
+        assert_eq!(world.undo(), None);
+        world.end_transaction();
+        assert_eq!(world.history.as_ref().map(EditHistory::undo_count), Some(2));
+        assert_eq!(world.undo().as_deref(), Some("fill"));
+        assert_eq!(world.get_block((0, 0, 0)), test_block);
+        assert_eq!(world.get_block((4, 0, 0)), Id::AIR);
+        assert_eq!(world.get_block((5, 1, 1)), Id::AIR);
+        assert_eq!(world.undo().as_deref(), Some("place"));
+        assert_eq!(world.get_block((2, 0, 0)), Id::AIR);
+        // The block placed by on_place is also undone.
+        assert_eq!(world.get_block((2, 1, 0)), Id::AIR);
+        assert_eq!(world.get_data((2, 0, 0)), None);
+        assert!(!world.enabled((2, 0, 0)));
+        assert!(world.face_visible((1, 0, 0), Direction::PosX));
+        assert_eq!(world.undo(), None);
+        // Redoing doesn't call on_place, so the block above is restored from the history.
+        assert_eq!(world.redo().as_deref(), Some("place"));
+        assert_eq!(world.get_block((2, 0, 0)), stack_block);
+        assert_eq!(world.get_block((2, 1, 0)), test_block);
+        assert_eq!(world.get_data((2, 0, 0)), Some(&Tag::from(3i32)));
+        assert!(world.enabled((2, 0, 0)));
+        assert!(!world.face_visible((2, 0, 0), Direction::PosY));
+        assert_eq!(world.redo().as_deref(), Some("fill"));
+        assert_eq!(world.get_block((5, 1, 1)), test_block);
+        assert_eq!(world.get_block((0, 0, 0)), Id::AIR);
+        // A new transaction clears the redo history.
+        world.undo();
+        world.transaction("replace", |world| world.set_block((7, 0, 0), test_block));
+        assert_eq!(world.redo(), None);
+        // Old transactions are forgotten once the limit is reached.
+        world.history.as_mut().unwrap().limits = HistoryLimits::new(2, usize::MAX);
+        for x in 0..4 {
+            world.transaction(format!("block {x}"), |world| world.set_block((x, 5, 0), test_block));
+        }
+        let history = world.history.as_ref().unwrap();
+        assert_eq!(history.undo_count(), 2);
+        assert_eq!(history.undo_name(), Some("block 3"));
+        assert_eq!(world.undo().as_deref(), Some("block 3"));
+        assert_eq!(world.undo().as_deref(), Some("block 2"));
+        assert_eq!(world.undo(), None);
+        assert_eq!(world.get_block((1, 5, 0)), test_block);
+        // Transactions larger than the memory limit are not kept.
+        world.history.as_mut().unwrap().limits = HistoryLimits::new(100, 0);
+        world.transaction("too big", |world| world.set_block((0, 6, 0), test_block));
+        assert_eq!(world.undo(), None);
+        assert_eq!(world.history.as_ref().unwrap().memory_usage(), 0);
+    }
+}
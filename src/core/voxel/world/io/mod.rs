@@ -15,16 +15,41 @@ use super::section::Section;
 use super::update::UpdateRef;
 use super::VoxelWorld;
 
+/// Reads the blocks of a section. States whose block isn't registered are loaded as
+/// [UnknownBlock](crate::core::voxel::blocks::UnknownBlock)s so that they're saved unchanged.
 pub fn read_section_blocks<R: Read>(reader: &mut R, blocks: &mut Option<Box<[Id]>>, block_count: &mut u16) -> Result<()> {
+    let Some((palette, indices)) = read_section_palette(reader)? else {
+        *blocks = None;
+        *block_count = 0;
+        return Ok(());
+    };
+    let ids = palette.iter().map(BlockState::register_or_unknown).collect::<Vec<_>>();
+    fill_section_blocks(blocks, block_count, &ids, &indices);
+    Ok(())
+}
+
+/// Sets every block of a section to the [Id] at its palette index and counts the blocks that aren't air.
+pub fn fill_section_blocks(blocks: &mut Option<Box<[Id]>>, block_count: &mut u16, ids: &[Id], indices: &[u16]) {
+    let blocks = blocks.get_or_insert_with(|| (0..4096).map(|_| Id::AIR).collect());
+    *block_count = 0;
+    blocks.iter_mut().zip(indices.iter()).for_each(|(block, &index)| {
+        *block = ids[index as usize];
+        if block.is_non_air() {
+            *block_count += 1;
+        }
+    });
+}
+
+/// Reads the palette of a section's blocks along with the palette index of every block (in yzx order)
+/// without registering the states. Returns `None` if the section has no blocks.
+pub fn read_section_palette<R: Read>(reader: &mut R) -> Result<Option<(Vec<BlockState>, Box<[u16]>)>> {
     let mut state_count = [0u8; 2];
     reader.read_exact(&mut state_count[0..1])?;
     // First byte is null, so the chunk is empty.
     // I can completely avoid even reading this byte if I have
     // flags for which pieces of the section are present.
-    *block_count = 0;
     if state_count[0] == 0 {
-        *blocks = None;
-        return Ok(());
+        return Ok(None);
     }
     // If there is a bit at index 7, there is another byte to represent the count.
     let state_count = if state_count[0] & 0b10000000 != 0 {
@@ -34,84 +59,37 @@ pub fn read_section_blocks<R: Read>(reader: &mut R, blocks: &mut Option<Box<[Id]
     } else {
         state_count[0] as usize
     };
+    let palette = (0..state_count).map(|_| {
+        BlockState::read_from(reader)
+    }).collect::<Result<Vec<_>>>()?;
     // Only a single block in the entire chunk which fills the whole chunk
     if state_count == 1 {
-        let state = BlockState::read_from(reader)?;
-        let id = state.register();
-        blocks.replace((0..4096).map(|_| id).collect());
-        return Ok(());
+        return Ok(Some((palette, (0..4096).map(|_| 0u16).collect())));
     }
-    // let mut ids = Vec::with_capacity(state_count);
-    let ids = (0..state_count).map(|_| {
-        let state = BlockState::read_from(reader)?;
-        Ok(state.register())
-    }).collect::<Result<Box<[Id]>>>()?;
-    // This operation would fail if state_count is less than 2, but thankfully
-    // it's not going to be.
     let bit_width = state_count.next_power_of_two().trailing_zeros();
     // Multiplied by 512 because 4096 / 8 = 512, and we multiply the bit_width by
     // 4096 then divide by 8, which is the equivalent of multiplying by 512.
     let byte_count = bit_width as usize * 512;
     let bytes = read_bytes(reader, byte_count)?;
-    struct BitReader<'a> {
-        blocks: &'a mut Box<[Id]>,
-        block_count: &'a mut u16,
-        block_index: usize,
-        accum: u16,
-        accum_size: u32,
-        bit_width: u32,
-        id_table: Box<[Id]>,
-    }
-    impl<'a> BitReader<'a> {
-        
-        fn push_id(&mut self, id: Id) {
-            if id.is_non_air() {
-                *self.block_count += 1;
+    // Indices are packed starting at the least significant bit of each byte.
+    let mask = (1u32 << bit_width) - 1;
+    let mut indices = Vec::with_capacity(4096);
+    let mut accum = 0u32;
+    let mut accum_size = 0u32;
+    for byte in bytes {
+        accum |= (byte as u32) << accum_size;
+        accum_size += 8;
+        while accum_size >= bit_width {
+            let index = (accum & mask) as u16;
+            if index as usize >= state_count {
+                return Err(Error::InvalidBinaryFormat);
             }
-            self.blocks[self.block_index] = id;
-            self.block_index += 1;
-        }
-
-        fn push_bits(&mut self, bits: u8, count: u32) {
-            if self.block_index == 4096 {
-                return;
-            }
-            let space = self.bit_width - self.accum_size;
-            if space >= count {
-                let start = self.accum_size;
-                let end = start + count;
-                self.accum = self.accum.set_bitmask(start..end, bits as u16);
-                self.accum_size += count;
-                if self.accum_size == self.bit_width {
-                    self.push_id(self.id_table[self.accum as usize]);
-                    self.accum = 0;
-                    self.accum_size = 0;
-                }
-            } else { // space < count
-                self.push_bits(bits, space);
-                self.push_bits(bits >> space, count - space);
-            }
-        }
-        
-        fn push_byte(&mut self, byte: u8) {
-            self.push_bits(byte, 8);
+            indices.push(index);
+            accum >>= bit_width;
+            accum_size -= bit_width;
         }
     }
-    let mut bitreader = BitReader {
-        blocks: blocks.get_or_insert_with(|| (0..4096).map(|_| Id::AIR).collect()),
-        block_count,
-        block_index: 0,
-        accum: 0,
-        accum_size: 0,
-        bit_width,
-        id_table: ids,
-    };
-    // When this iterator is finished, all Ids should be collected.
-    bytes.into_iter().for_each(|byte| {
-        bitreader.push_byte(byte);
-    });
-    assert!(bitreader.accum_size == 0);
-    Ok(())
+    Ok(Some((palette, indices.into_boxed_slice())))
 }
 
 pub fn write_section_blocks<W: Write>(writer: &mut W, blocks: &Option<Box<[Id]>>) -> Result<u64> {
@@ -121,15 +99,25 @@ pub fn write_section_blocks<W: Write>(writer: &mut W, blocks: &Option<Box<[Id]>>
     };
     // Map blocks to new ids.
     let mut id_map = HashMap::<Id, u16>::new();
-    let mut id_counter = 0u16;
     let mut ids = Vec::new();
-    for i in 0..4096 {
-        let entry = id_map.entry(blocks[i]).or_insert(id_counter);
-        if *entry == id_counter {
-            id_counter += 1;
-            ids.push(blocks[i]);
-        }
-    }
+    let indices = blocks.iter().map(|&id| {
+        *id_map.entry(id).or_insert_with(|| {
+            ids.push(id);
+            ids.len() as u16 - 1
+        })
+    }).collect::<Vec<_>>();
+    // Just because I want to make sure I don't somehow accidentally write the id.
+    let palette = ids.iter().map(|id| {
+        let blockstate: &BlockState = &**id;
+        blockstate
+    }).collect::<Vec<_>>();
+    write_section_palette(writer, &palette, &indices)
+}
+
+/// Writes the palette of a section's blocks and the palette index of every block (in yzx order).
+/// Every index must be less than the length of the palette.
+pub fn write_section_palette<W: Write>(writer: &mut W, palette: &[&BlockState], indices: &[u16]) -> Result<u64> {
+    let id_counter = palette.len() as u16;
     let mut length = 0u64;
     if id_counter < 128 {
         let count = id_counter as u8;
@@ -141,9 +129,7 @@ pub fn write_section_blocks<W: Write>(writer: &mut W, blocks: &Option<Box<[Id]>>
         // We're assuming the write_to method uses big endian byte order, which it should.
         length += add_bit.write_to(writer)?;
     }
-    length = ids.into_iter().try_fold(length, |length, id| {
-        // Just because I want to make sure I don't somehow accidentally write the id.
-        let blockstate: &BlockState = &*id;
+    length = palette.iter().try_fold(length, |length, blockstate| {
         Result::Ok(length + blockstate.write_to(writer)?)
     })?;
     // We only need to write a single block to the block table, and then return
@@ -220,9 +206,8 @@ pub fn write_section_blocks<W: Write>(writer: &mut W, blocks: &Option<Box<[Id]>>
         byte_index: 0,
     };
     
-    for i in 0..4096 {
-        let block_index = id_map[&blocks[i]];
-        bit_writer.push_index(block_index);
+    for &index in indices {
+        bit_writer.push_index(index);
     }
     // there are still bits in the accumilator, so add the accumilator to the end
     // of the buffer.
@@ -308,7 +293,9 @@ pub fn write_section_occlusions<W: Write>(writer: &mut W, occlusions: &Option<Bo
 }
 
 /// For reading both blocklight and skylight.
-pub fn read_section_light<R: Read>(reader: &mut R, block_light: &mut Option<Box<[u8]>>, light_count: &mut u16) -> Result<()> {
+/// `empty_level` is the level that a light value has when there is no light array (0 for blocklight, 15 for skylight).
+/// Only values that differ from `empty_level` are counted.
+pub fn read_section_light<R: Read>(reader: &mut R, block_light: &mut Option<Box<[u8]>>, light_count: &mut u16, empty_level: u8) -> Result<()> {
     let flag = bool::read_from(reader)?;
     *light_count = 0;
     if !flag {
@@ -319,8 +306,8 @@ pub fn read_section_light<R: Read>(reader: &mut R, block_light: &mut Option<Box<
     let light = block_light.get_or_insert_with(|| (0..2048).map(|_| 0).collect());
     reader.read_exact(light)?;
     *light_count = light.iter().map(|&light| {
-        (light & 0x0f != 0) as u16 +
-        (light & 0xf0 != 0) as u16
+        (light & 0x0f != empty_level) as u16 +
+        (light >> 4 != empty_level) as u16
     }).sum();
     Ok(())
 }
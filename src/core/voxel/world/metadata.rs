@@ -0,0 +1,306 @@
+#![allow(unused)]
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use hashbrown::HashMap;
+
+use crate::core::error::*;
+use crate::core::io::*;
+use crate::core::voxel::procgen::worldgenerator::GeneratorConfig;
+use crate::core::voxel::{coord::Coord, region::timestamp::Timestamp, tag::Tag};
+
+use super::VoxelWorld;
+
+/// The name of the metadata file in the world directory.
+pub const WORLD_METADATA_FILE: &str = "world.dat";
+/// The version of the world format that is written by [WorldMetadata::save].
+pub const WORLD_FORMAT_VERSION: u16 = 1;
+
+/// Information about a world that isn't stored in any chunk. This is shared by every subworld of the world.
+///
+/// The metadata is saved as a [Tag] map in [WORLD_METADATA_FILE]:
+/// - `version` (u16), `seed` (u64), `spawn` (IVec3), `created` (i64 UNIX timestamp), and `game_tick` (u64)
+/// - `generator` (String) and `generator_config` (any [Tag]), which are written by [WorldMetadata::set_generator]
+/// - `custom` (Map), which holds the values that games store with the world
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldMetadata {
+    version: u16,
+    seed: u64,
+    spawn: Coord,
+    created: Timestamp,
+    game_tick: u64,
+    generator: String,
+    generator_config: Tag,
+    /// Values that games store with the world.
+    pub custom: HashMap<String, Tag>,
+}
+
+impl WorldMetadata {
+    /// Creates the metadata for a new world.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            version: WORLD_FORMAT_VERSION,
+            seed,
+            spawn: Coord::splat(0),
+            created: Timestamp::utc_now(),
+            game_tick: 0,
+            generator: String::new(),
+            generator_config: Tag::Null,
+            custom: HashMap::new(),
+        }
+    }
+
+    /// The path of the metadata file of the world in `directory`.
+    pub fn path<P: AsRef<Path>>(directory: P) -> PathBuf {
+        directory.as_ref().join(WORLD_METADATA_FILE)
+    }
+
+    /// Reads the metadata of the world in `directory`. Returns `None` if the world doesn't have a metadata file.
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Option<Self>> {
+        let path = Self::path(directory);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Some(Self::from_tag(&Tag::read_from(&mut reader)?)?))
+    }
+
+    /// Reads the metadata of the world in `directory`, or creates and saves new metadata with the given seed
+    /// if the world doesn't have any. Call this before opening a new world to choose its seed.
+    pub fn load_or_create<P: AsRef<Path>>(directory: P, seed: u64) -> Result<Self> {
+        let directory = directory.as_ref();
+        if let Some(metadata) = Self::load(directory)? {
+            return Ok(metadata);
+        }
+        let metadata = Self::new(seed);
+        std::fs::create_dir_all(directory)?;
+        metadata.save(directory)?;
+        Ok(metadata)
+    }
+
+    /// Writes the metadata to the world in `directory`. The metadata is written to a temporary file that then
+    /// replaces the old file, so the old metadata is still intact if writing fails partway through.
+    pub fn save<P: AsRef<Path>>(&self, directory: P) -> Result<()> {
+        let path = Self::path(directory);
+        let temp_path = path.with_extension("dat.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            self.to_tag().write_to(&mut writer)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        std::fs::rename(temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn to_tag(&self) -> Tag {
+        let spawn: bevy::math::IVec3 = self.spawn.into();
+        let mut map = HashMap::new();
+        map.insert(String::from("version"), Tag::from(self.version));
+        map.insert(String::from("seed"), Tag::from(self.seed));
+        map.insert(String::from("spawn"), Tag::from(spawn));
+        map.insert(String::from("created"), Tag::from(self.created.timestamp()));
+        map.insert(String::from("game_tick"), Tag::from(self.game_tick));
+        map.insert(String::from("generator"), Tag::from(self.generator.as_str()));
+        map.insert(String::from("generator_config"), self.generator_config.clone());
+        map.insert(String::from("custom"), Tag::from(self.custom.clone()));
+        Tag::from(map)
+    }
+
+    /// Reads the metadata from a [Tag] map that was created with [WorldMetadata::to_tag].
+    /// Returns [Error::UnsupportedWorldVersion] if the metadata was written by a newer version of the world format.
+    pub fn from_tag(tag: &Tag) -> Result<Self> {
+        if !matches!(tag, Tag::Map(_)) {
+            return Err(Error::InvalidBinaryFormat);
+        }
+        let &Tag::U16(version) = &tag["version"] else {
+            return Err(Error::InvalidBinaryFormat);
+        };
+        if version > WORLD_FORMAT_VERSION {
+            return Err(Error::UnsupportedWorldVersion(version));
+        }
+        let (&Tag::U64(seed), &Tag::IVec3(spawn), &Tag::I64(created), &Tag::U64(game_tick)) = (&tag["seed"], &tag["spawn"], &tag["created"], &tag["game_tick"]) else {
+            return Err(Error::InvalidBinaryFormat);
+        };
+        let generator = match &tag["generator"] {
+            Tag::String(generator) => generator.as_ref().clone(),
+            Tag::Null => String::new(),
+            _ => return Err(Error::InvalidBinaryFormat),
+        };
+        let custom = match &tag["custom"] {
+            Tag::Map(custom) => custom.as_ref().clone(),
+            Tag::Null => HashMap::new(),
+            _ => return Err(Error::InvalidBinaryFormat),
+        };
+        Ok(Self {
+            version,
+            seed,
+            spawn: spawn.into(),
+            created: Timestamp::new(created),
+            game_tick,
+            generator,
+            generator_config: tag["generator_config"].clone(),
+            custom,
+        })
+    }
+
+    /// The version of the world format that the metadata was read from.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// The seed that the world's terrain is generated from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Changing the seed of a world with generated chunks will cause seams between the old terrain and new terrain.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn spawn(&self) -> Coord {
+        self.spawn
+    }
+
+    pub fn set_spawn<C: Into<(i32, i32, i32)>>(&mut self, spawn: C) {
+        let spawn: (i32, i32, i32) = spawn.into();
+        self.spawn = spawn.into();
+    }
+
+    /// The time that the world was created.
+    pub fn created(&self) -> Timestamp {
+        self.created
+    }
+
+    /// The number of ticks that the world had run for when it was last saved.
+    pub fn game_tick(&self) -> u64 {
+        self.game_tick
+    }
+
+    pub fn set_game_tick(&mut self, game_tick: u64) {
+        self.game_tick = game_tick;
+    }
+
+    /// The name of the generator that was stored with [WorldMetadata::set_generator]. This is empty if no generator was stored.
+    pub fn generator(&self) -> &str {
+        &self.generator
+    }
+
+    pub fn generator_config(&self) -> &Tag {
+        &self.generator_config
+    }
+
+    /// Stores the name and configuration of a generator so that it can be recreated with [WorldMetadata::create_generator].
+    pub fn set_generator<G: GeneratorConfig>(&mut self, generator: &G) {
+        self.generator = G::NAME.to_owned();
+        self.generator_config = generator.to_config();
+    }
+
+    /// Recreates the stored generator with the world's seed, so it generates the same terrain that it did before.
+    /// Returns `None` if the stored generator isn't a `G`.
+    pub fn create_generator<G: GeneratorConfig>(&self) -> Option<Result<G>> {
+        if self.generator != G::NAME {
+            return None;
+        }
+        Some(G::from_config(self.seed, &self.generator_config))
+    }
+}
+
+/// A handle to the [WorldMetadata] of a world. Clones of the handle share the same metadata,
+/// which is how the subworlds of a world all see the same seed, spawn, and game time.
+#[derive(Debug, Clone)]
+pub struct SharedMetadata(Arc<RwLock<WorldMetadata>>);
+
+impl SharedMetadata {
+    pub fn new(metadata: WorldMetadata) -> Self {
+        Self(Arc::new(RwLock::new(metadata)))
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, WorldMetadata> {
+        self.0.read().expect("Failed to lock the world metadata")
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, WorldMetadata> {
+        self.0.write().expect("Failed to lock the world metadata")
+    }
+}
+
+impl VoxelWorld {
+    /// The [WorldMetadata] of the world, which is shared with the other subworlds that were opened with it.
+    pub fn metadata(&self) -> RwLockReadGuard<'_, WorldMetadata> {
+        self.metadata.read()
+    }
+
+    pub fn metadata_mut(&mut self) -> RwLockWriteGuard<'_, WorldMetadata> {
+        self.metadata.write()
+    }
+
+    /// Writes the [WorldMetadata] with the current game tick.
+    pub(crate) fn save_metadata(&mut self) -> Result<()> {
+        let mut metadata = self.metadata.write();
+        // Subworlds that share the metadata may not have been updated for the same number of ticks,
+        // so the game time only ever moves forward.
+        let game_tick = metadata.game_tick().max(self.scheduled_updates.current_tick());
+        metadata.set_game_tick(game_tick);
+        metadata.save(&self.world_directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::testing::{open_world_with_generator, register_test_blocks, TestDirectory};
+    use crate::core::voxel::procgen::worldgenerator::FlatWorldGenerator;
+    use crate::core::voxel::world::WORLD_BOTTOM;
+
+    use super::*;
+
+    #[test]
+    fn metadata_tag_test() -> Result<()> {
+        let mut metadata = WorldMetadata::new(1234);
+        metadata.set_spawn((5, 64, -7));
+        metadata.set_game_tick(300);
+        metadata.custom.insert(String::from("difficulty"), Tag::from("hard"));
+        let tag = metadata.to_tag();
+        assert_eq!(WorldMetadata::from_tag(&tag)?, metadata);
+        let mut newer = tag.clone();
+        newer["version"] = Tag::from(WORLD_FORMAT_VERSION + 1);
+        assert!(matches!(WorldMetadata::from_tag(&newer), Err(Error::UnsupportedWorldVersion(_))));
+        let mut broken = tag;
+        broken["seed"] = Tag::from("1234");
+        assert!(matches!(WorldMetadata::from_tag(&broken), Err(Error::InvalidBinaryFormat)));
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_world_test() -> Result<()> {
+        register_test_blocks();
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("metadata_world_test");
+        let mut metadata = WorldMetadata::load_or_create(&directory, 42)?;
+        metadata.set_generator(&FlatWorldGenerator::new(vec![(3, state).into()]));
+        metadata.save(&directory)?;
+        let generator = metadata.create_generator::<FlatWorldGenerator>().expect("Generator was not stored")?;
+        let mut world = open_world_with_generator(&directory, 2, (0, 0, 0), Some(Box::new(generator)));
+        assert_eq!(world.metadata().seed(), 42);
+        assert_eq!(world.get_block((0, WORLD_BOTTOM + 2, 0)), state);
+        world.metadata_mut().set_spawn((1, 2, 3));
+        (0..10).for_each(|_| world.update());
+        world.save_world()?;
+        drop(world);
+        assert!(!WorldMetadata::path(&directory).with_extension("dat.tmp").exists());
+        let world = VoxelWorld::open_headless(&directory, 2, (0, 0, 0), None)?;
+        assert_eq!(world.metadata().spawn(), Coord::new(1, 2, 3));
+        assert_eq!(world.metadata().game_tick(), 10);
+        assert_eq!(world.scheduled_updates.current_tick(), 10);
+        assert_eq!(world.metadata().generator(), FlatWorldGenerator::NAME);
+        // A world with corrupt metadata can't be opened.
+        let corrupt = TestDirectory::new("metadata_corrupt_test");
+        std::fs::write(WorldMetadata::path(&corrupt), b"not metadata")?;
+        assert!(VoxelWorld::open_headless(&corrupt, 2, (0, 0, 0), None).is_err());
+        Ok(())
+    }
+}
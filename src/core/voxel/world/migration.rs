@@ -0,0 +1,553 @@
+#![allow(unused)]
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use hashbrown::HashMap;
+use itertools::Itertools;
+
+use crate::core::error::*;
+use crate::core::io::*;
+use crate::core::util::traits::StrToOwned;
+use crate::core::voxel::{blocks::Id, blockstate::BlockState, region::regionfile::RegionFile, statevalue::StateValue, tag::Tag};
+
+use super::blockdata::{BlockDataContainer, BlockDataRef};
+use super::heightmap::Heightmap;
+use super::io::*;
+use super::subworld::list_subworlds;
+use super::WORLD_HEIGHT;
+
+/// Upgrades block states and block data that were saved by an older version of a game.
+///
+/// Migrations are registered with a data version using [register_migration]. Chunks are saved with the
+/// current [data_version], and when a chunk with an older data version is read, every migration with a
+/// newer version is applied to it in order.
+pub trait Migration: Send + Sync {
+    /// Returns the new state for a state that was saved before this migration, or `None` to keep the state.
+    fn migrate_state(&self, state: &BlockState) -> Option<BlockState> {
+        None
+    }
+
+    /// Changes the data of a block. `state` is the block's state before [Migration::migrate_state] was applied.
+    fn migrate_data(&self, state: &BlockState, data: &mut Tag) {}
+}
+
+/// Migrations in order of their versions. Each world uses a copy of the migrations that were registered
+/// with [register_migration] when it was opened.
+#[derive(Default, Clone)]
+pub struct MigrationRegistry {
+    migrations: Vec<(u16, Arc<dyn Migration>)>,
+}
+
+impl MigrationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a migration that upgrades data saved before `version`. Migrations must be registered
+    /// in order of their versions, and each version can only be used once.
+    pub fn register<M: Migration + 'static>(&mut self, version: u16, migration: M) {
+        if let Some(&(latest, _)) = self.migrations.last() {
+            assert!(version > latest, "Migration version {version} must be greater than {latest}");
+        }
+        assert!(version > 0, "Migration versions start at 1");
+        self.migrations.push((version, Arc::new(migration)));
+    }
+
+    /// The data version that chunks are saved with, which is the version of the last migration.
+    /// This is `0` if there are no migrations.
+    pub fn data_version(&self) -> u16 {
+        self.migrations.last().map(|&(version, _)| version).unwrap_or(0)
+    }
+
+    /// The migrations that upgrade data saved with `version` to the current [data_version](MigrationRegistry::data_version),
+    /// in the order that they're applied.
+    pub fn migrations_since(&self, version: u16) -> Vec<Arc<dyn Migration>> {
+        self.migrations.iter()
+            .filter(|&&(migration_version, _)| migration_version > version)
+            .map(|(_, migration)| migration.clone())
+            .collect()
+    }
+}
+
+static MIGRATIONS: LazyLock<RwLock<MigrationRegistry>> = LazyLock::new(|| RwLock::new(MigrationRegistry::new()));
+
+/// Registers a migration that upgrades data saved before `version`. Migrations must be registered
+/// before any world is opened, in order of their versions, and each version can only be used once.
+pub fn register_migration<M: Migration + 'static>(version: u16, migration: M) {
+    MIGRATIONS.write().expect("Failed to lock migrations").register(version, migration);
+}
+
+/// The data version of the registered migrations. See [MigrationRegistry::data_version].
+pub fn data_version() -> u16 {
+    MIGRATIONS.read().expect("Failed to lock migrations").data_version()
+}
+
+/// The registered migrations that upgrade data saved with `version`. See [MigrationRegistry::migrations_since].
+pub fn migrations_since(version: u16) -> Vec<Arc<dyn Migration>> {
+    MIGRATIONS.read().expect("Failed to lock migrations").migrations_since(version)
+}
+
+/// A copy of the registered migrations.
+pub fn registered_migrations() -> MigrationRegistry {
+    MIGRATIONS.read().expect("Failed to lock migrations").clone()
+}
+
+/// Renames a block. The properties of its states are kept.
+pub struct RenameBlock {
+    from: String,
+    to: String,
+}
+
+impl RenameBlock {
+    pub fn new<S: StrToOwned, S2: StrToOwned>(from: S, to: S2) -> Self {
+        Self {
+            from: from.str_to_owned(),
+            to: to.str_to_owned(),
+        }
+    }
+}
+
+impl Migration for RenameBlock {
+    fn migrate_state(&self, state: &BlockState) -> Option<BlockState> {
+        if state.name() != self.from {
+            return None;
+        }
+        let mut state = state.clone();
+        state.set_name(self.to.as_str());
+        Some(state)
+    }
+}
+
+/// Renames a property of a block's states.
+pub struct RenameProperty {
+    block: String,
+    from: String,
+    to: String,
+}
+
+impl RenameProperty {
+    pub fn new<S: StrToOwned, S2: StrToOwned, S3: StrToOwned>(block: S, from: S2, to: S3) -> Self {
+        Self {
+            block: block.str_to_owned(),
+            from: from.str_to_owned(),
+            to: to.str_to_owned(),
+        }
+    }
+}
+
+impl Migration for RenameProperty {
+    fn migrate_state(&self, state: &BlockState) -> Option<BlockState> {
+        if state.name() != self.block {
+            return None;
+        }
+        let mut state = state.clone();
+        let value = state.remove_property(&self.from)?;
+        state.set_property(&self.to, value);
+        Some(state)
+    }
+}
+
+/// Changes the value of a property of a block's states, such as when the type of the property changes.
+pub struct MapProperty<F: Fn(&StateValue) -> StateValue + Send + Sync> {
+    block: String,
+    property: String,
+    map: F,
+}
+
+impl<F: Fn(&StateValue) -> StateValue + Send + Sync> MapProperty<F> {
+    pub fn new<S: StrToOwned, S2: StrToOwned>(block: S, property: S2, map: F) -> Self {
+        Self {
+            block: block.str_to_owned(),
+            property: property.str_to_owned(),
+            map,
+        }
+    }
+}
+
+impl<F: Fn(&StateValue) -> StateValue + Send + Sync> Migration for MapProperty<F> {
+    fn migrate_state(&self, state: &BlockState) -> Option<BlockState> {
+        if state.name() != self.block {
+            return None;
+        }
+        let value = (self.map)(state.get_property(&self.property)?);
+        let mut state = state.clone();
+        state.set_property(&self.property, value);
+        Some(state)
+    }
+}
+
+/// The palette of a section after it was migrated.
+pub(crate) struct MigratedPalette {
+    /// The states that each palette entry had before each migration, followed by the migrated state.
+    chains: Vec<Vec<BlockState>>,
+    /// The palette index of each block (in yzx order).
+    indices: Box<[u16]>,
+}
+
+impl MigratedPalette {
+    pub(crate) fn new(palette: Vec<BlockState>, indices: Box<[u16]>, migrations: &[Arc<dyn Migration>]) -> Self {
+        let chains = palette.into_iter().map(|state| {
+            let mut chain = Vec::with_capacity(migrations.len() + 1);
+            chain.push(state);
+            migrations.iter().for_each(|migration| {
+                let state = chain.last().expect("Chain was empty");
+                let next = migration.migrate_state(state).unwrap_or_else(|| state.clone());
+                chain.push(next);
+            });
+            chain
+        }).collect();
+        Self {
+            chains,
+            indices,
+        }
+    }
+
+    /// The migrated states of the palette.
+    pub(crate) fn states(&self) -> impl Iterator<Item = &BlockState> {
+        self.chains.iter().map(|chain| chain.last().expect("Chain was empty"))
+    }
+
+    pub(crate) fn indices(&self) -> &[u16] {
+        &self.indices
+    }
+
+    /// Returns true if the migrations changed any state of the palette.
+    pub(crate) fn changed(&self) -> bool {
+        self.chains.iter().any(|chain| chain.first() != chain.last())
+    }
+
+    /// Applies [Migration::migrate_data] to the data of every block that has data. Returns the number of blocks whose data changed.
+    pub(crate) fn migrate_data(&self, block_data_refs: &[BlockDataRef], container: &mut BlockDataContainer, migrations: &[Arc<dyn Migration>]) -> u64 {
+        let mut changed = 0;
+        block_data_refs.iter().zip(self.indices.iter()).for_each(|(&dataref, &index)| {
+            let Some(data) = container.get_mut(dataref) else {
+                return;
+            };
+            let original = data.clone();
+            let chain = &self.chains[index as usize];
+            migrations.iter().zip(chain.iter()).for_each(|(migration, state)| {
+                migration.migrate_data(state, data);
+            });
+            if *data != original {
+                changed += 1;
+            }
+        });
+        changed
+    }
+}
+
+/// Reads the blocks of a section like [read_section_blocks], but migrates the states before they're registered.
+/// The returned palette is used to migrate the section's block data once it's read.
+pub(crate) fn read_migrated_section_blocks<R: Read>(reader: &mut R, blocks: &mut Option<Box<[Id]>>, block_count: &mut u16, migrations: &[Arc<dyn Migration>]) -> Result<Option<MigratedPalette>> {
+    let Some((palette, indices)) = read_section_palette(reader)? else {
+        *blocks = None;
+        *block_count = 0;
+        return Ok(None);
+    };
+    let migrated = MigratedPalette::new(palette, indices, migrations);
+    let ids = migrated.states().map(BlockState::register_or_unknown).collect_vec();
+    fill_section_blocks(blocks, block_count, &ids, migrated.indices());
+    Ok(Some(migrated))
+}
+
+/// What [upgrade_world] changed (or would change during a dry run).
+#[derive(Debug, Default, Clone)]
+pub struct UpgradeReport {
+    /// The data version that the chunks were upgraded to.
+    pub data_version: u16,
+    pub dry_run: bool,
+    /// The number of chunks in the world.
+    pub chunks: u64,
+    /// The number of chunks that were saved with an older data version.
+    pub outdated_chunks: u64,
+    /// The number of chunks that were written. This is `0` during a dry run.
+    pub upgraded_chunks: u64,
+    /// The number of blocks whose state changed.
+    pub blocks_changed: u64,
+    /// The number of blocks whose data changed.
+    pub data_changed: u64,
+    /// The number of blocks that changed from one state to another, keyed by the old and new states.
+    pub state_changes: HashMap<(String, String), u64>,
+}
+
+impl std::fmt::Display for UpgradeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.dry_run {
+            writeln!(f, "Dry run, nothing was written.")?;
+        }
+        writeln!(f, "Data version: {}", self.data_version)?;
+        writeln!(f, "Chunks: {}", self.chunks)?;
+        writeln!(f, "Outdated chunks: {}", self.outdated_chunks)?;
+        writeln!(f, "Upgraded chunks: {}", self.upgraded_chunks)?;
+        writeln!(f, "Blocks changed: {}", self.blocks_changed)?;
+        write!(f, "Block data changed: {}", self.data_changed)?;
+        self.state_changes.iter().sorted().try_for_each(|((from, to), count)| {
+            write!(f, "\n    {from} -> {to}: {count}")
+        })
+    }
+}
+
+/// Migrates chunk data that was saved with [Chunk::write_to](super::chunk::Chunk::write_to) without loading it into a world.
+/// The block states don't need to be registered. The pending ticks and entities are copied unchanged.
+///
+/// The occlusion and light of a section depend on its blocks, which can't be calculated without registering them. When the
+/// blocks of a section change, its occlusion and light are dropped and the heightmap of the chunk is cleared, which
+/// tells [Chunk::read_from](super::chunk::Chunk::read_from) to calculate them again when the chunk is loaded.
+pub fn upgrade_chunk_data<R: Read, W: Write>(reader: &mut R, writer: &mut W, migrations: &[Arc<dyn Migration>], report: &mut UpgradeReport) -> Result<()> {
+    let mut heightmap = Heightmap::new();
+    heightmap.read_from(reader)?;
+    // The sections are written after the heightmap, which isn't known until every section is migrated.
+    let mut sections = Vec::new();
+    let mut changed = false;
+    for _ in 0..WORLD_HEIGHT >> 4 {
+        let flag = bool::read_from(reader)?;
+        flag.write_to(&mut sections)?;
+        if !flag {
+            continue;
+        }
+        let migrated = match read_section_palette(reader)? {
+            Some((palette, indices)) => {
+                let migrated = MigratedPalette::new(palette, indices, migrations);
+                write_migrated_palette(&mut sections, &migrated, report)?;
+                Some(migrated)
+            }
+            None => {
+                0u8.write_to(&mut sections)?;
+                None
+            }
+        };
+        let stale = migrated.as_ref().is_some_and(MigratedPalette::changed);
+        changed |= stale;
+        let mut occlusion = None;
+        let mut count = 0;
+        read_section_occlusions(reader, &mut occlusion, &mut count)?;
+        let mut block_light = None;
+        read_section_light(reader, &mut block_light, &mut count, 0)?;
+        let mut sky_light = None;
+        read_section_light(reader, &mut sky_light, &mut count, 15)?;
+        if stale {
+            occlusion = None;
+            block_light = None;
+            sky_light = None;
+        }
+        write_section_occlusions(&mut sections, &occlusion)?;
+        write_section_light(&mut sections, &block_light)?;
+        write_section_light(&mut sections, &sky_light)?;
+        let mut block_data_refs = None;
+        let mut block_data = BlockDataContainer::new();
+        let mut data_count = 0;
+        read_block_data(reader, &mut block_data_refs, &mut block_data, &mut data_count)?;
+        if let (Some(migrated), Some(refs)) = (&migrated, &block_data_refs) {
+            report.data_changed += migrated.migrate_data(refs, &mut block_data, migrations);
+        }
+        write_block_data(&mut sections, &block_data_refs, &block_data, data_count)?;
+        let mut enabled = Vec::new();
+        read_enabled(reader, |index| enabled.push(index), &mut count)?;
+        (enabled.len() as u16).write_to(&mut sections)?;
+        enabled.into_iter().try_for_each(|index| {
+            index.write_to(&mut sections)?;
+            Result::Ok(())
+        })?;
+    }
+    if changed {
+        heightmap = Heightmap::new();
+    }
+    heightmap.write_to(writer)?;
+    writer.write_all(&sections)?;
+    std::io::copy(reader, writer)?;
+    Ok(())
+}
+
+/// Writes the migrated states of a palette. States that became the same state are merged.
+fn write_migrated_palette<W: Write>(writer: &mut W, migrated: &MigratedPalette, report: &mut UpgradeReport) -> Result<u64> {
+    let mut state_map = HashMap::<&BlockState, u16>::new();
+    let mut palette = Vec::new();
+    let remap = migrated.states().map(|state| {
+        *state_map.entry(state).or_insert_with(|| {
+            palette.push(state);
+            palette.len() as u16 - 1
+        })
+    }).collect_vec();
+    let mut counts = vec![0u64; migrated.chains.len()];
+    let indices = migrated.indices().iter().map(|&index| {
+        counts[index as usize] += 1;
+        remap[index as usize]
+    }).collect_vec();
+    migrated.chains.iter().zip(counts).for_each(|(chain, count)| {
+        let (Some(first), Some(last)) = (chain.first(), chain.last()) else {
+            return;
+        };
+        if count == 0 || first == last {
+            return;
+        }
+        report.blocks_changed += count;
+        *report.state_changes.entry((first.to_string(), last.to_string())).or_default() += count;
+    });
+    write_section_palette(writer, &palette, &indices)
+}
+
+/// Upgrades every chunk of every subworld of the world in `directory` to the current [data_version].
+/// The world must not be open. If `dry_run` is true, the chunks are migrated in memory so that the
+/// report can be made, but nothing is written.
+pub fn upgrade_world<P: AsRef<Path>>(directory: P, dry_run: bool) -> Result<UpgradeReport> {
+    upgrade_world_with(directory, &registered_migrations(), dry_run)
+}
+
+/// Upgrades the world in `directory` like [upgrade_world], using `migrations` instead of the registered migrations.
+pub fn upgrade_world_with<P: AsRef<Path>>(directory: P, migrations: &MigrationRegistry, dry_run: bool) -> Result<UpgradeReport> {
+    let directory = directory.as_ref();
+    let version = migrations.data_version();
+    let mut report = UpgradeReport {
+        data_version: version,
+        dry_run,
+        ..Default::default()
+    };
+    for name in list_subworlds(directory)? {
+        let subworld_directory = directory.join("subworlds").join(name);
+        let mut region_paths = Vec::new();
+        for entry in std::fs::read_dir(subworld_directory)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|extension| extension == "rg") {
+                region_paths.push(path);
+            }
+        }
+        region_paths.sort();
+        for path in region_paths {
+            let mut region = RegionFile::open(&path)?;
+            for z in 0..32 {
+                for x in 0..32 {
+                    let chunk_version = match region.get_version((x, z)) {
+                        Err(Error::ChunkNotFound) => continue,
+                        result => result?,
+                    };
+                    report.chunks += 1;
+                    if chunk_version >= version {
+                        continue;
+                    }
+                    report.outdated_chunks += 1;
+                    let chunk_migrations = migrations.migrations_since(chunk_version);
+                    let data = region.read((x, z), |reader| {
+                        let mut data = Vec::new();
+                        upgrade_chunk_data(reader, &mut data, &chunk_migrations, &mut report)?;
+                        Ok(data)
+                    })?;
+                    if dry_run {
+                        continue;
+                    }
+                    let timestamp = region.get_timestamp((x, z));
+                    region.write_timestamped_versioned((x, z), timestamp, version, |writer| {
+                        writer.write_all(&data)?;
+                        Ok(())
+                    })?;
+                    report.upgraded_chunks += 1;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{blocks, direction::Direction, testing::{self, open_world, PlainTestBlock, TestDirectory}, world::VoxelWorld};
+
+    use super::*;
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(PlainTestBlock("migration_test_new"));
+        });
+    }
+
+    /// Marks the data of blocks that were renamed by the earlier migrations.
+    struct MarkData;
+    impl Migration for MarkData {
+        fn migrate_data(&self, state: &BlockState, data: &mut Tag) {
+            if state.name() == "migration_test_new" && state.get_property("facing").is_some() {
+                *data = Tag::from("migrated");
+            }
+        }
+    }
+
+    fn create_world(directory: &TestDirectory) -> Result<()> {
+        let old = blockstate!(migration_test_old, axis = 1i64).register_or_unknown();
+        let mut world = open_world(directory, 2, (0, 0, 0));
+        world.set_block((1, 2, 3), old);
+        world.set_block((4, 5, 6), old);
+        world.set_data((4, 5, 6), "original");
+        // Removed by a migration, which uncovers the stone below it.
+        world.set_block((8, 8, 8), blockstate!(test_stone));
+        world.set_block((8, 9, 8), blockstate!(migration_test_removed).register_or_unknown());
+        assert!(world.get_occlusion((8, 8, 8)).hidden(Direction::PosY));
+        world.save_world()
+    }
+
+    /// Opens a world that loads its chunks with `migrations` instead of the registered migrations.
+    fn open_migrated_world(directory: &TestDirectory, migrations: &MigrationRegistry) -> VoxelWorld {
+        let mut world = VoxelWorld::open_headless(directory, 2, (0, 0, 0), None).expect("Failed to open the world");
+        world.migrations = migrations.clone();
+        world.flush_load_queue();
+        world
+    }
+
+    /// Checks that the heightmap, occlusion, and light of the section whose block was removed were calculated again.
+    fn assert_removed(world: &VoxelWorld) {
+        assert_eq!(world.get_block((8, 9, 8)), Id::AIR);
+        assert_eq!(world.height(8, 8), 9);
+        assert!(world.get_occlusion((8, 8, 8)).visible(Direction::PosY));
+        assert_eq!(world.get_sky_light((8, 9, 8)), 15);
+    }
+
+    #[test]
+    fn migration_test() -> Result<()> {
+        register_test_blocks();
+        let loaded_directory = TestDirectory::new("migration_test");
+        let upgraded_directory = TestDirectory::new("migration_upgrade_test");
+        create_world(&loaded_directory)?;
+        create_world(&upgraded_directory)?;
+        // The migrations aren't registered so that they don't affect the worlds of other tests.
+        let base = data_version();
+        let mut migrations = MigrationRegistry::new();
+        migrations.register(base + 1, RenameBlock::new("migration_test_old", "migration_test_new"));
+        migrations.register(base + 2, RenameProperty::new("migration_test_new", "axis", "facing"));
+        migrations.register(base + 3, MarkData);
+        migrations.register(base + 4, RenameBlock::new("migration_test_removed", "air"));
+        assert_eq!(migrations.data_version(), base + 4);
+        assert_eq!(migrations.migrations_since(base + 1).len(), 3);
+        assert_eq!(data_version(), base);
+        let new = blockstate!(migration_test_new, facing = 1i64).register();
+
+        // Chunks are migrated when they're loaded.
+        let mut world = open_migrated_world(&loaded_directory, &migrations);
+        assert_eq!(world.get_block((1, 2, 3)), new);
+        assert_eq!(world.get_block((4, 5, 6)), new);
+        assert_eq!(world.get_data((4, 5, 6)), Some(&Tag::from("migrated")));
+        assert_removed(&world);
+        world.save_world()?;
+        drop(world);
+        let mut region = RegionFile::open(loaded_directory.path().join("subworlds/main/0.0.rg"))?;
+        assert_eq!(region.get_version((0, 0))?, base + 4);
+        drop(region);
+
+        // Nothing is written during a dry run.
+        let report = upgrade_world_with(&upgraded_directory, &migrations, true)?;
+        assert!(report.outdated_chunks > 0);
+        assert_eq!(report.upgraded_chunks, 0);
+        assert_eq!(report.blocks_changed, 3);
+        assert_eq!(report.data_changed, 1);
+        assert_eq!(report.state_changes.get(&(String::from("migration_test_old[axis=1]"), new.to_string())), Some(&2));
+        let report = upgrade_world_with(&upgraded_directory, &migrations, false)?;
+        assert_eq!(report.upgraded_chunks, report.outdated_chunks);
+        assert_eq!(upgrade_world_with(&upgraded_directory, &migrations, true)?.outdated_chunks, 0);
+        let mut world = open_migrated_world(&upgraded_directory, &migrations);
+        assert_eq!(world.get_block((1, 2, 3)), new);
+        assert_eq!(world.get_data((4, 5, 6)), Some(&Tag::from("migrated")));
+        assert_removed(&world);
+        Ok(())
+    }
+}
@@ -8,8 +8,18 @@ pub mod chunkprovider;
 pub mod chunkcoord;
 pub mod blockdata;
 pub mod update;
+pub mod tick;
 pub mod world;
+pub mod edit;
+pub mod clipboard;
+pub mod schematic;
+pub mod history;
+pub mod subworld;
+pub mod metadata;
+pub mod unknown;
+pub mod migration;
 pub mod query;
 pub mod io;
 pub use world::*;
-pub mod externevent;
\ No newline at end of file
+pub mod externevent;
+pub mod worker;
\ No newline at end of file
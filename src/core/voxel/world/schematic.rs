@@ -0,0 +1,527 @@
+#![allow(unused)]
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hashbrown::{HashMap, HashSet};
+
+use crate::core::error::*;
+use crate::core::io::*;
+use crate::core::voxel::{blocks::Id, blockstate::BlockState, coord::Coord, region::timestamp::Timestamp, tag::Tag};
+
+use super::clipboard::{Clipboard, PasteOptions};
+use super::io::{read_section_palette, write_section_palette};
+use super::section::Section;
+use super::VoxelWorld;
+
+/// The first bytes of every (decompressed) schematic file.
+pub const SCHEMATIC_MAGIC: [u8; 4] = *b"UVSC";
+/// The version of the schematic format that is written by [Schematic::write_to].
+/// Version 1 schematics, which have a single palette for all of their blocks, can still be read.
+pub const SCHEMATIC_VERSION: u16 = 2;
+/// The largest number of blocks that a schematic file can have. Larger files are rejected by [Schematic::read_from]
+/// so that a corrupt size can't allocate an enormous amount of memory.
+pub const MAX_SCHEMATIC_VOLUME: u64 = 1 << 27;
+
+/// A structure that can be saved to a file and shared between worlds.
+///
+/// Unlike a [Clipboard], blocks are stored as [BlockState]s, so a schematic can be read
+/// without registering the blocks that are in it.
+///
+/// Schematic files are gzip-compressed. The format is:
+/// - magic (`UVSC`) and version (u16)
+/// - author, origin, and creation [Timestamp]
+/// - size, followed by the blocks in 16x16x16 cells (in yzx order), which are written like the blocks of a
+///   section, each with its own palette of [BlockState]s
+/// - the [Tag] data of each block that has data, and the blocks that are enabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    pub author: String,
+    /// The point (relative to the min corner) that ends up at the paste position.
+    pub origin: Coord,
+    pub created: Timestamp,
+    size: Coord,
+    palette: Vec<BlockState>,
+    /// Palette indices in yzx order.
+    blocks: Box<[u32]>,
+    data: HashMap<Coord, Tag>,
+    enabled: HashSet<Coord>,
+}
+
+impl Schematic {
+    pub fn from_clipboard(clipboard: &Clipboard) -> Self {
+        let mut palette = Vec::new();
+        let mut palette_map = HashMap::<Id, u32>::new();
+        let blocks = clipboard.iter().map(|(_, state)| {
+            *palette_map.entry(state).or_insert_with(|| {
+                let blockstate: &BlockState = &*state;
+                palette.push(blockstate.clone());
+                palette.len() as u32 - 1
+            })
+        }).collect();
+        let mut data = HashMap::new();
+        let mut enabled = HashSet::new();
+        clipboard.iter().for_each(|(coord, _)| {
+            if let Some(tag) = clipboard.get_data(coord) {
+                data.insert(coord, tag.clone());
+            }
+            if clipboard.enabled(coord) {
+                enabled.insert(coord);
+            }
+        });
+        Self {
+            author: String::new(),
+            origin: Coord::splat(0),
+            created: Timestamp::utc_now(),
+            size: clipboard.size(),
+            palette,
+            blocks,
+            data,
+            enabled,
+        }
+    }
+
+    pub fn with_author<S: Into<String>>(mut self, author: S) -> Self {
+        self.author = author.into();
+        self
+    }
+
+    pub fn with_origin<C: Into<(i32, i32, i32)>>(mut self, origin: C) -> Self {
+        let origin: (i32, i32, i32) = origin.into();
+        self.origin = origin.into();
+        self
+    }
+
+    /// Registers the states in the palette and creates a [Clipboard] from the schematic.
+    /// States whose block isn't registered become [UnknownBlock](crate::core::voxel::blocks::UnknownBlock)s.
+    pub fn to_clipboard(&self) -> Clipboard {
+        let ids: Vec<Id> = self.palette.iter().map(BlockState::register_or_unknown).collect();
+        let mut clipboard = Clipboard::new(self.size);
+        let mut blocks = self.blocks.iter();
+        for y in 0..self.size.y {
+            for z in 0..self.size.z {
+                for x in 0..self.size.x {
+                    let Some(&index) = blocks.next() else {
+                        unreachable!()
+                    };
+                    clipboard.set((x, y, z), ids[index as usize]);
+                }
+            }
+        }
+        self.data.iter().for_each(|(&coord, tag)| clipboard.set_data(coord, tag.clone()));
+        self.enabled.iter().for_each(|&coord| clipboard.set_enabled(coord, true));
+        clipboard
+    }
+
+    pub fn size(&self) -> Coord {
+        self.size
+    }
+
+    pub fn palette(&self) -> &[BlockState] {
+        &self.palette
+    }
+
+    /// Gets the [BlockState] at `coord`. Returns `None` if `coord` is outside of the schematic.
+    pub fn get<C: Into<(i32, i32, i32)>>(&self, coord: C) -> Option<&BlockState> {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        let index = self.blocks[block_index(self.size, coord)?];
+        Some(&self.palette[index as usize])
+    }
+
+    pub fn get_data<C: Into<(i32, i32, i32)>>(&self, coord: C) -> Option<&Tag> {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.data.get(&coord)
+    }
+
+    pub fn enabled<C: Into<(i32, i32, i32)>>(&self, coord: C) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        self.enabled.contains(&coord)
+    }
+
+    /// Writes the schematic to a gzip-compressed file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        self.write_to(&mut encoder)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Reads a schematic from a gzip-compressed file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut decoder = GzDecoder::new(BufReader::new(file));
+        Self::read_from(&mut decoder)
+    }
+}
+
+/// The index in yzx order of the block at `coord`, or `None` if `coord` is outside of a schematic of `size`.
+fn block_index(size: Coord, coord: Coord) -> Option<usize> {
+    if coord.x < 0 || coord.y < 0 || coord.z < 0
+    || coord.x >= size.x || coord.y >= size.y || coord.z >= size.z {
+        return None;
+    }
+    let (x, y, z) = (coord.x as usize, coord.y as usize, coord.z as usize);
+    let (size_x, size_z) = (size.x as usize, size.z as usize);
+    Some(x + z * size_x + y * size_x * size_z)
+}
+
+/// The number of bits that each palette index takes up in a version 1 schematic.
+fn index_bit_width(palette_len: usize) -> u32 {
+    palette_len.next_power_of_two().trailing_zeros()
+}
+
+/// Unpacks the palette indices of a version 1 schematic, which start at the least significant bit of each byte.
+fn unpack_indices(bytes: &[u8], bit_width: u32, count: usize) -> Box<[u32]> {
+    if bit_width == 0 {
+        return (0..count).map(|_| 0).collect();
+    }
+    let mask = (1u64 << bit_width) - 1;
+    let mut indices = Vec::with_capacity(count);
+    let mut accum = 0u64;
+    let mut accum_size = 0u32;
+    for &byte in bytes {
+        accum |= (byte as u64) << accum_size;
+        accum_size += 8;
+        while accum_size >= bit_width && indices.len() < count {
+            indices.push((accum & mask) as u32);
+            accum >>= bit_width;
+            accum_size -= bit_width;
+        }
+    }
+    indices.into_boxed_slice()
+}
+
+/// Reads the blocks of a version 1 schematic: a palette followed by the packed palette index of every block in yzx order.
+fn read_packed_blocks<R: Read>(reader: &mut R, volume: u64) -> Result<(Vec<BlockState>, Box<[u32]>)> {
+    let palette_len = u32::read_from(reader)? as u64;
+    if (palette_len == 0 && volume != 0) || palette_len > volume.max(1) {
+        return Err(Error::InvalidBinaryFormat);
+    }
+    let palette = (0..palette_len)
+        .map(|_| BlockState::read_from(reader))
+        .collect::<Result<Vec<_>>>()?;
+    let bit_width = index_bit_width(palette.len());
+    let bytes = read_bytes(reader, ((volume * bit_width as u64 + 7) / 8) as usize)?;
+    let blocks = unpack_indices(&bytes, bit_width, volume as usize);
+    if blocks.iter().any(|&index| index as u64 >= palette_len) {
+        return Err(Error::InvalidBinaryFormat);
+    }
+    Ok((palette, blocks))
+}
+
+/// Reads the blocks of a schematic that were written in cells by [Schematic::write_to].
+fn read_cell_blocks<R: Read>(reader: &mut R, size: Coord, volume: u64) -> Result<(Vec<BlockState>, Box<[u32]>)> {
+    let mut cell_palette = Vec::new();
+    let mut cell_palette_map = HashMap::<BlockState, u32>::new();
+    let mut cell_blocks: Box<[u32]> = (0..volume).map(|_| 0).collect();
+    for cell in cells(size) {
+        let Some((states, indices)) = read_section_palette(reader)? else {
+            return Err(Error::InvalidBinaryFormat);
+        };
+        let ids = states.into_iter().map(|state| {
+            *cell_palette_map.entry(state).or_insert_with_key(|state| {
+                cell_palette.push(state.clone());
+                cell_palette.len() as u32 - 1
+            })
+        }).collect::<Vec<_>>();
+        indices.iter().enumerate().for_each(|(index, &cell_index)| {
+            if let Some(block) = block_index(size, cell + Section::coord(index as u16)) {
+                cell_blocks[block] = ids[cell_index as usize];
+            }
+        });
+    }
+    Ok((cell_palette, cell_blocks))
+}
+
+/// The min corner of every 16x16x16 cell of a schematic of `size`, in yzx order.
+fn cells(size: Coord) -> impl Iterator<Item = Coord> {
+    let (cells_x, cells_y, cells_z) = ((size.x + 15) >> 4, (size.y + 15) >> 4, (size.z + 15) >> 4);
+    (0..cells_y).flat_map(move |y| {
+        (0..cells_z).flat_map(move |z| {
+            (0..cells_x).map(move |x| Coord::new(x, y, z) * 16)
+        })
+    })
+}
+
+impl Writeable for Schematic {
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        let mut length = write_bytes(writer, &SCHEMATIC_MAGIC)?;
+        length += SCHEMATIC_VERSION.write_to(writer)?;
+        length += self.author.write_to(writer)?;
+        length += self.origin.xyz().write_to(writer)?;
+        length += self.created.write_to(writer)?;
+        length += self.size.xyz().write_to(writer)?;
+        length = cells(self.size).try_fold(length, |length, cell| {
+            // Blocks outside of the schematic are padded with the block at the min corner of the cell.
+            let padding = self.blocks[block_index(self.size, cell).expect("Cell was outside of the schematic")];
+            let mut palette = Vec::new();
+            let mut palette_map = HashMap::<u32, u16>::new();
+            let indices = (0..4096u16).map(|index| {
+                let block = block_index(self.size, cell + Section::coord(index))
+                    .map(|block| self.blocks[block])
+                    .unwrap_or(padding);
+                *palette_map.entry(block).or_insert_with(|| {
+                    palette.push(&self.palette[block as usize]);
+                    palette.len() as u16 - 1
+                })
+            }).collect::<Vec<_>>();
+            Result::Ok(length + write_section_palette(writer, &palette, &indices)?)
+        })?;
+        length += (self.data.len() as u32).write_to(writer)?;
+        length = self.data.iter().try_fold(length, |length, (coord, tag)| {
+            Result::Ok(length + coord.xyz().write_to(writer)? + tag.write_to(writer)?)
+        })?;
+        length += (self.enabled.len() as u32).write_to(writer)?;
+        self.enabled.iter().try_fold(length, |length, coord| {
+            Result::Ok(length + coord.xyz().write_to(writer)?)
+        })
+    }
+}
+
+impl Readable for Schematic {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let magic = read_bytes(reader, SCHEMATIC_MAGIC.len())?;
+        if magic != SCHEMATIC_MAGIC {
+            return Err(Error::NotASchematic);
+        }
+        let version = u16::read_from(reader)?;
+        if !(1..=SCHEMATIC_VERSION).contains(&version) {
+            return Err(Error::UnsupportedSchematicVersion(version));
+        }
+        let author = String::read_from(reader)?;
+        let origin: Coord = <(i32, i32, i32)>::read_from(reader)?.into();
+        let created = Timestamp::read_from(reader)?;
+        let size: Coord = <(i32, i32, i32)>::read_from(reader)?.into();
+        if size.x < 0 || size.y < 0 || size.z < 0 {
+            return Err(Error::InvalidBinaryFormat);
+        }
+        let volume = (size.x as u64).checked_mul(size.y as u64)
+            .and_then(|volume| volume.checked_mul(size.z as u64))
+            .filter(|&volume| volume <= MAX_SCHEMATIC_VOLUME)
+            .ok_or(Error::SchematicTooLarge(size.x, size.y, size.z))?;
+        let (cell_palette, cell_blocks) = if version == 1 {
+            read_packed_blocks(reader, volume)?
+        } else {
+            read_cell_blocks(reader, size, volume)?
+        };
+        // The palette is put in the order that the states first appear in, like the palette of Schematic::from_clipboard.
+        let mut palette = Vec::new();
+        let mut palette_map = HashMap::<u32, u32>::new();
+        let blocks = cell_blocks.iter().map(|&block| {
+            *palette_map.entry(block).or_insert_with(|| {
+                palette.push(cell_palette[block as usize].clone());
+                palette.len() as u32 - 1
+            })
+        }).collect();
+        let data_len = u32::read_from(reader)?;
+        let data = (0..data_len).map(|_| {
+            let coord: Coord = <(i32, i32, i32)>::read_from(reader)?.into();
+            Result::Ok((coord, Tag::read_from(reader)?))
+        }).collect::<Result<HashMap<_, _>>>()?;
+        let enabled_len = u32::read_from(reader)?;
+        let enabled = (0..enabled_len).map(|_| {
+            Result::Ok(<(i32, i32, i32)>::read_from(reader)?.into())
+        }).collect::<Result<HashSet<Coord>>>()?;
+        Ok(Self {
+            author,
+            origin,
+            created,
+            size,
+            palette,
+            blocks,
+            data,
+            enabled,
+        })
+    }
+}
+
+impl VoxelWorld {
+    /// Copies the blocks between two corners (inclusive) into a [Schematic] and saves it to `path`.
+    /// To set the author or origin, use [VoxelWorld::copy] and [Schematic::from_clipboard] instead.
+    pub fn export_schematic<A: Into<(i32, i32, i32)>, B: Into<(i32, i32, i32)>, P: AsRef<Path>>(&self, a: A, b: B, path: P) -> Result<Schematic> {
+        let schematic = Schematic::from_clipboard(&self.copy(a, b));
+        schematic.save(path)?;
+        Ok(schematic)
+    }
+
+    /// Pastes a [Schematic] so that its origin is at `position`. See [VoxelWorld::paste].
+    /// Returns the number of blocks that were changed.
+    pub fn paste_schematic<C: Into<(i32, i32, i32)>>(&mut self, schematic: &Schematic, position: C, options: PasteOptions) -> usize {
+        let position: (i32, i32, i32) = position.into();
+        let position: Coord = position.into();
+        let min_corner = position - options.orientation.transform(schematic.origin);
+        self.paste(&schematic.to_clipboard(), min_corner, options)
+    }
+
+    /// Loads a [Schematic] from `path` and pastes it so that its origin is at `position`.
+    /// Returns the number of blocks that were changed.
+    pub fn import_schematic<P: AsRef<Path>, C: Into<(i32, i32, i32)>>(&mut self, path: P, position: C, options: PasteOptions) -> Result<usize> {
+        let schematic = Schematic::load(path)?;
+        Ok(self.paste_schematic(&schematic, position, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::testing::{open_world, register_test_blocks, TestDirectory};
+
+    use super::*;
+
+    #[test]
+    fn schematic_cells_test() {
+        register_test_blocks();
+        let states: Vec<Id> = (0..300i64).map(|i| blockstate!(test_stone, variant = i).register()).collect();
+        // Crosses cell boundaries on every axis, and the cells have more than one byte of palette indices.
+        let mut clipboard = Clipboard::new((20, 17, 33));
+        clipboard.iter().map(|(coord, _)| coord).collect::<Vec<_>>().into_iter().for_each(|coord| {
+            let index = (coord.x * 7 + coord.y * 13 + coord.z * 31) as usize % states.len();
+            clipboard.set(coord, states[index]);
+        });
+        let schematic = Schematic::from_clipboard(&clipboard);
+        let mut buffer = Vec::new();
+        schematic.write_to(&mut buffer).expect("Failed to write schematic");
+        let read = Schematic::read_from(&mut buffer.as_slice()).expect("Failed to read schematic");
+        assert_eq!(read, schematic);
+        assert_eq!(read.to_clipboard(), clipboard);
+        // A size that overflows is rejected before anything is allocated.
+        let mut header = Vec::new();
+        write_bytes(&mut header, &SCHEMATIC_MAGIC).expect("Failed to write header");
+        SCHEMATIC_VERSION.write_to(&mut header).expect("Failed to write header");
+        String::new().write_to(&mut header).expect("Failed to write header");
+        (0, 0, 0).write_to(&mut header).expect("Failed to write header");
+        Timestamp::new(0).write_to(&mut header).expect("Failed to write header");
+        (i32::MAX, i32::MAX, i32::MAX).write_to(&mut header).expect("Failed to write header");
+        assert!(matches!(
+            Schematic::read_from(&mut header.as_slice()),
+            Err(Error::SchematicTooLarge(i32::MAX, i32::MAX, i32::MAX))
+        ));
+    }
+
+    #[test]
+    fn schematic_round_trip_test() {
+        register_test_blocks();
+        let states: Vec<Id> = (0..5i64).map(|i| blockstate!(test_stone, variant = i).register()).collect();
+        let mut clipboard = Clipboard::new((4, 3, 2));
+        clipboard.iter().map(|(coord, _)| coord).collect::<Vec<_>>().into_iter().for_each(|coord| {
+            if (coord.x + coord.y + coord.z) % 3 != 0 {
+                clipboard.set(coord, states[(coord.x + coord.z) as usize % states.len()]);
+            }
+        });
+        clipboard.set_data((1, 1, 1), "sign text");
+        clipboard.set_enabled((2, 0, 1), true);
+        let schematic = Schematic::from_clipboard(&clipboard)
+            .with_author("tester")
+            .with_origin((1, 0, 1));
+        assert_eq!(schematic.to_clipboard(), clipboard);
+        // In memory.
+        let mut buffer = Vec::new();
+        schematic.write_to(&mut buffer).expect("Failed to write schematic");
+        let read = Schematic::read_from(&mut buffer.as_slice()).expect("Failed to read schematic");
+        assert_eq!(read, schematic);
+        // Compressed on disk.
+        let directory = TestDirectory::new("schematic_round_trip_test");
+        let path = directory.path().join("test.schem");
+        schematic.save(&path).expect("Failed to save schematic");
+        let loaded = Schematic::load(&path).expect("Failed to load schematic");
+        assert_eq!(loaded, schematic);
+        assert_eq!(loaded.author, "tester");
+        assert_eq!(loaded.get((1, 1, 1)), schematic.get((1, 1, 1)));
+        // Other versions are rejected.
+        buffer[4..6].copy_from_slice(&(SCHEMATIC_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            Schematic::read_from(&mut buffer.as_slice()),
+            Err(Error::UnsupportedSchematicVersion(version)) if version == SCHEMATIC_VERSION + 1
+        ));
+        assert!(matches!(Schematic::read_from(&mut &b"nope"[..]), Err(Error::NotASchematic)));
+    }
+
+    #[test]
+    fn schematic_v1_test() {
+        register_test_blocks();
+        let states: Vec<Id> = (0..3i64).map(|i| blockstate!(test_stone, variant = i).register()).collect();
+        let mut clipboard = Clipboard::new((3, 2, 2));
+        clipboard.iter().map(|(coord, _)| coord).collect::<Vec<_>>().into_iter().for_each(|coord| {
+            clipboard.set(coord, states[(coord.x + coord.y) as usize % states.len()]);
+        });
+        clipboard.set_data((1, 1, 1), "sign text");
+        clipboard.set_enabled((2, 0, 1), true);
+        let schematic = Schematic::from_clipboard(&clipboard).with_author("tester").with_origin((1, 0, 1));
+        // Written the way that version 1 schematics were written, with one palette and the indices packed into bytes.
+        let mut buffer = Vec::new();
+        write_bytes(&mut buffer, &SCHEMATIC_MAGIC).expect("Failed to write schematic");
+        1u16.write_to(&mut buffer).expect("Failed to write schematic");
+        schematic.author.write_to(&mut buffer).expect("Failed to write schematic");
+        schematic.origin.xyz().write_to(&mut buffer).expect("Failed to write schematic");
+        schematic.created.write_to(&mut buffer).expect("Failed to write schematic");
+        schematic.size.xyz().write_to(&mut buffer).expect("Failed to write schematic");
+        (schematic.palette.len() as u32).write_to(&mut buffer).expect("Failed to write schematic");
+        schematic.palette.iter().for_each(|state| {
+            state.write_to(&mut buffer).expect("Failed to write schematic");
+        });
+        let bit_width = index_bit_width(schematic.palette.len());
+        let mut accum = 0u64;
+        let mut accum_size = 0u32;
+        schematic.blocks.iter().for_each(|&index| {
+            accum |= (index as u64) << accum_size;
+            accum_size += bit_width;
+            while accum_size >= 8 {
+                buffer.push(accum as u8);
+                accum >>= 8;
+                accum_size -= 8;
+            }
+        });
+        if accum_size > 0 {
+            buffer.push(accum as u8);
+        }
+        1u32.write_to(&mut buffer).expect("Failed to write schematic");
+        (1, 1, 1).write_to(&mut buffer).expect("Failed to write schematic");
+        Tag::from("sign text").write_to(&mut buffer).expect("Failed to write schematic");
+        1u32.write_to(&mut buffer).expect("Failed to write schematic");
+        (2, 0, 1).write_to(&mut buffer).expect("Failed to write schematic");
+        let read = Schematic::read_from(&mut buffer.as_slice()).expect("Failed to read version 1 schematic");
+        assert_eq!(read, schematic);
+        assert_eq!(read.to_clipboard(), clipboard);
+        // Loading it from a file and saving it again upgrades it to the current version.
+        let directory = TestDirectory::new("schematic_v1_test");
+        let path = directory.path().join("old.schem");
+        let mut encoder = GzEncoder::new(File::create(&path).expect("Failed to create file"), Compression::default());
+        encoder.write_all(&buffer).expect("Failed to write file");
+        encoder.finish().expect("Failed to write file");
+        let loaded = Schematic::load(&path).expect("Failed to load version 1 schematic");
+        assert_eq!(loaded, schematic);
+        loaded.save(&path).expect("Failed to save schematic");
+        let mut decoder = GzDecoder::new(File::open(&path).expect("Failed to open file"));
+        let bytes = read_bytes(&mut decoder, 6).expect("Failed to read file");
+        assert_eq!(&bytes[4..6], &SCHEMATIC_VERSION.to_be_bytes());
+        assert_eq!(Schematic::load(&path).expect("Failed to load schematic"), schematic);
+    }
+
+    #[test]
+    fn world_schematic_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("world_schematic_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        world.set_block((0, 0, 0), stone);
+        world.set_block((0, 1, 0), stone);
+        world.set_data((0, 1, 0), 7i32);
+        let path = directory.path().join("tower.schem");
+        let exported = world.export_schematic((0, 0, 0), (0, 2, 0), &path).expect("Failed to export schematic");
+        assert_eq!(exported.size(), Coord::new(1, 3, 1));
+        assert_eq!(world.import_schematic(&path, (5, 0, 5), PasteOptions::new()).expect("Failed to import schematic"), 2);
+        assert_eq!(world.get_block((5, 0, 5)), stone);
+        assert_eq!(world.get_block((5, 1, 5)), stone);
+        assert_eq!(world.get_data((5, 1, 5)), Some(&Tag::from(7i32)));
+        // The origin ends up at the paste position.
+        let schematic = exported.with_origin((0, 1, 0));
+        world.paste_schematic(&schematic, (10, 1, 10), PasteOptions::new());
+        assert_eq!(world.get_block((10, 0, 10)), stone);
+        assert_eq!(world.get_block((10, 1, 10)), stone);
+    }
+}
@@ -2,8 +2,11 @@
 use bevy::{asset::Assets, prelude::{state_changed, ResMut}, render::mesh::Mesh, utils::tracing::Instrument};
 
 use crate::{core::{collections::objectpool::PoolId, error::*}, prelude::{BitFlags, BitFlags8, SwapVal, Writeable}};
-use crate::core::voxel::{blocks::Id, blockstate::BlockState, coord::Coord, direction::Direction, rendering::voxelmaterial::VoxelMaterial, tag::Tag};
+use crate::core::voxel::{blocks::{self, Id}, blockstate::BlockState, coord::Coord, direction::Direction, rendering::voxelmaterial::VoxelMaterial, tag::Tag};
 
+use std::sync::Arc;
+
+use super::migration::{read_migrated_section_blocks, MigratedPalette, Migration};
 use super::{blockdata::{BlockDataContainer, BlockDataRef}, dirty::Dirty, heightmap::Heightmap, io::{read_block_data, read_enabled, read_section_blocks, read_section_light, read_section_occlusions}, occlusion::Occlusion, query::VoxelQuery, update::UpdateRef, DirtyIdMarker, MemoryUsage, SaveIdMarker, VoxelWorld, WORLD_HEIGHT};
 use crate::core::io::*;
 
@@ -25,6 +28,9 @@ pub struct Section {
     pub update_refs: Option<Box<[UpdateRef]>>,
     pub block_data: BlockDataContainer,
     pub block_count: u16,
+    /// The number of blocks that opt into random ticks with [Block::ticks_randomly](crate::core::voxel::block::Block::ticks_randomly).
+    /// Sections where this is `0` are skipped when random ticking.
+    pub random_tick_count: u16,
     pub occlusion_count: u16,
     pub block_light_count: u16,
     pub sky_light_count: u16,
@@ -56,6 +62,7 @@ impl Section {
             update_refs: None,
             block_data: BlockDataContainer::new(),
             block_count: 0,
+            random_tick_count: 0,
             occlusion_count: 0,
             block_light_count: 0,
             sky_light_count: 0,
@@ -211,6 +218,12 @@ impl Section {
         std::mem::swap(&mut blocks[index], &mut old);
         // Check that the new state is different than the old state
         if state != old {
+            if !old.is_air() && old.block().ticks_randomly() {
+                self.random_tick_count -= 1;
+            }
+            if !state.is_air() && state.block().ticks_randomly() {
+                self.random_tick_count += 1;
+            }
             if old.is_air() && !state.is_air() {
                 // if the old state is air and the new state isn't air, increment the block_count.
                 // this allows to keep track of when the section is all air blocks.
@@ -347,13 +360,13 @@ impl Section {
         let sky_light = if let Some(sky_light) = &self.sky_light {
             sky_light[mask_index] >> shift & 0xF
         } else {
-            0
+            15
         };
         if self.block_light.is_none() {
             if level != 0 {
                 // if level isn't 0, we want to make an empty lightmap
                 self.used_flags.set(Self::BLOCK_LIGHT_BIT_INDEX, true);
-                self.block_light = Some(make_empty_section_light());
+                self.block_light = Some(make_empty_section_light(0));
             } else {
                 // No change has occurred
                 return SectionUpdate::new(LightChange {
@@ -424,12 +437,12 @@ impl Section {
         let block_light = if let Some(block_light) = &self.block_light {
             block_light[mask_index] >> shift & 0xF
         } else {
-            15
+            0
         };
         if self.sky_light.is_none() {
             if level != 15 {
                 self.used_flags.set(Self::SKY_LIGHT_BIT_INDEX, true);
-                self.sky_light = Some(make_empty_section_light());
+                self.sky_light = Some(make_empty_section_light(15));
             } else {
                 return SectionUpdate::new(LightChange {
                     old_max: block_light,
@@ -570,7 +583,7 @@ impl Section {
             let sky_light = if let Some(sky_light) = &self.sky_light {
                 sky_light[mask_index] >> shift & 0xF
             } else {
-                0
+                15
             };
             let light = block_light.max(sky_light);
             dest[i] = light;
@@ -600,31 +613,69 @@ impl Section {
         Ok(length)
     }
 
-    pub fn read_from<R: std::io::Read>(&mut self, reader: &mut R, world: &mut VoxelWorld, offset: Coord) -> Result<bool> {
+    /// Reads the section into this empty section, applying `migrations` to its block states and block data.
+    /// This doesn't touch the world, so it can run on the IO worker. The update queue belongs to the world, so the
+    /// indices of the blocks that have updates enabled are passed to `enable`, and [Section::take_decoded] enables them.
+    /// Returns true if the migrations changed any of the section's block states.
+    pub fn decode<R: std::io::Read, F: FnMut(u16)>(&mut self, reader: &mut R, migrations: &[Arc<dyn Migration>], enable: F) -> Result<bool> {
         let flag = bool::read_from(reader)?;
         if !flag {
-            // We assume the chunk has already been unloaded.
             return Ok(false);
         }
-        read_section_blocks(reader, &mut self.blocks, &mut self.block_count)?;
+        let migrated = if migrations.is_empty() {
+            read_section_blocks(reader, &mut self.blocks, &mut self.block_count)?;
+            None
+        } else {
+            read_migrated_section_blocks(reader, &mut self.blocks, &mut self.block_count, migrations)?
+        };
+        self.random_tick_count = self.blocks.as_ref().map(|blocks| {
+            blocks.iter().filter(|state| !state.is_air() && state.block().ticks_randomly()).count() as u16
+        }).unwrap_or(0);
         read_section_occlusions(reader, &mut self.occlusion, &mut self.occlusion_count)?;
-        read_section_light(reader, &mut self.block_light, &mut self.block_light_count)?;
-        read_section_light(reader, &mut self.sky_light, &mut self.sky_light_count)?;
+        read_section_light(reader, &mut self.block_light, &mut self.block_light_count, 0)?;
+        read_section_light(reader, &mut self.sky_light, &mut self.sky_light_count, 15)?;
         read_block_data(reader, &mut self.block_data_refs, &mut self.block_data, &mut self.block_data_count)?;
-        // We're assuming that the old data hasn't been safely unloaded yet.
-        let update_refs = self.update_refs.get_or_insert_with(|| (0..4096).map(|_| UpdateRef::NULL).collect());
-        update_refs.iter_mut().for_each(|uref| {
-            if !uref.null() {
-                world.update_queue.remove(*uref);
-                *uref = UpdateRef::NULL;
-            }
-        });
-        read_enabled(reader, |index| {
-            let block_coord = Section::coord(index) + offset;
-            // You can't use world.set_enabled here because world.set_enabled needs access to chunks, which is already being borrowed.
-            let uref = world.update_queue.push(block_coord);
-            update_refs[index as usize] = uref;
-        }, &mut self.update_ref_count)?;
+        if let (Some(migrated), Some(block_data_refs)) = (&migrated, &self.block_data_refs) {
+            migrated.migrate_data(block_data_refs, &mut self.block_data, migrations);
+        }
+        read_enabled(reader, enable, &mut self.update_ref_count)?;
+        Ok(migrated.as_ref().is_some_and(MigratedPalette::changed))
+    }
+
+    /// The number of [UnknownBlock](crate::core::voxel::blocks::UnknownBlock)s in the section.
+    pub fn unknown_count(&self) -> u64 {
+        if !blocks::has_unknown_states() {
+            return 0;
+        }
+        self.blocks.as_ref().map(|blocks| blocks.iter().filter(|state| state.is_unknown()).count() as u64).unwrap_or(0)
+    }
+
+    /// Moves a section that was read with [Section::decode] into this section, which should be unloaded, and enables
+    /// updates for the blocks at `enabled` (indices in the section). `offset` is the coordinate of the section's first block.
+    pub fn take_decoded(&mut self, decoded: Section, world: &mut VoxelWorld, offset: Coord, enabled: &[u16]) {
+        world.unknown_blocks_loaded += decoded.unknown_count();
+        self.blocks = decoded.blocks;
+        self.block_count = decoded.block_count;
+        self.random_tick_count = decoded.random_tick_count;
+        self.occlusion = decoded.occlusion;
+        self.occlusion_count = decoded.occlusion_count;
+        self.block_light = decoded.block_light;
+        self.block_light_count = decoded.block_light_count;
+        self.sky_light = decoded.sky_light;
+        self.sky_light_count = decoded.sky_light_count;
+        self.block_data_refs = decoded.block_data_refs;
+        self.block_data = decoded.block_data;
+        self.block_data_count = decoded.block_data_count;
+        self.update_ref_count = 0;
+        if !enabled.is_empty() {
+            let mut update_refs: Box<[UpdateRef]> = (0..4096).map(|_| UpdateRef::NULL).collect();
+            enabled.iter().for_each(|&index| {
+                // You can't use world.set_enabled here because world.set_enabled needs access to chunks, which is already being borrowed.
+                update_refs[index as usize] = world.update_queue.push(Section::coord(index) + offset);
+            });
+            self.update_refs = Some(update_refs);
+            self.update_ref_count = enabled.len() as u16;
+        }
         self.used_flags.set(Self::BLOCKS_BIT_INDEX, self.blocks.is_some());
         self.used_flags.set(Self::OCCLUSION_BIT_INDEX, self.occlusion.is_some());
         self.used_flags.set(Self::BLOCK_LIGHT_BIT_INDEX, self.block_light.is_some());
@@ -633,7 +684,7 @@ impl Section {
         self.used_flags.set(Self::BLOCK_DATA_BIT_INDEX, self.block_data_refs.is_some());
         self.blocks_dirty.mark();
         self.light_dirty.mark();
-        return Ok(self.section_dirty.mark());
+        self.section_dirty.mark();
     }
 
     fn disable_all(&mut self, world: &mut VoxelWorld) {
@@ -660,6 +711,7 @@ impl Section {
         self.disable_all(world);
         self.block_data.clear();
         self.block_count = 0;
+        self.random_tick_count = 0;
         self.occlusion_count = 0;
         self.block_light_count = 0;
         self.sky_light_count = 0;
@@ -672,6 +724,34 @@ impl Section {
     pub fn is_empty(&self) -> bool {
         self.used_flags.0 == 0
     }
+
+    /// Shows every face in the section so that its occlusion can be calculated again.
+    pub fn reset_occlusion(&mut self) {
+        self.occlusion = None;
+        self.occlusion_count = 0;
+        self.used_flags.set(Self::OCCLUSION_BIT_INDEX, false);
+        self.blocks_dirty.mark();
+    }
+
+    /// Moves the blocks, occlusion, and light of a section that was generated outside of the world into this section,
+    /// which should be empty. Block data and updates aren't moved because generators don't set them.
+    pub fn take_generated(&mut self, generated: Section) {
+        self.blocks = generated.blocks;
+        self.block_count = generated.block_count;
+        self.random_tick_count = generated.random_tick_count;
+        self.occlusion = generated.occlusion;
+        self.occlusion_count = generated.occlusion_count;
+        self.block_light = generated.block_light;
+        self.block_light_count = generated.block_light_count;
+        self.sky_light = generated.sky_light;
+        self.sky_light_count = generated.sky_light_count;
+        self.used_flags.set(Self::BLOCKS_BIT_INDEX, self.blocks.is_some());
+        self.used_flags.set(Self::OCCLUSION_BIT_INDEX, self.occlusion.is_some());
+        self.used_flags.set(Self::BLOCK_LIGHT_BIT_INDEX, self.block_light.is_some());
+        self.used_flags.set(Self::SKY_LIGHT_BIT_INDEX, self.sky_light.is_some());
+        self.blocks_dirty.mark();
+        self.light_dirty.mark();
+    }
 }
 
 
@@ -736,8 +816,10 @@ fn make_empty_section_blocks() -> Box<[Id]> {
 }
 
 /// Create empty [Section] lightmap.
-fn make_empty_section_light() -> Box<[u8]> {
-    (0..2048).map(|_| 15).collect()
+/// Create an empty light array with every nibble set to `level`.
+fn make_empty_section_light(level: u8) -> Box<[u8]> {
+    let packed = level & 0xF | level << 4;
+    (0..2048).map(|_| packed).collect()
 }
 
 /// Create empty [Section] block data ref grid.
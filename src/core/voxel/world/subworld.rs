@@ -0,0 +1,452 @@
+#![allow(unused)]
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bevy::asset::Assets;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Commands, Query, ResMut, Resource};
+use bevy::math::Vec3;
+use bevy::render::mesh::Mesh;
+use bevy::transform::components::Transform;
+use hashbrown::HashMap;
+use rollgrid::rollgrid3d::Bounds3D;
+
+use crate::core::error::*;
+use crate::core::io::*;
+use crate::core::voxel::entity::EntityId;
+use crate::core::voxel::tag::Tag;
+use crate::core::voxel::procgen::worldgenerator::WorldGenerator;
+use crate::core::voxel::rendering::voxelmaterial::VoxelMaterial;
+
+use super::metadata::{SharedMetadata, WorldMetadata};
+use super::{RenderChunkMarker, VoxelWorld, WORLD_BOTTOM, WORLD_TOP};
+
+/// The subworld that [VoxelWorld::open] and [VoxelWorld::open_headless] open.
+pub const MAIN_SUBWORLD: &str = "main";
+
+/// The name of the file in a subworld's directory that holds the subworld's bounds.
+pub const SUBWORLD_DATA_FILE: &str = "subworld.dat";
+
+/// Subworld names are used as directory names, so they are limited to ASCII letters, digits, `_`, and `-`.
+pub fn valid_subworld_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Lists the subworlds that exist in the world in `directory`, sorted by name.
+pub fn list_subworlds<P: AsRef<Path>>(directory: P) -> Result<Vec<String>> {
+    let subworlds = directory.as_ref().join("subworlds");
+    if !subworlds.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(subworlds)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if valid_subworld_name(&name) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Reads the bounds that were saved in [SUBWORLD_DATA_FILE] when the subworld was created.
+/// Returns `None` if the subworld doesn't have the file.
+pub(crate) fn read_subworld_bounds<P: AsRef<Path>>(subworld_directory: P) -> Result<Option<Bounds3D>> {
+    let path = subworld_directory.as_ref().join(SUBWORLD_DATA_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    let tag = Tag::read_from(&mut reader)?;
+    if !matches!(tag, Tag::Map(_)) {
+        return Err(Error::InvalidBinaryFormat);
+    }
+    let (&Tag::IVec3(min), &Tag::IVec3(max)) = (&tag["min"], &tag["max"]) else {
+        return Err(Error::InvalidBinaryFormat);
+    };
+    Ok(Some(Bounds3D::new(min.into(), max.into())))
+}
+
+/// Writes the bounds of a subworld to [SUBWORLD_DATA_FILE] as a [Tag] map with `min` and `max` (IVec3).
+pub(crate) fn write_subworld_bounds<P: AsRef<Path>>(subworld_directory: P, bounds: Bounds3D) -> Result<()> {
+    let path = subworld_directory.as_ref().join(SUBWORLD_DATA_FILE);
+    let temp_path = path.with_extension("dat.tmp");
+    let mut map = HashMap::new();
+    map.insert(String::from("min"), Tag::from(bevy::math::IVec3::from(bounds.min)));
+    map.insert(String::from("max"), Tag::from(bevy::math::IVec3::from(bounds.max)));
+    {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        Tag::from(map).write_to(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+    std::fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// The settings that a subworld is opened with. The bounds are saved in [SUBWORLD_DATA_FILE] when the
+/// subworld is created, and the saved bounds are used instead of `bounds` whenever it's opened again.
+pub struct SubworldOptions {
+    /// The area that blocks can be placed in. This is clamped to [VoxelWorld::WORLD_BOUNDS].
+    pub bounds: Bounds3D,
+    pub generator: Option<Box<dyn WorldGenerator>>,
+}
+
+impl SubworldOptions {
+    pub const fn new() -> Self {
+        Self {
+            bounds: VoxelWorld::WORLD_BOUNDS,
+            generator: None,
+        }
+    }
+
+    pub fn with_generator(mut self, generator: Option<Box<dyn WorldGenerator>>) -> Self {
+        self.generator = generator;
+        self
+    }
+
+    /// Limits the subworld to the given block bounds (`max` is exclusive).
+    pub fn with_bounds(mut self, bounds: Bounds3D) -> Self {
+        let Bounds3D { min: (min_x, min_y, min_z), max: (max_x, max_y, max_z) } = bounds;
+        let (min_y, max_y) = (min_y.max(WORLD_BOTTOM), max_y.min(WORLD_TOP));
+        self.bounds = Bounds3D::new((min_x, min_y, min_z), (max_x.max(min_x), max_y.max(min_y), max_z.max(min_z)));
+        self
+    }
+
+    /// Limits the height of the subworld to the blocks from `bottom` up to (but not including) `top`.
+    /// The height can't be larger than the height of the world ([WORLD_BOTTOM] to [WORLD_TOP]).
+    pub fn with_height(self, bottom: i32, top: i32) -> Self {
+        let Bounds3D { min: (min_x, _, min_z), max: (max_x, _, max_z) } = self.bounds;
+        self.with_bounds(Bounds3D::new((min_x, bottom, min_z), (max_x, top, max_z)))
+    }
+}
+
+impl Default for SubworldOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The subworlds (or dimensions) of a world directory that are open. Every open subworld is a
+/// separate [VoxelWorld], so they can all be updated at the same time. One of them can be active,
+/// which is the one that the [RenderLayer](super::RenderLayer) is attached to.
+///
+/// The subworlds share one [WorldMetadata], which is read when the first subworld is opened.
+#[derive(Resource)]
+pub struct Subworlds {
+    directory: PathBuf,
+    worlds: HashMap<String, VoxelWorld>,
+    active: Option<String>,
+    metadata: Option<SharedMetadata>,
+}
+
+impl Subworlds {
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_owned(),
+            worlds: HashMap::new(),
+            active: None,
+            metadata: None,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The [WorldMetadata] that the subworlds share. This is `None` until a subworld has been opened.
+    pub fn metadata(&self) -> Option<&SharedMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Lists the subworlds that exist on disk, whether they are open or not.
+    pub fn list(&self) -> Result<Vec<String>> {
+        list_subworlds(&self.directory)
+    }
+
+    /// Opens a subworld (headless) and loads its chunks in the background like [VoxelWorld::open_headless].
+    /// If the subworld is already open, the open subworld is returned and the arguments are ignored.
+    /// The first subworld that is opened becomes the active subworld.
+    pub fn open<C: Into<(i32, i32, i32)>>(&mut self, name: &str, render_distance: u8, center: C, options: SubworldOptions) -> Result<&mut VoxelWorld> {
+        if !valid_subworld_name(name) {
+            return Err(Error::InvalidSubworldName(name.to_owned()));
+        }
+        if !self.worlds.contains_key(name) {
+            let metadata = match &self.metadata {
+                Some(metadata) => metadata.clone(),
+                None => {
+                    std::fs::create_dir_all(&self.directory)?;
+                    SharedMetadata::new(WorldMetadata::load_or_create(&self.directory, rand::random())?)
+                }
+            };
+            let world = VoxelWorld::open_subworld_with_metadata(&self.directory, name, render_distance, center, options, metadata.clone())?;
+            self.worlds.insert(name.to_owned(), world);
+            self.metadata = Some(metadata);
+        }
+        if self.active.is_none() {
+            self.active = Some(name.to_owned());
+        }
+        Ok(self.worlds.get_mut(name).expect("Subworld was not open"))
+    }
+
+    /// Saves and closes a subworld. If the subworld was active, there is no active subworld afterwards.
+    /// The render layer should be detached from the subworld before it's closed.
+    pub fn close(&mut self, name: &str) -> Result<VoxelWorld> {
+        let mut world = self.worlds.remove(name).ok_or_else(|| Error::SubworldNotOpen(name.to_owned()))?;
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        world.save_world()?;
+        if world.workers.is_some() {
+            world.finish_chunk_jobs();
+        }
+        Ok(world)
+    }
+
+    pub fn is_open(&self, name: &str) -> bool {
+        self.worlds.contains_key(name)
+    }
+
+    /// The names of the subworlds that are open.
+    pub fn open_names(&self) -> impl Iterator<Item = &str> {
+        self.worlds.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VoxelWorld> {
+        self.worlds.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut VoxelWorld> {
+        self.worlds.get_mut(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &VoxelWorld)> {
+        self.worlds.iter().map(|(name, world)| (name.as_str(), world))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut VoxelWorld)> {
+        self.worlds.iter_mut().map(|(name, world)| (name.as_str(), world))
+    }
+
+    pub fn active_name(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub fn active(&self) -> Option<&VoxelWorld> {
+        self.worlds.get(self.active.as_deref()?)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut VoxelWorld> {
+        self.worlds.get_mut(self.active.as_deref()?)
+    }
+
+    /// Makes an open subworld the active subworld. If the active subworld has a [RenderLayer](super::RenderLayer),
+    /// it's detached and a new one is attached to the subworld that becomes active.
+    /// Returns false if the subworld isn't open.
+    pub fn switch(&mut self, name: &str, commands: &mut Commands) -> bool {
+        if !self.worlds.contains_key(name) {
+            return false;
+        }
+        if self.active.as_deref() == Some(name) {
+            return true;
+        }
+        let render = self.active_mut().and_then(|world| world.detach_render_layer(commands));
+        self.active = Some(name.to_owned());
+        if let Some(render) = render {
+            let world = self.worlds.get_mut(name).expect("Subworld was not open");
+            world.attach_render_layer(render.array_texture);
+        }
+        true
+    }
+
+    /// Renders the active subworld with [VoxelWorld::talk_to_bevy]. Use this (or [render_active_subworld])
+    /// instead of a [VoxelWorld] resource when the world is opened with [Subworlds].
+    pub fn talk_to_bevy(
+        &mut self,
+        commands: Commands,
+        meshes: ResMut<Assets<Mesh>>,
+        materials: ResMut<Assets<VoxelMaterial>>,
+        render_chunks: Query<&mut Transform, With<RenderChunkMarker>>,
+    ) {
+        if let Some(world) = self.active_mut() {
+            world.talk_to_bevy(commands, meshes, materials, render_chunks);
+        }
+    }
+
+    /// Sorts the transparent faces of the active subworld with [VoxelWorld::sort_transparent_faces].
+    pub fn sort_transparent_faces(&mut self, camera: Vec3, meshes: &mut Assets<Mesh>) {
+        if let Some(world) = self.active_mut() {
+            world.sort_transparent_faces(camera, meshes);
+        }
+    }
+
+    /// Moves an entity from one open subworld to another. The entity is placed at `position` in the
+    /// destination subworld. If the chunk at `position` isn't loaded in the destination, the entity stays
+    /// where it was and `None` is returned.
+    ///
+    /// Games can move players the same way by moving their camera to the destination subworld.
+    pub fn transfer_entity(&mut self, id: EntityId, from: &str, to: &str, position: Vec3) -> Option<EntityId> {
+        if from == to || !self.worlds.contains_key(to) {
+            return None;
+        }
+        let source = self.worlds.get_mut(from)?;
+        let mut entity = source.remove_entity(id)?;
+        let old_position = entity.position;
+        entity.position = position;
+        let destination = self.worlds.get_mut(to).expect("Subworld was not open");
+        if let Some(new_id) = destination.spawn_entity(entity.clone()) {
+            return Some(new_id);
+        }
+        entity.position = old_position;
+        let source = self.worlds.get_mut(from).expect("Subworld was not open");
+        // Entity ids can change when an entity is spawned again, so the original id may no longer be valid.
+        source.spawn_entity(entity);
+        None
+    }
+
+    /// Pumps every open subworld, active or not. Each subworld loads and generates chunks for up to `budget`
+    /// per queue, integrates the jobs that its workers finished, and then runs [VoxelWorld::update].
+    /// Use this (or [update_subworlds]) instead of updating only the active subworld.
+    pub fn update(&mut self, budget: std::time::Duration) {
+        self.worlds.values_mut().for_each(|world| {
+            world.process_load_queue(budget);
+            world.process_worldgen_queue(budget);
+            if world.workers.is_some() {
+                world.process_job_results(budget);
+            }
+            world.update();
+        });
+    }
+
+    /// Saves every open subworld, then writes the [WorldMetadata] once.
+    pub fn save_all(&mut self) -> Result<()> {
+        self.worlds.values_mut().try_for_each(VoxelWorld::save_chunks)?;
+        // The metadata is shared, so it's written by the subworld that has run for the most ticks.
+        let Some(world) = self.worlds.values_mut().max_by_key(|world| world.scheduled_updates.current_tick()) else {
+            return Ok(());
+        };
+        world.save_metadata()
+    }
+}
+
+/// Renders the active subworld of the [Subworlds] resource. Add this system in place of the one that calls
+/// [VoxelWorld::talk_to_bevy] when the world is opened with [Subworlds].
+pub fn render_active_subworld(
+    mut subworlds: ResMut<Subworlds>,
+    commands: Commands,
+    meshes: ResMut<Assets<Mesh>>,
+    materials: ResMut<Assets<VoxelMaterial>>,
+    render_chunks: Query<&mut Transform, With<RenderChunkMarker>>,
+) {
+    subworlds.talk_to_bevy(commands, meshes, materials, render_chunks);
+}
+
+/// Updates every open subworld of the [Subworlds] resource with [Subworlds::update].
+pub fn update_subworlds(mut subworlds: ResMut<Subworlds>) {
+    subworlds.update(std::time::Duration::from_millis(2));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{blocks::Id, coord::Coord, entity::VoxelEntity, testing::{register_test_blocks, TestDirectory}};
+
+    use super::*;
+
+    #[test]
+    fn subworld_name_test() {
+        assert!(valid_subworld_name("main"));
+        assert!(valid_subworld_name("the_nether-2"));
+        assert!(!valid_subworld_name(""));
+        assert!(!valid_subworld_name(".."));
+        assert!(!valid_subworld_name("a/b"));
+    }
+
+    #[test]
+    fn subworlds_test() {
+        register_test_blocks();
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("subworlds_test");
+        let mut subworlds = Subworlds::new(&directory);
+        assert!(matches!(subworlds.open("../escape", 2, (0, 0, 0), SubworldOptions::new()), Err(Error::InvalidSubworldName(_))));
+        subworlds.open(MAIN_SUBWORLD, 2, (0, 0, 0), SubworldOptions::new()).expect("Failed to open main").flush_load_queue();
+        let caves = subworlds.open("caves", 2, (0, 0, 0), SubworldOptions::new().with_height(-64, 64)).expect("Failed to open caves");
+        caves.flush_load_queue();
+        assert_eq!(subworlds.active_name(), Some(MAIN_SUBWORLD));
+        assert_eq!(subworlds.list().expect("Failed to list subworlds"), vec!["caves".to_owned(), "main".to_owned()]);
+        // Subworlds have separate blocks.
+        subworlds.get_mut(MAIN_SUBWORLD).unwrap().set_block((0, 0, 0), state);
+        assert_eq!(subworlds.get("caves").unwrap().get_block((0, 0, 0)), Id::AIR);
+        // But they share the world metadata.
+        subworlds.get_mut(MAIN_SUBWORLD).unwrap().metadata_mut().set_spawn((7, 8, 9));
+        assert_eq!(subworlds.get("caves").unwrap().metadata().spawn(), Coord::new(7, 8, 9));
+        // Blocks can't be placed outside of the height of a subworld.
+        let caves = subworlds.get_mut("caves").unwrap();
+        caves.set_block((0, 64, 0), state);
+        caves.set_block((0, 63, 0), state);
+        assert_eq!(caves.get_block((0, 64, 0)), Id::AIR);
+        assert_eq!(caves.get_block((0, 63, 0)), state);
+        // Entities can be moved between subworlds.
+        let id = subworlds.get_mut(MAIN_SUBWORLD).unwrap().spawn_entity(VoxelEntity::new("box", Vec3::new(0.5, 1.0, 0.5), Vec3::splat(0.5))).expect("Failed to spawn entity");
+        let moved = subworlds.transfer_entity(id, MAIN_SUBWORLD, "caves", Vec3::new(4.5, 10.0, 4.5)).expect("Failed to transfer entity");
+        assert!(subworlds.get(MAIN_SUBWORLD).unwrap().get_entity(id).is_none());
+        assert_eq!(subworlds.get("caves").unwrap().get_entity(moved).map(|entity| entity.position), Some(Vec3::new(4.5, 10.0, 4.5)));
+        // Subworlds are saved separately.
+        subworlds.close("caves").expect("Failed to close caves");
+        subworlds.close(MAIN_SUBWORLD).expect("Failed to close main");
+        assert!(matches!(subworlds.close("caves"), Err(Error::SubworldNotOpen(_))));
+        let caves = subworlds.open("caves", 2, (0, 0, 0), SubworldOptions::new().with_height(-64, 64)).expect("Failed to reopen caves");
+        caves.flush_load_queue();
+        assert_eq!(caves.get_block((0, 63, 0)), state);
+        assert_eq!(caves.get_block((0, 0, 0)), Id::AIR);
+        // The bounds are saved, so they are kept when the subworld is opened with different options.
+        subworlds.close("caves").expect("Failed to close caves");
+        assert!(directory.path().join("subworlds").join("caves").join(SUBWORLD_DATA_FILE).is_file());
+        let caves = subworlds.open("caves", 2, (0, 0, 0), SubworldOptions::new()).expect("Failed to reopen caves");
+        caves.flush_load_queue();
+        let bounds = SubworldOptions::new().with_height(-64, 64).bounds;
+        assert_eq!((caves.subworld_bounds.min, caves.subworld_bounds.max), (bounds.min, bounds.max));
+        caves.set_block((0, 64, 0), state);
+        assert_eq!(caves.get_block((0, 64, 0)), Id::AIR);
+    }
+
+    #[test]
+    fn update_subworlds_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let scheduled = blockstate!(test_scheduled).register();
+        let directory = TestDirectory::new("update_subworlds_test");
+        let mut subworlds = Subworlds::new(&directory);
+        let caves = subworlds.open("caves", 2, (0, 0, 0), SubworldOptions::new()).expect("Failed to open caves");
+        caves.flush_load_queue();
+        caves.set_block((1, 2, 3), stone);
+        subworlds.close("caves").expect("Failed to close caves");
+        subworlds.open(MAIN_SUBWORLD, 2, (0, 0, 0), SubworldOptions::new()).expect("Failed to open main");
+        subworlds.open("caves", 2, (0, 0, 0), SubworldOptions::new()).expect("Failed to reopen caves");
+        assert_eq!(subworlds.active_name(), Some(MAIN_SUBWORLD));
+        // The subworld that isn't active loads its chunks too.
+        for _ in 0..64 {
+            let caves = subworlds.get("caves").unwrap();
+            if caves.load_queue.is_empty() && caves.worldgen_queue.is_empty() {
+                break;
+            }
+            subworlds.update(std::time::Duration::from_millis(50));
+        }
+        let caves = subworlds.get_mut("caves").unwrap();
+        assert!(caves.load_queue.is_empty());
+        assert_eq!(caves.get_block((1, 2, 3)), stone);
+        // And it runs its scheduled updates.
+        caves.set_block((1, 3, 3), scheduled);
+        assert!(caves.schedule_update((1, 3, 3), 1, 0));
+        subworlds.update(std::time::Duration::from_millis(2));
+        assert_eq!(subworlds.get("caves").unwrap().get_block((1, 3, 3)), Id::AIR);
+        assert!(subworlds.get(MAIN_SUBWORLD).unwrap().load_queue.is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+#![allow(unused)]
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+
+use hashbrown::HashMap;
+
+use crate::core::error::*;
+use crate::core::io::*;
+use crate::core::voxel::coord::Coord;
+
+use super::WORLD_BOTTOM;
+
+/// A block update that will happen on a specific tick.
+///
+/// Ticks are ordered by `tick`, then by `priority` (lower priorities run first), then by the order
+/// that they were scheduled in, so the order is the same every time the world runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScheduledTick {
+    pub tick: u64,
+    pub priority: i32,
+    pub order: u64,
+    pub coord: Coord,
+}
+
+/// Schedules block updates for future ticks.
+///
+/// A block can't be scheduled twice for the same tick. The first time that it's scheduled wins, so
+/// scheduling an update that is already pending does nothing.
+#[derive(Debug, Default)]
+pub struct TickScheduler {
+    current_tick: u64,
+    next_order: u64,
+    queue: BTreeSet<ScheduledTick>,
+    /// The pending updates of each chunk, keyed by the block that they update, so that the updates of a block
+    /// or chunk can be found without searching the whole queue.
+    chunks: HashMap<(i32, i32), HashMap<Coord, Vec<ScheduledTick>>>,
+}
+
+impl TickScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of ticks that have passed.
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    /// Sets the number of ticks that have passed. This is used to resume the game time of a saved world.
+    /// Pending updates keep the number of ticks that are left.
+    pub fn set_current_tick(&mut self, tick: u64) {
+        let old_tick = std::mem::replace(&mut self.current_tick, tick);
+        if old_tick == tick || self.queue.is_empty() {
+            return;
+        }
+        let queue = std::mem::take(&mut self.queue);
+        self.chunks.clear();
+        self.queue = queue.into_iter().map(|mut scheduled| {
+            scheduled.tick = tick + scheduled.tick.saturating_sub(old_tick);
+            self.index(scheduled);
+            scheduled
+        }).collect();
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Schedules an update for `delay` ticks from now. A delay of `0` is treated as `1` so that
+    /// blocks that schedule themselves while they are updating can't stall the tick.
+    /// Returns false if the same block was already scheduled for the same tick.
+    pub fn schedule(&mut self, coord: Coord, delay: u32, priority: i32) -> bool {
+        let tick = self.current_tick + delay.max(1) as u64;
+        let pending = self.chunks.get(&chunk_of(coord)).and_then(|blocks| blocks.get(&coord));
+        if pending.is_some_and(|ticks| ticks.iter().any(|scheduled| scheduled.tick == tick)) {
+            return false;
+        }
+        let scheduled = ScheduledTick {
+            tick,
+            priority,
+            order: self.next_order,
+            coord,
+        };
+        self.next_order += 1;
+        self.index(scheduled);
+        self.queue.insert(scheduled);
+        true
+    }
+
+    /// Determines if the block at `coord` has any pending updates.
+    pub fn is_scheduled(&self, coord: Coord) -> bool {
+        self.chunks.get(&chunk_of(coord)).is_some_and(|blocks| blocks.contains_key(&coord))
+    }
+
+    /// Removes every pending update for the block at `coord`. Returns the number of updates that were removed.
+    pub fn cancel(&mut self, coord: Coord) -> usize {
+        let chunk = chunk_of(coord);
+        let Some(blocks) = self.chunks.get_mut(&chunk) else {
+            return 0;
+        };
+        let Some(ticks) = blocks.remove(&coord) else {
+            return 0;
+        };
+        if blocks.is_empty() {
+            self.chunks.remove(&chunk);
+        }
+        ticks.iter().for_each(|scheduled| {
+            self.queue.remove(scheduled);
+        });
+        ticks.len()
+    }
+
+    /// Removes and returns the next update that is due on the current tick.
+    pub fn pop_due(&mut self) -> Option<ScheduledTick> {
+        let first = *self.queue.first()?;
+        if first.tick > self.current_tick {
+            return None;
+        }
+        self.queue.remove(&first);
+        self.unindex(first);
+        Some(first)
+    }
+
+    /// Moves to the next tick.
+    pub fn advance(&mut self) {
+        self.current_tick += 1;
+    }
+
+    /// Iterates over the pending updates of a chunk in the order that they will happen.
+    pub fn chunk_ticks(&self, chunk_x: i32, chunk_z: i32) -> impl Iterator<Item = &ScheduledTick> {
+        let mut ticks = self.chunks.get(&(chunk_x, chunk_z))
+            .map(|blocks| blocks.values().flatten().collect::<Vec<_>>())
+            .unwrap_or_default();
+        ticks.sort();
+        ticks.into_iter()
+    }
+
+    /// Removes every pending update of a chunk. This is used when a chunk is unloaded.
+    pub fn unschedule_chunk(&mut self, chunk_x: i32, chunk_z: i32) -> usize {
+        let Some(blocks) = self.chunks.remove(&(chunk_x, chunk_z)) else {
+            return 0;
+        };
+        let mut count = 0;
+        blocks.values().flatten().for_each(|scheduled| {
+            self.queue.remove(scheduled);
+            count += 1;
+        });
+        count
+    }
+
+    fn index(&mut self, scheduled: ScheduledTick) {
+        self.chunks.entry(chunk_of(scheduled.coord))
+            .or_default()
+            .entry(scheduled.coord)
+            .or_default()
+            .push(scheduled);
+    }
+
+    fn unindex(&mut self, scheduled: ScheduledTick) {
+        let chunk = chunk_of(scheduled.coord);
+        let Some(blocks) = self.chunks.get_mut(&chunk) else {
+            return;
+        };
+        let Some(ticks) = blocks.get_mut(&scheduled.coord) else {
+            return;
+        };
+        ticks.retain(|pending| *pending != scheduled);
+        if ticks.is_empty() {
+            blocks.remove(&scheduled.coord);
+            if blocks.is_empty() {
+                self.chunks.remove(&chunk);
+            }
+        }
+    }
+
+    /// Writes the pending updates of a chunk. Updates are stored with the number of ticks that are
+    /// left rather than the tick that they happen on, so they resume where they left off when the chunk is loaded.
+    pub fn write_chunk<W: Write>(&self, writer: &mut W, chunk_x: i32, chunk_z: i32) -> Result<u64> {
+        let ticks = self.chunk_ticks(chunk_x, chunk_z).collect::<Vec<_>>();
+        let mut length = (ticks.len() as u32).write_to(writer)?;
+        for scheduled in ticks {
+            length += ((scheduled.coord.x & 0xF) as u8).write_to(writer)?;
+            length += ((scheduled.coord.y - WORLD_BOTTOM) as u16).write_to(writer)?;
+            length += ((scheduled.coord.z & 0xF) as u8).write_to(writer)?;
+            length += (scheduled.tick.saturating_sub(self.current_tick) as u32).write_to(writer)?;
+            length += scheduled.priority.write_to(writer)?;
+        }
+        Ok(length)
+    }
+
+    /// Reads the pending updates of a chunk that were written with [TickScheduler::write_chunk] and schedules them.
+    /// Chunks that were saved before updates could be scheduled don't have any, so reaching the end of the data
+    /// is the same as reading no updates.
+    pub fn read_chunk<R: Read>(&mut self, reader: &mut R, chunk_x: i32, chunk_z: i32) -> Result<()> {
+        Self::decode_chunk(reader, chunk_x, chunk_z)?.into_iter().for_each(|(coord, delay, priority)| {
+            self.schedule(coord, delay, priority);
+        });
+        Ok(())
+    }
+
+    /// Reads the pending updates of a chunk like [TickScheduler::read_chunk] without scheduling them, so that chunks can
+    /// be read off the main thread. Returns the `(coord, delay, priority)` of each update.
+    pub fn decode_chunk<R: Read>(reader: &mut R, chunk_x: i32, chunk_z: i32) -> Result<Vec<(Coord, u32, i32)>> {
+        let Some(count) = read_optional::<u32, _>(reader)? else {
+            return Ok(Vec::new());
+        };
+        let mut ticks = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let x = u8::read_from(reader)? as i32;
+            let y = u16::read_from(reader)? as i32;
+            let z = u8::read_from(reader)? as i32;
+            let delay = u32::read_from(reader)?;
+            let priority = i32::read_from(reader)?;
+            let coord = Coord::new(chunk_x * 16 + x, y + WORLD_BOTTOM, chunk_z * 16 + z);
+            ticks.push((coord, delay, priority));
+        }
+        Ok(ticks)
+    }
+}
+
+/// The (x, z) coordinate of the chunk that a block is in.
+fn chunk_of(coord: Coord) -> (i32, i32) {
+    (coord.x >> 4, coord.z >> 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn tick_order_test() {
+        let mut scheduler = TickScheduler::new();
+        let a = Coord::new(0, 0, 0);
+        let b = Coord::new(1, 0, 0);
+        let c = Coord::new(2, 0, 0);
+        assert!(scheduler.schedule(a, 2, 0));
+        assert!(scheduler.schedule(b, 2, -1));
+        assert!(scheduler.schedule(c, 2, 0));
+        assert!(scheduler.schedule(c, 1, 0));
+        // The same block on the same tick is only scheduled once.
+        assert!(!scheduler.schedule(a, 2, 5));
+        assert_eq!(scheduler.len(), 4);
+        assert_eq!(scheduler.pop_due(), None);
+        scheduler.advance();
+        assert_eq!(scheduler.pop_due().map(|tick| tick.coord), Some(c));
+        assert_eq!(scheduler.pop_due(), None);
+        scheduler.advance();
+        let order = std::iter::from_fn(|| scheduler.pop_due()).map(|tick| tick.coord).collect::<Vec<_>>();
+        assert_eq!(order, vec![b, a, c]);
+        assert!(scheduler.is_empty());
+        // Once it has run, it can be scheduled again.
+        assert!(scheduler.schedule(a, 0, 0));
+        assert_eq!(scheduler.cancel(a), 1);
+        assert!(!scheduler.is_scheduled(a));
+    }
+
+    #[test]
+    fn write_read_chunk_test() -> Result<()> {
+        let mut scheduler = TickScheduler::new();
+        scheduler.advance();
+        scheduler.schedule(Coord::new(17, WORLD_BOTTOM + 5, -3), 20, 1);
+        scheduler.schedule(Coord::new(18, 64, -16), 3, 0);
+        // In a different chunk.
+        scheduler.schedule(Coord::new(0, 0, 0), 3, 0);
+        let mut buffer = Cursor::new(Vec::new());
+        scheduler.write_chunk(&mut buffer, 1, -1)?;
+        assert_eq!(scheduler.unschedule_chunk(1, -1), 2);
+        assert_eq!(scheduler.len(), 1);
+        assert!(!scheduler.is_scheduled(Coord::new(18, 64, -16)));
+        assert!(scheduler.is_scheduled(Coord::new(0, 0, 0)));
+        buffer.set_position(0);
+        let mut loaded = TickScheduler::new();
+        loaded.read_chunk(&mut buffer, 1, -1)?;
+        let ticks = loaded.chunk_ticks(1, -1).map(|tick| (tick.coord, tick.tick, tick.priority)).collect::<Vec<_>>();
+        assert_eq!(ticks, vec![
+            (Coord::new(18, 64, -16), 3, 0),
+            (Coord::new(17, WORLD_BOTTOM + 5, -3), 20, 1),
+        ]);
+        // Chunks without any tick data load without any ticks.
+        let mut empty = Cursor::new(Vec::new());
+        loaded.read_chunk(&mut empty, 0, 0)?;
+        assert_eq!(loaded.len(), 2);
+        Ok(())
+    }
+}
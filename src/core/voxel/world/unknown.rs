@@ -0,0 +1,102 @@
+#![allow(unused)]
+use hashbrown::HashMap;
+
+use crate::core::voxel::{blocks::{self, Id}, blockstate::BlockState, coord::Coord, tag::Tag};
+
+use super::section::Section;
+use super::VoxelWorld;
+
+impl VoxelWorld {
+    /// Finds every [UnknownBlock](crate::core::voxel::blocks::UnknownBlock) in the loaded chunks, grouped by state.
+    pub fn find_unknown_blocks(&self) -> HashMap<Id, Vec<Coord>> {
+        let mut found = HashMap::<Id, Vec<Coord>>::new();
+        if !blocks::has_unknown_states() {
+            return found;
+        }
+        self.chunks.bounds().iter().for_each(|chunk_coord| {
+            let Some(chunk) = self.chunks.get(chunk_coord) else {
+                return;
+            };
+            chunk.sections.iter().enumerate().for_each(|(section_index, section)| {
+                let Some(blocks) = &section.blocks else {
+                    return;
+                };
+                let section_offset = chunk.block_offset + Coord::new(0, section_index as i32 * 16, 0);
+                blocks.iter().enumerate()
+                    .filter(|(_, state)| state.is_unknown())
+                    .for_each(|(index, &state)| {
+                        found.entry(state).or_default().push(section_offset + Section::coord(index as u16));
+                    });
+            });
+        });
+        found
+    }
+
+    /// Counts the [UnknownBlock](crate::core::voxel::blocks::UnknownBlock)s of each unknown state in the loaded chunks.
+    pub fn count_unknown_blocks(&self) -> HashMap<Id, usize> {
+        self.find_unknown_blocks().into_iter().map(|(state, coords)| (state, coords.len())).collect()
+    }
+
+    /// Replaces the unknown blocks in the loaded chunks. `replace` is called once for each unknown state, and
+    /// the blocks of that state are replaced with the returned state (or left alone if it returns `None`).
+    /// The data of the replaced blocks is kept. Returns the number of blocks that were replaced.
+    pub fn replace_unknown_blocks<F: FnMut(&BlockState) -> Option<Id>>(&mut self, mut replace: F) -> usize {
+        let replacements = self.find_unknown_blocks().into_iter().filter_map(|(state, coords)| {
+            let blockstate: &BlockState = &*state;
+            replace(blockstate).map(|new_state| (new_state, coords))
+        }).collect::<Vec<_>>();
+        let data = replacements.iter()
+            .flat_map(|(_, coords)| coords.iter().copied())
+            .filter(|&coord| self.get_data(coord).is_some())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|coord| (coord, self.take_data(coord)))
+            .collect::<Vec<(Coord, Tag)>>();
+        let count = self.set_blocks(replacements.into_iter().flat_map(|(new_state, coords)| {
+            coords.into_iter().map(move |coord| (coord, new_state))
+        }));
+        data.into_iter().for_each(|(coord, tag)| self.set_data(coord, tag));
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::testing::{open_world, register_test_blocks, TestDirectory};
+
+    use super::*;
+
+    #[test]
+    fn unknown_block_test() -> crate::core::error::Result<()> {
+        register_test_blocks();
+        let replacement = blockstate!(test_stone).register();
+        // The block was never registered, so this is what loading a world with a removed block looks like.
+        let removed = blockstate!(unknown_test_removed, facing = 3i64);
+        let unknown = removed.register_or_unknown();
+        assert!(unknown.is_unknown());
+        assert!(!replacement.is_unknown());
+        assert_eq!(&*unknown, &removed);
+        assert!(blocks::unknown_states().contains(&unknown));
+        let directory = TestDirectory::new("unknown_block_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        world.set_block((1, 2, 3), unknown);
+        world.set_block((4, 5, 6), unknown);
+        world.set_data((4, 5, 6), "kept");
+        world.save_world()?;
+        drop(world);
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // The state is saved unchanged.
+        assert_eq!(world.get_block((1, 2, 3)), unknown);
+        assert_eq!(world.unknown_blocks_loaded, 2);
+        assert_eq!(world.count_unknown_blocks().get(&unknown), Some(&2));
+        let replaced = world.replace_unknown_blocks(|state| {
+            (state.name() == "unknown_test_removed").then_some(replacement)
+        });
+        assert_eq!(replaced, 2);
+        assert_eq!(world.get_block((1, 2, 3)), replacement);
+        assert_eq!(world.get_data((4, 5, 6)), Some(&Tag::from("kept")));
+        assert!(world.count_unknown_blocks().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+#![allow(unused)]
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use hashbrown::HashMap;
+use rollgrid::rollgrid2d::Bounds2D;
+
+use crate::core::collections::objectpool::PoolId;
+use crate::core::voxel::coord::Coord;
+use crate::core::voxel::level_of_detail::LOD;
+use crate::core::voxel::lighting::engine::compute_detached_sky_light;
+use crate::core::voxel::rendering::sectionmesh::{mesh_section, SectionMesh, SectionSnapshot};
+use crate::core::voxel::procgen::worldgenerator::ChunkGenerator;
+use crate::core::voxel::region::regionfile::RegionFile;
+use crate::core::voxel::region::timestamp::Timestamp;
+use crate::core::error::*;
+
+use super::chunk::{Chunk, DecodedChunk};
+use super::migration::MigrationRegistry;
+use super::WORLD_BOTTOM;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkJobMarker;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshJobMarker;
+
+/// Work that is sent to the [ChunkWorkers].
+///
+/// Loads and saves are handled by a single IO thread in the order that they were sent,
+/// so a save that is sent before a load of the same chunk will always be written first.
+pub enum ChunkJob {
+    /// Read, decompress, and decode a chunk from a region file.
+    Load {
+        id: PoolId<ChunkJobMarker>,
+        coord: (i32, i32),
+        region_path: PathBuf,
+        /// The migrations of the world, which are applied to chunks that were saved with an older data version.
+        migrations: MigrationRegistry,
+    },
+    /// Run a [ChunkGenerator] on a detached [Chunk].
+    Generate {
+        id: PoolId<ChunkJobMarker>,
+        coord: (i32, i32),
+        generator: Arc<dyn ChunkGenerator>,
+    },
+    /// Mesh a section from a snapshot that was taken on the main thread.
+    Mesh {
+        id: PoolId<MeshJobMarker>,
+        snapshot: Box<SectionSnapshot>,
+        level_of_detail: LOD,
+    },
+    /// Write chunk data that was serialized on the main thread.
+    Save {
+        coord: (i32, i32),
+        region_path: PathBuf,
+        timestamp: Timestamp,
+        /// The data version that the chunk was serialized with.
+        version: u16,
+        data: Vec<u8>,
+    },
+}
+
+/// The result of a [ChunkJob].
+/// Results carry the [PoolId] of the job so that stale results can be discarded.
+pub enum ChunkJobResult {
+    /// The decoded chunk. The main thread only needs to move it into the world.
+    Loaded {
+        id: PoolId<ChunkJobMarker>,
+        coord: (i32, i32),
+        timestamp: Timestamp,
+        /// The data version that the chunk was saved with.
+        version: u16,
+        chunk: Box<DecodedChunk>,
+    },
+    /// The chunk wasn't found on disk, so it needs to be generated.
+    NotFound {
+        id: PoolId<ChunkJobMarker>,
+        coord: (i32, i32),
+    },
+    /// The generated chunk, with its sky light calculated by [compute_detached_sky_light].
+    Generated {
+        id: PoolId<ChunkJobMarker>,
+        coord: (i32, i32),
+        chunk: Chunk,
+    },
+    Meshed {
+        id: PoolId<MeshJobMarker>,
+        section_coord: Coord,
+        mesh: SectionMesh,
+    },
+    LoadFailed {
+        id: PoolId<ChunkJobMarker>,
+        coord: (i32, i32),
+        error: Error,
+    },
+    SaveFailed {
+        coord: (i32, i32),
+        error: Error,
+    },
+}
+
+/// A pool of background threads for chunk loading, generation, and saving.
+///
+/// There is one IO thread that owns every [RegionFile] that it opens, and any number of generation threads.
+/// The generation threads also mesh sections.
+/// Dropping the workers waits for all queued jobs (including saves) to finish.
+pub struct ChunkWorkers {
+    io_sender: Option<Sender<ChunkJob>>,
+    gen_sender: Option<Sender<ChunkJob>>,
+    // The Receiver is behind a Mutex so that the workers can live in a Resource, which must be Sync.
+    results: Mutex<Receiver<ChunkJobResult>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ChunkWorkers {
+    /// Spawns the IO thread and `generator_threads` generation threads (at least 1).
+    pub fn new(generator_threads: usize) -> Self {
+        let (io_sender, io_receiver) = channel::<ChunkJob>();
+        let (gen_sender, gen_receiver) = channel::<ChunkJob>();
+        let (result_sender, results) = channel::<ChunkJobResult>();
+        let gen_receiver = Arc::new(Mutex::new(gen_receiver));
+        let mut threads = Vec::with_capacity(generator_threads.max(1) + 1);
+        let io_results = result_sender.clone();
+        threads.push(std::thread::Builder::new()
+            .name("chunk-io".to_owned())
+            .spawn(move || io_worker(io_receiver, io_results))
+            .expect("Failed to spawn chunk IO thread."));
+        for i in 0..generator_threads.max(1) {
+            let receiver = gen_receiver.clone();
+            let results = result_sender.clone();
+            threads.push(std::thread::Builder::new()
+                .name(format!("chunk-gen-{i}"))
+                .spawn(move || gen_worker(receiver, results))
+                .expect("Failed to spawn chunk generation thread."));
+        }
+        Self {
+            io_sender: Some(io_sender),
+            gen_sender: Some(gen_sender),
+            results: Mutex::new(results),
+            threads,
+        }
+    }
+
+    /// Sends a job to the thread that handles it.
+    pub fn send(&self, job: ChunkJob) {
+        let sender = match &job {
+            ChunkJob::Generate { .. } | ChunkJob::Mesh { .. } => &self.gen_sender,
+            ChunkJob::Load { .. } | ChunkJob::Save { .. } => &self.io_sender,
+        };
+        sender.as_ref()
+            .expect("Workers were shut down")
+            .send(job)
+            .expect("Chunk worker thread has stopped");
+    }
+
+    /// Returns the next finished result without blocking.
+    pub fn try_recv(&self) -> Option<ChunkJobResult> {
+        match self.results.lock().expect("Chunk result receiver was poisoned").try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("Chunk worker threads have stopped"),
+        }
+    }
+
+    /// Blocks until the next result is finished.
+    pub fn recv(&self) -> ChunkJobResult {
+        self.results.lock().expect("Chunk result receiver was poisoned").recv().expect("Chunk worker threads have stopped")
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        // Closing the channels lets the threads finish their queues and exit.
+        self.io_sender.take();
+        self.gen_sender.take();
+        self.threads.drain(..).for_each(|thread| {
+            thread.join();
+        });
+    }
+}
+
+fn io_worker(jobs: Receiver<ChunkJob>, results: Sender<ChunkJobResult>) {
+    let mut regions = HashMap::<PathBuf, RegionFile>::new();
+    while let Ok(job) = jobs.recv() {
+        let result = match job {
+            ChunkJob::Load { id, coord, region_path, migrations } => {
+                match load_chunk_data(&mut regions, &region_path, coord, &migrations) {
+                    Ok(Some((timestamp, version, chunk))) => ChunkJobResult::Loaded { id, coord, timestamp, version, chunk: Box::new(chunk) },
+                    Ok(None) => ChunkJobResult::NotFound { id, coord },
+                    Err(error) => ChunkJobResult::LoadFailed { id, coord, error },
+                }
+            }
+            ChunkJob::Save { coord, region_path, timestamp, version, data } => {
+                match save_chunk_data(&mut regions, region_path, coord, timestamp, version, &data) {
+                    Ok(()) => continue,
+                    Err(error) => ChunkJobResult::SaveFailed { coord, error },
+                }
+            }
+            ChunkJob::Generate { .. } | ChunkJob::Mesh { .. } => unreachable!("Generate and Mesh jobs are not sent to the IO thread."),
+        };
+        if results.send(result).is_err() {
+            // The world was dropped, but we still want to finish any saves.
+            continue;
+        }
+    }
+}
+
+fn load_chunk_data(regions: &mut HashMap<PathBuf, RegionFile>, region_path: &PathBuf, (chunk_x, chunk_z): (i32, i32), migrations: &MigrationRegistry) -> Result<Option<(Timestamp, u16, DecodedChunk)>> {
+    if !regions.contains_key(region_path) {
+        if !region_path.is_file() {
+            return Ok(None);
+        }
+        regions.insert(region_path.clone(), RegionFile::open(region_path)?);
+    }
+    let region = regions.get_mut(region_path).expect("Region was just inserted");
+    let coord = (chunk_x & 31, chunk_z & 31);
+    let result = region.read_versioned(coord, |reader, version| {
+        let chunk = Chunk::decode(reader, chunk_x, chunk_z, &migrations.migrations_since(version))?;
+        Ok((version, chunk))
+    });
+    match result {
+        Ok((version, chunk)) => Ok(Some((region.get_timestamp(coord), version, chunk))),
+        Err(Error::ChunkNotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn save_chunk_data(regions: &mut HashMap<PathBuf, RegionFile>, region_path: PathBuf, (chunk_x, chunk_z): (i32, i32), timestamp: Timestamp, version: u16, data: &[u8]) -> Result<()> {
+    if !regions.contains_key(&region_path) {
+        let region = RegionFile::open_or_create(&region_path)?;
+        regions.insert(region_path.clone(), region);
+    }
+    let region = regions.get_mut(&region_path).expect("Region was just inserted");
+    region.write_timestamped_versioned((chunk_x & 31, chunk_z & 31), timestamp, version, |writer| {
+        writer.write_all(data)?;
+        Ok(())
+    })
+}
+
+fn gen_worker(jobs: Arc<Mutex<Receiver<ChunkJob>>>, results: Sender<ChunkJobResult>) {
+    loop {
+        // The lock is held while waiting for the next job, so only one idle thread waits on the channel at a time.
+        // It's released before the job runs so that the other threads can take jobs in the meantime.
+        let job = {
+            let Ok(receiver) = jobs.lock() else {
+                return;
+            };
+            receiver.recv()
+        };
+        let Ok(job) = job else {
+            return;
+        };
+        let result = match job {
+            ChunkJob::Generate { id, coord: (chunk_x, chunk_z), generator } => {
+                let mut chunk = Chunk::new(Coord::new(chunk_x * 16, WORLD_BOTTOM, chunk_z * 16));
+                let area = Bounds2D {
+                    min: (chunk_x * 16, chunk_z * 16),
+                    max: (chunk_x * 16 + 16, chunk_z * 16 + 16),
+                };
+                generator.generate_chunk(&mut chunk, area);
+                compute_detached_sky_light(&mut chunk);
+                ChunkJobResult::Generated { id, coord: (chunk_x, chunk_z), chunk }
+            }
+            ChunkJob::Mesh { id, snapshot, level_of_detail } => {
+                let mesh = mesh_section(&snapshot, level_of_detail);
+                ChunkJobResult::Meshed { id, section_coord: snapshot.section_coord, mesh }
+            }
+            ChunkJob::Load { .. } | ChunkJob::Save { .. } => unreachable!("Load and Save jobs are only sent to the IO thread."),
+        };
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
@@ -4,33 +4,46 @@ use bevy::prelude::*;
 use bevy::render::mesh::PrimitiveTopology;
 use bevy::render::render_asset::RenderAssetUsages;
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use tap::{Tap, TapFallible};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::{collections::VecDeque, iter::Sum};
 
 use bevy::{asset::Handle, render::mesh::Mesh};
 use hashbrown::HashMap;
 use super::chunkcoord::ChunkCoord;
-use super::externevent::ExternEvent;
+use super::externevent::{EventFilter, ExternEvent};
+use super::history::EditHistory;
+use super::metadata::{SharedMetadata, WorldMetadata};
+use super::migration::{self, MigrationRegistry};
+use super::subworld::{read_subworld_bounds, valid_subworld_name, write_subworld_bounds, SubworldOptions, MAIN_SUBWORLD};
 use super::occlusion::Occlusion;
 use super::query::{BlockLight, VoxelQuery, SkyLight};
 use rollgrid::{rollgrid2d::*, rollgrid3d::*};
 use super::section::{LightChange, Section, StateChange};
+use super::tick::TickScheduler;
 use super::update::{BlockUpdateQueue, UpdateRef};
+use super::worker::{ChunkJob, ChunkJobMarker, ChunkJobResult, ChunkWorkers, MeshJobMarker};
 
 use crate::core::collections::objectpool::{ObjectPool, PoolId};
 use crate::core::math::aabb::AABB;
 use crate::core::math::grid::{calculate_region_min, calculate_region_requirement};
 use crate::core::util::lend::Lend;
-use crate::core::voxel::level_of_detail::LOD;
-use crate::core::voxel::procgen::worldgenerator::WorldGenerator;
+use crate::core::voxel::entity::{physics, EntityId, EntityMarker, VoxelEntity};
+use crate::core::voxel::level_of_detail::{DEFAULT_LOD_DISTANCES, LOD};
+use crate::core::voxel::lighting::engine::{compute_chunk_block_light, compute_chunk_sky_light, compute_detached_sky_light, merge_chunk_sky_light, update_block_light, update_light, update_sky_light, LightChannel};
+use crate::core::voxel::procgen::worldgenerator::{ChunkGenerator, WorldGenerator};
 use crate::core::voxel::region::regionfile::RegionFile;
 use crate::core::voxel::region::timestamp::Timestamp;
 use crate::core::voxel::rendering::meshbuilder::MeshBuilder;
-use crate::core::{math::grid::calculate_center_offset, voxel::{blocks::Id, coord::Coord, direction::Direction, engine::VoxelEngine, faces::Faces, rendering::voxelmaterial::VoxelMaterial}};
-use crate::prelude::{f32_not_zero, ResultExtension, SwapVal};
+use crate::core::voxel::rendering::lightmap::{assemble_lightmap, gather_pads, SectionLightmap, LIGHTMAP_SIZE};
+use crate::core::voxel::rendering::sectionmesh::{mesh_section, SectionMesh, SectionSnapshot};
+use crate::core::voxel::rendering::voxelmesh::MeshData;
+use crate::core::{math::grid::calculate_center_offset, voxel::{blocklayer::BlockLayer, blocks::Id, coord::Coord, direction::Direction, engine::VoxelEngine, faces::Faces, rendering::voxelmaterial::VoxelMaterial}};
+use crate::prelude::{f32_not_zero, Orientation, ResultExtension, SwapVal};
 
-use super::chunk::Chunk;
+use super::chunk::{Chunk, SkyLightState};
 
 use crate::core::voxel::tag::Tag;
 use crate::core::error::*;
@@ -49,6 +62,12 @@ pub const WORLD_SIZE_MAX: usize = 64;
 /// light updates can span multple chunks).
 pub const WORLD_SIZE_PAD: usize = 2;
 pub const PADDED_WORLD_SIZE_MAX: usize = WORLD_SIZE_MAX + WORLD_SIZE_PAD;
+/// How far (in blocks) the camera needs to move before every transparent layer is sorted again.
+pub const TRANSPARENT_SORT_DISTANCE: f32 = 1.0;
+/// The default number of blocks that are picked for a random tick in each section every tick.
+pub const DEFAULT_RANDOM_TICK_RATE: u32 = 3;
+/// The default distance (in chunks) from the center of the world that random ticks happen in.
+pub const DEFAULT_SIMULATION_DISTANCE: i32 = 8;
 
 macro_rules! cast_coord {
     ($name:ident) => {
@@ -57,16 +76,14 @@ macro_rules! cast_coord {
     };
 }
 
-/* todo
-World Edit
-*/
-
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DirtyIdMarker;
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SaveIdMarker;
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MoveRenderChunkMarker;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LodUpdateMarker;
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct WorldGenMarker;
@@ -79,14 +96,17 @@ pub struct RenderChunkMarker;
 
 #[derive(Resource)]
 pub struct VoxelWorld {
-    pub array_texture: Handle<Image>,
+    /// The bridge to Bevy's render world. This is `None` for headless worlds.
+    pub render: Option<RenderLayer>,
     /// Determines if the render world has been initialized.
     // pub initialized: bool,
     // pub dirty_sections: Vec<Coord>,
     pub dirty_queue: Lend<ObjectPool<Coord, DirtyIdMarker>>,
     pub save_queue: ObjectPool<ChunkCoord, SaveIdMarker>,
     pub chunks: Lend<RollGrid2D<Chunk>>,
-    pub render_chunks: Lend<RollGrid3D<RenderChunk>>,
+    /// The section bounds of the area that is meshed when a [RenderLayer] is attached.
+    /// This is tracked even in headless worlds so that the render layer can be attached later.
+    pub render_area: Bounds3D,
     // I gotta figure out grid positioning for the loaded region files.
     // I can give it a buffer of 1 region so that there's room for the
     // world to move around without overflow.
@@ -96,6 +116,18 @@ pub struct VoxelWorld {
     pub regions: Lend<RollGrid2D<RegionFile>>,
     pub update_queue: BlockUpdateQueue,
     pub lock_update_queue: bool,
+    /// Updates that happen on a future tick. These are saved with the chunk that they're in.
+    pub scheduled_updates: TickScheduler,
+    /// The number of blocks that are picked for a random tick in each section of the simulation area every tick.
+    pub random_tick_rate: u32,
+    /// The distance (in chunks) from the chunk at the center of the world that random ticks happen in.
+    /// This is separate from the render distance, but only loaded chunks are ticked.
+    pub simulation_distance: i32,
+    /// Picks the blocks for random ticks. It starts out seeded from the world's seed.
+    /// Use [VoxelWorld::set_random_tick_seed] to make random ticks repeatable.
+    pub random_tick_rng: StdRng,
+    /// Every entity in the loaded chunks. Each chunk keeps track of the entities that are stored in it.
+    pub entities: ObjectPool<VoxelEntity, EntityMarker>,
     /// (Coord, new)
     pub update_modification_queue: Vec<(Coord, bool)>,
     /// The value is the index in the update_modification_queue where
@@ -103,11 +135,48 @@ pub struct VoxelWorld {
     pub update_modification_map: HashMap<Coord, u32>,
     pub world_directory: PathBuf,
     pub subworld_directory: PathBuf,
-    pub move_render_chunk_queue: Lend<ObjectPool<Coord, MoveRenderChunkMarker>>,
+    /// The name of the subworld (or dimension) that this world is.
+    pub subworld_name: String,
+    /// Blocks can only be set inside of these bounds. This is [VoxelWorld::WORLD_BOUNDS] unless
+    /// the subworld was created with smaller bounds. The bounds are saved in the subworld directory.
+    pub subworld_bounds: Bounds3D,
+    /// The seed, spawn point, generator, and game time of the world. This is read when the world is opened
+    /// and written by [VoxelWorld::save_world]. The subworlds that are opened by [Subworlds](super::subworld::Subworlds)
+    /// share the same metadata. Use [VoxelWorld::metadata] and [VoxelWorld::metadata_mut] to access it.
+    pub(crate) metadata: SharedMetadata,
+    /// The migrations that are applied to chunks saved with an older data version. This is a copy of the
+    /// migrations that were registered when the world was opened.
+    pub(crate) migrations: MigrationRegistry,
     pub render_distance: i32,
+    /// The block coordinate that the world is centered on.
+    pub center: Coord,
+    /// The distances (in sections) from the center at which sections are meshed at [LOD::Level1],
+    /// [LOD::Level2], and [LOD::Level3].
+    pub lod_distances: [i32; 3],
     pub worldgen_queue: Lend<ObjectPool<(i32, i32), WorldGenMarker>>,
     pub load_queue: Lend<ObjectPool<(i32, i32), LoadChunkMarker>>,
+    /// A generator that writes directly into the world. It always runs on the main thread (see [VoxelWorld::generate_next_chunk]),
+    /// so use a `chunk_generator` to generate chunks on the [ChunkWorkers].
     pub world_generator: Option<Box<dyn WorldGenerator>>,
+    /// When this is true, [VoxelWorld::set_block] does not update light.
+    /// This is used during world generation so that light can be calculated once for the whole chunk.
+    pub defer_lighting: bool,
+    /// Background threads for loading, generating, and saving chunks. When this is `None`,
+    /// all of that work happens on the main thread.
+    pub workers: Option<ChunkWorkers>,
+    /// A generator that can run on the [ChunkWorkers]. It takes priority over the `world_generator`.
+    pub chunk_generator: Option<Arc<dyn ChunkGenerator>>,
+    /// The jobs that have been sent to the [ChunkWorkers] and haven't been integrated yet.
+    /// A chunk that moves or unloads removes its job from this pool so that the result is discarded.
+    pub pending_jobs: ObjectPool<(i32, i32), ChunkJobMarker>,
+    /// Records changes so that they can be undone. This is `None` until [VoxelWorld::enable_history] is called.
+    pub history: Option<EditHistory>,
+    /// Events that have happened since the last time they were drained with [VoxelWorld::drain_events].
+    pub events: Vec<ExternEvent>,
+    /// The events that are recorded in `events`. Nothing is recorded by default.
+    pub event_filter: EventFilter,
+    /// The number of [UnknownBlock](crate::core::voxel::blocks::UnknownBlock)s that have been loaded from disk.
+    pub unknown_blocks_loaded: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -137,6 +206,9 @@ impl VoxelWorld {
     };
     /// Open or create a world centered at the specified block coordinate with the (chunk) render distance specified.
     /// The resulting width in chunks will be `render_distance * 2`.
+    /// 
+    /// This is the same as calling [VoxelWorld::open_headless] followed by [VoxelWorld::attach_render_layer].
+    #[must_use]
     pub fn open<P: AsRef<Path>, C: Into<(i32, i32, i32)>>(
             directory: P,
             render_distance: u8,
@@ -146,113 +218,626 @@ impl VoxelWorld {
             meshes: &mut ResMut<Assets<Mesh>>,
             materials: &mut ResMut<Assets<VoxelMaterial>>,
             generator: Option<Box<dyn WorldGenerator>>,
-        ) -> Self {
-            
+        ) -> Result<Self> {
+        Ok(Self::open_headless(directory, render_distance, center, generator)?.tap_mut(|world| {
+            world.attach_render_layer(array_texture);
+        }))
+    }
+
+    /// Open or create a world without any render state.
+    /// Chunk loading, block updates, saving, and raycasting all work the same as they do
+    /// in a rendered world, but nothing is meshed until a [RenderLayer] is attached with
+    /// [VoxelWorld::attach_render_layer].
+    /// 
+    /// Chunks are queued for loading, but not loaded. Use [VoxelWorld::flush_load_queue] to
+    /// load them all at once, or [VoxelWorld::process_load_queue] to load them over time.
+    /// 
+    /// This opens the [MAIN_SUBWORLD]. Use [VoxelWorld::open_subworld_headless] to open other subworlds.
+    /// 
+    /// Returns an error if the [WorldMetadata] can't be read, such as when it's corrupt or was written by a newer version.
+    #[must_use]
+    pub fn open_headless<P: AsRef<Path>, C: Into<(i32, i32, i32)>>(
+            directory: P,
+            render_distance: u8,
+            center: C,
+            generator: Option<Box<dyn WorldGenerator>>,
+        ) -> Result<Self> {
+        Self::open_subworld_headless(directory, MAIN_SUBWORLD, render_distance, center, SubworldOptions::new().with_generator(generator))
+    }
+
+    /// Open or create a named subworld (or dimension) of the world in `directory` without any render state.
+    /// Each subworld has its own region files in `subworlds/<name>`. Several subworlds of the same world can be
+    /// open at the same time (see [Subworlds](super::subworld::Subworlds)).
+    /// 
+    /// Panics if `name` is not a valid subworld name (see [valid_subworld_name]).
+    /// Returns an error if the world directory can't be created or the [WorldMetadata] can't be read.
+    #[must_use]
+    pub fn open_subworld_headless<P: AsRef<Path>, C: Into<(i32, i32, i32)>>(
+            directory: P,
+            name: &str,
+            render_distance: u8,
+            center: C,
+            options: SubworldOptions,
+        ) -> Result<Self> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)?;
+        let metadata = WorldMetadata::load_or_create(directory, rand::random())?;
+        Self::open_subworld_with_metadata(directory, name, render_distance, center, options, SharedMetadata::new(metadata))
+    }
+
+    /// Opens a subworld that shares its [WorldMetadata] with the other subworlds of the world.
+    pub(crate) fn open_subworld_with_metadata<P: AsRef<Path>, C: Into<(i32, i32, i32)>>(
+            directory: P,
+            name: &str,
+            render_distance: u8,
+            center: C,
+            options: SubworldOptions,
+            metadata: SharedMetadata,
+        ) -> Result<Self> {
         let center: (i32, i32, i32) = center.into();
         let center: Coord = center.into();
-        let mut center = center;
         if render_distance as usize + WORLD_SIZE_PAD > PADDED_WORLD_SIZE_MAX {
             panic!("Size greater than {PADDED_WORLD_SIZE_MAX} (PADDED_WORLD_SIZE_MAX)");
         }
+        if !valid_subworld_name(name) {
+            panic!("Invalid subworld name: {name:?}");
+        }
         let pad_distance = (render_distance as usize + WORLD_SIZE_PAD);
         let pad_size = pad_distance * 2;
-        let render_size = render_distance as usize * 2;
-        let render_height = render_size.min(WORLD_HEIGHT >> 4);
         let (chunk_x, chunk_z) = calculate_center_offset(pad_distance as i32, center, Some(Self::WORLD_BOUNDS)).chunk_coord().xz();
         let region_size = calculate_region_requirement(pad_size as i32);
         let region_min = calculate_region_min((chunk_x, chunk_z));
-        let (render_x, render_y, render_z) = calculate_center_offset(render_distance as i32, center, Some(Self::WORLD_BOUNDS)).section_coord().xyz();
         let directory = directory.as_ref();
-        std::fs::create_dir_all(directory);
-        let subworlds = directory.join("subworlds");
-        std::fs::create_dir(&subworlds);
-        let main_world = subworlds.join("main");
-        std::fs::create_dir(&main_world);
+        std::fs::create_dir_all(directory)?;
+        let subworld_directory = directory.join("subworlds").join(name);
+        std::fs::create_dir_all(&subworld_directory)?;
+        let subworld_bounds = match read_subworld_bounds(&subworld_directory)? {
+            Some(bounds) => bounds,
+            None => {
+                write_subworld_bounds(&subworld_directory, options.bounds)?;
+                options.bounds
+            }
+        };
+        let mut scheduled_updates = TickScheduler::new();
+        scheduled_updates.set_current_tick(metadata.read().game_tick());
+        let random_tick_rng = StdRng::seed_from_u64(metadata.read().seed());
         let mut load_queue = Lend::new(ObjectPool::new());
-        Self {
-            array_texture: array_texture.clone(),
-            subworld_directory: main_world,
+        Ok(Self {
+            render: None,
+            subworld_directory,
+            subworld_name: name.to_owned(),
+            subworld_bounds,
+            metadata,
+            migrations: migration::registered_migrations(),
             dirty_queue: Lend::new(ObjectPool::new()),
             save_queue: ObjectPool::new(),
             render_distance: render_distance as i32,
+            center,
+            lod_distances: DEFAULT_LOD_DISTANCES,
             world_directory: directory.to_owned(),
             chunks: Lend::new(RollGrid2D::new_with_init(pad_size, pad_size, (chunk_x, chunk_z), |(x, z): (i32, i32)| {
                 Some(Chunk::new(Coord::new(x * 16, WORLD_BOTTOM, z * 16)).tap_mut(|chunk| {
                     chunk.load_id = load_queue.insert((x, z));
                 }))
             })),
-            render_chunks: Lend::new(RollGrid3D::new(render_size, render_height, render_size, (render_x, render_y, render_z))),
+            render_area: Self::calculate_render_area(render_distance as i32, center),
             regions: Lend::new(RollGrid2D::new(region_size as usize, region_size as usize, region_min)),
             update_queue: BlockUpdateQueue::default(),
             lock_update_queue: false,
+            scheduled_updates,
+            random_tick_rate: DEFAULT_RANDOM_TICK_RATE,
+            simulation_distance: DEFAULT_SIMULATION_DISTANCE,
+            random_tick_rng,
+            entities: ObjectPool::new(),
             update_modification_queue: Vec::new(),
             update_modification_map: HashMap::new(),
-            move_render_chunk_queue: Lend::new(ObjectPool::new()),
             worldgen_queue: Lend::new(ObjectPool::new()),
             load_queue,
-            world_generator: generator,
+            world_generator: options.generator,
+            defer_lighting: false,
+            workers: None,
+            chunk_generator: None,
+            pending_jobs: ObjectPool::new(),
+            history: None,
+            events: Vec::new(),
+            event_filter: EventFilter::NONE,
+            unknown_blocks_loaded: 0,
+        })
+    }
+
+    /// Calculates the section bounds of the render area for a given render distance and center.
+    fn calculate_render_area(render_distance: i32, center: Coord) -> Bounds3D {
+        let render_size = render_distance * 2;
+        let render_height = render_size.min((WORLD_HEIGHT >> 4) as i32);
+        let (render_x, render_y, render_z) = calculate_center_offset(render_distance, center, Some(Self::WORLD_BOUNDS)).section_coord().xyz();
+        Bounds3D::new(
+            (render_x, render_y, render_z),
+            (render_x + render_size, render_y + render_height, render_z + render_size)
+        )
+    }
+
+    /// Returns true if there is no [RenderLayer] attached to this world.
+    pub fn is_headless(&self) -> bool {
+        self.render.is_none()
+    }
+
+    /// Attach a [RenderLayer] to the world so that sections in the render area are meshed by [VoxelWorld::talk_to_bevy].
+    /// Every non-empty section in the render area is marked dirty.
+    /// Panics if a [RenderLayer] is already attached.
+    pub fn attach_render_layer(&mut self, array_texture: Handle<Image>) {
+        if self.render.is_some() {
+            panic!("Render layer already attached.");
         }
-        // .initial_load()
+        let (width, height, depth) = (
+            self.render_area.width() as usize,
+            self.render_area.height() as usize,
+            self.render_area.depth() as usize
+        );
+        self.render = Some(RenderLayer {
+            array_texture,
+            render_chunks: RollGrid3D::new(width, height, depth, self.render_area.min),
+            move_render_chunk_queue: ObjectPool::new(),
+            pending_meshes: ObjectPool::new(),
+            finished_meshes: Vec::new(),
+            lod_queue: ObjectPool::new(),
+            unsorted: Vec::new(),
+            last_sort_position: None,
+        });
+        let render_area = self.render_area;
+        render_area.iter().for_each(|section_coord| {
+            let section_coord: Coord = section_coord.into();
+            let Some(sect) = self.get_section_mut(section_coord) else {
+                return;
+            };
+            if sect.blocks.is_none() {
+                return;
+            }
+            sect.light_dirty.mark();
+            sect.blocks_dirty.mark();
+            sect.section_dirty.mark();
+            self.mark_section_dirty(section_coord);
+        });
     }
-    
-    fn initial_load(mut self) -> Self {
-        self.chunks.bounds().iter().try_for_each(|(chunk_x, chunk_z)| {
-            let (region_x, region_z) = (chunk_x >> 5, chunk_z >> 5);
-            let mut chunk = self.chunks.take((chunk_x, chunk_z)).expect("Chunk was None");
-            chunk.unload(&mut self);
-            let mut region = if let Some(region) = self.regions.take((region_x, region_z)) {
-                region
-            } else {
-                let region_path = self.get_region_path(region_x, region_z);
-                if region_path.is_file() {
-                    RegionFile::open_or_create(region_path)?
-                } else {
-                    chunk.edit_time = Timestamp::new(0);
-                    self.chunks.set((chunk_x, chunk_z), chunk);
-                    return Result::Ok(());
+
+    /// Detach the [RenderLayer] from the world, despawning all of its entities.
+    /// The world will continue to work as a headless world.
+    pub fn detach_render_layer(&mut self, commands: &mut Commands) -> Option<RenderLayer> {
+        let mut render = self.render.take()?;
+        let mut dirty = self.dirty_queue.lend("clearing the dirty_queue in detach_render_layer");
+        dirty.drain().for_each(|coord| {
+            if let Some(sect) = self.get_section_mut(coord) {
+                sect.dirty_id = PoolId::NULL;
+            }
+        });
+        self.dirty_queue.give(dirty);
+        render.move_render_chunk_queue.clear();
+        render.pending_meshes.clear();
+        render.finished_meshes.clear();
+        render.lod_queue.clear();
+        render.unsorted.clear();
+        self.render_area.iter().for_each(|section_coord| {
+            if let Some(render_chunk) = render.render_chunks.take(section_coord) {
+                commands.entity(render_chunk.entity).despawn_recursive();
+            }
+        });
+        Some(render)
+    }
+
+    /// Loads every chunk in the load queue.
+    /// This is useful for headless worlds where there is no frame budget to worry about.
+    pub fn flush_load_queue(&mut self) {
+        if self.workers.is_some() {
+            self.dispatch_load_jobs();
+            self.finish_chunk_jobs();
+            return;
+        }
+        while self.load_next_chunk() {}
+    }
+
+    /// Loads chunks from the load queue until the queue is empty or the time budget has been used.
+    /// Returns the number of chunks that were loaded.
+    /// 
+    /// When [ChunkWorkers] are enabled, the load queue is sent to the workers instead, and finished
+    /// jobs (loaded or generated) are integrated until the time budget has been used.
+    pub fn process_load_queue(&mut self, budget: std::time::Duration) -> usize {
+        if self.workers.is_some() {
+            self.dispatch_load_jobs();
+            return self.process_job_results(budget);
+        }
+        let start_time = std::time::Instant::now();
+        let mut count = 0;
+        while start_time.elapsed() < budget && self.load_next_chunk() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Loads the next chunk in the load queue. Chunks that can't be found on disk are added to the worldgen queue.
+    /// Returns false if the load queue was empty.
+    pub fn load_next_chunk(&mut self) -> bool {
+        // Region files belong to the IO worker while the workers are enabled.
+        if self.workers.is_some() {
+            let dispatched = !self.load_queue.is_empty();
+            self.dispatch_load_jobs();
+            return dispatched;
+        }
+        let Some((chunk_x, chunk_z)) = self.load_queue.pop() else {
+            return false;
+        };
+        let mut chunk = self.chunks.take((chunk_x, chunk_z)).expect("Chunk was not present");
+        chunk.load_id.swap_null();
+        chunk.unload(self);
+        chunk.block_offset = Coord::new(chunk_x * 16, WORLD_BOTTOM, chunk_z * 16);
+        let (region_x, region_z) = (chunk_x >> 5, chunk_z >> 5);
+        let mut region = if let Some(region) = self.regions.take((region_x, region_z)) {
+            region
+        } else {
+            let region_path = self.get_region_path(region_x, region_z);
+            if region_path.is_file() {
+                match RegionFile::open_or_create(region_path) {
+                    Ok(region) => region,
+                    Err(err) => {
+                        self.chunks.set((chunk_x, chunk_z), chunk);
+                        self.fail_load((chunk_x, chunk_z), err);
+                        return true;
+                    }
                 }
-            };
-            let result = region.read((chunk_x & 31, chunk_z & 31), |reader| {
-                chunk.read_from(reader, &mut self)
+            } else {
+                chunk.edit_time = Timestamp::new(0);
+                chunk.sky_light_state = SkyLightState::Valid;
+                chunk.world_gen_id = self.worldgen_queue.insert((chunk_x, chunk_z));
+                self.chunks.set((chunk_x, chunk_z), chunk);
+                return true;
+            }
+        };
+        let result = self.load_chunk(&mut region, &mut chunk, chunk_x, chunk_z);
+        let (loaded, migrated, error) = match result {
+            Err(Error::ChunkNotFound) => {
+                chunk.sky_light_state = SkyLightState::Valid;
+                chunk.world_gen_id = self.worldgen_queue.insert((chunk_x, chunk_z));
+                (false, None, None)
+            }
+            Err(err) => (false, None, Some(err)),
+            Ok(migrated) => (true, migrated, None),
+        };
+        chunk.edit_time = region.get_timestamp((chunk_x & 31, chunk_z & 31));
+        self.regions.set((region_x, region_z), region);
+        self.chunks.set((chunk_x, chunk_z), chunk);
+        if let Some(err) = error {
+            self.fail_load((chunk_x, chunk_z), err);
+            return true;
+        }
+        // Migrated chunks are saved so that they don't need to be migrated again.
+        if let Some(sections) = migrated {
+            self.refresh_migrated_sections(chunk_x, chunk_z, &sections);
+            self.mark_modified(ChunkCoord::new(chunk_x, chunk_z));
+        }
+        self.mark_loaded_chunk_dirty(chunk_x, chunk_z);
+        if loaded {
+            self.finish_chunk_sky_light(chunk_x, chunk_z);
+            self.emit(ExternEvent::ChunkLoaded(ChunkCoord::new(chunk_x, chunk_z)));
+        }
+        true
+    }
+
+    /// Finishes the sky light of a chunk that was just loaded or generated. Light that was calculated without the
+    /// neighboring chunks is merged with them, and stale light (when some of the blocks can only be lit on the main thread)
+    /// is calculated again.
+    fn finish_chunk_sky_light(&mut self, chunk_x: i32, chunk_z: i32) {
+        let state = self.chunks.get((chunk_x, chunk_z)).expect("Chunk was not present").sky_light_state;
+        match state {
+            SkyLightState::Valid => (),
+            SkyLightState::Detached => self.without_block_events(|world| merge_chunk_sky_light(world, chunk_x, chunk_z)),
+            SkyLightState::Stale => self.without_block_events(|world| compute_chunk_sky_light(world, chunk_x, chunk_z)),
+        }
+    }
+
+    /// Marks the sky light of the chunk that `coord` is in as stale because the block at `coord` changed while lighting was deferred.
+    pub(crate) fn mark_sky_light_stale(&mut self, coord: Coord) {
+        if let Some(chunk) = self.chunks.get_mut(coord.chunk_coord().xz()) {
+            chunk.sky_light_state = SkyLightState::Stale;
+        }
+    }
+
+    /// Calculates the occlusion and block light of the sections of a loaded chunk whose blocks were changed by migrations,
+    /// since the saved ones were calculated from the old blocks. The heightmap and sky light were calculated when the chunk was decoded.
+    pub(crate) fn refresh_migrated_sections(&mut self, chunk_x: i32, chunk_z: i32, sections: &[usize]) {
+        if sections.is_empty() {
+            return;
+        }
+        let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
+        sections.iter().for_each(|&index| {
+            chunk.sections[index].reset_occlusion();
+        });
+        let block_offset = chunk.block_offset;
+        let section_offsets = sections.iter()
+            .map(|&index| block_offset + Coord::new(0, index as i32 * 16, 0))
+            .collect_vec();
+        let mut dirty_sections = hashbrown::HashSet::new();
+        section_offsets.iter().for_each(|&section_offset| {
+            let section_coord = section_offset.section_coord();
+            dirty_sections.insert(section_coord);
+            (0..4096u16).for_each(|index| {
+                let coord = section_offset + Section::coord(index);
+                let air = self.get_block(coord).is_air();
+                Direction::iter().for_each(|dir| {
+                    let Some(adj_coord) = coord.checked_neighbor(dir) else {
+                        return;
+                    };
+                    // The faces of the neighboring sections were hidden by the old blocks, so they're always calculated.
+                    // Faces inside of the section start out visible, so only faces between two blocks can be hidden.
+                    if adj_coord.section_coord() != section_coord {
+                        self.occlude_pair(coord, dir);
+                        dirty_sections.insert(adj_coord.section_coord());
+                    } else if !air
+                    && matches!(dir, Direction::PosX | Direction::PosY | Direction::PosZ)
+                    && self.get_block(adj_coord).is_non_air() {
+                        self.occlude_pair(coord, dir);
+                    }
+                });
             });
-            match result {
-                Err(Error::ChunkNotFound) => {
-                    /* chunk.unload() */
-                    // chunk.unload(self);
-                },
-                other => {
-                    other?;
-                },
-                _ => (),
+        });
+        let block_coords = section_offsets.iter().flat_map(|&section_offset| {
+            (0..4096u16).map(move |index| section_offset + Section::coord(index))
+        }).collect_vec();
+        self.without_block_events(|world| update_light(world, LightChannel::Block, block_coords));
+        dirty_sections.into_iter().for_each(|section_coord| self.mark_section_dirty(section_coord));
+    }
+
+    /// Marks every section of a freshly loaded chunk that is in the render area as dirty.
+    fn mark_loaded_chunk_dirty(&mut self, chunk_x: i32, chunk_z: i32) {
+        if self.render.is_none() ||
+        chunk_x < self.render_area.x_min() ||
+        chunk_x >= self.render_area.x_max() ||
+        chunk_z < self.render_area.z_min() ||
+        chunk_z >= self.render_area.z_max() {
+            return;
+        }
+        let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was not present");
+        for y in self.render_area.y_min()..self.render_area.y_max() {
+            let section_index = (y - chunk.section_y()) as usize;
+            let section = &mut chunk.sections[section_index];
+            section.light_dirty.mark();
+            section.blocks_dirty.mark();
+            section.section_dirty.mark();
+            if section.dirty_id.null() {
+                section.dirty_id = self.dirty_queue.insert(Coord::new(chunk_x, y, chunk_z));
+            } else {
+                self.dirty_queue.swap_insert(&mut section.dirty_id, Coord::new(chunk_x, y, chunk_z));
             }
-            let chunk_y = chunk.section_y();
-            // TODO: There's a better way to do this, lol
-            let render_y_min = self.render_chunks.bounds().y_min();
-            let render_y_max = self.render_chunks.bounds().y_max();
-            for y in render_y_min..render_y_max {
-                let chunk_bottom = chunk.block_offset.y >> 4;
-                let diff = render_y_min - chunk_bottom;
-                let yi = y - render_y_min;
-                let i = (y - chunk_bottom) as usize;
-                let section_coord = Coord::new(chunk_x, y, chunk_z);
-                if !self.render_chunks.bounds().contains(section_coord) {
-                    continue;
+        }
+    }
+
+    /// Starts the [ChunkWorkers] so that chunk loading, generation, and saving happen on background threads.
+    /// The region files that the world has open are closed because the IO worker opens its own.
+    pub fn enable_workers(&mut self, generator_threads: usize) {
+        if self.workers.is_some() {
+            panic!("Workers are already enabled.");
+        }
+        let bounds = self.regions.bounds();
+        bounds.iter().for_each(|coord| {
+            self.regions.take(coord);
+        });
+        self.workers = Some(ChunkWorkers::new(generator_threads));
+    }
+
+    /// Waits for every pending job to finish, then stops the [ChunkWorkers].
+    /// Saves that were sent to the workers are written before this returns.
+    pub fn disable_workers(&mut self) {
+        self.finish_chunk_jobs();
+        // Dropping the workers joins the threads.
+        self.workers.take();
+    }
+
+    fn send_job(&self, job: ChunkJob) {
+        self.workers.as_ref().expect("Workers are not enabled").send(job);
+    }
+
+    /// Sends every chunk in the load queue to the workers.
+    pub fn dispatch_load_jobs(&mut self) {
+        if self.workers.is_none() {
+            return;
+        }
+        while let Some((chunk_x, chunk_z)) = self.load_queue.pop() {
+            let mut chunk = self.chunks.take((chunk_x, chunk_z)).expect("Chunk was not present");
+            chunk.load_id.swap_null();
+            chunk.unload(self);
+            chunk.block_offset = Coord::new(chunk_x * 16, WORLD_BOTTOM, chunk_z * 16);
+            chunk.job_id = self.pending_jobs.insert((chunk_x, chunk_z));
+            self.send_job(ChunkJob::Load {
+                id: chunk.job_id,
+                coord: (chunk_x, chunk_z),
+                region_path: self.get_region_path(chunk_x >> 5, chunk_z >> 5),
+                migrations: self.migrations.clone(),
+            });
+            self.chunks.set((chunk_x, chunk_z), chunk);
+        }
+    }
+
+    /// Sends every chunk in the worldgen queue to the workers.
+    /// This does nothing unless there is a [ChunkGenerator]. A [WorldGenerator] can't run on the workers,
+    /// so its chunks are left in the queue for [VoxelWorld::generate_next_chunk].
+    pub fn dispatch_worldgen_jobs(&mut self) {
+        if self.workers.is_none() {
+            return;
+        }
+        let Some(generator) = self.chunk_generator.clone() else {
+            return;
+        };
+        while let Some((chunk_x, chunk_z)) = self.worldgen_queue.pop() {
+            let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
+            chunk.world_gen_id = PoolId::NULL;
+            self.pending_jobs.swap_insert(&mut chunk.job_id, (chunk_x, chunk_z));
+            let id = chunk.job_id;
+            self.send_job(ChunkJob::Generate {
+                id,
+                coord: (chunk_x, chunk_z),
+                generator: generator.clone(),
+            });
+        }
+    }
+
+    /// Integrates finished jobs until there are none left or the time budget has been used.
+    /// Returns the number of chunks that were integrated.
+    pub fn process_job_results(&mut self, budget: std::time::Duration) -> usize {
+        let start_time = std::time::Instant::now();
+        let mut count = 0;
+        while start_time.elapsed() < budget {
+            let Some(result) = self.workers.as_ref().and_then(ChunkWorkers::try_recv) else {
+                break;
+            };
+            if self.integrate_job_result(result) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Blocks until every pending job has been integrated.
+    pub fn finish_chunk_jobs(&mut self) {
+        while !self.pending_jobs.is_empty() {
+            let Some(workers) = self.workers.as_ref() else {
+                return;
+            };
+            let result = workers.recv();
+            self.integrate_job_result(result);
+        }
+    }
+
+    /// Claims the chunk that a job was for. Returns false if the job is stale, which happens when the
+    /// chunk moved or unloaded while the job was in flight.
+    fn claim_job(&mut self, id: PoolId<ChunkJobMarker>, coord: (i32, i32)) -> bool {
+        let Some(chunk) = self.chunks.get_mut(coord) else {
+            return false;
+        };
+        if id.null() || chunk.job_id != id {
+            return false;
+        }
+        chunk.job_id = PoolId::NULL;
+        self.pending_jobs.remove(id);
+        true
+    }
+
+    /// Integrates the result of a job into the world. Stale results are discarded.
+    /// Returns true if a chunk was integrated.
+    fn integrate_job_result(&mut self, result: ChunkJobResult) -> bool {
+        match result {
+            ChunkJobResult::Loaded { id, coord, timestamp, version, chunk: decoded } => {
+                if !self.claim_job(id, coord) {
+                    return false;
                 }
-                // this probably isn't necessary.
-                // self.dirty_queue.remove(chunk.sections[i].dirty_id.swap_null());
-                if chunk.sections[i].blocks.is_some() {
-                    chunk.sections[i].dirty_id = self.dirty_queue.insert(section_coord);
-                    chunk.sections[i].light_dirty.mark();
-                    chunk.sections[i].blocks_dirty.mark();
-                    chunk.sections[i].section_dirty.mark();
+                let (chunk_x, chunk_z) = coord;
+                let mut chunk = self.chunks.take(coord).expect("Chunk was not present");
+                let migrated = chunk.take_decoded(*decoded, self);
+                chunk.edit_time = timestamp;
+                self.chunks.set(coord, chunk);
+                self.refresh_migrated_sections(chunk_x, chunk_z, &migrated);
+                if version < self.migrations.data_version() || !migrated.is_empty() {
+                    self.mark_modified(ChunkCoord::new(chunk_x, chunk_z));
                 }
+                self.mark_loaded_chunk_dirty(chunk_x, chunk_z);
+                self.finish_chunk_sky_light(chunk_x, chunk_z);
+                self.emit(ExternEvent::ChunkLoaded(ChunkCoord::new(chunk_x, chunk_z)));
+                true
             }
-            chunk.edit_time = region.get_timestamp((chunk_x & 31, chunk_z & 31));
-            self.chunks.set((chunk_x, chunk_z), chunk);
-            self.regions.set((region_x, region_z), region);
-            Ok(())
+            ChunkJobResult::NotFound { id, coord } => {
+                if !self.claim_job(id, coord) {
+                    return false;
+                }
+                self.queue_generation(coord);
+                false
+            }
+            ChunkJobResult::Generated { id, coord, chunk } => {
+                if !self.claim_job(id, coord) {
+                    return false;
+                }
+                self.integrate_generated_chunk(coord.0, coord.1, chunk);
+                true
+            }
+            ChunkJobResult::Meshed { id, section_coord, mesh } => {
+                // The mesh is swapped in by talk_to_bevy, which checks that it isn't stale.
+                if let Some(render) = &mut self.render {
+                    render.finished_meshes.push((id, section_coord, mesh));
+                }
+                false
+            }
+            ChunkJobResult::LoadFailed { id, coord, error } => {
+                if !self.claim_job(id, coord) {
+                    return false;
+                }
+                self.fail_load(coord, error);
+                false
+            }
+            ChunkJobResult::SaveFailed { coord, error } => {
+                let (chunk_x, chunk_z) = coord;
+                if self.chunks.get(coord).is_some() {
+                    bevy::log::error!("Failed to save chunk {chunk_x} {chunk_z}: {error}. It will be saved again with the next save.");
+                    self.mark_modified(ChunkCoord::new(chunk_x, chunk_z));
+                } else {
+                    bevy::log::error!("Failed to save chunk {chunk_x} {chunk_z}: {error}. The chunk was unloaded, so its changes are lost.");
+                }
+                false
+            }
+        }
+    }
+
+    /// Queues a chunk to be generated because it wasn't found on disk.
+    fn queue_generation(&mut self, coord: (i32, i32)) {
+        let chunk = self.chunks.get_mut(coord).expect("Chunk was not present");
+        chunk.edit_time = Timestamp::new(0);
+        // The chunk is empty until it's generated, and empty chunks are lit by the sky everywhere.
+        chunk.sky_light_state = SkyLightState::Valid;
+        self.worldgen_queue.swap_insert(&mut chunk.world_gen_id, coord);
+    }
+
+    /// Leaves a chunk that is on disk but couldn't be read empty. It isn't generated or saved,
+    /// so the data on disk is kept until the chunk can be read again.
+    fn fail_load(&mut self, coord: (i32, i32), error: Error) {
+        let (chunk_x, chunk_z) = coord;
+        bevy::log::error!("Failed to load chunk {chunk_x} {chunk_z}: {error}. It won't be saved so that the data on disk is kept.");
+        let mut chunk = self.chunks.take(coord).expect("Chunk was not present");
+        // The chunk may have been partially read, so whatever was read is discarded.
+        chunk.unload(self);
+        chunk.load_failed = true;
+        self.chunks.set(coord, chunk);
+    }
+
+    /// Moves the blocks of a chunk from a [ChunkGenerator] into the world in bulk, then calculates occlusion and light for the chunk.
+    /// Occlusion is calculated here rather than on the worker because blocks need the world to determine how they occlude.
+    fn integrate_generated_chunk(&mut self, chunk_x: i32, chunk_z: i32, generated: Chunk) {
+        let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
+        chunk.take_generated(generated);
+        let solid = chunk.sections.iter().enumerate().flat_map(|(i, section)| {
+            let section_offset = chunk.block_offset + Coord::new(0, i as i32 * 16, 0);
+            section.blocks.iter().flat_map(move |blocks| {
+                blocks.iter().enumerate()
+                    .filter(|(_, state)| state.is_non_air())
+                    .map(move |(index, _)| section_offset + Section::coord(index as u16))
+            })
+        }).collect_vec();
+        // Every face starts out visible, so only faces between two blocks can be hidden.
+        // Faces between two blocks of this chunk are calculated once, from the block on the negative side.
+        let mut border_sections = hashbrown::HashSet::new();
+        solid.into_iter().for_each(|coord| {
+            Direction::iter().for_each(|dir| {
+                let Some(adj_coord) = coord.checked_neighbor(dir) else {
+                    return;
+                };
+                if self.get_block(adj_coord).is_air() {
+                    return;
+                }
+                let outside = adj_coord.chunk_coord() != coord.chunk_coord();
+                let positive = matches!(dir, Direction::PosX | Direction::PosY | Direction::PosZ);
+                if positive || outside {
+                    self.occlude_pair(coord, dir);
+                }
+                if outside {
+                    border_sections.insert(adj_coord.section_coord());
+                }
+            });
         });
-        self
+        border_sections.into_iter().for_each(|section_coord| self.mark_section_dirty(section_coord));
+        self.finish_generated_chunk(chunk_x, chunk_z, self.defer_lighting);
     }
 
     pub fn render_bounds_aabb(&self) -> AABB {
@@ -296,7 +881,7 @@ impl VoxelWorld {
         let id = self.get_block(current);
         if filter(self, current, id) {
             let orientation = id.block().orientation(self, current, id);
-            if let Some(dist) = id.block().raycast(ray, self, current, id, orientation) {
+            if let Some((dist, _)) = id.block().raycast(ray, self, current, id, orientation) {
                 return Some(RaycastResult::new(ray.origin + (ray.direction * dist), current, None, id));
             }
         }
@@ -322,12 +907,8 @@ impl VoxelWorld {
                     let xcoord = Coord::from(xcoord);
                     if filter(self, xcoord, id) {
                         let orientation = id.block().orientation(self, xcoord, id);
-                        if let Some(dist) = id.block().raycast(ray, self, current, id, orientation) {
-                            let direction = match stepx {
-                                1 => Direction::NegX,
-                                -1 => Direction::PosX,
-                                _ => unreachable!()
-                            };
+                        // The face comes from the box that was hit rather than the step, since the box may not fill the block.
+                        if let Some((dist, direction)) = id.block().raycast(ray, self, xcoord, id, orientation) {
                             return Some(RaycastResult::new(ray.origin + (ray.direction * dist), xcoord, Some(direction), id));
                         }
                     }
@@ -350,12 +931,7 @@ impl VoxelWorld {
                     let stepcoord = Coord::from(stepcoord);
                     if filter(self, stepcoord, id) {
                         let orientation = id.block().orientation(self, stepcoord, id);
-                        if let Some(dist) = id.block().raycast(ray, self, current, id, orientation) {
-                            let direction = match stepz {
-                                1 => Direction::NegZ,
-                                -1 => Direction::PosZ,
-                                _ => unreachable!()
-                            };
+                        if let Some((dist, direction)) = id.block().raycast(ray, self, stepcoord, id, orientation) {
                             return Some(RaycastResult::new(ray.origin + (ray.direction * dist), stepcoord, Some(direction), id));
                         }
                     }
@@ -378,12 +954,7 @@ impl VoxelWorld {
                     let stepcoord = Coord::from(stepcoord);
                     if filter(self, stepcoord, id) {
                         let orientation = id.block().orientation(self, stepcoord, id);
-                        if let Some(dist) = id.block().raycast(ray, self, current, id, orientation) {
-                            let direction = match stepy {
-                                1 => Direction::NegY,
-                                -1 => Direction::PosY,
-                                _ => unreachable!()
-                            };
+                        if let Some((dist, direction)) = id.block().raycast(ray, self, stepcoord, id, orientation) {
                             return Some(RaycastResult::new(ray.origin + (ray.direction * dist), stepcoord, Some(direction), id));
                         }
                     }
@@ -531,68 +1102,33 @@ impl VoxelWorld {
         mut materials: ResMut<Assets<VoxelMaterial>>,
         mut render_chunks: Query<&mut Transform, With<RenderChunkMarker>>,
     ) {
-        let mut load = self.load_queue.lend("loading some chunks in talk_to_bevy");
-        let start_time = std::time::Instant::now();
-        // We'll try 5 milliseconds for now. We only have 16 milliseconds of frame time.
-        while start_time.elapsed().as_millis() < 2 {
-            if let Some((chunk_x, chunk_z)) = load.pop() {
-                let mut chunk = self.chunks.take((chunk_x, chunk_z)).expect("Chunk was not present");
-                chunk.load_id.swap_null();
-                chunk.unload(self);
-                chunk.block_offset = Coord::new(chunk_x * 16, WORLD_BOTTOM, chunk_z * 16);
-                let (region_x, region_z) = (chunk_x >> 5, chunk_z >> 5);
-                let mut region = if let Some(region) = self.regions.take((region_x, region_z)) {
-                    region
-                } else {
-                    let region_path = self.get_region_path(region_x, region_z);
-                    if region_path.is_file() {
-                        RegionFile::open_or_create(region_path).expect("Failed to open or create region file.")
-                    } else {
-                        chunk.edit_time = Timestamp::new(0);
-                        chunk.world_gen_id = self.worldgen_queue.insert((chunk_x, chunk_z));
-                        self.chunks.set((chunk_x, chunk_z), chunk);
-                        continue;
-                    }
-                };
-                let result = self.load_chunk(&mut region, &mut chunk, chunk_x, chunk_z);
-                match result {
-                    Err(Error::ChunkNotFound) => {
-                        chunk.world_gen_id = self.worldgen_queue.insert((chunk_x, chunk_z));
-                    }
-                    Err(err) => panic!("{err}"),
-                    _ => (),
-                }
-                chunk.edit_time = region.get_timestamp((chunk_x & 31, chunk_z & 31));
-                if chunk_x >= self.render_chunks.x_min() &&
-                chunk_x < self.render_chunks.x_max() &&
-                chunk_z >= self.render_chunks.z_min() &&
-                chunk_z < self.render_chunks.z_max() {
-                    for y in self.render_chunks.y_min()..self.render_chunks.y_max() {
-                        // self.mark_section_dirty(Coord::new(chunk_x, y, chunk_z));
-                        let section_index = (y - chunk.section_y()) as usize;
-                        let section = &mut chunk.sections[section_index];
-                        section.light_dirty.mark();
-                        section.blocks_dirty.mark();
-                        section.section_dirty.mark();
-                        if section.dirty_id.null() {
-                            section.dirty_id = self.dirty_queue.insert(Coord::new(chunk_x, y, chunk_z));
-                        } else {
-                            self.dirty_queue.swap_insert(&mut section.dirty_id, Coord::new(chunk_x, y, chunk_z));
-                        }
-                    }
-                }
-                self.regions.set((region_x, region_z), region);
-                // if let Some(mut region) = self.regions.take(region_coord) {
-                // } else {
-                //     // Region was not found, which means the chunk has not been created yet, so generate a new one.
-                //     chunk.world_gen_id = self.worldgen_queue.insert((chunk_x, chunk_z));
-                // }
-                self.chunks.set((chunk_x, chunk_z), chunk);
-            } else {
-                break;
-            }
+        // Headless worlds have nothing to talk to Bevy about.
+        if self.render.is_none() {
+            return;
         }
-        self.load_queue.give(load);
+        // The queues are processed while the render layer is still attached so that
+        // the sections they change are marked dirty.
+        // We'll try 2 milliseconds for now. We only have 16 milliseconds of frame time.
+        self.process_load_queue(std::time::Duration::from_millis(2));
+        self.process_worldgen_queue(std::time::Duration::from_millis(4));
+        self.process_lod_queue(std::time::Duration::from_millis(1));
+        let mut render = self.render.take().expect("Render layer was detached");
+        // Swap in the meshes that the workers finished.
+        std::mem::take(&mut render.finished_meshes).into_iter().for_each(|(id, coord, section_mesh)| {
+            let Some(render_chunk) = render.render_chunks.get_mut(coord) else {
+                return;
+            };
+            // The section was remeshed or the render chunk was moved after this mesh was requested.
+            if id.null() || render_chunk.mesh_job != id {
+                return;
+            }
+            render_chunk.mesh_job = PoolId::NULL;
+            render.pending_meshes.remove(id);
+            if render_chunk.apply_section_mesh(section_mesh, &mut commands, &mut meshes, &mut materials) {
+                render.unsorted.push(coord);
+            }
+            self.emit(ExternEvent::SectionMeshed(coord));
+        });
         let mut dirty = self.dirty_queue.lend("draining the dirty_queue in talk_to_bevy");
         let start_time = std::time::Instant::now();
         // TODO: Right now, despawning is broken under certain move condition.s
@@ -620,13 +1156,13 @@ impl VoxelWorld {
                 // let Some(render_chunk) = self.render_chunks.get_opt_mut(coord) else {
                 //     panic!("Render chunk out of bounds");
                 // };
-                let mut render_chunk = self.render_chunks.take(coord);
+                let mut render_chunk = render.render_chunks.take(coord);
                 if make_render_chunk {
                     if render_chunk.is_none() {
                         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
                         MeshBuilder::build_mesh(&mut mesh, |build| ());
                         let mesh = meshes.add(mesh);
-                        let material = materials.add(VoxelMaterial::new(self.array_texture.clone()));
+                        let material = materials.add(VoxelMaterial::new(render.array_texture.clone()));
                         let (x, y, z) = (
                             (coord.x * 16) as f32,
                             (coord.y * 16) as f32,
@@ -648,6 +1184,10 @@ impl VoxelWorld {
                             mesh: mesh,
                             material: material,
                             move_id: PoolId::NULL,
+                            mesh_job: PoolId::NULL,
+                            lod: LOD::Level0,
+                            lod_id: PoolId::NULL,
+                            layers: Vec::new(),
                             entity,
                         });
                         // Some()
@@ -655,65 +1195,288 @@ impl VoxelWorld {
                     }
                 } else {
                     if let Some(unload_chunk) = render_chunk.take() {
-                        self.move_render_chunk_queue.remove(unload_chunk.move_id);
+                        render.move_render_chunk_queue.remove(unload_chunk.move_id);
+                        render.pending_meshes.remove(unload_chunk.mesh_job);
+                        render.lod_queue.remove(unload_chunk.lod_id);
                         commands.entity(unload_chunk.entity).despawn_recursive();
                     }
                 }
                 let Some(render_chunk_mut) = render_chunk.as_mut() else {
-                    self.render_chunks.set_opt(coord, render_chunk);
+                    render.render_chunks.set_opt(coord, render_chunk);
                     continue;
                 };
                 if blocks_dirty {
-                    let mesh = meshes.get_mut(render_chunk_mut.mesh.id()).expect("Failed to get the mesh");
-                    MeshBuilder::build_mesh(mesh, |build| {
-                        for y in 0..16 {
-                            for z in 0..16 {
-                                'xloop: for x in 0..16 {
-                                    let block_coord = (sect_x * 16 + x, sect_y * 16 + y, sect_z * 16 + z);
-                                    let offset = vec3(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5);
-                                    let state = self.get_block(block_coord);
-                                    if state == Id::AIR {
-                                        continue 'xloop;
-                                    }
-                                    let orientation = state.block().orientation(self, block_coord.into(), state);
-                                    let occlusion = self.get_occlusion(block_coord);
-                                    build.set_offset(offset);
-                                    build.set_orientation(orientation);
-                                    // TODO: Determine distance of chunk to determine LOD
-                                    //       Add queue for updating LOD
-                                    state.block().push_mesh(build, LOD::Level0, self, block_coord.into(), state, occlusion, orientation);
-                                }
-                            }
+                    let snapshot = self.snapshot_section(coord);
+                    let level_of_detail = self.section_lod(coord);
+                    render_chunk_mut.lod = level_of_detail;
+                    if self.workers.is_some() {
+                        // Requesting a new mesh makes any mesh that is still in flight stale.
+                        render.pending_meshes.swap_insert(&mut render_chunk_mut.mesh_job, coord);
+                        self.send_job(ChunkJob::Mesh {
+                            id: render_chunk_mut.mesh_job,
+                            snapshot: Box::new(snapshot),
+                            level_of_detail,
+                        });
+                    } else {
+                        let section_mesh = mesh_section(&snapshot, level_of_detail);
+                        if render_chunk_mut.apply_section_mesh(section_mesh, &mut commands, &mut meshes, &mut materials) {
+                            render.unsorted.push(coord);
                         }
-                    });
+                        self.emit(ExternEvent::SectionMeshed(coord));
+                    }
                 }
                 if light_map_dirty {
-                    // TODO: Rebuild lightmap
+                    let lightmap = self.build_section_lightmap(coord);
+                    render_chunk_mut.apply_lightmap(lightmap, &mut materials);
                 }
-                self.render_chunks.set_opt(coord, render_chunk);
+                render.render_chunks.set_opt(coord, render_chunk);
             } else {
                 break;
             }
         }
         self.dirty_queue.give(dirty);
-        let mut move_render_chunk_queue = self.move_render_chunk_queue.lend("Moving chunk entities");
+        let RenderLayer { render_chunks: rend_chunks, move_render_chunk_queue, .. } = &mut render;
         move_render_chunk_queue.drain().for_each(|coord| {
             // We expect that if a render chunk requested to move, that means that it's not None.
-            let rend_chunk = self.render_chunks.get_mut(coord).expect("Render chunk was not found");
+            let rend_chunk = rend_chunks.get_mut(coord).expect("Render chunk was not found");
             let ent = rend_chunk.entity.clone();
             rend_chunk.move_id = PoolId::NULL;
             let mut trans = render_chunks.get_mut(ent).expect("Failed to get transform");
             let offset = vec3((coord.x * 16) as f32, (coord.y * 16) as f32, (coord.z * 16) as f32);
             trans.translation = offset;
+            self.emit(ExternEvent::RenderChunkMoved(coord));
         });
-        self.move_render_chunk_queue.give(move_render_chunk_queue);
+        self.render = Some(render);
+    }
+
+    /// Generates every chunk in the worldgen queue.
+    pub fn flush_worldgen_queue(&mut self) {
+        if self.workers.is_some() && self.chunk_generator.is_some() {
+            self.dispatch_worldgen_jobs();
+            self.finish_chunk_jobs();
+        }
+        while self.generate_next_chunk() {}
+    }
+
+    /// Generates chunks from the worldgen queue until the queue is empty or the time budget has been used.
+    /// Returns the number of chunks that were generated.
+    /// 
+    /// When [ChunkWorkers] and a [ChunkGenerator] are available, the worldgen queue is sent to the workers instead
+    /// and the generated chunks are integrated by [VoxelWorld::process_load_queue].
+    /// A [WorldGenerator] always runs here on the main thread.
+    pub fn process_worldgen_queue(&mut self, budget: std::time::Duration) -> usize {
+        self.dispatch_worldgen_jobs();
+        let start_time = std::time::Instant::now();
+        let mut count = 0;
+        while start_time.elapsed() < budget && self.generate_next_chunk() {
+            count += 1;
+        }
+        count
     }
 
-    pub fn load_chunk(&mut self, region: &mut RegionFile, chunk: &mut Chunk, x: i32, z: i32) -> crate::core::error::Result<()> {
+    /// Generates the next chunk in the worldgen queue on the main thread, with the world's [ChunkGenerator] if it has one,
+    /// or else with its [WorldGenerator]. A [WorldGenerator] is only ever run here because it needs the world.
+    /// Light is calculated for the whole chunk after the generator is finished.
+    /// Returns false if the worldgen queue was empty.
+    pub fn generate_next_chunk(&mut self) -> bool {
+        let Some((chunk_x, chunk_z)) = self.worldgen_queue.pop() else {
+            return false;
+        };
+        let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
+        chunk.world_gen_id = PoolId::NULL;
+        let area = Bounds2D {
+            min: (chunk_x * 16, chunk_z * 16),
+            max: (chunk_x * 16 + 16, chunk_z * 16 + 16),
+        };
+        if let Some(generator) = self.chunk_generator.clone() {
+            let mut generated = Chunk::new(Coord::new(chunk_x * 16, WORLD_BOTTOM, chunk_z * 16));
+            generator.generate_chunk(&mut generated, area);
+            compute_detached_sky_light(&mut generated);
+            self.integrate_generated_chunk(chunk_x, chunk_z, generated);
+            return true;
+        }
+        // Without a generator, the chunk stays empty and doesn't need to be saved.
+        let Some(mut generator) = self.world_generator.take() else {
+            return true;
+        };
+        let defer_lighting = self.defer_lighting.swap(true);
+        self.without_block_events(|world| generator.generate_chunk(world, area));
+        self.defer_lighting = defer_lighting;
+        self.world_generator = Some(generator);
+        self.finish_generated_chunk(chunk_x, chunk_z, defer_lighting);
+        true
+    }
+
+    /// Calculates light for a chunk that was just generated, then marks it as modified and dirty.
+    /// Light isn't calculated if lighting is being deferred by the caller.
+    fn finish_generated_chunk(&mut self, chunk_x: i32, chunk_z: i32, defer_lighting: bool) {
+        if !defer_lighting {
+            self.finish_chunk_sky_light(chunk_x, chunk_z);
+            self.without_block_events(|world| compute_chunk_block_light(world, chunk_x, chunk_z));
+        }
+        self.mark_modified(ChunkCoord::new(chunk_x, chunk_z));
+        self.emit(ExternEvent::ChunkGenerated(ChunkCoord::new(chunk_x, chunk_z)));
+        let (y_min, y_max) = (self.render_area.y_min(), self.render_area.y_max());
+        for y in y_min..y_max {
+            let section_coord = Coord::new(chunk_x, y, chunk_z);
+            let Some(sect) = self.get_section_mut(section_coord) else {
+                continue;
+            };
+            if sect.blocks.is_none() {
+                continue;
+            }
+            sect.blocks_dirty.mark();
+            sect.light_dirty.mark();
+            sect.section_dirty.mark();
+            self.mark_section_dirty(section_coord);
+        }
+    }
+
+    /// Takes a snapshot of a section and the blocks that border it so that it can be meshed off of the main thread.
+    pub fn snapshot_section(&self, section_coord: Coord) -> SectionSnapshot {
+        let mut snapshot = SectionSnapshot::new(section_coord);
+        let block_offset = section_coord * 16;
+        for y in -1..17 {
+            for z in -1..17 {
+                for x in -1..17 {
+                    let coord = block_offset + Coord::new(x, y, z);
+                    let state = self.get_block(coord);
+                    if state == Id::AIR {
+                        continue;
+                    }
+                    let block = state.block();
+                    let orientation = block.orientation(self, coord, state);
+                    snapshot.set(coord, state, self.get_occlusion(coord), orientation);
+                    let full = block.occluder(self, state).is_full();
+                    let layer = block.layer(self, coord, state);
+                    // Transparent blocks don't darken their neighbors.
+                    snapshot.set_solid(coord, full && !layer.transparent());
+                    snapshot.set_layer(coord, layer);
+                    if orientation == Orientation::default()
+                    && full
+                    && block.greedy_faces(state).is_some() {
+                        snapshot.set_greedy(coord, true);
+                    }
+                }
+            }
+        }
+        let level_of_detail = self.section_lod(section_coord);
+        Direction::iter().for_each(|dir| {
+            snapshot.set_seam(dir, self.section_lod(section_coord + dir) != level_of_detail);
+        });
+        snapshot
+    }
+
+    /// The [LOD] that a section is meshed at, based on its distance (in sections) from the center of the world.
+    pub fn section_lod(&self, section_coord: Coord) -> LOD {
+        let center = self.center.section_coord();
+        let distance = (section_coord.x - center.x).abs()
+            .max((section_coord.y - center.y).abs())
+            .max((section_coord.z - center.z).abs());
+        LOD::from_distance(distance, &self.lod_distances)
+    }
+
+    /// Checks the [LOD] of render chunks in the LOD queue until the queue is empty or the time budget has been used.
+    /// Sections whose LOD changed are remeshed along with their neighbors, since the seams between them changed.
+    /// Returns the number of sections whose LOD changed.
+    pub fn process_lod_queue(&mut self, budget: std::time::Duration) -> usize {
+        let start_time = std::time::Instant::now();
+        let mut count = 0;
+        while start_time.elapsed() < budget {
+            let Some(coord) = self.render.as_mut().and_then(|render| render.lod_queue.pop()) else {
+                break;
+            };
+            let level_of_detail = self.section_lod(coord);
+            let render = self.render.as_mut().expect("Render layer was detached");
+            let Some(render_chunk) = render.render_chunks.get_mut(coord) else {
+                continue;
+            };
+            render_chunk.lod_id = PoolId::NULL;
+            if render_chunk.lod == level_of_detail {
+                continue;
+            }
+            count += 1;
+            self.mark_section_remesh(coord);
+            Direction::iter().for_each(|dir| {
+                let neighbor = coord + dir;
+                let has_render_chunk = self.render.as_ref()
+                    .map(|render| render.render_chunks.get(neighbor).is_some())
+                    .unwrap_or(false);
+                if has_render_chunk {
+                    self.mark_section_remesh(neighbor);
+                }
+            });
+        }
+        count
+    }
+
+    /// Sorts the faces of transparent layers back-to-front from the camera's point of view so that they blend correctly.
+    /// Every transparent layer is sorted when the camera has moved at least [TRANSPARENT_SORT_DISTANCE] blocks since
+    /// the last time, otherwise only the layers that were remeshed since the last sort are sorted.
+    pub fn sort_transparent_faces(&mut self, camera: Vec3, meshes: &mut Assets<Mesh>) {
+        let render_area = self.render_area;
+        let Some(render) = self.render.as_mut() else {
+            return;
+        };
+        let viewpoint = |coord: Coord| camera - vec3((coord.x * 16) as f32, (coord.y * 16) as f32, (coord.z * 16) as f32);
+        let moved = render.last_sort_position
+            .map(|last| last.distance(camera) >= TRANSPARENT_SORT_DISTANCE)
+            .unwrap_or(true);
+        if moved {
+            render.last_sort_position = Some(camera);
+            render.unsorted.clear();
+            render_area.iter().for_each(|section_coord| {
+                if let Some(render_chunk) = render.render_chunks.get(section_coord) {
+                    render_chunk.sort_transparent_faces(viewpoint(section_coord.into()), meshes);
+                }
+            });
+            return;
+        }
+        std::mem::take(&mut render.unsorted).into_iter().for_each(|coord| {
+            if let Some(render_chunk) = render.render_chunks.get(coord) {
+                render_chunk.sort_transparent_faces(viewpoint(coord), meshes);
+            }
+        });
+    }
+
+    /// Marks a section's blocks dirty so that it's remeshed by [VoxelWorld::talk_to_bevy].
+    fn mark_section_remesh(&mut self, section_coord: Coord) {
+        let Some(sect) = self.get_section_mut(section_coord) else {
+            return;
+        };
+        sect.blocks_dirty.mark();
+        sect.section_dirty.mark();
+        self.mark_section_dirty(section_coord);
+    }
+
+    /// Builds the lightmap for a section, including the light of the blocks that border the section.
+    pub fn build_section_lightmap(&self, section_coord: Coord) -> SectionLightmap {
+        let mut levels = [15u8; LIGHTMAP_SIZE];
+        if let Some(section) = self.get_section(section_coord) {
+            section.copy_lightmap(&mut levels);
+        }
+        let block_offset = section_coord * 16;
+        let pads = gather_pads(|x, y, z| {
+            let coord = block_offset + Coord::new(x, y, z);
+            // Above the world is open sky.
+            if coord.y >= WORLD_TOP {
+                15
+            } else {
+                self.get_light_level(coord)
+            }
+        });
+        assemble_lightmap(&levels, &pads)
+    }
+
+    /// Reads a chunk from its region. If the chunk was migrated from an older data version (or was changed by
+    /// [upgrade_world](migration::upgrade_world)), returns the indices of the sections whose blocks were changed.
+    /// Those sections need to be passed to [VoxelWorld::refresh_migrated_sections] once the chunk is in the world.
+    pub fn load_chunk(&mut self, region: &mut RegionFile, chunk: &mut Chunk, x: i32, z: i32) -> crate::core::error::Result<Option<Vec<usize>>> {
         // self.unload_chunk(chunk);
         chunk.block_offset = Coord::new(x * 16, WORLD_BOTTOM, z * 16);
-        region.read((x & 31, z & 31), |reader| {
-            chunk.read_from(reader, self)
+        region.read_versioned((x & 31, z & 31), |reader, version| {
+            let migrated = chunk.read_from(reader, self, version)?;
+            Ok((version < self.migrations.data_version() || !migrated.is_empty()).then_some(migrated))
         })
         // match result {
         //     Err(Error::ChunkNotFound) => {
@@ -731,10 +1494,12 @@ impl VoxelWorld {
         let center: Coord = center.into();
         let padded_distance = self.render_distance + WORLD_SIZE_PAD as i32;
         let padded_size = padded_distance * 2;
-        let render_min = self.render_chunks.bounds().min;
-        let (render_x, render_y, render_z) = calculate_center_offset(self.render_distance, center, Some(Self::WORLD_BOUNDS)).section_coord().xyz();
+        let render_min = self.render_area.min;
+        let render_area = Self::calculate_render_area(self.render_distance, center);
+        let (render_x, render_y, render_z) = render_area.min;
         let (chunk_x, chunk_z) = calculate_center_offset(padded_distance, center, Some(Self::WORLD_BOUNDS)).chunk_coord().xz();
         let (region_x, region_z) = calculate_region_min((chunk_x, chunk_z));
+        self.center = center;
         // World hasn't moved
         if render_min == (render_x, render_y, render_z) {
             return;
@@ -744,13 +1509,15 @@ impl VoxelWorld {
         if chunk_min != (chunk_x, chunk_z) {
             // This operation will be kinda slow if a lot of chunks need to be saved.
             // Thankfully that shouldn't be too much of a problem since you can expect that only the nearest 4 chunks might be edited before the world moves.
+            // With workers, this only serializes the chunks. The writes happen in the background.
             self.save_world().expect("Failed to save the world");
         }
         // take temporary ownership of 
         let mut regions = self.regions.lend("regions in move_center");
         let result = regions.try_reposition((region_x, region_z), |old_pos, (x, z), region| {
             let rg_path = self.get_region_path(x, z);
-            if rg_path.is_file() {
+            // The IO worker owns the region files while the workers are enabled.
+            if self.workers.is_none() && rg_path.is_file() {
                 Result::Ok(Some(RegionFile::open(rg_path)?))
             } else {
                 // There's no region file, so just return None. We're not reusing RegionFile instances.
@@ -763,6 +1530,10 @@ impl VoxelWorld {
         chunks.reposition((chunk_x, chunk_z), |old_pos, (x, z), chunk| {
             //                   The chunk should never be None. If it is, that's an error.
             let mut chunk = chunk.expect("Chunk was None");
+            self.emit(ExternEvent::ChunkUnloaded(ChunkCoord::new(old_pos.0, old_pos.1)));
+            // Discard the result of any job that's in flight for the old position.
+            let job_id = chunk.job_id.swap_null();
+            self.pending_jobs.remove(job_id);
             if chunk.load_id.null() {
                 chunk.load_id = self.load_queue.insert((x, z));
             } else {
@@ -791,12 +1562,20 @@ impl VoxelWorld {
         });
         self.chunks.give(chunks);
         self.regions.give(regions);
-        let mut render_chunks = self.render_chunks.lend("render_chunks in move_center");
+        self.render_area = render_area;
+        // Headless worlds don't have any render chunks to move.
+        let Some(mut render) = self.render.take() else {
+            return;
+        };
+        let RenderLayer { render_chunks, move_render_chunk_queue, pending_meshes, lod_queue, .. } = &mut render;
         render_chunks.reposition((render_x, render_y, render_z), |old_pos, new_pos, mut chunk| {
             if let Some(rendchunk) = &mut chunk {
                 let old_id = rendchunk.move_id.swap(PoolId::NULL);
-                self.move_render_chunk_queue.remove(old_id);
-                rendchunk.move_id = self.move_render_chunk_queue.insert(Coord::from(new_pos));
+                move_render_chunk_queue.remove(old_id);
+                // The section is remeshed at its new position.
+                pending_meshes.remove(rendchunk.mesh_job.swap_null());
+                lod_queue.remove(rendchunk.lod_id.swap_null());
+                rendchunk.move_id = move_render_chunk_queue.insert(Coord::from(new_pos));
             }
             let section_coord: Coord = new_pos.into();
             let sect = self.get_section_mut(new_pos.into()).expect("failed to get section in render_chunks.reposition");
@@ -812,39 +1591,82 @@ impl VoxelWorld {
             block_chunk.sections[section_index].dirty_id = self.dirty_queue.insert(section_coord);
             chunk
         });
-        self.render_chunks.give(render_chunks);
+        // The center moved, so the sections that stayed in the render area might need a different LOD.
+        render_area.iter().for_each(|section_coord| {
+            let section_coord: Coord = section_coord.into();
+            if let Some(render_chunk) = render.render_chunks.get_mut(section_coord) {
+                if render_chunk.lod_id.null() {
+                    render_chunk.lod_id = render.lod_queue.insert(section_coord);
+                }
+            }
+        });
+        self.render = Some(render);
 
     }
 
+    /// Saves every modified chunk and the [WorldMetadata].
     #[must_use]
     pub fn save_world(&mut self) -> Result<()> {
-        self.save_queue.drain().try_for_each(|coord| {
+        self.save_metadata()?;
+        self.save_chunks()
+    }
+
+    /// Saves every modified chunk, but not the [WorldMetadata].
+    #[must_use]
+    pub fn save_chunks(&mut self) -> Result<()> {
+        self.mark_moved_entities();
+        if self.workers.is_some() {
+            return self.dispatch_save_jobs();
+        }
+        let coords = self.save_queue.drain().collect_vec();
+        coords.into_iter().for_each(|coord| {
             let (chunk_x, chunk_z) = coord.xz();
             let mut chunks = self.chunks.lend("chunks in save_world");
             let mut regions = self.regions.lend("regions in save_world");
             let mut chunk = chunks.take((chunk_x, chunk_z)).expect("Chunk was None");
-            // chunk.save_id = PoolId::NULL;
             let (region_x, region_z) = (chunk_x >> 5, chunk_z >> 5);
-            let mut region = if let Some(region) = regions.take((region_x, region_z)) {
-                region
-            } else {
-                RegionFile::open_or_create(self.subworld_directory.join(format!("{region_x}.{region_z}.rg")))?
+            let region = match regions.take((region_x, region_z)) {
+                Some(region) => Ok(region),
+                None => RegionFile::open_or_create(self.subworld_directory.join(format!("{region_x}.{region_z}.rg"))),
             };
-            let result = region.write_timestamped((chunk_x, chunk_z), chunk.edit_time, |writer| {
-                chunk.write_to(writer)?;
-                Ok(())
+            let result = region.and_then(|mut region| {
+                let result = region.write_timestamped_versioned((chunk_x, chunk_z), chunk.edit_time, self.migrations.data_version(), |writer| {
+                    chunk.write_to(writer, &self.scheduled_updates, &self.entities)?;
+                    Ok(())
+                });
+                regions.set((region_x, region_z), region);
+                result
             });
-            match result {
-                Err(err) => {
-                    panic!("{err} at {chunk_x} {chunk_z}");
-                },
-                _ => (),
-            }
             chunk.save_id = PoolId::NULL;
             chunks.set((chunk_x, chunk_z), chunk);
-            regions.set((region_x, region_z), region);
             self.chunks.give(chunks);
             self.regions.give(regions);
+            // A failed save (such as from a full disk) shouldn't lose the chunk, so it stays in the save queue.
+            if let Err(err) = result {
+                bevy::log::error!("Failed to save chunk {chunk_x} {chunk_z}: {err}. It will be saved again with the next save.");
+                self.mark_modified(coord);
+            }
+        });
+        Ok(())
+    }
+
+    /// Serializes every chunk in the save queue and sends it to the workers to be written.
+    fn dispatch_save_jobs(&mut self) -> Result<()> {
+        let coords = self.save_queue.drain().collect_vec();
+        coords.into_iter().try_for_each(|coord| {
+            let (chunk_x, chunk_z) = coord.xz();
+            let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
+            chunk.save_id = PoolId::NULL;
+            let mut data = Vec::new();
+            chunk.write_to(&mut data, &self.scheduled_updates, &self.entities)?;
+            let timestamp = chunk.edit_time;
+            self.send_job(ChunkJob::Save {
+                coord: (chunk_x, chunk_z),
+                region_path: self.get_region_path(chunk_x >> 5, chunk_z >> 5),
+                timestamp,
+                version: self.migrations.data_version(),
+                data,
+            });
             Ok(())
         })
     }
@@ -871,7 +1693,8 @@ impl VoxelWorld {
 
     pub fn mark_section_dirty(&mut self, section_coord: Coord) {
         let block_y = section_coord.y * 16;
-        if !self.render_chunks.bounds().contains(section_coord)
+        if self.render.is_none()
+        || !self.render_area.contains(section_coord)
         || block_y < WORLD_BOTTOM
         || block_y >= WORLD_TOP {
             return;
@@ -885,11 +1708,38 @@ impl VoxelWorld {
         }
     }
 
-    fn mark_modified(&mut self, chunk_coord: ChunkCoord) {
+    /// The sections that border `coord` and have it in their lightmap padding.
+    pub(crate) fn light_neighbor_sections(coord: Coord) -> impl Iterator<Item = Coord> {
+        let (x, y, z) = (coord.x & 0xF, coord.y & 0xF, coord.z & 0xF);
+        Direction::iter().filter(move |dir| match dir {
+            Direction::NegX => x == 0,
+            Direction::NegY => y == 0,
+            Direction::NegZ => z == 0,
+            Direction::PosX => x == 15,
+            Direction::PosY => y == 15,
+            Direction::PosZ => z == 15,
+        }).map(move |dir| coord.section_coord() + dir)
+    }
+
+    /// Marks sections as light dirty so that their lightmap padding gets rebuilt.
+    /// The light engine collects the sections while it propagates light and marks each of them once at the end.
+    pub(crate) fn mark_light_dirty_sections<It: IntoIterator<Item = Coord>>(&mut self, sections: It) {
+        sections.into_iter().for_each(|section_coord| {
+            let Some(sect) = self.get_section_mut(section_coord) else {
+                return;
+            };
+            sect.light_dirty.mark();
+            sect.section_dirty.mark();
+            self.mark_section_dirty(section_coord);
+        });
+    }
+
+    pub(crate) fn mark_modified(&mut self, chunk_coord: ChunkCoord) {
         let Some(mut chunk) = self.chunks.take(chunk_coord.xz()) else {
             panic!("Chunk was None");
         };
-        if chunk.save_id.null() {
+        // Saving a chunk that couldn't be loaded would overwrite the data on disk.
+        if chunk.save_id.null() && !chunk.load_failed {
             chunk.save_id = self.save_queue.insert(chunk_coord);
         }
         self.chunks.set(chunk_coord.xz(), chunk);
@@ -1006,6 +1856,7 @@ impl VoxelWorld {
         if state == old {
             return old;
         }
+        self.record_change(coord);
         let mut place_context = PlaceContext::new(coord, state, old);
         state.block().on_place(self, &mut place_context);
         while place_context.changed {
@@ -1027,6 +1878,8 @@ impl VoxelWorld {
             self.set_data(coord, data);
         }
         let (state, enable) = (place_context.replacement, place_context.enable);
+        let old_light = old.block().light_args(self, coord, old);
+        let old_height = self.height(coord.x, coord.z);
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
         let change = {
@@ -1036,14 +1889,17 @@ impl VoxelWorld {
         match change.change {
             StateChange::Unchanged => state,
             StateChange::Changed(old) => {
+                self.emit(ExternEvent::BlockChanged { coord, old, new: state });
                 let cur_ref = self.get_update_ref(coord);
                 if cur_ref.null() && (matches!(enable, Some(true))
                 || (!matches!(enable, Some(false)) && state.block().enable_on_place(self, coord, state))) {
                     let new_ref = self.update_queue.push(coord);
                     self.set_update_ref(coord, new_ref);
+                    self.emit(ExternEvent::EnabledChanged { coord, enabled: true });
                 } else if !cur_ref.null() {
                     self.set_update_ref(coord, UpdateRef::NULL);
                     self.update_queue.remove(cur_ref);
+                    self.emit(ExternEvent::EnabledChanged { coord, enabled: false });
                 }
                 self.mark_modified(coord.chunk_coord());
                 if self.render_bounds().contains(coord) {
@@ -1067,7 +1923,9 @@ impl VoxelWorld {
                         adj_block.neighbor_updated(self, adj_dir, adj_coord, coord, adj_state, state);
                     }
                     // No occlusion happens if they are on different layers.
-                    if adj_layer != my_layer {
+                    // Transparent blocks only occlude the same block so that they can be seen through each other.
+                    if adj_layer != my_layer
+                    || (my_layer.transparent() && adj_state.block_id() != state.block_id()) {
                         self.show_face(coord, dir);
                         self.show_face(adj_coord, adj_dir);
                         return;
@@ -1090,7 +1948,17 @@ impl VoxelWorld {
                         self.show_face(adj_coord, adj_dir);
                     }
                 });
-                
+                if !self.defer_lighting {
+                    let light_changed = block.light_args(self, coord, state) != old_light;
+                    if light_changed {
+                        update_block_light(self, coord);
+                    }
+                    if light_changed || self.height(coord.x, coord.z) != old_height {
+                        update_sky_light(self, coord, old_height);
+                    }
+                } else {
+                    self.mark_sky_light_stale(coord);
+                }
                 old
             },
         }
@@ -1186,6 +2054,23 @@ impl VoxelWorld {
     pub fn set_block_light<C: Into<(i32, i32, i32)>>(&mut self, coord: C, level: u8) -> LightChange {
         let coord: (i32, i32, i32) = coord.into();
         let coord: Coord = coord.into();
+        let change = self.set_block_light_silent(coord, level);
+        if change.changed() {
+            self.mark_light_dirty_sections(Self::light_neighbor_sections(coord));
+        }
+        if change.new_max != change.old_max {
+            let block = self.get_block(coord);
+            if block != Id::AIR {
+                block.block().light_updated(self, coord, change.old_max, change.new_max);
+            }
+        }
+        change
+    }
+
+    /// Sets the block light without calling [Block::light_updated](crate::core::voxel::block::Block::light_updated)
+    /// or marking the neighboring sections light dirty (see [VoxelWorld::light_neighbor_sections]).
+    /// The light engine uses this so that both can be deferred until propagation has finished.
+    pub(crate) fn set_block_light_silent(&mut self, coord: Coord, level: u8) -> LightChange {
         if !self.bounds().contains(coord) {
             return LightChange::default();
         }
@@ -1194,6 +2079,7 @@ impl VoxelWorld {
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
         let change = chunk.set_block_light(coord, level);
         if change.change.changed() {
+            self.emit(ExternEvent::LightChanged { coord, channel: LightChannel::Block, old: change.change.old_level, new: change.change.new_level });
             self.mark_modified(coord.chunk_coord());
             if self.render_bounds().contains(coord) {
                 let section_coord = coord.section_coord();
@@ -1201,12 +2087,6 @@ impl VoxelWorld {
                 self.mark_section_dirty(section_coord);
             }
         }
-        if change.change.new_max != change.change.old_max {
-            let block = self.get_block(coord);
-            if block != Id::AIR {
-                block.block().light_updated(self, coord, change.change.old_max, change.change.new_max);
-            }
-        }
         change.change
     }
 
@@ -1225,6 +2105,23 @@ impl VoxelWorld {
     pub fn set_sky_light<C: Into<(i32, i32, i32)>>(&mut self, coord: C, level: u8) -> LightChange {
         let coord: (i32, i32, i32) = coord.into();
         let coord: Coord = coord.into();
+        let change = self.set_sky_light_silent(coord, level);
+        if change.changed() {
+            self.mark_light_dirty_sections(Self::light_neighbor_sections(coord));
+        }
+        if change.new_max != change.old_max {
+            let block = self.get_block(coord);
+            if block != Id::AIR {
+                block.block().light_updated(self, coord, change.old_max, change.new_max);
+            }
+        }
+        change
+    }
+
+    /// Sets the sky light without calling [Block::light_updated](crate::core::voxel::block::Block::light_updated)
+    /// or marking the neighboring sections light dirty (see [VoxelWorld::light_neighbor_sections]).
+    /// The light engine uses this so that both can be deferred until propagation has finished.
+    pub(crate) fn set_sky_light_silent(&mut self, coord: Coord, level: u8) -> LightChange {
         if !self.bounds().contains(coord) {
             return LightChange::default();
         }
@@ -1233,18 +2130,13 @@ impl VoxelWorld {
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
         let change = chunk.set_sky_light(coord, level);
         if change.change.changed() {
+            self.emit(ExternEvent::LightChanged { coord, channel: LightChannel::Sky, old: change.change.old_level, new: change.change.new_level });
             self.mark_modified(coord.chunk_coord());
             if self.render_bounds().contains(coord) {
                 let section_coord = coord.section_coord();
                 self.mark_section_dirty(section_coord);
             }
         }
-        if change.change.new_max != change.change.old_max {
-            let block = self.get_block(coord);
-            if block != Id::AIR {
-                block.block().light_updated(self, coord, change.change.old_max, change.change.new_max);
-            }
-        }
         change.change
     }
 
@@ -1268,6 +2160,7 @@ impl VoxelWorld {
         }
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
+        self.record_change(coord);
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
         chunk.get_data_mut(coord)
     }
@@ -1280,8 +2173,10 @@ impl VoxelWorld {
         }
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
+        self.record_change(coord);
         if self.get_data(coord).is_none() {
             self.mark_modified(coord.chunk_coord());
+            self.emit(ExternEvent::DataSet(coord));
         }
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
         chunk.get_or_insert_data(coord, value)
@@ -1295,8 +2190,10 @@ impl VoxelWorld {
         }
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
+        self.record_change(coord);
         if self.get_data(coord).is_none() {
             self.mark_modified(coord.chunk_coord());
+            self.emit(ExternEvent::DataSet(coord));
         }
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
         chunk.get_or_insert_data_with(coord, f)
@@ -1308,11 +2205,13 @@ impl VoxelWorld {
         if !self.bounds().contains(coord) {
             return Tag::Null;
         }
+        self.record_change(coord);
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
         if let Some(data) = chunk.delete_data(coord) {
             self.mark_modified(coord.chunk_coord());
+            self.emit(ExternEvent::DataDeleted(coord));
             data
         } else {
             Tag::Null
@@ -1325,6 +2224,7 @@ impl VoxelWorld {
         if !self.bounds().contains(coord) {
             return;
         }
+        self.record_change(coord);
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
@@ -1334,6 +2234,7 @@ impl VoxelWorld {
                 state.block().on_data_delete(self, coord, state, data);
             }
             self.mark_modified(coord.chunk_coord());
+            self.emit(ExternEvent::DataDeleted(coord));
         }
     }
 
@@ -1351,6 +2252,7 @@ impl VoxelWorld {
                 old_state.block().on_data_delete(self, coord, old_state, data);
             }
             self.mark_modified(coord.chunk_coord());
+            self.emit(ExternEvent::DataDeleted(coord));
         }
     }
 
@@ -1363,6 +2265,7 @@ impl VoxelWorld {
         }
         let chunk_x = coord.x >> 4;
         let chunk_z = coord.z >> 4;
+        self.record_change(coord);
         let state = self.get_block(coord);
         state.block().on_data_set(self, coord, state, &mut tag);
         let chunk = self.chunks.get_mut((chunk_x, chunk_z)).expect("Chunk was None");
@@ -1372,6 +2275,7 @@ impl VoxelWorld {
             }
         }
         self.mark_modified(coord.chunk_coord());
+        self.emit(ExternEvent::DataSet(coord));
     }
 
     
@@ -1409,6 +2313,7 @@ impl VoxelWorld {
         if state.is_air() {
             return false;
         }
+        self.record_change(coord);
         let cur_ref = self.get_update_ref(coord);
         // if cur_ref is null, check if 
         if enabled {
@@ -1420,6 +2325,7 @@ impl VoxelWorld {
                 self.mark_modified(coord.chunk_coord());
                 let new_ref = self.update_queue.push(coord);
                 self.set_update_ref(coord, new_ref);
+                self.emit(ExternEvent::EnabledChanged { coord, enabled: true });
                 state.block().on_enabled_changed(self, coord, state, true);
                 false
             } else {
@@ -1433,6 +2339,7 @@ impl VoxelWorld {
                 self.mark_modified(coord.chunk_coord());
                 let cur_ref = self.set_update_ref(coord, UpdateRef::NULL);
                 self.update_queue.remove(cur_ref);
+                self.emit(ExternEvent::EnabledChanged { coord, enabled: false });
                 state.block().on_enabled_changed(self, coord, state, false);
                 true
             }
@@ -1450,10 +2357,30 @@ impl VoxelWorld {
         self.set_enabled(coord, false);
     }
 
+    /// Schedule an update for the block at `coord` in `delay` ticks. When the update is due,
+    /// [Block::on_scheduled_update](crate::core::voxel::block::Block::on_scheduled_update) is called on whatever block is there.
+    /// Updates on the same tick happen in order of `priority` (lowest first), then in the order that they were scheduled.
+    /// Returns false if the coordinate is out of bounds or the block is already scheduled for that tick.
+    pub fn schedule_update<C: Into<(i32, i32, i32)>>(&mut self, coord: C, delay: u32, priority: i32) -> bool {
+        let coord: (i32, i32, i32) = coord.into();
+        let coord: Coord = coord.into();
+        if !self.bounds().contains(coord) {
+            return false;
+        }
+        if !self.scheduled_updates.schedule(coord, delay, priority) {
+            return false;
+        }
+        self.mark_modified(coord.chunk_coord());
+        true
+    }
+
+    /// Moves to the next tick, then runs every enabled block's [Block::on_update](crate::core::voxel::block::Block::on_update)
+    /// followed by every scheduled update that is due and the random ticks.
     pub fn update(&mut self) {
         if self.lock_update_queue.swap(true) {
             panic!("World is already updating!");
         }
+        self.scheduled_updates.advance();
         self.update_modification_queue.clear();
         self.update_modification_map.clear();
         (0..self.update_queue.update_queue.len()).for_each(|i| {
@@ -1461,6 +2388,17 @@ impl VoxelWorld {
             let state = self.get_block(coord);
             state.block().on_update(self, coord, state);
         });
+        // Updates that are scheduled while these run are always at least one tick away, so this loop ends.
+        while let Some(scheduled) = self.scheduled_updates.pop_due() {
+            let coord = scheduled.coord;
+            if !self.bounds().contains(coord) {
+                continue;
+            }
+            // Blocks that change mark their chunk as modified, so chunks where nothing happened aren't saved again.
+            let state = self.get_block(coord);
+            state.block().on_scheduled_update(self, coord, state);
+        }
+        self.random_tick();
         (0..self.update_modification_queue.len()).for_each(|i| {
             let (coord, enabled) = self.update_modification_queue[i];
             self.set_enabled(coord, enabled);
@@ -1468,6 +2406,158 @@ impl VoxelWorld {
         self.lock_update_queue = false;
     }
 
+    pub fn set_random_tick_seed(&mut self, seed: u64) {
+        self.random_tick_rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Picks [VoxelWorld::random_tick_rate] blocks in each section of the loaded chunks within the
+    /// [VoxelWorld::simulation_distance] and calls [Block::on_random_tick](crate::core::voxel::block::Block::on_random_tick)
+    /// on the ones that tick randomly. Sections without any blocks that tick randomly are skipped.
+    fn random_tick(&mut self) {
+        if self.random_tick_rate == 0 || self.simulation_distance < 0 {
+            return;
+        }
+        let (center_x, center_z) = self.center.chunk_coord().xz();
+        let distance = self.simulation_distance;
+        let (section_bottom, section_top) = (WORLD_BOTTOM >> 4, WORLD_TOP >> 4);
+        let simulation_area = (center_z - distance..=center_z + distance)
+            .cartesian_product(center_x - distance..=center_x + distance)
+            .filter(|&(chunk_z, chunk_x)| self.entity_chunk_ready((chunk_x, chunk_z)))
+            .flat_map(|(chunk_z, chunk_x)| (section_bottom..section_top).map(move |y| Coord::new(chunk_x, y, chunk_z)))
+            .collect_vec();
+        simulation_area.into_iter().for_each(|section_coord| {
+            let ticking = self.get_section(section_coord)
+                .map(|section| section.random_tick_count > 0)
+                .unwrap_or(false);
+            if !ticking {
+                return;
+            }
+            let offset = section_coord * 16;
+            for _ in 0..self.random_tick_rate {
+                let index = self.random_tick_rng.gen_range(0..4096u16);
+                let coord = offset + Section::coord(index);
+                let state = self.get_block(coord);
+                if !state.is_air() && state.block().ticks_randomly() {
+                    state.block().on_random_tick(self, coord, state);
+                }
+            }
+        });
+    }
+
+    /// Determines if a chunk is loaded and isn't waiting to be loaded again. Entities can only be added
+    /// to these chunks, and entities in any other chunk don't move.
+    fn entity_chunk_ready(&self, chunk_coord: (i32, i32)) -> bool {
+        self.chunks.get(chunk_coord)
+            .map(|chunk| chunk.chunk_coord() == chunk_coord && chunk.load_id.null() && chunk.job_id.null() && !chunk.load_failed)
+            .unwrap_or(false)
+    }
+
+    /// Adds an entity to the chunk that its position is in.
+    /// Returns `None` if that chunk isn't loaded.
+    pub fn spawn_entity(&mut self, mut entity: VoxelEntity) -> Option<EntityId> {
+        let chunk_coord = VoxelEntity::chunk_at(entity.position);
+        if !self.entity_chunk_ready(chunk_coord) {
+            return None;
+        }
+        entity.chunk = chunk_coord;
+        let id = self.entities.insert(entity);
+        self.chunks.get_mut(chunk_coord).expect("Chunk was None").entities.push(id);
+        self.mark_modified(ChunkCoord::new(chunk_coord.0, chunk_coord.1));
+        Some(id)
+    }
+
+    /// Removes an entity from the world. Returns `None` if the entity was already removed.
+    pub fn remove_entity(&mut self, id: EntityId) -> Option<VoxelEntity> {
+        let entity = self.entities.take(id)?;
+        if let Some(chunk) = self.chunks.get_mut(entity.chunk) {
+            chunk.entities.retain(|&other| other != id);
+            self.mark_modified(ChunkCoord::new(entity.chunk.0, entity.chunk.1));
+        }
+        Some(entity)
+    }
+
+    pub fn get_entity(&self, id: EntityId) -> Option<&VoxelEntity> {
+        self.entities.get(id)
+    }
+
+    pub fn get_entity_mut(&mut self, id: EntityId) -> Option<&mut VoxelEntity> {
+        self.entities.get_mut(id)
+    }
+
+    /// Moves every entity by its velocity over `delta` seconds, colliding with blocks along the way.
+    /// [Block::on_entity_collide](crate::core::voxel::block::Block::on_entity_collide) is called for each block
+    /// that an entity starts touching after that entity has moved. Blocks that it was already touching aren't called again.
+    pub fn update_entities(&mut self, delta: f32) {
+        let ids = self.entities.iter().map(|(id, _)| id).collect_vec();
+        ids.into_iter().for_each(|id| self.update_entity(id, delta));
+    }
+
+    fn update_entity(&mut self, id: EntityId, delta: f32) {
+        // The entity might have been removed by a collision with an entity that moved before it.
+        let Some(entity) = self.entities.get(id) else {
+            return;
+        };
+        if !self.entity_chunk_ready(entity.chunk) {
+            return;
+        }
+        let mut velocity = entity.velocity;
+        if entity.gravity {
+            velocity.y = (velocity.y - physics::GRAVITY * delta).max(-physics::TERMINAL_VELOCITY);
+        }
+        let motion = velocity * delta;
+        let sweep = physics::move_aabb(self, entity.aabb(), motion, entity.step_height, entity.on_ground);
+        if sweep.motion.x != motion.x {
+            velocity.x = 0.0;
+        }
+        if sweep.motion.y != motion.y {
+            velocity.y = 0.0;
+        }
+        if sweep.motion.z != motion.z {
+            velocity.z = 0.0;
+        }
+        let on_ground = sweep.landed();
+        let entity = self.entities.get_mut(id).expect("Entity was None");
+        let old_chunk = entity.chunk;
+        entity.position += sweep.motion;
+        entity.velocity = velocity;
+        entity.on_ground = on_ground;
+        // Moving inside of a chunk doesn't mark it as modified every tick. Its position is saved by mark_moved_entities instead.
+        entity.moved |= sweep.motion != Vec3::ZERO;
+        let old_contacts = std::mem::replace(&mut entity.contacts, sweep.hits.clone());
+        let new_chunk = VoxelEntity::chunk_at(entity.position);
+        let fell_out = entity.position.y < (WORLD_BOTTOM - 64) as f32;
+        if fell_out {
+            self.remove_entity(id);
+            return;
+        }
+        // Entities stay in their old chunk until the chunk that they moved into is loaded.
+        if new_chunk != old_chunk && self.entity_chunk_ready(new_chunk) {
+            self.chunks.get_mut(old_chunk).expect("Chunk was None").entities.retain(|&other| other != id);
+            self.chunks.get_mut(new_chunk).expect("Chunk was None").entities.push(id);
+            self.entities.get_mut(id).expect("Entity was None").chunk = new_chunk;
+            self.mark_modified(ChunkCoord::new(old_chunk.0, old_chunk.1));
+            self.mark_modified(ChunkCoord::new(new_chunk.0, new_chunk.1));
+        }
+        sweep.hits.into_iter()
+            .filter(|hit| !old_contacts.contains(hit))
+            .for_each(|(coord, face)| {
+                let state = self.get_block(coord);
+                state.block().on_entity_collide(self, coord, state, face, id);
+            });
+    }
+
+    /// Marks the chunks of the entities that moved since they were last saved as modified so that the new positions are saved.
+    fn mark_moved_entities(&mut self) {
+        let chunks = self.entities.iter_mut()
+            .filter(|(_, entity)| entity.moved)
+            .map(|(_, entity)| {
+                entity.moved = false;
+                entity.chunk
+            })
+            .collect::<hashbrown::HashSet<_>>();
+        chunks.into_iter().for_each(|(chunk_x, chunk_z)| self.mark_modified(ChunkCoord::new(chunk_x, chunk_z)));
+    }
+
     pub fn height(&self, x: i32, z: i32) -> i32 {
         let chunk_x = x >> 4;
         let chunk_z = z >> 4;
@@ -1516,8 +2606,10 @@ impl VoxelWorld {
             max_x * 16,
             max_z * 16
         );
-        let min_y = WORLD_BOTTOM;
-        let max_y = WORLD_TOP;
+        let Bounds3D { min: (sub_min_x, min_y, sub_min_z), max: (sub_max_x, max_y, sub_max_z) } = self.subworld_bounds;
+        let (min_x, min_z) = (min_x.max(sub_min_x), min_z.max(sub_min_z));
+        // The minimum is clamped so that the bounds are empty (rather than inverted) outside of the subworld bounds.
+        let (maxx, maxz) = (maxx.min(sub_max_x).max(min_x), maxz.min(sub_max_z).max(min_z));
         Bounds3D::new(
             (min_x, min_y, min_z),
             (maxx, max_y, maxz)
@@ -1525,7 +2617,7 @@ impl VoxelWorld {
     }
 
     pub fn render_bounds(&self) -> Bounds3D {
-        let bounds = self.render_chunks.bounds();
+        let bounds = self.render_area;
         let (min_x, min_y, min_z) = bounds.min;
         let (max_x, max_y, max_z) = bounds.max;
         let (min_x, min_y, min_z) = (
@@ -1591,11 +2683,148 @@ impl MemoryUsage {
     }
 }
 
+/// The optional render state of a [VoxelWorld].
+/// Headless worlds don't have one of these.
+pub struct RenderLayer {
+    pub array_texture: Handle<Image>,
+    pub render_chunks: RollGrid3D<RenderChunk>,
+    pub move_render_chunk_queue: ObjectPool<Coord, MoveRenderChunkMarker>,
+    /// The mesh jobs that have been sent to the [ChunkWorkers].
+    pub pending_meshes: ObjectPool<Coord, MeshJobMarker>,
+    /// Meshes that the [ChunkWorkers] finished, waiting to be swapped into their [RenderChunk].
+    pub finished_meshes: Vec<(PoolId<MeshJobMarker>, Coord, SectionMesh)>,
+    /// Render chunks whose [LOD] needs to be checked after the center of the world moved.
+    pub lod_queue: ObjectPool<Coord, LodUpdateMarker>,
+    /// Sections with transparent layers that were remeshed since the last sort.
+    pub unsorted: Vec<Coord>,
+    /// Where the camera was the last time that every transparent layer was sorted.
+    pub last_sort_position: Option<Vec3>,
+}
+
 pub struct RenderChunk {
     pub entity: Entity,
     pub mesh: Handle<Mesh>,
     pub material: Handle<VoxelMaterial>,
     pub move_id: PoolId<MoveRenderChunkMarker>,
+    pub mesh_job: PoolId<MeshJobMarker>,
+    /// The [LOD] that the current mesh was built at.
+    pub lod: LOD,
+    pub lod_id: PoolId<LodUpdateMarker>,
+    /// The meshes of the layers other than [BlockLayer::Base], which is the chunk's own mesh.
+    pub layers: Vec<LayerMesh>,
+}
+
+/// The mesh of a [BlockLayer] other than [BlockLayer::Base].
+/// Layer entities are children of the [RenderChunk]'s entity so that they move and despawn with it.
+pub struct LayerMesh {
+    pub layer: BlockLayer,
+    pub entity: Entity,
+    pub mesh: Handle<Mesh>,
+    /// Transparent layers have their own blended material. Other layers share the chunk's material.
+    pub material: Handle<VoxelMaterial>,
+    /// A copy of the mesh data of a transparent layer so that its faces can be sorted.
+    pub sort_data: Option<MeshData>,
+}
+
+impl RenderChunk {
+    /// Replaces the meshes of every layer with the meshes of a [SectionMesh]. Entities are spawned for new layers
+    /// and despawned for layers that no longer have any blocks.
+    /// Returns true if the section has a transparent layer that needs to be sorted.
+    fn apply_section_mesh(
+        &mut self,
+        mut section_mesh: SectionMesh,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<VoxelMaterial>,
+    ) -> bool {
+        let base = section_mesh.take_layer(BlockLayer::Base);
+        base.push_to_mesh(meshes.get_mut(self.mesh.id()).expect("Failed to get the mesh"));
+        self.layers.retain(|layer_mesh| {
+            if section_mesh.layer(layer_mesh.layer).is_some() {
+                return true;
+            }
+            commands.entity(layer_mesh.entity).despawn_recursive();
+            false
+        });
+        let mut transparent = false;
+        section_mesh.layers.into_iter().for_each(|(layer, mesh_data)| {
+            let index = match self.layers.iter().position(|layer_mesh| layer_mesh.layer == layer) {
+                Some(index) => index,
+                None => {
+                    let layer_mesh = self.spawn_layer(layer, commands, meshes, materials);
+                    self.layers.push(layer_mesh);
+                    self.layers.len() - 1
+                }
+            };
+            let layer_mesh = &mut self.layers[index];
+            if layer.transparent() {
+                layer_mesh.sort_data = Some(mesh_data.clone());
+                transparent = true;
+            }
+            mesh_data.push_to_mesh(meshes.get_mut(layer_mesh.mesh.id()).expect("Failed to get the mesh"));
+        });
+        transparent
+    }
+
+    /// Spawns the entity for a layer as a child of this chunk's entity.
+    fn spawn_layer(
+        &self,
+        layer: BlockLayer,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<VoxelMaterial>,
+    ) -> LayerMesh {
+        let mesh = meshes.add(MeshBuilder::create_mesh(RenderAssetUsages::all(), |build| ()));
+        let material = if layer.transparent() {
+            // The copy keeps the lightmap of the chunk's material.
+            let mut material = materials.get(self.material.id()).expect("Failed to get the material").clone();
+            material.alpha_mode = AlphaMode::Blend;
+            materials.add(material)
+        } else {
+            self.material.clone()
+        };
+        use bevy::render::primitives::Aabb;
+        let aabb = Aabb::from_min_max(vec3(0.0, 0.0, 0.0), vec3(16.0, 16.0, 16.0));
+        let entity = commands.spawn((
+            MaterialMeshBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                ..Default::default()
+            },
+            aabb,
+        )).id();
+        commands.entity(self.entity).add_child(entity);
+        LayerMesh {
+            layer,
+            entity,
+            mesh,
+            material,
+            sort_data: None,
+        }
+    }
+
+    /// Applies a lightmap to the chunk's material and the materials of its transparent layers.
+    fn apply_lightmap(&self, lightmap: SectionLightmap, materials: &mut Assets<VoxelMaterial>) {
+        self.layers.iter()
+            .filter(|layer_mesh| layer_mesh.material != self.material)
+            .for_each(|layer_mesh| {
+                let material = materials.get_mut(layer_mesh.material.id()).expect("Failed to get the material");
+                material.apply_lightmap(lightmap.clone());
+            });
+        let material = materials.get_mut(self.material.id()).expect("Failed to get the material");
+        material.apply_lightmap(lightmap);
+    }
+
+    /// Sorts the faces of the transparent layers back-to-front from a viewpoint relative to the section.
+    fn sort_transparent_faces(&self, viewpoint: Vec3, meshes: &mut Assets<Mesh>) {
+        self.layers.iter().for_each(|layer_mesh| {
+            let Some(mesh_data) = &layer_mesh.sort_data else {
+                return;
+            };
+            let mesh = meshes.get_mut(layer_mesh.mesh.id()).expect("Failed to get the mesh");
+            mesh.insert_indices(bevy::render::mesh::Indices::U32(mesh_data.sorted_indices(viewpoint)));
+        });
+    }
 }
 
 pub struct PlaceContext {
@@ -1660,4 +2889,474 @@ impl PlaceContext {
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enable.swap(Some(true));
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::blockstate;
+    use crate::core::voxel::{block::Block, blocks, blockstate::BlockState, testing::{self, open_world, open_world_with_generator, TestDirectory}};
+    use crate::prelude::{Flip, Rotation, StateValue};
+
+    use super::*;
+
+    /// A block that can be seen through. It's greedy meshed, but has no mesh of its own.
+    struct TransparentTestBlock(&'static str);
+    impl Block for TransparentTestBlock {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn default_state(&self) -> BlockState {
+            BlockState::new(self.0, [])
+        }
+
+        fn layer(&self, world: &VoxelWorld, coord: Coord, state: Id) -> BlockLayer {
+            BlockLayer::Transparent
+        }
+
+        fn greedy_faces(&self, state: Id) -> Option<Faces<u32>> {
+            Some(Faces::new(0, 0, 0, 0, 0, 0))
+        }
+    }
+
+    /// Turns into `test_stone` when it's randomly ticked, like grass that dies.
+    struct RandomTickTestBlock;
+    impl Block for RandomTickTestBlock {
+        fn name(&self) -> &str {
+            "test_random_tick"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_random_tick)
+        }
+
+        fn ticks_randomly(&self) -> bool {
+            true
+        }
+
+        fn on_random_tick(&self, world: &mut VoxelWorld, coord: Coord, state: Id) {
+            world.set_block(coord, blockstate!(test_stone).register());
+        }
+    }
+
+    /// Half of a block tall, so entities can step up onto it. It's flipped upside down with its orientation.
+    struct SlabTestBlock;
+    impl Block for SlabTestBlock {
+        fn name(&self) -> &str {
+            "test_slab"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_slab)
+        }
+
+        fn orientation(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Orientation {
+            if let StateValue::Orientation(orientation) = state["orientation"] {
+                orientation
+            } else {
+                Orientation::UNORIENTED
+            }
+        }
+
+        fn selection_shape(&self, world: &VoxelWorld, coord: Coord, state: Id) -> Vec<AABB> {
+            vec![AABB::new(Vec3::splat(-0.5), vec3(0.5, 0.0, 0.5))]
+        }
+    }
+
+    /// Stores the face that an entity collided with in the entity's data.
+    struct CollideTestBlock;
+    impl Block for CollideTestBlock {
+        fn name(&self) -> &str {
+            "test_collide"
+        }
+
+        fn default_state(&self) -> BlockState {
+            blockstate!(test_collide)
+        }
+
+        fn on_entity_collide(&self, world: &mut VoxelWorld, coord: Coord, state: Id, face: Direction, entity: EntityId) {
+            if let Some(entity) = world.get_entity_mut(entity) {
+                entity.data = Tag::from(face);
+            }
+        }
+    }
+
+    /// Registers the shared test blocks and the blocks that only these tests use.
+    fn register_test_blocks() {
+        testing::register_test_blocks();
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| {
+            blocks::register_block(TransparentTestBlock("test_glass"));
+            blocks::register_block(TransparentTestBlock("test_ice"));
+            blocks::register_block(RandomTickTestBlock);
+            blocks::register_block(SlabTestBlock);
+            blocks::register_block(CollideTestBlock);
+        });
+    }
+
+    #[test]
+    fn headless_world_test() {
+        register_test_blocks();
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("headless_world_test");
+        {
+            let mut world = VoxelWorld::open_headless(&directory, 2, (0, 0, 0), None).expect("Failed to open the world");
+            assert!(world.is_headless());
+            world.flush_load_queue();
+            assert!(world.load_queue.is_empty());
+            assert_eq!(world.set_block((1, 2, 3), state), Id::AIR);
+            assert_eq!(world.get_block((1, 2, 3)), state);
+            let ray = Ray3d::new(vec3(1.5, 10.5, 3.5), Vec3::NEG_Y);
+            let hit = world.raycast(ray, 16.0).expect("Raycast did not hit the block");
+            assert_eq!(hit.coord, Coord::new(1, 2, 3));
+            assert_eq!(hit.id, state);
+            world.save_world().expect("Failed to save the world");
+        }
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        assert_eq!(world.get_block((1, 2, 3)), state);
+        assert_eq!(world.get_block((1, 3, 3)), Id::AIR);
+    }
+
+    #[test]
+    fn worldgen_queue_test() {
+        register_test_blocks();
+        use crate::core::voxel::procgen::worldgenerator::FlatWorldGenerator;
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("worldgen_queue_test");
+        let generator = FlatWorldGenerator::from_iter([(4u16, state)]);
+        let mut world = open_world_with_generator(&directory, 1, (0, 0, 0), Some(Box::new(generator)));
+        assert!(!world.worldgen_queue.is_empty());
+        world.flush_worldgen_queue();
+        assert!(world.worldgen_queue.is_empty());
+        assert_eq!(world.get_block((5, WORLD_BOTTOM + 3, -7)), state);
+        assert_eq!(world.get_block((5, WORLD_BOTTOM + 4, -7)), Id::AIR);
+        assert_eq!(world.height(5, -7), WORLD_BOTTOM + 4);
+        assert_eq!(world.get_sky_light((5, WORLD_BOTTOM + 4, -7)), 15);
+        assert_eq!(world.get_sky_light((5, WORLD_BOTTOM + 3, -7)), 0);
+        assert!(!world.save_queue.is_empty());
+    }
+
+    #[test]
+    fn snapshot_section_test() {
+        register_test_blocks();
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("snapshot_section_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        world.set_block((15, 0, 0), state);
+        world.set_block((16, 0, 0), state);
+        world.set_block((17, 0, 0), state);
+        let snapshot = world.snapshot_section(Coord::new(0, 0, 0));
+        assert_eq!(snapshot.get_block(Coord::new(15, 0, 0)), state);
+        // The border is included, but nothing past it.
+        assert_eq!(snapshot.get_block(Coord::new(16, 0, 0)), state);
+        assert_eq!(snapshot.get_block(Coord::new(17, 0, 0)), Id::AIR);
+        assert!(snapshot.occlusion(Coord::new(15, 0, 0)).hidden(Direction::PosX));
+        assert!(snapshot.occlusion(Coord::new(15, 0, 0)).visible(Direction::NegX));
+    }
+
+    #[test]
+    fn section_lod_test() {
+        let directory = TestDirectory::new("section_lod_test");
+        let mut world = VoxelWorld::open_headless(&directory, 8, (8, 8, 8), None).expect("Failed to open the world");
+        world.lod_distances = [1, 2, 4];
+        assert_eq!(world.section_lod(Coord::new(0, 0, 0)), LOD::Level0);
+        assert_eq!(world.section_lod(Coord::new(1, 0, -1)), LOD::Level1);
+        assert_eq!(world.section_lod(Coord::new(0, 3, 0)), LOD::Level2);
+        assert_eq!(world.section_lod(Coord::new(-6, 0, 0)), LOD::Level3);
+        // The faces that border a section with a different LOD are seams.
+        let snapshot = world.snapshot_section(Coord::new(0, 0, 0));
+        assert!(Direction::iter().all(|dir| snapshot.seam(dir)));
+        let snapshot = world.snapshot_section(Coord::new(2, 0, 0));
+        assert!(snapshot.seam(Direction::NegX));
+        assert!(!snapshot.seam(Direction::PosX));
+        world.move_center((40, 8, 8));
+        assert_eq!(world.section_lod(Coord::new(2, 0, 0)), LOD::Level0);
+        assert_eq!(world.section_lod(Coord::new(0, 0, 0)), LOD::Level2);
+    }
+
+    #[test]
+    fn chunk_workers_test() {
+        register_test_blocks();
+        use crate::core::voxel::procgen::worldgenerator::FlatWorldGenerator;
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("chunk_workers_test");
+        {
+            let mut world = VoxelWorld::open_headless(&directory, 1, (0, 0, 0), None).expect("Failed to open the world");
+            world.chunk_generator = Some(Arc::new(FlatWorldGenerator::from_iter([(4u16, state)])));
+            world.enable_workers(2);
+            world.flush_load_queue();
+            world.flush_worldgen_queue();
+            assert!(world.pending_jobs.is_empty());
+            assert_eq!(world.get_block((5, WORLD_BOTTOM + 3, -7)), state);
+            assert_eq!(world.get_block((5, WORLD_BOTTOM + 4, -7)), Id::AIR);
+            assert_eq!(world.get_sky_light((5, WORLD_BOTTOM + 4, -7)), 15);
+            world.set_block((1, 2, 3), state);
+            // Move away and dispatch loads, then move back before they're integrated.
+            world.move_center((4096, 0, 4096));
+            world.dispatch_load_jobs();
+            assert!(!world.pending_jobs.is_empty());
+            world.move_center((0, 0, 0));
+            assert!(world.pending_jobs.is_empty());
+            world.flush_load_queue();
+            assert!(world.pending_jobs.is_empty());
+            // Stale results from the far chunks must not have been integrated.
+            assert_eq!(world.get_block((1, 2, 3)), state);
+            assert_eq!(world.get_block((5, WORLD_BOTTOM + 3, -7)), state);
+            world.set_block((1, 3, 3), state);
+            world.save_world().expect("Failed to save the world");
+            world.disable_workers();
+        }
+        let mut world = open_world(&directory, 1, (0, 0, 0));
+        assert!(world.worldgen_queue.is_empty());
+        assert_eq!(world.get_block((1, 2, 3)), state);
+        assert_eq!(world.get_block((1, 3, 3)), state);
+        assert_eq!(world.get_block((5, WORLD_BOTTOM + 3, -7)), state);
+    }
+
+    #[test]
+    fn corrupt_chunk_test() {
+        use std::io::{Read, Write};
+        register_test_blocks();
+        let state = blockstate!(test_stone).register();
+        let directory = TestDirectory::new("corrupt_chunk_test");
+        let region_path = {
+            let mut world = open_world(&directory, 2, (0, 0, 0));
+            world.set_block((1, 2, 3), state);
+            world.set_block((17, 2, 3), state);
+            world.save_world().expect("Failed to save the world");
+            world.get_region_path(0, 0)
+        };
+        // Data that can't be read as a chunk.
+        let corrupt = vec![1u8, 2, 3];
+        RegionFile::open(&region_path).expect("Failed to open the region file")
+            .write_timestamped_versioned((0, 0), Timestamp::new(1), 0, |writer| {
+                writer.write_all(&corrupt)?;
+                Ok(())
+            }).expect("Failed to corrupt the chunk");
+        let read_raw = || {
+            RegionFile::open(&region_path).expect("Failed to open the region file")
+                .read((0, 0), |reader| {
+                    let mut data = Vec::new();
+                    reader.read_to_end(&mut data)?;
+                    Ok(data)
+                }).expect("The chunk was deleted")
+        };
+        for workers in [false, true] {
+            let mut world = VoxelWorld::open_headless(&directory, 2, (0, 0, 0), None).expect("Failed to open the world");
+            if workers {
+                world.enable_workers(1);
+            }
+            world.flush_load_queue();
+            let chunk = world.get_chunk((0, 0)).expect("Chunk was not present");
+            assert!(chunk.load_failed);
+            assert!(chunk.world_gen_id.null());
+            assert!(!world.get_chunk((1, 0)).unwrap().load_failed);
+            assert_eq!(world.get_block((17, 2, 3)), state);
+            // Edits to the chunk that couldn't be loaded aren't saved, but the rest of the world is.
+            world.set_block((2, 2, 3), state);
+            world.set_block((17, 3, 3), state);
+            world.save_world().expect("Failed to save the world");
+            world.disable_workers();
+            drop(world);
+            assert_eq!(read_raw(), corrupt);
+        }
+        let world = open_world(&directory, 2, (0, 0, 0));
+        assert_eq!(world.get_block((17, 3, 3)), state);
+    }
+
+    #[test]
+    fn transparent_occlusion_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let glass = blockstate!(test_glass).register();
+        let ice = blockstate!(test_ice).register();
+        let directory = TestDirectory::new("transparent_occlusion_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // The faces between two of the same transparent block are hidden.
+        world.set_block((0, 0, 0), glass);
+        world.set_block((1, 0, 0), glass);
+        assert!(world.get_occlusion((0, 0, 0)).hidden(Direction::PosX));
+        assert!(world.get_occlusion((1, 0, 0)).hidden(Direction::NegX));
+        // Different transparent blocks can be seen through each other.
+        world.set_block((1, 0, 0), ice);
+        assert!(world.get_occlusion((0, 0, 0)).visible(Direction::PosX));
+        assert!(world.get_occlusion((1, 0, 0)).visible(Direction::NegX));
+        // Opaque blocks don't hide transparent blocks and transparent blocks don't hide opaque blocks.
+        world.set_block((1, 0, 0), stone);
+        assert!(world.get_occlusion((0, 0, 0)).visible(Direction::PosX));
+        assert!(world.get_occlusion((1, 0, 0)).visible(Direction::NegX));
+        // Transparent blocks are meshed separately from the base layer (the stone block has no mesh).
+        let snapshot = world.snapshot_section(Coord::new(0, 0, 0));
+        let mesh = mesh_section(&snapshot, LOD::Level0);
+        assert!(mesh.layer(BlockLayer::Base).is_none());
+        let transparent = mesh.layer(BlockLayer::Transparent).expect("Transparent layer was not meshed");
+        // All six faces of the glass block are visible.
+        assert_eq!(transparent.indices.len(), 6 * 6);
+    }
+
+    #[test]
+    fn scheduled_update_test() {
+        register_test_blocks();
+        let state = blockstate!(test_scheduled).register();
+        let directory = TestDirectory::new("scheduled_update_test");
+        {
+            let mut world = open_world(&directory, 2, (0, 0, 0));
+            world.set_block((1, 2, 3), state);
+            assert!(world.schedule_update((1, 2, 3), 3, 0));
+            assert!(!world.schedule_update((1, 2, 3), 3, 0));
+            world.update();
+            world.update();
+            assert_eq!(world.get_block((1, 2, 3)), state);
+            world.update();
+            assert_eq!(world.get_block((1, 2, 3)), Id::AIR);
+            world.set_block((1, 2, 3), state);
+            world.schedule_update((1, 2, 3), 5, 0);
+            world.update();
+            world.save_world().expect("Failed to save the world");
+        }
+        // The pending update was saved with 4 ticks left.
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        assert_eq!(world.scheduled_updates.len(), 1);
+        (0..3).for_each(|_| world.update());
+        assert_eq!(world.get_block((1, 2, 3)), state);
+        world.update();
+        assert_eq!(world.get_block((1, 2, 3)), Id::AIR);
+    }
+
+    #[test]
+    fn random_tick_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let grass = blockstate!(test_random_tick).register();
+        let directory = TestDirectory::new("random_tick_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        // Random ticks are seeded from the world's seed until another seed is set.
+        let mut seeded = StdRng::seed_from_u64(world.metadata().seed());
+        assert_eq!(world.random_tick_rng.gen::<u64>(), seeded.gen::<u64>());
+        world.set_random_tick_seed(1234);
+        world.set_block((1, 2, 3), grass);
+        world.set_block((2, 2, 3), stone);
+        assert_eq!(world.get_section(Coord::new(0, 0, 0)).unwrap().random_tick_count, 1);
+        world.random_tick_rate = 0;
+        world.update();
+        assert_eq!(world.get_block((1, 2, 3)), grass);
+        // With this many picks, every block in the section is all but certain to be picked.
+        world.random_tick_rate = 4096 * 16;
+        world.update();
+        assert_eq!(world.get_block((1, 2, 3)), stone);
+        assert_eq!(world.get_section(Coord::new(0, 0, 0)).unwrap().random_tick_count, 0);
+        // Random ticks happen within the simulation distance, even outside of the render area.
+        world.set_block((49, 2, 3), grass);
+        assert!(!world.render_area.contains((3, 0, 0)));
+        world.simulation_distance = 2;
+        world.update();
+        assert_eq!(world.get_block((49, 2, 3)), grass);
+        world.simulation_distance = 3;
+        world.update();
+        assert_eq!(world.get_block((49, 2, 3)), stone);
+    }
+
+    #[test]
+    fn entity_physics_test() {
+        register_test_blocks();
+        let stone = blockstate!(test_stone).register();
+        let slab = blockstate!(test_slab).register();
+        let wall = blockstate!(test_collide).register();
+        let directory = TestDirectory::new("entity_physics_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        for x in -2..6 {
+            world.set_block((x, 0, 0), stone);
+        }
+        world.set_block((0, 0, 0), wall);
+        world.set_block((2, 1, 0), slab);
+        world.set_block((3, 1, 0), slab);
+        world.set_block((4, 1, 0), wall);
+        world.set_block((4, 2, 0), wall);
+        let id = world.spawn_entity(VoxelEntity::new("test", vec3(0.5, 5.0, 0.5), vec3(0.6, 1.8, 0.6))).expect("Chunk was not loaded");
+        (0..20).for_each(|_| world.update_entities(0.05));
+        let entity = world.get_entity(id).unwrap();
+        assert_eq!(entity.position, vec3(0.5, 1.0, 0.5));
+        assert_eq!(entity.velocity, Vec3::ZERO);
+        assert!(entity.on_ground);
+        assert_eq!(entity.data, Tag::from(Direction::PosY));
+        // Resting on the ground doesn't collide with it again.
+        world.get_entity_mut(id).unwrap().data = Tag::Null;
+        (0..5).for_each(|_| world.update_entities(0.05));
+        assert_eq!(world.get_entity(id).unwrap().data, Tag::Null);
+        // Walk up onto the slabs, then into the wall, which is too tall to step up.
+        (0..20).for_each(|_| {
+            world.get_entity_mut(id).unwrap().velocity.x = 4.0;
+            world.update_entities(0.05);
+        });
+        let entity = world.get_entity(id).unwrap();
+        assert!((entity.position.x - 3.7).abs() < 0.0001);
+        assert!((entity.position.y - 1.5).abs() < 0.0001);
+        assert!(entity.on_ground);
+        assert_eq!(entity.data, Tag::from(Direction::NegX));
+        assert!(world.remove_entity(id).is_some());
+        assert!(world.get_entity(id).is_none());
+        assert!(world.get_chunk((0, 0)).unwrap().entities.is_empty());
+    }
+
+    #[test]
+    fn entity_save_test() {
+        let directory = TestDirectory::new("entity_save_test");
+        {
+            let mut world = open_world(&directory, 2, (0, 0, 0));
+            let entity = VoxelEntity::new("test", vec3(15.5, 10.0, 0.5), vec3(0.5, 0.5, 0.5))
+                .with_gravity(false)
+                .with_velocity(vec3(2.0, 0.0, 0.0))
+                .with_data("hello");
+            let id = world.spawn_entity(entity).expect("Chunk was not loaded");
+            (0..10).for_each(|_| world.update_entities(0.05));
+            // The entity moved into the next chunk.
+            assert!(world.get_chunk((0, 0)).unwrap().entities.is_empty());
+            assert_eq!(world.get_chunk((1, 0)).unwrap().entities, vec![id]);
+            world.save_world().expect("Failed to save the world");
+            // Moving inside of a chunk doesn't mark it as modified, but the position is still saved.
+            (0..5).for_each(|_| world.update_entities(0.05));
+            assert!(world.save_queue.is_empty());
+            world.save_world().expect("Failed to save the world");
+        }
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        assert_eq!(world.entities.len(), 1);
+        let &id = world.get_chunk((1, 0)).unwrap().entities.first().expect("Entity was not loaded");
+        let entity = world.get_entity(id).unwrap();
+        assert_eq!(entity.kind, "test");
+        assert!((entity.position - vec3(17.0, 10.0, 0.5)).length() < 0.0001);
+        assert_eq!(entity.velocity, vec3(2.0, 0.0, 0.0));
+        assert_eq!(entity.data, Tag::from("hello"));
+        assert!(!entity.gravity);
+    }
+
+    #[test]
+    fn block_shape_test() {
+        register_test_blocks();
+        let slab = blockstate!(test_slab).register();
+        let top_slab = blockstate!(test_slab, orientation = Orientation::new(Rotation::new(Direction::PosY, 0), Flip::Y)).register();
+        let directory = TestDirectory::new("block_shape_test");
+        let mut world = open_world(&directory, 2, (0, 0, 0));
+        world.set_block((0, 0, 0), slab);
+        world.set_block((2, 0, 0), top_slab);
+        assert_eq!(slab.block().collision_boxes(&world, Coord::new(0, 0, 0), slab), vec![
+            AABB::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.5, 1.0)),
+        ]);
+        assert_eq!(top_slab.block().selection_boxes(&world, Coord::new(2, 0, 0), top_slab), vec![
+            AABB::new(vec3(2.0, 0.5, 0.0), vec3(3.0, 1.0, 1.0)),
+        ]);
+        let hit = world.raycast(Ray3d::new(vec3(0.5, 5.0, 0.5), Vec3::NEG_Y), 16.0).expect("Raycast did not hit the slab");
+        assert_eq!(hit.coord, Coord::new(0, 0, 0));
+        assert_eq!(hit.hit_point.y, 0.5);
+        let hit = world.raycast(Ray3d::new(vec3(2.5, 5.0, 0.5), Vec3::NEG_Y), 16.0).expect("Raycast did not hit the top slab");
+        assert_eq!(hit.hit_point.y, 1.0);
+        // Passes over the bottom slab and hits the side of the top slab.
+        let hit = world.raycast(Ray3d::new(vec3(-1.5, 0.75, 0.5), Vec3::X), 16.0).expect("Raycast did not hit the top slab");
+        assert_eq!(hit.coord, Coord::new(2, 0, 0));
+        assert_eq!(hit.direction, Some(Direction::NegX));
+        assert_eq!(hit.hit_point.x, 2.0);
+        // A diagonal ray enters the block through its side, but the face that it hits is the top of the slab.
+        let hit = world.raycast(Ray3d::new(vec3(-0.5, 0.9, -0.3), vec3(1.0, -0.5, 1.0)), 16.0).expect("Raycast did not hit the slab");
+        assert_eq!(hit.coord, Coord::new(0, 0, 0));
+        assert_eq!(hit.direction, Some(Direction::PosY));
+        assert!((hit.hit_point.y - 0.5).abs() < 0.0001);
+    }
+}
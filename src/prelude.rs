@@ -14,6 +14,7 @@ pub use crate::core::{
         world::{
             occlusion::Occlusion,
             chunkcoord::ChunkCoord,
+            edit::Selection,
         },
         tag::*,
     },